@@ -0,0 +1,140 @@
+//! Channel micro-benchmark of a broadcast fan-out at 100, 1k and 10k sessions.
+//!
+//! This does not run the room code. The server is a binary crate, so the loops below are hand-written
+//! stand-ins for `Room::broadcast_clients` and the `BingoServer` command loop over the same tokio channels,
+//! and they have to be kept in step with the real ones by hand. Each group compares one `String` copy per
+//! session, the design before `Msg` became an `Arc<str>`, against sharing one `Arc<str>` payload and against
+//! batching several messages into a single frame before the fan-out. Read the results as the cost of the
+//! channel sends and payload copies only.
+//!
+//! Run with `cargo bench --bench channel_fanout`. The real `Room::broadcast` is timed by the ignored
+//! `broadcast_fan_out` test of the room module, `cargo test --release broadcast_fan_out -- --ignored --nocapture`.
+
+use std::sync::Arc;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::sync::mpsc;
+
+const SESSION_COUNTS: [usize; 3] = [100, 1_000, 10_000];
+
+/// Messages a batched design would collect into one frame, about one presence flush worth of updates.
+const BATCH_SIZE: usize = 10;
+
+/// Commands pushed through the command loop per iteration.
+const COMMANDS: usize = 100;
+
+/// A typical `draw` broadcast.
+const DRAW: &str = r#"{"type":"draw","number":42,"call":17,"duplicate":false,"bonus":false}"#;
+
+struct Sessions<T>{
+    senders: Vec<mpsc::UnboundedSender<T>>,
+    receivers: Vec<mpsc::UnboundedReceiver<T>>,
+}
+
+impl<T> Sessions<T>{
+    fn new(count: usize) -> Self {
+        let (senders, receivers) = (0..count).map(|_| mpsc::unbounded_channel()).unzip();
+        Self{ senders, receivers }
+    }
+
+    /// Empties the queues the way the socket writers would, so memory doesn't grow between iterations.
+    fn drain(&mut self){
+        for rx in &mut self.receivers{
+            while rx.try_recv().is_ok() {}
+        }
+    }
+}
+
+fn broadcast(c: &mut Criterion){
+    let mut group = c.benchmark_group("broadcast");
+    for count in SESSION_COUNTS{
+        group.throughput(Throughput::Elements(count as u64));
+
+        let mut sessions = Sessions::<String>::new(count);
+        group.bench_with_input(BenchmarkId::new("string_clone", count), &count, |b, _| b.iter(|| {
+            for tx in &sessions.senders{
+                let _ = tx.send(DRAW.to_owned());
+            }
+            sessions.drain();
+        }));
+
+        let mut sessions = Sessions::<Arc<str>>::new(count);
+        group.bench_with_input(BenchmarkId::new("arc_str", count), &count, |b, _| b.iter(|| {
+            let msg: Arc<str> = Arc::from(DRAW);
+            for tx in &sessions.senders{
+                let _ = tx.send(msg.clone());
+            }
+            sessions.drain();
+        }));
+    }
+    group.finish();
+}
+
+/// Cost of `BATCH_SIZE` messages, sent one by one or joined into one JSON array frame.
+fn batched(c: &mut Criterion){
+    let mut group = c.benchmark_group("batched");
+    for count in SESSION_COUNTS{
+        group.throughput(Throughput::Elements((count * BATCH_SIZE) as u64));
+
+        let mut sessions = Sessions::<Arc<str>>::new(count);
+        group.bench_with_input(BenchmarkId::new("per_message", count), &count, |b, _| b.iter(|| {
+            for _ in 0..BATCH_SIZE{
+                let msg: Arc<str> = Arc::from(DRAW);
+                for tx in &sessions.senders{
+                    let _ = tx.send(msg.clone());
+                }
+            }
+            sessions.drain();
+        }));
+
+        let mut sessions = Sessions::<Arc<str>>::new(count);
+        group.bench_with_input(BenchmarkId::new("one_frame", count), &count, |b, _| b.iter(|| {
+            let frame: Arc<str> = Arc::from(format!("[{}]", [DRAW; BATCH_SIZE].join(",")));
+            for tx in &sessions.senders{
+                let _ = tx.send(frame.clone());
+            }
+            sessions.drain();
+        }));
+    }
+    group.finish();
+}
+
+/// Broadcast commands queued to the actor and handled one at a time, like `BingoServer::run`.
+fn command_loop(c: &mut Criterion){
+    let mut group = c.benchmark_group("command_loop");
+    for count in SESSION_COUNTS{
+        group.throughput(Throughput::Elements((count * COMMANDS) as u64));
+
+        let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<String>();
+        let mut sessions = Sessions::<String>::new(count);
+        group.bench_with_input(BenchmarkId::new("string_clone", count), &count, |b, _| b.iter(|| {
+            for _ in 0..COMMANDS{
+                cmd_tx.send(DRAW.to_owned()).unwrap();
+            }
+            while let Ok(msg) = cmd_rx.try_recv(){
+                for tx in &sessions.senders{
+                    let _ = tx.send(msg.clone());
+                }
+            }
+            sessions.drain();
+        }));
+
+        let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<Arc<str>>();
+        let mut sessions = Sessions::<Arc<str>>::new(count);
+        group.bench_with_input(BenchmarkId::new("arc_str", count), &count, |b, _| b.iter(|| {
+            for _ in 0..COMMANDS{
+                cmd_tx.send(Arc::from(DRAW)).unwrap();
+            }
+            while let Ok(msg) = cmd_rx.try_recv(){
+                for tx in &sessions.senders{
+                    let _ = tx.send(msg.clone());
+                }
+            }
+            sessions.drain();
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, broadcast, batched, command_loop);
+criterion_main!(benches);
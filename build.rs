@@ -0,0 +1,27 @@
+use tonic_build::manual::{Builder, Method, Service};
+
+fn method(name: &str, route: &str, input: &str, output: &str) -> tonic_build::manual::MethodBuilder {
+    Method::builder()
+        .name(name)
+        .route_name(route)
+        .input_type(format!("crate::grpc::{}", input))
+        .output_type(format!("crate::grpc::{}", output))
+        .codec_path("tonic::codec::ProstCodec")
+}
+
+// Generates the gRPC service of proto/control.proto from the hand written message types, so no protoc is needed
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=migrations");
+
+    let control = Service::builder()
+        .name("Control")
+        .package("bingo.control")
+        .method(method("create_room", "CreateRoom", "CreateRoomRequest", "RoomCredentials").build())
+        .method(method("draw", "Draw", "DrawRequest", "DrawReply").build())
+        .method(method("list_rooms", "ListRooms", "ListRoomsRequest", "ListRoomsReply").build())
+        .method(method("stream_events", "StreamEvents", "StreamEventsRequest", "RoomEvent").server_streaming().build())
+        .build();
+
+    Builder::new().build_client(false).compile(&[control]);
+}
@@ -0,0 +1,9 @@
+-- Feature flags, a NULL host or room_id applies the flag to every host or room.
+-- The most specific matching row wins: room, then host, then global.
+CREATE TABLE IF NOT EXISTS feature_flags (
+  id serial PRIMARY KEY,
+  flag TEXT NOT NULL,
+  host TEXT,
+  room_id INTEGER,
+  enabled BOOLEAN NOT NULL
+);
@@ -0,0 +1,8 @@
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS require_ticket BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS tickets (
+  room_id INTEGER NOT NULL,
+  code TEXT NOT NULL,
+  used_at TIMESTAMPTZ,
+  PRIMARY KEY (room_id, code)
+);
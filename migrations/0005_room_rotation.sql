@@ -0,0 +1,4 @@
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS valid_date DATE NOT NULL DEFAULT CURRENT_DATE;
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS archived_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS rooms_host_valid_date ON rooms (host, valid_date) WHERE archived_at IS NULL;
@@ -0,0 +1 @@
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS board_token TEXT NOT NULL DEFAULT md5(random()::text || clock_timestamp()::text);
@@ -0,0 +1,21 @@
+CREATE TABLE IF NOT EXISTS calls (
+  room_id INTEGER NOT NULL,
+  host TEXT NOT NULL,
+  round INTEGER NOT NULL,
+  number SMALLINT NOT NULL,
+  call_index INTEGER NOT NULL,
+  called_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS calls_host ON calls (host, room_id);
+
+CREATE TABLE IF NOT EXISTS wins (
+  room_id INTEGER NOT NULL,
+  host TEXT NOT NULL,
+  round INTEGER NOT NULL,
+  pattern TEXT NOT NULL,
+  calls_to_win INTEGER NOT NULL,
+  won_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS wins_host ON wins (host, room_id);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS round_audits (
+  room_id INTEGER NOT NULL,
+  round INTEGER NOT NULL,
+  commitment TEXT NOT NULL,
+  seed TEXT,
+  calls SMALLINT[],
+  started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (room_id, round)
+);
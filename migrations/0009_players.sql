@@ -0,0 +1,8 @@
+-- Players are identified by a server issued token that the client keeps between visits.
+CREATE TABLE IF NOT EXISTS players (
+  token TEXT PRIMARY KEY,
+  display_name TEXT,
+  last_ip TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  last_seen_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+-- Wins within the jackpot call limit of their round.
+ALTER TABLE wins ADD COLUMN IF NOT EXISTS jackpot BOOLEAN NOT NULL DEFAULT FALSE;
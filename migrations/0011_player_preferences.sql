@@ -0,0 +1,2 @@
+-- Client settings like card color and sound, shared by every device using the player token.
+ALTER TABLE players ADD COLUMN IF NOT EXISTS preferences JSONB NOT NULL DEFAULT '{}';
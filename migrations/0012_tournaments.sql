@@ -0,0 +1,32 @@
+-- Tournaments group rooms of a host, players collect points for their wins across the rooms.
+CREATE TABLE IF NOT EXISTS tournaments (
+  id SERIAL PRIMARY KEY,
+  host TEXT NOT NULL,
+  name TEXT NOT NULL,
+  win_points INTEGER NOT NULL,
+  prize_points INTEGER NOT NULL,
+  jackpot_points INTEGER NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS tournaments_host ON tournaments (host);
+
+CREATE TABLE IF NOT EXISTS tournament_rooms (
+  tournament_id INTEGER NOT NULL REFERENCES tournaments (id) ON DELETE CASCADE,
+  room_id INTEGER NOT NULL,
+  PRIMARY KEY (tournament_id, room_id)
+);
+
+CREATE INDEX IF NOT EXISTS tournament_rooms_room ON tournament_rooms (room_id);
+
+CREATE TABLE IF NOT EXISTS tournament_points (
+  tournament_id INTEGER NOT NULL REFERENCES tournaments (id) ON DELETE CASCADE,
+  player_token TEXT NOT NULL,
+  room_id INTEGER NOT NULL,
+  round INTEGER NOT NULL,
+  pattern TEXT NOT NULL,
+  points INTEGER NOT NULL,
+  awarded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS tournament_points_player ON tournament_points (tournament_id, player_token);
@@ -0,0 +1,9 @@
+-- Failed host logins per claimed username and per client address, for backoff and lockouts.
+CREATE TABLE IF NOT EXISTS login_failures (
+  kind TEXT NOT NULL,
+  subject TEXT NOT NULL,
+  failures INTEGER NOT NULL,
+  last_failure_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  locked_until TIMESTAMPTZ,
+  PRIMARY KEY (kind, subject)
+);
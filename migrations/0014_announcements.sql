@@ -0,0 +1,10 @@
+-- Host announcements waiting to be sent, removed once they fire or are cancelled.
+CREATE TABLE IF NOT EXISTS announcements (
+  id UUID PRIMARY KEY,
+  room_id INTEGER NOT NULL,
+  text TEXT NOT NULL,
+  fire_at TIMESTAMPTZ NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS announcements_room_idx ON announcements (room_id);
@@ -0,0 +1,3 @@
+-- Object storage addresses of the report and event log of a closed room.
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS report_url TEXT;
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS event_log_url TEXT;
@@ -0,0 +1,14 @@
+-- Upcoming games a host publishes in its calendar feed.
+CREATE TABLE IF NOT EXISTS scheduled_rooms (
+  id UUID PRIMARY KEY,
+  host TEXT NOT NULL,
+  title TEXT NOT NULL,
+  description TEXT,
+  location TEXT,
+  starts_at TIMESTAMPTZ NOT NULL,
+  duration_mins INTEGER NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  cancelled_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS scheduled_rooms_host_idx ON scheduled_rooms (host, starts_at);
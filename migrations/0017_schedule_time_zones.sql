@@ -0,0 +1,2 @@
+-- IANA time zone the scheduled game is played in, start times stay stored in UTC.
+ALTER TABLE scheduled_rooms ADD COLUMN IF NOT EXISTS time_zone TEXT NOT NULL DEFAULT 'UTC';
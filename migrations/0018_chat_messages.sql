@@ -0,0 +1,11 @@
+-- Chat of rooms whose retention policy keeps it, stored as relayed.
+CREATE TABLE IF NOT EXISTS chat_messages (
+  id BIGSERIAL PRIMARY KEY,
+  room_id INTEGER NOT NULL,
+  host TEXT NOT NULL,
+  sender TEXT NOT NULL,
+  message JSONB NOT NULL,
+  sent_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS chat_messages_room_idx ON chat_messages (room_id, sent_at);
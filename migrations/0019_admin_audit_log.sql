@@ -0,0 +1,11 @@
+-- Admin actions that reach into rooms of hosts, e.g. observing a room for support.
+CREATE TABLE IF NOT EXISTS admin_audit_log (
+  id BIGSERIAL PRIMARY KEY,
+  action TEXT NOT NULL,
+  room_id INTEGER,
+  reason TEXT,
+  ip TEXT,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS admin_audit_log_room_idx ON admin_audit_log (room_id, created_at);
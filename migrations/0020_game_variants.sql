@@ -0,0 +1,2 @@
+-- Game variant of a room, 75_ball or 90_ball. Rooms created before variants existed are 75-ball.
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS variant TEXT NOT NULL DEFAULT '75_ball';
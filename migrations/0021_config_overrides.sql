@@ -0,0 +1,6 @@
+-- Runtime overrides of server settings, edited through /admin/config and polled by every instance.
+CREATE TABLE IF NOT EXISTS config (
+  key TEXT PRIMARY KEY,
+  value JSONB NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,4 @@
+-- Sender of player chat, so it is erased with the rest of the player's data.
+ALTER TABLE chat_messages ADD COLUMN IF NOT EXISTS player_token TEXT;
+
+CREATE INDEX IF NOT EXISTS chat_messages_player_idx ON chat_messages (player_token) WHERE player_token IS NOT NULL;
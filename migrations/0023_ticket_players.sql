@@ -0,0 +1,2 @@
+-- Player a ticket was redeemed by, who may use it again to reconnect.
+ALTER TABLE tickets ADD COLUMN IF NOT EXISTS player_token TEXT;
@@ -0,0 +1,7 @@
+-- Phone numbers that agreed to get a host's announcements by entering the code they were sent.
+CREATE TABLE IF NOT EXISTS sms_opt_ins (
+  host TEXT NOT NULL,
+  phone TEXT NOT NULL,
+  confirmed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (host, phone)
+);
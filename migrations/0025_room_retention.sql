@@ -0,0 +1,2 @@
+-- Retention policy the host set for a room, rooms without one use the default policy.
+ALTER TABLE rooms ADD COLUMN IF NOT EXISTS retention JSONB;
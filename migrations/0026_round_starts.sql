@@ -0,0 +1,8 @@
+-- Start of every round, so replays show when rounds began whether or not they were provably fair.
+CREATE TABLE IF NOT EXISTS round_starts (
+  room_id INTEGER NOT NULL,
+  host TEXT NOT NULL,
+  round INTEGER NOT NULL,
+  started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY (room_id, round)
+);
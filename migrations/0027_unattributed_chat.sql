@@ -0,0 +1,3 @@
+-- Player chat stored before 0022 has no sender token and can't be matched to a deletion request,
+-- it is removed instead of being kept without a way to erase it. Host chat is purged with the host.
+DELETE FROM chat_messages WHERE sender = 'client' AND player_token IS NULL;
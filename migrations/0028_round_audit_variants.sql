@@ -0,0 +1,3 @@
+-- Game variant a round was drawn for, the draw order of a seed depends on it. Audits recorded before
+-- variants existed are 75-ball.
+ALTER TABLE round_audits ADD COLUMN IF NOT EXISTS variant TEXT NOT NULL DEFAULT '75_ball';
@@ -0,0 +1,10 @@
+-- Cards traded in for a fresh one before the first call, part of the room's event log.
+CREATE TABLE IF NOT EXISTS card_trade_ins (
+  room_id INTEGER NOT NULL,
+  host TEXT NOT NULL,
+  round INTEGER NOT NULL,
+  voided_card INTEGER NOT NULL,
+  card INTEGER NOT NULL,
+  traded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+CREATE INDEX IF NOT EXISTS card_trade_ins_host ON card_trade_ins (host, room_id);
@@ -0,0 +1,49 @@
+use actix_web::{dev::ServiceRequest, middleware::Logger};
+
+/// Query parameters that carry credentials, their values are left out of the access log.
+const SECRET_PARAMS: [&str; 5] = ["player_token", "key", "ticket", "ws_ticket", "room_token"];
+
+/// Request logger like `Logger::default`, with the credentials some clients have to send in the query string
+/// redacted, e.g. calendar apps that subscribe to the schedule feed can't send headers.
+pub fn logger() -> Logger {
+    Logger::new(r#"%a "%{request}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+        .custom_request_replace("request", request_line)
+}
+
+fn request_line(req: &ServiceRequest) -> String {
+    let query = redact_query(req.query_string());
+    let separator = if query.is_empty() { "" } else { "?" };
+    format!("{} {}{}{} {:?}", req.method(), req.path(), separator, query, req.version())
+}
+
+fn redact_query(query: &str) -> String {
+    query.split('&').map(|pair| match pair.split_once('=') {
+        Some((name, _)) if is_secret(name) => format!("{}=[redacted]", name),
+        _ => pair.to_owned(),
+    }).collect::<Vec<_>>().join("&")
+}
+
+/// Compares the decoded name, the query parser accepts `player%5Ftoken` for `player_token` too.
+fn is_secret(name: &str) -> bool {
+    form_urlencoded::parse(name.as_bytes()).next().is_some_and(|(name, _)| SECRET_PARAMS.contains(&name.as_ref()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn credentials_are_redacted_from_the_query(){
+        assert_eq!(redact_query("player_token=abc&name=Ann"), "player_token=[redacted]&name=Ann");
+        assert_eq!(redact_query("key=bk_1_secret"), "key=[redacted]");
+        assert_eq!(redact_query("format=plain"), "format=plain");
+        assert_eq!(redact_query(""), "");
+    }
+
+    #[test]
+    fn encoded_parameter_names_are_redacted(){
+        assert_eq!(redact_query("player%5Ftoken=abc&name=Ann"), "player%5Ftoken=[redacted]&name=Ann");
+        assert_eq!(redact_query("room%5ftoken=abc"), "room%5ftoken=[redacted]");
+        assert_eq!(redact_query("%6Bey=bk_1_secret"), "%6Bey=[redacted]");
+    }
+}
@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+
+use serde_json::Value;
+
+use crate::card::{Card, FREE_SPACE};
+use crate::variant::GameVariant;
+
+/// Wire format of a websocket connection, picked with the `format` query parameter when connecting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageFormat{
+    #[default]
+    Json,
+    /// One plain sentence per event for screen readers and SMS/IVR bridges.
+    Plain,
+}
+
+impl MessageFormat{
+    /// Converts an outgoing JSON message of a room played with `variant`, `None` when the event has nothing
+    /// worth reading out. JSON is passed through without a copy.
+    pub fn render(self, msg: &str, variant: GameVariant) -> Option<Cow<'_, str>> {
+        match self {
+            MessageFormat::Json => Some(Cow::Borrowed(msg)),
+            MessageFormat::Plain => describe(msg, variant).map(Cow::Owned),
+        }
+    }
+}
+
+/// How one socket's messages are written, settled when it connects.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SocketFormat{
+    pub format: MessageFormat,
+    /// Variant of the room the socket is in, plain text calls are read the way the game is called.
+    pub variant: GameVariant,
+}
+
+impl SocketFormat{
+    pub fn render(self, msg: &str) -> Option<Cow<'_, str>> {
+        self.format.render(msg, self.variant)
+    }
+}
+
+const COLUMNS: [&str; 5] = ["B", "I", "N", "G", "O"];
+
+fn number(value: &Value, variant: GameVariant) -> String {
+    match value.as_u64() {
+        // Read the column letter out like a caller would, e.g. "B 12", 90-ball calls have no letters
+        Some(number @ 1..=75) if variant == GameVariant::Ball75 => format!("{} {}", COLUMNS[(number as usize - 1) / 15], number),
+        Some(0) => "free space".to_string(),
+        Some(number) => number.to_string(),
+        None => "unknown".to_string(),
+    }
+}
+
+fn numbers(value: &Value, variant: GameVariant) -> String {
+    match value.as_array() {
+        Some(values) if !values.is_empty() => values.iter().map(|value| number(value, variant)).collect::<Vec<_>>().join(", "),
+        _ => "none".to_string(),
+    }
+}
+
+fn text<'a>(message: &'a Value, field: &str) -> Option<&'a str> {
+    message.get(field).and_then(Value::as_str)
+}
+
+fn describe_card(card: Card) -> String {
+    if card.variant() == GameVariant::Ball90{
+        // Tickets are read row by row, the blanks are left out
+        let rows = (0..card.columns.first().map(Vec::len).unwrap_or_default()).map(|row| {
+            let cells = card.columns.iter()
+                .filter_map(|column| column.get(row).copied().filter(|cell| *cell != FREE_SPACE))
+                .map(|cell| cell.to_string())
+                .collect::<Vec<_>>();
+            format!("Row {}: {}", row + 1, cells.join(" "))
+        }).collect::<Vec<_>>();
+        return format!("Your ticket number {}. {}.", card.id, rows.join(". "));
+    }
+    let columns = COLUMNS.iter().zip(card.columns.iter()).map(|(letter, cells)| {
+        let cells = cells.iter()
+            .map(|&cell| if cell == FREE_SPACE { "free".to_string() } else { cell.to_string() })
+            .collect::<Vec<_>>();
+        format!("{}: {}", letter, cells.join(" "))
+    }).collect::<Vec<_>>();
+    format!("Your card number {}. {}.", card.id, columns.join(". "))
+}
+
+/// Plain text description of a protocol message sent in a room played with `variant`.
+pub fn describe(msg: &str, variant: GameVariant) -> Option<String> {
+    let message: Value = serde_json::from_str(msg).ok()?;
+    let description = match text(&message, "type")? {
+        "draw" if message["duplicate"].as_bool() == Some(true) => return None,
+        "draw" if message["bonus"].as_bool() == Some(true) => format!("Call {}: {}, a bonus ball.", message["call"], number(&message["number"], variant)),
+        "draw" => format!("Call {}: {}.", message["call"], number(&message["number"], variant)),
+        "call_undone" => format!("Correction, {} was not called. Called so far: {}.", number(&message["number"], variant), numbers(&message["called"], variant)),
+        "round_started" => format!("Round {} has started.", message["round"]),
+        "round_ended" => match message["winners"].as_array().map(Vec::len).unwrap_or_default() {
+            0 => format!("Round {} is over with no winner.", message["round"]),
+            1 => format!("Round {} is over with one winner.", message["round"]),
+            winners => format!("Round {} is over with {} winners.", message["round"], winners),
+        },
+        "card" => describe_card(serde_json::from_value::<Card>(message["card"].clone()).ok()?),
+        "card_traded" => format!("Card {} was replaced. {}", message["voided_card_id"], describe_card(serde_json::from_value::<Card>(message["card"].clone()).ok()?)),
+        "card_status" => message["cards"].as_array()?.iter()
+            .map(|card| format!("Card {} needs {} more.", card["card_id"], card["numbers_to_go"]))
+            .collect::<Vec<_>>()
+            .join(" "),
+        "prize_won" => format!("The {} prize was won by player {}.", text(&message, "pattern")?.replace('_', " "), message["client_id"]),
+        "claim_result" if message["jackpot"].as_bool() == Some(true) => format!("Your bingo won the jackpot after {} calls, congratulations!", message["calls"]),
+        "claim_result" if message["valid"].as_bool() == Some(true) => "Your bingo is valid, congratulations!".to_string(),
+        "claim_result" => "Your bingo claim was not valid.".to_string(),
+        "board" => format!("Called so far: {}.", numbers(&message["called"], variant)),
+        "chat" => match (text(&message, "name"), text(&message, "text")) {
+            (Some(name), Some(chat)) => format!("{} says: {}", name, chat),
+            (None, Some(chat)) => format!("Chat: {}", chat),
+            _ => return None,
+        },
+        "id" => format!("You are player {}.", message["conn_id"]),
+        "player" => "Connected.".to_string(),
+        "throttled" => "You are sending messages too fast, please slow down.".to_string(),
+        "maintenance" | "error" => text(&message, "message")?.to_string(),
+        "connection_rejected" => format!("Connection refused: {}.", text(&message, "reason")?),
+        // Host, display and sync messages have no spoken form
+        _ => return None,
+    };
+    Some(description)
+}
@@ -0,0 +1,370 @@
+use std::fmt;
+
+use actix_web::{
+    dev::Payload, error, get, http::{header, Method}, post, put, web, Error, FromRequest, HttpRequest, HttpResponse
+};
+use argon2::password_hash::PasswordHash;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::future::{ready, Ready};
+use sha2::{Digest, Sha256};
+use sqlx::types::Uuid;
+
+use crate::auth::{generate_user_token, hash_password, AuthProvider};
+use crate::ghosts::GhostStatus;
+use crate::mailbox::MailboxStatus;
+use crate::message_log::{MessageLog, MessageLogLevel};
+use crate::persistence::PersistenceStatus;
+use crate::chaos::ChaosRequest;
+use crate::recent_errors::RecentErrors;
+use crate::room::{BingoServerHandle, RoomId};
+use crate::versions::ForceRefreshRequest;
+
+/// Operator access to the admin API, disabled unless `ADMIN_TOKEN` is set.
+#[derive(Clone)]
+pub struct AdminConfig{
+    pub token: Option<String>,
+}
+
+impl fmt::Debug for AdminConfig{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AdminConfig").field("enabled", &self.token.is_some()).finish()
+    }
+}
+
+/// An operator authenticated with `Authorization: Bearer <ADMIN_TOKEN>`, or with the token as the
+/// password of HTTP basic auth so browsers can open the admin page, see `admin_ui`.
+pub struct Admin;
+
+fn provided_token(req: &HttpRequest) -> Option<String> {
+    let value = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    if let Some(token) = value.strip_prefix("Bearer "){
+        return Some(token.to_owned());
+    }
+    // Browsers attach cached basic credentials to cross-site requests too, so they only unlock
+    // reads, anything that changes state needs the bearer token
+    if req.method() != Method::GET && req.method() != Method::HEAD{
+        return None;
+    }
+    // Any user name goes, only the password is checked
+    let credentials = String::from_utf8(STANDARD.decode(value.strip_prefix("Basic ")?).ok()?).ok()?;
+    credentials.split_once(':').map(|(_, password)| password.to_owned())
+}
+
+impl FromRequest for Admin{
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let expected = match req.app_data::<web::Data<AdminConfig>>().and_then(|config| config.token.clone()) {
+            Some(token) => token,
+            None => return ready(Err(error::ErrorNotFound("Admin API is disabled"))),
+        };
+        let provided = provided_token(req).unwrap_or_default();
+
+        // Compare digests so the check doesn't leak the token length or a matching prefix
+        if Sha256::digest(provided.as_bytes()) == Sha256::digest(expected.as_bytes()){
+            ready(Ok(Admin))
+        }
+        else{
+            log::warn!("Rejected admin request to {}", req.path());
+            let challenge = HttpResponse::Unauthorized()
+                .insert_header((header::WWW_AUTHENTICATE, "Basic realm=\"bingoserver admin\""))
+                .body("Invalid admin token");
+            ready(Err(error::InternalError::from_response("Invalid admin token", challenge).into()))
+        }
+    }
+}
+
+/// Records an admin action on the data of a host, see `admin_audit_log`.
+pub async fn audit(database: &sqlx::PgPool, action: &str, room: Option<RoomId>, reason: Option<&str>, ip: Option<&str>) -> sqlx::Result<()> {
+    sqlx::query("INSERT INTO admin_audit_log (action, room_id, reason, ip) VALUES ($1, $2, $3, $4)")
+        .bind(action)
+        .bind(room)
+        .bind(reason)
+        .bind(ip)
+        .execute(database)
+        .await
+        .map(|_| ())
+}
+
+#[get("/admin/persistence")]
+async fn persistence_status(
+    _admin: Admin,
+    status: web::Data<PersistenceStatus>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(status.report())
+}
+
+/// Depth of the room server's command queue and the low priority commands shed while it was overloaded.
+#[get("/admin/mailbox")]
+async fn mailbox_status(
+    _admin: Admin,
+    status: web::Data<MailboxStatus>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(status.report())
+}
+
+/// Connections the periodic sweep removed because their socket task was gone.
+#[get("/admin/ghosts")]
+async fn ghost_status(
+    _admin: Admin,
+    status: web::Data<GhostStatus>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(status.report())
+}
+
+/// Failed database writes and crashed sockets, newest first.
+#[get("/admin/errors")]
+async fn list_recent_errors(
+    _admin: Admin,
+    errors: web::Data<RecentErrors>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(errors.list())
+}
+
+/// Rooms with open connections and how far their game is.
+#[get("/admin/rooms")]
+async fn live_rooms(
+    _admin: Admin,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(server.live_rooms().await)
+}
+
+#[derive(serde::Deserialize)]
+struct MessageLogRequest{
+    /// Unset on a room to go back to the default level.
+    level: Option<MessageLogLevel>,
+}
+
+/// Default and per room levels of the socket message log.
+#[get("/admin/message-log")]
+async fn message_log_levels(
+    _admin: Admin,
+    message_log: web::Data<MessageLog>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(message_log.report())
+}
+
+/// Sets the message log level of rooms without their own level, takes effect on open sockets immediately.
+#[put("/admin/message-log")]
+async fn set_message_log_level(
+    _admin: Admin,
+    request: web::Json<MessageLogRequest>,
+    message_log: web::Data<MessageLog>,
+) -> actix_web::Result<HttpResponse> {
+    let level = request.level.ok_or_else(|| error::ErrorBadRequest("level is required"))?;
+    log::warn!("Message log level set to {:?}", level);
+    message_log.set_default(level);
+    Ok(HttpResponse::Ok().json(message_log.report()))
+}
+
+/// Logs the socket messages of a single room at another level than the default.
+#[put("/admin/rooms/{room}/message-log")]
+async fn set_room_message_log_level(
+    _admin: Admin,
+    path: web::Path<(RoomId,)>,
+    request: web::Json<MessageLogRequest>,
+    message_log: web::Data<MessageLog>,
+) -> HttpResponse {
+    log::warn!("Message log level of room {} set to {:?}", path.0, request.level);
+    message_log.set_room(path.0, request.level);
+    HttpResponse::Ok().json(message_log.report())
+}
+
+/// App versions and platforms of the connected players per room, for judging when to raise the minimum version.
+#[get("/admin/client-versions")]
+async fn client_versions(
+    _admin: Admin,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(server.all_client_versions().await)
+}
+
+/// Asks the clients of every room to reload after a breaking frontend fix.
+#[post("/admin/force-refresh")]
+async fn force_refresh(
+    _admin: Admin,
+    request: web::Json<ForceRefreshRequest>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    if request.close_outdated && request.min_version.is_none(){
+        return Err(error::ErrorBadRequest("close_outdated requires a min_version"));
+    }
+    Ok(HttpResponse::Ok().json(server.force_refresh(None, request.into_inner()).await))
+}
+
+/// Injects latency, dropped messages and disconnects into any room, e.g. to test a frontend release.
+#[post("/admin/rooms/{room}/chaos")]
+async fn room_chaos(
+    _admin: Admin,
+    path: web::Path<(RoomId,)>,
+    request: web::Json<ChaosRequest>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    match server.set_chaos(path.0, request.into_inner(), true).await {
+        Some(Ok(result)) => Ok(HttpResponse::Ok().json(result)),
+        Some(Err(error)) => Err(error::ErrorBadRequest(error)),
+        None => Err(error::ErrorNotFound("Room not found")),
+    }
+}
+
+/// Open host sockets per room, many long lived sockets on one room usually means a leaked room token.
+#[get("/admin/host-connections")]
+async fn host_connections(
+    _admin: Admin,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(server.host_connections().await)
+}
+
+/// Ends a room as if its host did, players can no longer join.
+#[post("/admin/rooms/{room}/close")]
+async fn close_room(
+    _admin: Admin,
+    path: web::Path<(RoomId,)>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    match server.close_room(path.0).await {
+        Some(true) => Ok(HttpResponse::NoContent().finish()),
+        Some(false) => Err(error::ErrorConflict("Room already ended")),
+        None => Err(error::ErrorNotFound("Room not found")),
+    }
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct UserAccount{
+    id: Uuid,
+    username: String,
+}
+
+/// Password accounts, without their token hashes.
+#[get("/admin/users")]
+async fn list_users(
+    _admin: Admin,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let users: Vec<UserAccount> = sqlx::query_as("SELECT id, username FROM users ORDER BY username")
+        .fetch_all(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to list users: {}", e);
+            error::ErrorInternalServerError("Failed to list users")
+        })?;
+    Ok(HttpResponse::Ok().json(users))
+}
+
+#[derive(serde::Deserialize)]
+struct CreateUserRequest{
+    username: String,
+    /// argon2 hash of the token in the PHC string format, see `/admin/hash-token`.
+    token: String,
+}
+
+/// Adds a password account, like the `USER_n_*` secrets do at startup.
+#[post("/admin/users")]
+async fn create_user(
+    _admin: Admin,
+    request: web::Json<CreateUserRequest>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let username = request.username.trim();
+    if username.is_empty(){
+        return Err(error::ErrorBadRequest("username can't be empty"));
+    }
+    if PasswordHash::new(&request.token).is_err(){
+        return Err(error::ErrorBadRequest("token must be an argon2 hash in the PHC string format"));
+    }
+    // The table has no unique constraint on usernames, a second account would never be used to log in
+    let user: Option<UserAccount> = sqlx::query_as("INSERT INTO users (id, username, token) \
+        SELECT $1, $2, $3 WHERE NOT EXISTS (SELECT 1 FROM users WHERE username = $2) RETURNING id, username")
+        .bind(Uuid::new_v4())
+        .bind(username)
+        .bind(&request.token)
+        .fetch_optional(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to create user {}: {}", username, e);
+            error::ErrorInternalServerError("Failed to create user")
+        })?;
+
+    match user {
+        Some(user) => {
+            log::info!("Created user {} ({})", user.username, user.id);
+            Ok(HttpResponse::Created().json(user))
+        }
+        None => Err(error::ErrorConflict(format!("User {} already exists", username))),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RotateTokenRequest{
+    /// argon2 hash of the new token in the PHC string format.
+    token: String,
+}
+
+/// Replaces the token hash of a password account. The old token stops working on this instance right away
+/// and on other instances once their cached copy expires, see `AUTH_CACHE_TTL_SECS`.
+#[put("/admin/users/{id}/token")]
+async fn rotate_user_token(
+    _admin: Admin,
+    path: web::Path<(Uuid,)>,
+    request: web::Json<RotateTokenRequest>,
+    database: web::Data<sqlx::PgPool>,
+    auth_provider: web::Data<dyn AuthProvider>,
+) -> actix_web::Result<HttpResponse> {
+    if PasswordHash::new(&request.token).is_err(){
+        return Err(error::ErrorBadRequest("token must be an argon2 hash in the PHC string format"));
+    }
+    let result = sqlx::query("UPDATE users SET token = $2 WHERE id = $1")
+        .bind(path.0)
+        .bind(&request.token)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to rotate the token of user {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to rotate token")
+        })?;
+    if result.rows_affected() == 0{
+        return Err(error::ErrorNotFound("User not found"));
+    }
+
+    auth_provider.invalidate(path.0);
+    log::info!("Rotated the token of user {}", path.0);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[derive(serde::Deserialize)]
+struct HashTokenRequest{
+    /// A random token is generated when missing.
+    token: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct HashTokenResult{
+    /// Only returned when it was generated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+    hash: String,
+}
+
+/// Hashes a host token with the server's argon2 parameters, for `USER_n_TOKEN` or `/admin/users/{id}/token`.
+#[post("/admin/hash-token")]
+async fn hash_token(
+    _admin: Admin,
+    request: web::Json<HashTokenRequest>,
+) -> actix_web::Result<HttpResponse> {
+    let (token, generated) = match request.into_inner().token {
+        Some(token) if token.is_empty() => return Err(error::ErrorBadRequest("token can't be empty")),
+        Some(token) => (token, false),
+        None => (generate_user_token(), true),
+    };
+    // argon2 is deliberately slow, keep it off the async workers
+    let to_hash = token.clone();
+    let hash = web::block(move || hash_password(&to_hash)).await?
+        .map_err(|e| {
+            log::error!("Failed to hash token: {}", e);
+            error::ErrorInternalServerError("Failed to hash token")
+        })?;
+    Ok(HttpResponse::Ok().json(HashTokenResult{ token: generated.then_some(token), hash }))
+}
@@ -0,0 +1,19 @@
+use actix_web::{get, HttpResponse};
+
+use crate::admin::Admin;
+
+/// Built into the binary so a one-off event needs nothing but the server. The page only holds markup,
+/// everything shown is loaded from the admin API with the credentials the browser asked for.
+const ADMIN_PAGE: &str = include_str!("admin_ui.html");
+
+/// Live rooms, connections and recent errors at a glance. Browsers prompt for the admin token as the
+/// password, any user name works.
+#[get("/admin")]
+async fn admin_page(
+    _admin: Admin,
+) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(ADMIN_PAGE)
+}
@@ -0,0 +1,294 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use tokio::{sync::mpsc, time::interval};
+
+use crate::object_store::{ObjectStore, ObjectStoreConfig};
+use crate::room::{DisconnectReason, RoomId};
+use crate::round::{RoundEndReason, RoundId};
+
+/// Events waiting for the next batch, later events are dropped while the sink is behind.
+const QUEUE_CAPACITY: usize = 10_000;
+/// A sink that doesn't answer in time fails the batch instead of holding up the next ones.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the analytics events are shipped, selected with `ANALYTICS_SINK`.
+#[derive(Clone)]
+pub enum AnalyticsSinkConfig{
+    /// JSON array of events posted to `ANALYTICS_URL`, with `ANALYTICS_TOKEN` as bearer token when set.
+    Http{
+        url: String,
+        token: Option<String>,
+    },
+    /// Rows inserted through the ClickHouse HTTP interface at `ANALYTICS_URL`.
+    ClickHouse{
+        url: String,
+        table: String,
+        user: Option<String>,
+        password: Option<String>,
+    },
+    /// One newline delimited JSON object per batch under `ANALYTICS_S3_PREFIX` in the configured bucket.
+    S3{
+        store: ObjectStoreConfig,
+        prefix: String,
+    },
+}
+
+impl fmt::Debug for AnalyticsSinkConfig{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalyticsSinkConfig::Http{ url, .. } => f.debug_struct("Http").field("url", url).finish(),
+            AnalyticsSinkConfig::ClickHouse{ url, table, .. } => f.debug_struct("ClickHouse").field("url", url).field("table", table).finish(),
+            AnalyticsSinkConfig::S3{ store, prefix } => f.debug_struct("S3").field("store", store).field("prefix", prefix).finish(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AnalyticsConfig{
+    pub sink: AnalyticsSinkConfig,
+    /// Mixed into the room and host hashes, keep it stable to follow an organizer across events.
+    pub salt: String,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+}
+
+impl fmt::Debug for AnalyticsConfig{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AnalyticsConfig").field("sink", &self.sink).field("batch_size", &self.batch_size).field("flush_interval", &self.flush_interval).finish()
+    }
+}
+
+/// What happened in a room, only counts and durations so no player can be told apart.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AnalyticsEventKind{
+    RoomCreated,
+    RoundStarted{
+        round: RoundId,
+        speed: bool,
+    },
+    RoundEnded{
+        round: RoundId,
+        reason: RoundEndReason,
+        calls: usize,
+        winners: usize,
+    },
+    PlayerJoined{
+        players: usize,
+    },
+    PlayerLeft{
+        players: usize,
+        /// Unknown for sockets that joined without a player token.
+        connected_secs: Option<i64>,
+        reason: DisconnectReason,
+    },
+    RoomEnded{
+        rounds: RoundId,
+        players_joined: usize,
+    },
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AnalyticsEvent{
+    at: DateTime<Utc>,
+    /// Salted hashes instead of the room id and host username.
+    room: String,
+    host: String,
+    #[serde(flatten)]
+    kind: AnalyticsEventKind,
+}
+
+/// Ships a batch of events, a failed batch is logged and dropped.
+pub trait AnalyticsSink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn export<'a>(&'a self, events: &'a [AnalyticsEvent]) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+impl fmt::Debug for dyn AnalyticsSink{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn create_sink(config: &AnalyticsSinkConfig) -> Arc<dyn AnalyticsSink> {
+    let client = reqwest::Client::builder().connect_timeout(CONNECT_TIMEOUT).timeout(EXPORT_TIMEOUT).build().unwrap();
+    match config.clone() {
+        AnalyticsSinkConfig::Http{ url, token } => Arc::new(HttpSink{ url, token, client }),
+        AnalyticsSinkConfig::ClickHouse{ url, table, user, password } => Arc::new(ClickHouseSink{ url, table, user, password, client }),
+        AnalyticsSinkConfig::S3{ store, prefix } => Arc::new(S3BatchSink{ store: ObjectStore::new(store), prefix: prefix.trim_end_matches('/').to_owned() }),
+    }
+}
+
+fn to_json_lines(events: &[AnalyticsEvent]) -> String {
+    events.iter().map(|event| serde_json::to_string(event).unwrap() + "\n").collect()
+}
+
+pub struct HttpSink{
+    url: String,
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl HttpSink{
+    async fn post(&self, events: &[AnalyticsEvent]) -> anyhow::Result<()> {
+        let mut request = self.client.post(&self.url).json(events);
+        if let Some(token) = &self.token{
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await?;
+        if !response.status().is_success(){
+            return Err(anyhow!("Analytics endpoint returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+impl AnalyticsSink for HttpSink{
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn export<'a>(&'a self, events: &'a [AnalyticsEvent]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.post(events))
+    }
+}
+
+/// Expects a table with `at`, `room`, `host` and `event` columns, fields of other events are skipped
+/// unless the table has columns for them.
+pub struct ClickHouseSink{
+    url: String,
+    table: String,
+    user: Option<String>,
+    password: Option<String>,
+    client: reqwest::Client,
+}
+
+impl ClickHouseSink{
+    async fn insert(&self, events: &[AnalyticsEvent]) -> anyhow::Result<()> {
+        let query = format!("INSERT INTO {} SETTINGS input_format_skip_unknown_fields = 1 FORMAT JSONEachRow", self.table);
+        let mut request = self.client.post(&self.url)
+            .query(&[("query", query)])
+            .body(to_json_lines(events));
+        if let Some(user) = &self.user{
+            request = request.basic_auth(user, self.password.as_ref());
+        }
+        let response = request.send().await?;
+        if !response.status().is_success(){
+            return Err(anyhow!("ClickHouse returned {}: {}", response.status(), response.text().await.unwrap_or_default()));
+        }
+        Ok(())
+    }
+}
+
+impl AnalyticsSink for ClickHouseSink{
+    fn name(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    fn export<'a>(&'a self, events: &'a [AnalyticsEvent]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.insert(events))
+    }
+}
+
+pub struct S3BatchSink{
+    store: ObjectStore,
+    prefix: String,
+}
+
+impl S3BatchSink{
+    async fn upload(&self, events: &[AnalyticsEvent]) -> anyhow::Result<()> {
+        // Partitioned by day, the uuid keeps batches of several instances apart
+        let now = Utc::now();
+        let key = format!("{}/{}/{}-{}.jsonl", self.prefix, now.format("%Y-%m-%d"), now.format("%H%M%S"), uuid::Uuid::new_v4());
+        self.store.put(&key, to_json_lines(events).into_bytes(), "application/x-ndjson").await?;
+        Ok(())
+    }
+}
+
+impl AnalyticsSink for S3BatchSink{
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn export<'a>(&'a self, events: &'a [AnalyticsEvent]) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.upload(events))
+    }
+}
+
+async fn ship(sink: &dyn AnalyticsSink, batch: &mut Vec<AnalyticsEvent>){
+    if batch.is_empty(){
+        return;
+    }
+    if let Err(e) = sink.export(batch).await{
+        log::warn!("Dropped {} analytics events, the {} export failed: {}", batch.len(), sink.name(), e);
+    }
+    batch.clear();
+}
+
+/// Collects room lifecycle and engagement events and ships them in batches from the background.
+#[derive(Debug, Clone)]
+pub struct Analytics{
+    tx: mpsc::Sender<AnalyticsEvent>,
+    salt: String,
+}
+
+impl Analytics{
+    pub fn start(config: &AnalyticsConfig) -> Self {
+        let sink = create_sink(&config.sink);
+        let (tx, mut rx) = mpsc::channel::<AnalyticsEvent>(QUEUE_CAPACITY);
+        let (batch_size, flush_interval) = (config.batch_size, config.flush_interval);
+        log::info!("Exporting analytics events to {:?}", sink);
+
+        tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(batch_size);
+            let mut flush = interval(flush_interval);
+            loop {
+                tokio::select! {
+                    event = rx.recv() => match event {
+                        Some(event) => {
+                            batch.push(event);
+                            if batch.len() < batch_size{
+                                continue;
+                            }
+                        }
+                        None => {
+                            ship(sink.as_ref(), &mut batch).await;
+                            return;
+                        }
+                    },
+                    _ = flush.tick() => {}
+                }
+                ship(sink.as_ref(), &mut batch).await;
+            }
+        });
+
+        Self{
+            tx,
+            salt: config.salt.clone(),
+        }
+    }
+
+    fn anonymize(&self, value: &str) -> String {
+        let digest = Sha256::digest(format!("{}:{}", self.salt, value).as_bytes());
+        digest.iter().take(8).map(|x| format!("{:02x}", x)).collect::<String>()
+    }
+
+    /// Queues an event without waiting on the sink.
+    pub fn record(&self, room: RoomId, host: &str, kind: AnalyticsEventKind){
+        let event = AnalyticsEvent{
+            at: Utc::now(),
+            room: self.anonymize(&room.to_string()),
+            host: self.anonymize(host),
+            kind,
+        };
+        if self.tx.try_send(event).is_err(){
+            log::warn!("Dropped an analytics event of room {}, the export queue is full", room);
+        }
+    }
+}
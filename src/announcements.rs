@@ -0,0 +1,160 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Uuid;
+
+use crate::persistence::PendingWrite;
+use crate::room::RoomId;
+
+/// How often due announcements are looked for.
+pub const ANNOUNCEMENT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Most announcements waiting in a room.
+pub const MAX_SCHEDULED: usize = 50;
+
+const MAX_TEXT_LENGTH: usize = 280;
+
+/// Farthest ahead an announcement can be scheduled.
+const MAX_LEAD_TIME: chrono::TimeDelta = chrono::TimeDelta::hours(24);
+
+/// Announcements missed by more than this, e.g. while the server was down, are dropped instead of
+/// sent late, "doors close in 5 minutes" is wrong an hour later.
+pub const MAX_LATENESS: chrono::TimeDelta = chrono::TimeDelta::minutes(5);
+
+/// Host request to send a message to the room later, at an absolute time or after a delay.
+#[derive(Debug, serde::Deserialize)]
+pub struct ScheduleAnnouncementRequest{
+    pub text: String,
+    pub at: Option<DateTime<Utc>>,
+    pub in_secs: Option<u64>,
+}
+
+impl ScheduleAnnouncementRequest{
+    /// The time to send the announcement at, checked against the limits.
+    pub fn fire_at(&self, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+        let text = self.text.trim();
+        if text.is_empty() || text.chars().count() > MAX_TEXT_LENGTH{
+            return Err(format!("Announcements must be 1 to {} characters", MAX_TEXT_LENGTH));
+        }
+        let fire_at = match (self.at, self.in_secs) {
+            (Some(at), None) => at,
+            (None, Some(secs)) => i64::try_from(secs).ok()
+                .and_then(chrono::TimeDelta::try_seconds)
+                .map(|delay| now + delay)
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            _ => return Err("Announcements need either at or in_secs".to_owned()),
+        };
+        if fire_at < now{
+            return Err("Announcements can't be scheduled in the past".to_owned());
+        }
+        if fire_at > now + MAX_LEAD_TIME{
+            return Err(format!("Announcements can be scheduled at most {} hours ahead", MAX_LEAD_TIME.num_hours()));
+        }
+        Ok(fire_at)
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CancelAnnouncementRequest{
+    pub id: Uuid,
+}
+
+/// An announcement waiting to be sent.
+#[derive(Debug, Clone, sqlx::FromRow, serde::Deserialize, serde::Serialize)]
+pub struct Announcement{
+    pub id: Uuid,
+    pub text: String,
+    pub fire_at: DateTime<Utc>,
+}
+
+impl Announcement{
+    pub fn new(request: ScheduleAnnouncementRequest, fire_at: DateTime<Utc>) -> Self {
+        Self{
+            id: Uuid::new_v4(),
+            text: request.text.trim().to_owned(),
+            fire_at,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct StoredAnnouncement{
+    pub room_id: RoomId,
+    #[sqlx(flatten)]
+    pub announcement: Announcement,
+}
+
+/// Announcements of every room still waiting to be sent, loaded at startup.
+pub async fn load_announcements(database: &sqlx::PgPool) -> Result<Vec<StoredAnnouncement>, sqlx::Error> {
+    sqlx::query_as("SELECT id, room_id, text, fire_at FROM announcements ORDER BY fire_at")
+        .fetch_all(database)
+        .await
+}
+
+pub fn save_announcement(room: RoomId, announcement: &Announcement) -> PendingWrite {
+    let announcement = announcement.clone();
+    PendingWrite::new(
+        format!("announcement {} of room {}", announcement.id, room),
+        Box::new(move |database| {
+            let announcement = announcement.clone();
+            Box::pin(async move {
+                sqlx::query("INSERT INTO announcements (id, room_id, text, fire_at) VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO NOTHING")
+                    .bind(announcement.id)
+                    .bind(room)
+                    .bind(announcement.text)
+                    .bind(announcement.fire_at)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).essential()
+}
+
+/// Forgets an announcement that was sent, dropped or cancelled.
+pub fn remove_announcement(room: RoomId, id: Uuid) -> PendingWrite {
+    PendingWrite::new(
+        format!("removal of announcement {} of room {}", id, room),
+        Box::new(move |database| Box::pin(async move {
+            sqlx::query("DELETE FROM announcements WHERE id = $1")
+                .bind(id)
+                .execute(&database)
+                .await
+                .map(|_| ())
+        })),
+    ).essential()
+}
+
+/// Sent to every connection of the room when an announcement is due.
+#[derive(serde::Serialize)]
+pub struct AnnouncementMessage<'a>{
+    r#type: String,
+    id: Uuid,
+    text: &'a str,
+}
+
+impl<'a> AnnouncementMessage<'a>{
+    pub fn new(announcement: &'a Announcement) -> Self {
+        Self{
+            r#type: "announcement".to_string(),
+            id: announcement.id,
+            text: &announcement.text,
+        }
+    }
+}
+
+/// The announcements still waiting, sent to the hosts whenever they change.
+#[derive(serde::Serialize)]
+pub struct ScheduledAnnouncementsMessage<'a>{
+    r#type: String,
+    scheduled: &'a [Announcement],
+}
+
+impl<'a> ScheduledAnnouncementsMessage<'a>{
+    pub fn new(scheduled: &'a [Announcement]) -> Self {
+        Self{
+            r#type: "scheduled_announcements".to_string(),
+            scheduled,
+        }
+    }
+}
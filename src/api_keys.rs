@@ -0,0 +1,233 @@
+use actix_identity::Identity;
+use actix_web::{
+    dev::Payload, error, get, post, web, Error, FromRequest, HttpRequest, HttpResponse
+};
+use chrono::{DateTime, Utc};
+use futures_util::future::LocalBoxFuture;
+use rand::{rng, Rng as _};
+use sha2::{Digest, Sha256};
+use sqlx::types::Uuid;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Operations an API key can be allowed to perform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope{
+    /// Create rooms through `/host`.
+    Host,
+    /// Import ticket lists.
+    Tickets,
+    /// Read the calendar feed, safe to publish in a subscription link.
+    Schedule,
+}
+
+impl Scope{
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scope::Host => "host",
+            Scope::Tickets => "tickets",
+            Scope::Schedule => "schedule",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "host" => Some(Scope::Host),
+            "tickets" => Some(Scope::Tickets),
+            "schedule" => Some(Scope::Schedule),
+            _ => None,
+        }
+    }
+}
+
+fn hash_secret(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes()).iter().map(|x| format!("{:02x}", x)).collect::<String>()
+}
+
+/// Splits a `bk_<id>_<secret>` key into its id and secret.
+fn parse_key(key: &str) -> Option<(Uuid, &str)> {
+    let (id, secret) = key.strip_prefix("bk_")?.split_once('_')?;
+    Some((id.parse().ok()?, secret))
+}
+
+#[derive(sqlx::FromRow)]
+struct ApiKeyRow{
+    username: String,
+    key_hash: String,
+    scopes: Vec<String>,
+}
+
+/// Resolves an API key to its owner and scopes, expired and revoked keys are rejected.
+pub async fn verify_api_key(database: &sqlx::PgPool, key: &str) -> Option<(String, Vec<Scope>)> {
+    let (id, secret) = parse_key(key)?;
+
+    let row: ApiKeyRow = sqlx::query_as("SELECT username, key_hash, scopes FROM api_keys WHERE id = $1 AND revoked_at IS NULL AND (expires_at IS NULL OR expires_at > now())")
+        .bind(id)
+        .fetch_optional(database)
+        .await
+        .map_err(|e| log::error!("Failed to look up API key {}: {}", id, e))
+        .ok()??;
+
+    if row.key_hash != hash_secret(secret){
+        log::warn!("API key {} used with a wrong secret", id);
+        return None;
+    }
+    Some((row.username, row.scopes.iter().filter_map(|scope| Scope::from_name(scope)).collect()))
+}
+
+/// A host authenticated either by the login session or by an API key in the `X-Api-Key` header.
+pub struct HostIdentity{
+    pub username: String,
+    /// Scopes granted by the API key, `None` for session logins which may do everything.
+    scopes: Option<Vec<Scope>>,
+}
+
+impl HostIdentity{
+    pub fn require_scope(&self, scope: Scope) -> Result<(), Error> {
+        match &self.scopes {
+            Some(scopes) if !scopes.contains(&scope) => {
+                Err(error::ErrorForbidden(format!("API key lacks the {} scope", scope.name())))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromRequest for HostIdentity{
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        let api_key = req.headers().get(API_KEY_HEADER).and_then(|value| value.to_str().ok()).map(str::to_owned);
+        let database = req.app_data::<web::Data<sqlx::PgPool>>().cloned();
+
+        Box::pin(async move {
+            let identity = Identity::extract(&req).await.ok();
+            if let Some(username) = identity.and_then(|identity| identity.id().ok()){
+                return Ok(HostIdentity{ username, scopes: None });
+            }
+
+            let (key, database) = match (api_key, database) {
+                (Some(key), Some(database)) => (key, database),
+                _ => return Err(error::ErrorUnauthorized("Login required using /host endpoint or an API key")),
+            };
+            match verify_api_key(&database, &key).await {
+                Some((username, scopes)) => Ok(HostIdentity{ username, scopes: Some(scopes) }),
+                None => Err(error::ErrorUnauthorized("Invalid API key")),
+            }
+        })
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CreateKeyRequest{
+    scopes: Vec<String>,
+    /// Days until the key expires, the key never expires when unset.
+    expires_in_days: Option<i32>,
+}
+
+#[derive(serde::Serialize)]
+struct CreatedKey{
+    id: Uuid,
+    /// Only returned once, store it safely.
+    key: String,
+    scopes: Vec<String>,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct ApiKeyInfo{
+    id: Uuid,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+fn session_user(user: Option<Identity>) -> Result<String, Error> {
+    match user {
+        Some(user) => Ok(user.id().unwrap()),
+        None => Err(error::ErrorUnauthorized("Login required using /host endpoint")),
+    }
+}
+
+#[post("/api-keys")]
+async fn create_api_key(
+    user: Option<Identity>,
+    request: web::Json<CreateKeyRequest>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    // Keys are only issued to interactive logins, so a leaked key can't mint new ones
+    let username = session_user(user)?;
+
+    if let Some(scope) = request.scopes.iter().find(|scope| Scope::from_name(scope).is_none()){
+        return Err(error::ErrorBadRequest(format!("Unknown scope {}", scope)));
+    }
+    if request.expires_in_days.is_some_and(|days| days <= 0){
+        return Err(error::ErrorBadRequest("expires_in_days must be positive"));
+    }
+
+    let id = Uuid::new_v4();
+    let secret = rng().random::<[u8; 32]>().iter().map(|x| format!("{:02x}", x)).collect::<String>();
+    let key = format!("bk_{}_{}", id.simple(), secret);
+
+    sqlx::query("INSERT INTO api_keys (id, username, key_hash, scopes, expires_at) VALUES ($1, $2, $3, $4, now() + make_interval(days => $5))")
+        .bind(id)
+        .bind(&username)
+        .bind(hash_secret(&secret))
+        .bind(&request.scopes)
+        .bind(request.expires_in_days)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to create API key for {}: {}", username, e);
+            error::ErrorInternalServerError("Failed to create API key")
+        })?;
+
+    log::info!("Issued API key {} to {} with scopes {:?}", id, username, request.scopes);
+    Ok(HttpResponse::Ok().json(CreatedKey{ id, key, scopes: request.scopes.clone() }))
+}
+
+#[get("/api-keys")]
+async fn list_api_keys(
+    user: Option<Identity>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let username = session_user(user)?;
+
+    let keys: Vec<ApiKeyInfo> = sqlx::query_as("SELECT id, scopes, created_at, expires_at, revoked_at FROM api_keys WHERE username = $1 ORDER BY created_at")
+        .bind(&username)
+        .fetch_all(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to list API keys for {}: {}", username, e);
+            error::ErrorInternalServerError("Failed to list API keys")
+        })?;
+
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+#[post("/api-keys/{id}/revoke")]
+async fn revoke_api_key(
+    user: Option<Identity>,
+    path: web::Path<(Uuid,)>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let username = session_user(user)?;
+
+    let result = sqlx::query("UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND username = $2 AND revoked_at IS NULL")
+        .bind(path.0)
+        .bind(&username)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to revoke API key {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to revoke API key")
+        })?;
+
+    if result.rows_affected() == 0{
+        return Err(error::ErrorNotFound("API key not found"));
+    }
+    log::info!("{} revoked API key {}", username, path.0);
+    Ok(HttpResponse::NoContent().finish())
+}
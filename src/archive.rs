@@ -0,0 +1,104 @@
+use std::sync::Arc;
+
+use crate::crypto::Keyring;
+use crate::object_store::{ObjectStore, ObjectStoreConfig};
+use crate::persistence::PendingWrite;
+use crate::replay::Replay;
+use crate::room::RoomId;
+
+/// Bucket the reports of closed rooms are uploaded to, enabled with `ARCHIVE_REPORTS`.
+#[derive(Debug, Clone)]
+pub struct ArchiveConfig{
+    pub store: ObjectStoreConfig,
+    /// Objects are stored as `<prefix>/<room>/report.json` and `<prefix>/<room>/events.json`.
+    pub prefix: String,
+    /// Delete the calls, wins and trade-ins of a room once its event log is uploaded, stats, history and replays
+    /// then only cover rooms that weren't archived yet.
+    pub prune: bool,
+}
+
+/// Uploads the final report and event log of a closed room to object storage, the database only keeps
+/// their addresses. Both are sealed with the room's key while encryption is configured.
+#[derive(Debug, Clone)]
+pub struct Archive{
+    store: ObjectStore,
+    prefix: String,
+    prune: bool,
+    keyring: Keyring,
+}
+
+impl Archive{
+    pub fn new(config: ArchiveConfig, keyring: Keyring) -> Self {
+        Self{
+            store: ObjectStore::new(config.store),
+            prefix: config.prefix.trim_end_matches('/').to_owned(),
+            prune: config.prune,
+            keyring,
+        }
+    }
+
+    /// Uploads an object, sealed objects are text and are opened with `Keyring::decrypt` for the room.
+    async fn put(&self, key: &str, room: RoomId, json: &str) -> anyhow::Result<String> {
+        if !self.keyring.is_enabled(){
+            return self.store.put(key, json.as_bytes().to_vec(), "application/json").await;
+        }
+        self.store.put(key, self.keyring.encrypt(room, json).into_bytes(), "text/plain").await
+    }
+
+    fn report_key(&self, room: RoomId) -> String {
+        format!("{}/{}/report.json", self.prefix, room)
+    }
+
+    fn events_key(&self, room: RoomId) -> String {
+        format!("{}/{}/events.json", self.prefix, room)
+    }
+
+    /// Queued behind the other writes of the room so the event log has every call and win. A failed
+    /// upload is retried like any other write, uploading the same objects again is harmless.
+    pub fn archive_room(&self, room: RoomId, report: String) -> PendingWrite {
+        let (archive, report) = (self.clone(), Arc::<str>::from(report));
+        PendingWrite::new(
+            format!("archive of room {}", room),
+            Box::new(move |database| {
+                let (archive, report) = (archive.clone(), report.clone());
+                Box::pin(async move {
+                    archive.upload(&database, room, &report).await.map_err(|e| match e.downcast::<sqlx::Error>() {
+                        Ok(e) => e,
+                        Err(e) => sqlx::Error::Io(std::io::Error::other(e.to_string())),
+                    })
+                })
+            }),
+        )
+    }
+
+    async fn upload(&self, database: &sqlx::PgPool, room: RoomId, report: &str) -> anyhow::Result<()> {
+        let report_url = self.put(&self.report_key(room), room, report).await?;
+        let events_url = match Replay::load(database, room, None).await? {
+            Some(replay) => Some(self.put(&self.events_key(room), room, &serde_json::to_string(&replay)?).await?),
+            None => None,
+        };
+
+        let mut tx = database.begin().await?;
+        sqlx::query("UPDATE rooms SET report_url = $2, event_log_url = $3 WHERE id = $1")
+            .bind(room)
+            .bind(&report_url)
+            .bind(&events_url)
+            .execute(&mut *tx)
+            .await?;
+        // The event log holds every call, win and trade-in now, the rows only grow the database
+        if self.prune && events_url.is_some(){
+            sqlx::query("DELETE FROM calls WHERE room_id = $1").bind(room).execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM wins WHERE room_id = $1").bind(room).execute(&mut *tx).await?;
+            sqlx::query("DELETE FROM card_trade_ins WHERE room_id = $1").bind(room).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        log::info!("Archived the report of room {} to {}", room, report_url);
+        Ok(())
+    }
+
+    /// Removes the archived report and event log of a room, for erasing a host's data.
+    pub async fn delete_room(&self, room: RoomId) -> anyhow::Result<()> {
+        self.store.delete(&self.report_key(room)).await?;
+        self.store.delete(&self.events_key(room)).await
+    }
+}
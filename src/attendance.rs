@@ -0,0 +1,80 @@
+use actix_web::{error, get, web, HttpResponse};
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::room::{BingoServerHandle, RoomId, SessionId};
+
+const CSV_HEADER: &str = "client_id,display_name,joined_at,left_at,duration_secs";
+
+/// A player connection of a room, players still connected have no leave time and count until now.
+#[derive(Debug)]
+pub struct AttendanceRow{
+    pub client_id: SessionId,
+    pub name: Option<String>,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>,
+}
+
+/// Quotes a field when needed. Names starting like a formula are prefixed with `'` so spreadsheets
+/// show them as text instead of evaluating them.
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@']) { format!("'{}", value) } else { value.to_owned() };
+    if value.contains([',', '"', '\n', '\r']){
+        format!("\"{}\"", value.replace('"', "\"\""))
+    }
+    else{
+        value
+    }
+}
+
+fn to_csv(rows: &[AttendanceRow], now: DateTime<Utc>) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for row in rows{
+        let duration = (row.left_at.unwrap_or(now) - row.joined_at).num_seconds().max(0);
+        csv.push_str(&format!("{},{},{},{},{}\n",
+            row.client_id,
+            csv_field(row.name.as_deref().unwrap_or_default()),
+            row.joined_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            row.left_at.map(|left_at| left_at.to_rfc3339_opts(SecondsFormat::Secs, true)).unwrap_or_default(),
+            duration,
+        ));
+    }
+    csv
+}
+
+/// Join and leave times of every player connection of the room, in the order they joined.
+#[get("/room/{room}/attendance.csv")]
+async fn attendance_csv(
+    user: HostIdentity,
+    path: web::Path<(RoomId,)>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+    let room = path.0;
+    let rows = server.attendance(room, user.username).await
+        .ok_or_else(|| error::ErrorNotFound("Room not found"))?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"attendance-{}.csv\"", room)))
+        .body(to_csv(&rows, Utc::now())))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn rows_are_escaped_for_spreadsheets(){
+        let client_id = |id: &str| serde_json::from_str::<SessionId>(id).unwrap();
+        let joined_at = DateTime::parse_from_rfc3339("2026-03-01T19:00:00Z").unwrap().with_timezone(&Utc);
+        let rows = vec![
+            AttendanceRow{ client_id: client_id("1"), name: Some("Smith, \"Granny\"".to_owned()), joined_at, left_at: Some(joined_at + chrono::Duration::minutes(90)) },
+            AttendanceRow{ client_id: client_id("2"), name: Some("=1+1".to_owned()), joined_at, left_at: None },
+        ];
+        let csv = to_csv(&rows, joined_at + chrono::Duration::minutes(30));
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], CSV_HEADER);
+        assert_eq!(lines[1], "1,\"Smith, \"\"Granny\"\"\",2026-03-01T19:00:00Z,2026-03-01T20:30:00Z,5400");
+        assert_eq!(lines[2], "2,'=1+1,2026-03-01T19:00:00Z,,1800");
+    }
+}
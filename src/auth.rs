@@ -0,0 +1,359 @@
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::{Duration, Instant}};
+
+use argon2::{
+    password_hash::{
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString
+    },
+    Argon2
+};
+use base64::prelude::*;
+use futures_util::future::BoxFuture;
+use jsonwebtoken::{decode, decode_header, jwk::{AlgorithmParameters, Jwk, JwkSet}, Algorithm, DecodingKey, Validation};
+use rand::{rng, Rng as _};
+use sha2::{Digest, Sha256};
+use sqlx::types::Uuid;
+use tokio::{sync::RwLock, task::spawn_blocking};
+
+use crate::config::AuthConfig;
+
+pub type AuthResult = Result<AuthenticatedUser, &'static str>;
+
+/// Identity of a host that passed authentication.
+#[derive(Debug)]
+pub struct AuthenticatedUser{
+    pub username: String,
+}
+
+/// Verifies the `Authorization` header of a host request.
+pub trait AuthProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn authenticate<'a>(&'a self, authorization: &'a str) -> BoxFuture<'a, AuthResult>;
+
+    /// Drops anything cached about the account, called after its token was rotated.
+    fn invalidate(&self, _user: Uuid){}
+
+    /// Stable id of the account the credentials are checked against, failed logins are counted against it.
+    fn claimed_user(&self, _authorization: &str) -> Option<String> {
+        None
+    }
+}
+
+/// Creates the provider selected by the `AUTH_PROVIDER` setting.
+pub fn create_provider(config: &AuthConfig, database: sqlx::PgPool) -> Arc<dyn AuthProvider> {
+    match config {
+        AuthConfig::Password { cache_ttl } => Arc::new(PasswordAuthProvider{
+            database,
+            cache_ttl: *cache_ttl,
+            users: Mutex::new(HashMap::new()),
+        }),
+        AuthConfig::Oidc { issuer, client_id, jwks_url, allowed_users } => Arc::new(OidcAuthProvider{
+            issuer: issuer.clone(),
+            client_id: client_id.clone(),
+            jwks_url: jwks_url.clone(),
+            allowed_users: allowed_users.clone(),
+            keys: RwLock::new(HashMap::new()),
+            last_refresh: Mutex::new(None),
+            client: reqwest::Client::new(),
+        }),
+        AuthConfig::Github { allowed_users } => Arc::new(GithubAuthProvider{
+            allowed_users: allowed_users.clone(),
+            client: reqwest::Client::new(),
+        }),
+    }
+}
+
+#[derive(sqlx::FromRow, serde::Deserialize, Debug)]
+pub struct AuthUser{
+    id: Uuid,
+    username: String,
+    token: String,
+}
+
+/// argon2 parameters of the server, hashes for the `users` table should be made with these.
+fn hasher() -> Argon2<'static> {
+    Argon2::default()
+}
+
+pub fn verify_password(user_token: &str, hash_token: &str) -> Result<bool, argon2::password_hash::Error> {
+    let parsed_hash = PasswordHash::new(hash_token)?;
+    Ok(hasher().verify_password(user_token.as_bytes(), &parsed_hash).is_ok())
+}
+
+/// Hashes a host token with a random salt, in the PHC string format stored in the `users` table.
+pub fn hash_password(user_token: &str) -> Result<String, argon2::password_hash::Error> {
+    let salt = SaltString::encode_b64(&rng().random::<[u8; 16]>())?;
+    Ok(hasher().hash_password(user_token.as_bytes(), &salt)?.to_string())
+}
+
+/// A new random host token, 256 bits hex encoded.
+pub fn generate_user_token() -> String {
+    rng().random::<[u8; 32]>().iter().map(|x| format!("{:02x}", x)).collect::<String>()
+}
+
+/// A `users` row reused by `PasswordAuthProvider` until the TTL runs out or the token is rotated.
+struct CachedUser{
+    token_hash: String,
+    /// SHA-256 of the last token that passed the argon2 check, repeated requests skip the slow hash.
+    verified: Option<[u8; 32]>,
+    fetched_at: Instant,
+}
+
+/// Accounts from the `users` table, the header holds base64 encoded `{id, username, token}` JSON.
+pub struct PasswordAuthProvider{
+    database: sqlx::PgPool,
+    cache_ttl: Duration,
+    users: Mutex<HashMap<Uuid, CachedUser>>,
+}
+
+impl PasswordAuthProvider{
+    /// The stored hash of the account, from the cache while it is fresh. The flag is set when the
+    /// token already passed verification against it.
+    async fn token_hash(&self, id: Uuid, digest: &[u8; 32]) -> Result<(String, bool), &'static str> {
+        if let Some(user) = self.users.lock().unwrap().get(&id).filter(|user| user.fetched_at.elapsed() < self.cache_ttl){
+            return Ok((user.token_hash.clone(), user.verified.as_ref() == Some(digest)));
+        }
+
+        //Using auth_token.id look up the user in the database
+        let user_data: AuthUser = sqlx::query_as("SELECT * FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_one(&self.database)
+            .await
+            .map_err(|_| "Invalid Authorization header, user not found")?;
+
+        self.users.lock().unwrap().insert(id, CachedUser{ token_hash: user_data.token.clone(), verified: None, fetched_at: Instant::now() });
+        Ok((user_data.token, false))
+    }
+
+    fn decode(authorization: &str) -> Result<AuthUser, &'static str> {
+        let decoded = BASE64_STANDARD.decode(authorization)
+            .map_err(|_| "Invalid Authorization header, unexpected encoding")?;
+
+        serde_json::from_slice(&decoded).map_err(|e| {
+            log::warn!("Failed to parse Authorization header: {}", e);
+            "Invalid Authorization header, unexpected format"
+        })
+    }
+
+    async fn verify(&self, authorization: &str) -> AuthResult {
+        let auth_token = Self::decode(authorization)?;
+
+        log::info!("Host request from {}", auth_token.username);
+        // Check if token is valid in the database and matches the user
+        // if not return unauthorized
+        let digest: [u8; 32] = Sha256::digest(auth_token.token.as_bytes()).into();
+        let (token_hash, verified) = self.token_hash(auth_token.id, &digest).await?;
+        if verified{
+            return Ok(AuthenticatedUser{ username: auth_token.username });
+        }
+
+        // argon2 is deliberately slow, keep it off the async workers
+        let token = auth_token.token;
+        let hash = token_hash.clone();
+        let result = spawn_blocking(move || verify_password(&token, &hash)).await
+            .map_err(|_| "Invalid Authorization header, token verification failed")?;
+
+        match result {
+            Ok(true) => {
+                // Unless the token was rotated while verifying
+                if let Some(user) = self.users.lock().unwrap().get_mut(&auth_token.id).filter(|user| user.token_hash == token_hash){
+                    user.verified = Some(digest);
+                }
+                Ok(AuthenticatedUser{ username: auth_token.username })
+            }
+            Ok(false) => Err("Invalid Authorization header, token does not match"),
+            Err(err) => {
+                log::warn!("Failed to verify token: {}", err);
+                Err("Invalid Authorization header, token verification failed")
+            }
+        }
+    }
+}
+
+impl AuthProvider for PasswordAuthProvider{
+    fn name(&self) -> &'static str {
+        "password"
+    }
+
+    fn authenticate<'a>(&'a self, authorization: &'a str) -> BoxFuture<'a, AuthResult> {
+        Box::pin(self.verify(authorization))
+    }
+
+    fn invalidate(&self, user: Uuid){
+        self.users.lock().unwrap().remove(&user);
+    }
+
+    fn claimed_user(&self, authorization: &str) -> Option<String> {
+        // The id is what gets verified, the username in the header is free text
+        Self::decode(authorization).ok().map(|user| user.id.to_string())
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct IdTokenClaims{
+    sub: String,
+    email: Option<String>,
+    email_verified: Option<bool>,
+}
+
+/// OpenID Connect ID tokens (e.g. Google) sent as `Bearer <id_token>`.
+pub struct OidcAuthProvider{
+    issuer: String,
+    client_id: String,
+    jwks_url: String,
+    /// Emails (or subjects) allowed to host rooms.
+    allowed_users: Vec<String>,
+    /// Signing keys of the issuer and the algorithm each is used with by key id, refreshed when an
+    /// unknown key id shows up.
+    keys: RwLock<HashMap<String, (DecodingKey, Algorithm)>>,
+    /// Anyone can send a token with a made up key id, the keys are fetched at most once per `JWKS_MIN_REFRESH`.
+    last_refresh: Mutex<Option<Instant>>,
+    client: reqwest::Client,
+}
+
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(60);
+
+/// Algorithm a key signs with. Keys that don't name one are taken as RS256, the default of OpenID Connect.
+fn key_algorithm(jwk: &Jwk) -> Option<Algorithm> {
+    match &jwk.common.key_algorithm {
+        // Both enums name the signature algorithms the same way
+        Some(algorithm) => format!("{:?}", algorithm).parse().ok(),
+        None if matches!(jwk.algorithm, AlgorithmParameters::RSA(_)) => Some(Algorithm::RS256),
+        None => None,
+    }
+}
+
+impl OidcAuthProvider{
+    async fn refresh_keys(&self) -> Result<(), &'static str> {
+        let jwks: JwkSet = self.client.get(&self.jwks_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                log::error!("Failed to fetch signing keys from {}: {}", self.jwks_url, e);
+                "Identity provider unavailable"
+            })?
+            .json()
+            .await
+            .map_err(|e| {
+                log::error!("Invalid signing keys from {}: {}", self.jwks_url, e);
+                "Identity provider unavailable"
+            })?;
+
+        let mut keys = self.keys.write().await;
+        keys.clear();
+        for jwk in &jwks.keys{
+            if let (Some(kid), Ok(key), Some(algorithm)) = (jwk.common.key_id.clone(), DecodingKey::from_jwk(jwk), key_algorithm(jwk)){
+                keys.insert(kid, (key, algorithm));
+            }
+        }
+        Ok(())
+    }
+
+    /// Claims the next key refresh, false while the last one is too recent.
+    fn may_refresh(&self) -> bool {
+        let mut last_refresh = self.last_refresh.lock().unwrap();
+        if last_refresh.is_some_and(|at| at.elapsed() < JWKS_MIN_REFRESH){
+            return false;
+        }
+        *last_refresh = Some(Instant::now());
+        true
+    }
+
+    async fn verify(&self, authorization: &str) -> AuthResult {
+        let token = authorization.strip_prefix("Bearer ")
+            .ok_or("Invalid Authorization header, bearer token expected")?;
+        let header = decode_header(token).map_err(|_| "Invalid Authorization header, malformed token")?;
+        let kid = header.kid.ok_or("Invalid Authorization header, token has no key id")?;
+
+        if !self.keys.read().await.contains_key(&kid) && self.may_refresh(){
+            self.refresh_keys().await?;
+        }
+        let keys = self.keys.read().await;
+        let (key, algorithm) = keys.get(&kid).ok_or("Invalid Authorization header, unknown signing key")?;
+        // The header is chosen by whoever made the token, only the algorithm the key is published for is accepted
+        if header.alg != *algorithm{
+            log::warn!("Rejected ID token signed with {:?}, key {} uses {:?}", header.alg, kid, algorithm);
+            return Err("Invalid Authorization header, unexpected signing algorithm");
+        }
+
+        let mut validation = Validation::new(*algorithm);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_issuer(&[&self.issuer]);
+        let claims = decode::<IdTokenClaims>(token, key, &validation)
+            .map_err(|e| {
+                log::warn!("Rejected ID token: {}", e);
+                "Invalid Authorization header, token verification failed"
+            })?
+            .claims;
+
+        let username = match (claims.email, claims.email_verified) {
+            (Some(email), Some(true)) => email,
+            _ => claims.sub,
+        };
+        if !self.allowed_users.contains(&username){
+            log::warn!("User {} is not allowed to host", username);
+            return Err("User is not allowed to host");
+        }
+        Ok(AuthenticatedUser{ username })
+    }
+}
+
+impl AuthProvider for OidcAuthProvider{
+    fn name(&self) -> &'static str {
+        "oidc"
+    }
+
+    fn authenticate<'a>(&'a self, authorization: &'a str) -> BoxFuture<'a, AuthResult> {
+        Box::pin(self.verify(authorization))
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct GithubUser{
+    login: String,
+}
+
+/// GitHub OAuth access tokens sent as `Bearer <token>`, resolved to the GitHub login.
+pub struct GithubAuthProvider{
+    /// GitHub logins allowed to host rooms.
+    allowed_users: Vec<String>,
+    client: reqwest::Client,
+}
+
+impl GithubAuthProvider{
+    async fn verify(&self, authorization: &str) -> AuthResult {
+        let token = authorization.strip_prefix("Bearer ")
+            .ok_or("Invalid Authorization header, bearer token expected")?;
+
+        let user: GithubUser = self.client.get("https://api.github.com/user")
+            .bearer_auth(token)
+            .header(reqwest::header::USER_AGENT, "bingoserver")
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| {
+                log::warn!("GitHub rejected access token: {}", e);
+                "Invalid Authorization header, token verification failed"
+            })?
+            .json()
+            .await
+            .map_err(|_| "Identity provider unavailable")?;
+
+        if !self.allowed_users.contains(&user.login){
+            log::warn!("User {} is not allowed to host", user.login);
+            return Err("User is not allowed to host");
+        }
+        Ok(AuthenticatedUser{ username: user.login })
+    }
+}
+
+impl AuthProvider for GithubAuthProvider{
+    fn name(&self) -> &'static str {
+        "github"
+    }
+
+    fn authenticate<'a>(&'a self, authorization: &'a str) -> BoxFuture<'a, AuthResult> {
+        Box::pin(self.verify(authorization))
+    }
+}
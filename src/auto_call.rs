@@ -0,0 +1,52 @@
+use std::{ops::RangeInclusive, time::Duration};
+
+/// Seconds between calls a host can set for the auto-caller.
+const AUTO_CALL_SECS: RangeInclusive<u64> = 2..=120;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct AutoCallRequest{
+    pub interval_secs: u64,
+}
+
+impl AutoCallRequest{
+    pub fn validate(&self) -> Result<(), String> {
+        if !AUTO_CALL_SECS.contains(&self.interval_secs){
+            return Err(format!("The auto-caller calls every {} to {} seconds", AUTO_CALL_SECS.start(), AUTO_CALL_SECS.end()));
+        }
+        Ok(())
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval_secs)
+    }
+}
+
+/// Hands-free calling of a round, the server draws the next number on a cadence until the host pauses
+/// it or the round ends. Unlike speed rounds players still daub and claim themselves.
+#[derive(Debug, Clone, Copy)]
+pub struct AutoCaller{
+    pub interval: Duration,
+    pub paused: bool,
+    /// Counts the timers started in the room, a timer of an earlier start or resume is ignored.
+    pub generation: u32,
+}
+
+/// Tells the hosts whether the auto-caller is running.
+#[derive(serde::Serialize)]
+pub struct AutoCallMessage{
+    r#type: String,
+    active: bool,
+    paused: bool,
+    interval_secs: Option<u64>,
+}
+
+impl AutoCallMessage{
+    pub fn new(caller: Option<&AutoCaller>) -> Self {
+        Self{
+            r#type: "auto_call".to_string(),
+            active: caller.is_some_and(|caller| !caller.paused),
+            paused: caller.is_some_and(|caller| caller.paused),
+            interval_secs: caller.map(|caller| caller.interval.as_secs()),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Command line client for the admin API of a running bingo server.
+//!
+//! The server address and the admin token are read from `BINGO_URL` (default `http://localhost:8000`)
+//! and `BINGO_ADMIN_TOKEN`, `--url` overrides the address.
+
+use std::{env, process::ExitCode};
+
+use reqwest::{Client, Method, RequestBuilder, Response};
+use serde_json::{json, Value};
+
+const DEFAULT_URL: &str = "http://localhost:8000";
+
+const USAGE: &str = "Usage: bingoctl [--url <server>] <command>
+
+Commands:
+  rooms                        List rooms with open connections
+  close <room>                 End a room, players can no longer join
+  users                        List password accounts
+  create-user <username>       Add an account with a generated token
+  rotate-token <username>      Replace the token of an account with a generated one
+  events                       Print the events of every room as they happen
+
+Environment:
+  BINGO_URL                    Server address, default http://localhost:8000
+  BINGO_ADMIN_TOKEN            Admin token of the server, see ADMIN_TOKEN";
+
+/// What to run, parsed from the arguments.
+#[derive(Debug, PartialEq)]
+enum Command{
+    Rooms,
+    Close(u32),
+    Users,
+    CreateUser(String),
+    RotateToken(String),
+    Events,
+    Help,
+}
+
+/// Parsed arguments, `url` is set when `--url` was given.
+#[derive(Debug, PartialEq)]
+struct Invocation{
+    url: Option<String>,
+    command: Command,
+}
+
+fn parse_args(args: &[String]) -> Result<Invocation, String> {
+    let (url, args) = match args {
+        [flag, url, rest @ ..] if flag == "--url" => (Some(url.clone()), rest),
+        [flag] if flag == "--url" => return Err(USAGE.to_owned()),
+        args => (None, args),
+    };
+    let command = match args {
+        [command] if command == "rooms" => Command::Rooms,
+        [command, room] if command == "close" => Command::Close(room.parse().map_err(|_| format!("Invalid room {}", room))?),
+        [command] if command == "users" => Command::Users,
+        [command, username] if command == "create-user" => Command::CreateUser(username.clone()),
+        [command, username] if command == "rotate-token" => Command::RotateToken(username.clone()),
+        [command] if command == "events" => Command::Events,
+        [command] if command == "help" || command == "--help" => Command::Help,
+        _ => return Err(USAGE.to_owned()),
+    };
+    Ok(Invocation{ url, command })
+}
+
+struct Api{
+    client: Client,
+    url: String,
+    token: String,
+}
+
+impl Api{
+    fn new(url: &str, token: String) -> Self {
+        Self{
+            client: Client::new(),
+            url: url.trim_end_matches('/').to_owned(),
+            token,
+        }
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.client.request(method, format!("{}{}", self.url, path)).bearer_auth(&self.token)
+    }
+
+    /// Sends the request, turning error responses into their message.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, String> {
+        let response = request.send().await.map_err(|e| format!("Request failed: {}", e))?;
+        let status = response.status();
+        if status.is_success(){
+            return Ok(response);
+        }
+        let body = response.text().await.unwrap_or_default();
+        Err(format!("Server answered {}: {}", status, body.trim()))
+    }
+
+    async fn get(&self, path: &str) -> Result<Value, String> {
+        let response = self.send(self.request(Method::GET, path)).await?;
+        response.json().await.map_err(|e| format!("Invalid response: {}", e))
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<Response, String> {
+        self.send(self.request(Method::POST, path).json(body)).await
+    }
+
+    /// Generates a token and its hash on the server, so the hash parameters match the server's.
+    async fn generate_token(&self) -> Result<(String, String), String> {
+        let result: Value = self.post("/admin/hash-token", &json!({})).await?
+            .json().await.map_err(|e| format!("Invalid response: {}", e))?;
+        match (result["token"].as_str(), result["hash"].as_str()) {
+            (Some(token), Some(hash)) => Ok((token.to_owned(), hash.to_owned())),
+            _ => Err("The server returned no token".to_owned()),
+        }
+    }
+
+    async fn find_user(&self, username: &str) -> Result<String, String> {
+        let users = self.get("/admin/users").await?;
+        users.as_array().into_iter().flatten()
+            .find(|user| user["username"] == username)
+            .and_then(|user| user["id"].as_str())
+            .map(str::to_owned)
+            .ok_or_else(|| format!("No user named {}", username))
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_owned(),
+        Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Prints rows of objects as aligned columns.
+fn print_table(rows: &Value, columns: &[&str]){
+    let rows: Vec<Vec<String>> = rows.as_array().into_iter().flatten()
+        .map(|row| columns.iter().map(|column| text(&row[column])).collect())
+        .collect();
+    let widths: Vec<usize> = columns.iter().enumerate()
+        .map(|(i, column)| rows.iter().map(|row| row[i].chars().count()).chain([column.len()]).max().unwrap_or_default())
+        .collect();
+    let line = |cells: Vec<String>| cells.iter().zip(&widths)
+        .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+        .collect::<Vec<_>>()
+        .join("  ");
+    println!("{}", line(columns.iter().map(|column| column.to_uppercase()).collect()).trim_end());
+    for row in rows{
+        println!("{}", line(row).trim_end());
+    }
+}
+
+async fn tail_events(api: &Api) -> Result<(), String> {
+    let mut response = api.send(api.request(Method::GET, "/admin/events")).await?;
+    let mut buffer = String::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| format!("Event stream failed: {}", e))?{
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(end) = buffer.find('\n'){
+            let line: String = buffer.drain(..=end).collect();
+            // Comments are keep-alives
+            if let Some(data) = line.trim_end().strip_prefix("data: "){
+                println!("{}", data);
+            }
+        }
+    }
+    Err("The server closed the event stream".to_owned())
+}
+
+async fn run(api: Api, command: Command) -> Result<(), String> {
+    match command {
+        Command::Rooms => {
+            let rooms = api.get("/admin/rooms").await?;
+            print_table(&rooms, &["room", "host", "variant", "phase", "round", "calls", "hosts", "players", "waiting", "boards", "observers"]);
+        }
+        Command::Close(room) => {
+            api.post(&format!("/admin/rooms/{}/close", room), &json!({})).await?;
+            println!("Closed room {}", room);
+        }
+        Command::Users => {
+            let users = api.get("/admin/users").await?;
+            print_table(&users, &["id", "username"]);
+        }
+        Command::CreateUser(username) => {
+            let (token, hash) = api.generate_token().await?;
+            let user: Value = api.post("/admin/users", &json!({ "username": username, "token": hash })).await?
+                .json().await.map_err(|e| format!("Invalid response: {}", e))?;
+            println!("Created user {} ({})", username, text(&user["id"]));
+            println!("Token: {}", token);
+        }
+        Command::RotateToken(username) => {
+            let id = api.find_user(&username).await?;
+            let (token, hash) = api.generate_token().await?;
+            api.send(api.request(Method::PUT, &format!("/admin/users/{}/token", id)).json(&json!({ "token": hash }))).await?;
+            println!("Rotated the token of {}", username);
+            println!("Token: {}", token);
+        }
+        Command::Events => tail_events(&api).await?,
+        Command::Help => println!("{}", USAGE),
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let invocation = match parse_args(&args) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if invocation.command == Command::Help{
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let Ok(token) = env::var("BINGO_ADMIN_TOKEN") else {
+        eprintln!("BINGO_ADMIN_TOKEN is not set");
+        return ExitCode::FAILURE;
+    };
+
+    let url = invocation.url.or_else(|| env::var("BINGO_URL").ok()).unwrap_or_else(|| DEFAULT_URL.to_owned());
+    match run(Api::new(&url, token), invocation.command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn commands_are_parsed(){
+        assert_eq!(parse_args(&args("rooms")), Ok(Invocation{ url: None, command: Command::Rooms }));
+        assert_eq!(parse_args(&args("close 12")), Ok(Invocation{ url: None, command: Command::Close(12) }));
+        assert_eq!(parse_args(&args("create-user ann")), Ok(Invocation{ url: None, command: Command::CreateUser("ann".to_owned()) }));
+        assert_eq!(parse_args(&args("rotate-token ann")), Ok(Invocation{ url: None, command: Command::RotateToken("ann".to_owned()) }));
+        assert_eq!(parse_args(&args("--help")), Ok(Invocation{ url: None, command: Command::Help }));
+        assert_eq!(
+            parse_args(&args("--url https://bingo.example events")),
+            Ok(Invocation{ url: Some("https://bingo.example".to_owned()), command: Command::Events }),
+        );
+    }
+
+    #[test]
+    fn invalid_arguments_print_the_usage(){
+        assert_eq!(parse_args(&args("")), Err(USAGE.to_owned()));
+        assert_eq!(parse_args(&args("--url")), Err(USAGE.to_owned()));
+        assert_eq!(parse_args(&args("rooms extra")), Err(USAGE.to_owned()));
+        assert_eq!(parse_args(&args("close")), Err(USAGE.to_owned()));
+        assert_eq!(parse_args(&args("close twelve")), Err("Invalid room twelve".to_owned()));
+    }
+
+    #[test]
+    fn requests_go_to_the_server_with_the_admin_token(){
+        let api = Api::new("https://bingo.example/", "secret".to_owned());
+        let request = api.request(Method::POST, "/admin/rooms/12/close").build().unwrap();
+
+        assert_eq!(request.method(), Method::POST);
+        assert_eq!(request.url().as_str(), "https://bingo.example/admin/rooms/12/close");
+        assert_eq!(request.headers()["authorization"], "Bearer secret");
+    }
+}
@@ -0,0 +1,80 @@
+use actix_web::{get, web, Error, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tokio::task::spawn_local;
+
+use crate::{accessibility::SocketFormat, config::WebSocketConfig, draw::{DrawPool, Number}, room::{BingoServerHandle, RoomId, SessionId, UserType}, round::{Round, RoundId}, variant::GameVariant, wshandler::{ws_handler, CommandHandler}};
+
+/// Authoritative state of the room for venue displays, sent to boards on connect and after every change.
+#[derive(serde::Serialize)]
+pub struct BoardMessage{
+    r#type: String,
+    /// Tells displays whether to lay out 75 or 90 numbers.
+    variant: GameVariant,
+    round: Option<RoundId>,
+    in_progress: bool,
+    called: Vec<Number>,
+    last_call: Option<Number>,
+    /// Lets displays show how long ago the last number was called.
+    last_call_at: Option<DateTime<Utc>>,
+    winners: Vec<SessionId>,
+}
+
+impl BoardMessage{
+    pub fn new(variant: GameVariant, round: Option<&Round>, in_progress: bool, draws: &DrawPool) -> Self {
+        Self{
+            r#type: "board".to_string(),
+            variant,
+            round: round.map(|round| round.id),
+            in_progress,
+            called: draws.called().to_vec(),
+            last_call: draws.called().last().copied(),
+            last_call_at: draws.call_times().last().copied(),
+            winners: round.map(|round| round.winners.clone()).unwrap_or_default(),
+        }
+    }
+}
+
+fn create_command_handler(room: RoomId) -> CommandHandler {
+    // Boards are display only, anything they send is ignored
+    Box::new(move |conn, msg| Box::pin(async move {
+        log::debug!("Ignoring message from board {} in room {}: {}", conn, room, msg);
+    }))
+}
+
+#[derive(Deserialize)]
+struct BoardQuery {
+    board_token: String,
+}
+
+#[get("/board/{room}")]
+async fn join_board(
+    req: HttpRequest,
+    payload: web::Payload,
+    path: web::Path<(RoomId,)>,
+    query: web::Query<BoardQuery>,
+    server: web::Data<BingoServerHandle>,
+    ws_config: web::Data<WebSocketConfig>,
+) -> Result<HttpResponse, Error> {
+    if !server.has_board_access(path.0, query.board_token.clone()).await {
+        log::info!("Rejected board for room {} with an invalid token", path.0);
+        return Err(actix_web::error::ErrorNotFound("Room not found"));
+    }
+
+    let (res, session, msg_stream) = actix_ws::handle(&req, payload)?;
+
+    log::info!("Board is joining room {}", path.0);
+    spawn_local(ws_handler(
+        server.clone(),
+        **ws_config,
+        SocketFormat::default(),
+        path.0,
+        UserType::Board,
+        None,
+        create_command_handler(path.0),
+        session,
+        msg_stream,
+    ));
+
+    Ok(res)
+}
@@ -0,0 +1,549 @@
+use std::ops::RangeInclusive;
+
+use rand::{rng, seq::{IndexedRandom, SliceRandom}};
+
+use crate::draw::Number;
+use crate::room::SessionId;
+use crate::variant::GameVariant;
+
+pub type CardId = u32;
+
+/// Marks the free space in the middle of 75-ball cards and the blank cells of 90-ball tickets, both count
+/// as covered.
+pub const FREE_SPACE: Number = 0;
+
+/// Numbers on each row of a 90-ball ticket, the other cells are blank.
+const NUMBERS_PER_ROW: usize = 5;
+
+/// Characters of claim codes, without 0, 1, I and O which are easily confused when read aloud.
+const CLAIM_CODE_ALPHABET: &[u8] = b"23456789ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+const CLAIM_CODE_LENGTH: usize = 8;
+
+/// Shapes a card can win with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern{
+    /// Any row, column or diagonal, only rows on 90-ball tickets.
+    Line,
+    FourCorners,
+    /// The B column and the bottom row.
+    LetterL,
+    /// Both diagonals.
+    LetterX,
+    /// Two rows of a 90-ball ticket.
+    TwoLines,
+    /// Every number on the card, a full house on 90-ball tickets.
+    Blackout,
+}
+
+impl Pattern{
+    pub const ALL: [Pattern; 6] = [Pattern::Line, Pattern::FourCorners, Pattern::LetterL, Pattern::LetterX, Pattern::TwoLines, Pattern::Blackout];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Line => "line",
+            Pattern::FourCorners => "four_corners",
+            Pattern::LetterL => "letter_l",
+            Pattern::LetterX => "letter_x",
+            Pattern::TwoLines => "two_lines",
+            Pattern::Blackout => "blackout",
+        }
+    }
+}
+
+/// A bingo card stored column by column from top to bottom, the B, I, N, G and O columns of a 75-ball card
+/// or the nine columns of a 90-ball ticket.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Card{
+    pub id: CardId,
+    pub columns: Vec<Vec<Number>>,
+    /// Read aloud by players without a device so the host can look the card up, set on cards the server issues.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub claim_code: Option<String>,
+}
+
+/// `count` random numbers of the range that are in the pool, any number of the range when the pool is empty.
+/// Fewer when the pool doesn't have enough, numbers outside the pool are never called.
+fn pick_numbers(range: RangeInclusive<Number>, pool: &[Number], count: usize) -> Vec<Number> {
+    let mut numbers: Vec<Number> = range.filter(|number| pool.is_empty() || pool.contains(number)).collect();
+    numbers.shuffle(&mut rng());
+    numbers.truncate(count);
+    numbers
+}
+
+impl Card{
+    /// A random card of the variant, numbers come from the round's pool where one is given and the cells a
+    /// column's pool numbers can't fill are left blank.
+    pub fn generate(id: CardId, pool: &[Number], variant: GameVariant) -> Self {
+        let columns = match variant {
+            GameVariant::Ball75 => Self::generate_75(pool),
+            GameVariant::Ball90 => Self::generate_90(pool),
+        };
+        Self{
+            id,
+            columns,
+            claim_code: Some(generate_claim_code()),
+        }
+    }
+
+    fn generate_75(pool: &[Number]) -> Vec<Vec<Number>> {
+        let (width, height) = GameVariant::Ball75.card_size();
+        (0..width).map(|index| {
+            // The free space of the middle column takes no number
+            let cells = if index == width / 2 { height - 1 } else { height };
+            let mut numbers = pick_numbers(GameVariant::Ball75.column_range(index), pool, cells);
+            numbers.resize(cells, FREE_SPACE);
+            numbers.shuffle(&mut rng());
+            if index == width / 2{
+                numbers.insert(height / 2, FREE_SPACE);
+            }
+            numbers
+        }).collect()
+    }
+
+    /// Every row holds five numbers and every column at least one, numbers go down a column in ascending order.
+    fn generate_90(pool: &[Number]) -> Vec<Vec<Number>> {
+        let (width, height) = GameVariant::Ball90.card_size();
+        let rows: Vec<Vec<usize>> = loop {
+            let rows: Vec<Vec<usize>> = (0..height).map(|_| {
+                let mut columns: Vec<usize> = (0..width).collect();
+                columns.shuffle(&mut rng());
+                columns.truncate(NUMBERS_PER_ROW);
+                columns
+            }).collect();
+            if (0..width).all(|column| rows.iter().any(|row| row.contains(&column))){
+                break rows;
+            }
+        };
+
+        (0..width).map(|index| {
+            let used: Vec<bool> = rows.iter().map(|row| row.contains(&index)).collect();
+            let mut numbers = pick_numbers(GameVariant::Ball90.column_range(index), pool, used.iter().filter(|used| **used).count());
+            numbers.sort_unstable();
+            let mut numbers = numbers.into_iter();
+            used.into_iter().map(|used| if used { numbers.next().unwrap_or(FREE_SPACE) } else { FREE_SPACE }).collect()
+        }).collect()
+    }
+
+    /// Variant the card is laid out for, told apart by its number of columns.
+    pub fn variant(&self) -> GameVariant {
+        if self.columns.len() == GameVariant::Ball90.card_size().0 { GameVariant::Ball90 } else { GameVariant::Ball75 }
+    }
+
+    fn rows(&self) -> usize {
+        self.columns.first().map(Vec::len).unwrap_or_default()
+    }
+
+    /// True for a standard layout of the variant: numbers of each column from its own range and no repeats,
+    /// a free middle on 75-ball cards and five numbers per row on 90-ball tickets.
+    pub fn is_valid_layout(&self, variant: GameVariant) -> bool {
+        let (width, height) = variant.card_size();
+        if self.columns.len() != width || self.columns.iter().any(|column| column.len() != height){
+            return false;
+        }
+        let numbers_valid = self.columns.iter().enumerate().all(|(index, column)| {
+            let range = variant.column_range(index);
+            column.iter().enumerate().all(|(row, number)| {
+                let is_middle = variant == GameVariant::Ball75 && index == width / 2 && row == height / 2;
+                if is_middle { *number == FREE_SPACE }
+                else if *number == FREE_SPACE { variant == GameVariant::Ball90 }
+                else { range.contains(number) && column.iter().filter(|other| *other == number).count() == 1 }
+            })
+        });
+        match variant {
+            GameVariant::Ball75 => numbers_valid,
+            GameVariant::Ball90 => numbers_valid
+                && (0..height).all(|row| self.columns.iter().filter(|column| column[row] != FREE_SPACE).count() == NUMBERS_PER_ROW)
+                && self.columns.iter().all(|column| column.iter().any(|number| *number != FREE_SPACE)),
+        }
+    }
+
+    /// Shape and column ranges of the variant, unlike `is_valid_layout` any cell may be blank since cards dealt
+    /// from a restricted pool can have them.
+    pub fn fits(&self, variant: GameVariant) -> bool {
+        let (width, height) = variant.card_size();
+        self.columns.len() == width && self.columns.iter().enumerate().all(|(index, column)| {
+            let range = variant.column_range(index);
+            column.len() == height && column.iter().all(|number| {
+                *number == FREE_SPACE || (range.contains(number) && column.iter().filter(|other| *other == number).count() == 1)
+            })
+        })
+    }
+
+    pub fn contains(&self, number: Number) -> bool {
+        number != FREE_SPACE && self.columns.iter().flatten().any(|n| *n == number)
+    }
+
+    /// Called numbers on the card, column by column.
+    pub fn marked(&self, called: &[Number]) -> Vec<Number> {
+        self.columns.iter().flatten().copied().filter(|number| *number != FREE_SPACE && called.contains(number)).collect()
+    }
+
+    fn is_marked(&self, column: usize, row: usize, called: &[Number]) -> bool {
+        match self.columns.get(column).and_then(|cells| cells.get(row)) {
+            Some(number) => *number == FREE_SPACE || called.contains(number),
+            None => false,
+        }
+    }
+
+    fn has_number(&self, column: usize, row: usize) -> bool {
+        self.columns.get(column).and_then(|cells| cells.get(row)).is_some_and(|number| *number != FREE_SPACE)
+    }
+
+    /// True when every cell is covered and at least one holds a number, cells left blank by a small pool
+    /// mustn't win on their own.
+    fn covers(&self, cells: &[(usize, usize)], called: &[Number]) -> bool {
+        cells.iter().all(|(column, row)| self.is_marked(*column, *row, called))
+            && cells.iter().any(|(column, row)| self.has_number(*column, *row))
+    }
+
+    fn row_cells(&self, row: usize) -> Vec<(usize, usize)> {
+        (0..self.columns.len()).map(|column| (column, row)).collect()
+    }
+
+    /// Rows, columns and diagonals of a 75-ball card that hold at least one number.
+    fn lines(&self) -> Vec<Vec<(usize, usize)>> {
+        let size = self.columns.len();
+        let mut lines: Vec<Vec<(usize, usize)>> = (0..size).map(|row| self.row_cells(row)).collect();
+        lines.extend((0..size).map(|column| (0..size).map(|row| (column, row)).collect()));
+        lines.push((0..size).map(|i| (i, i)).collect());
+        lines.push((0..size).map(|i| (i, size - 1 - i)).collect());
+        lines.retain(|cells| cells.iter().any(|(column, row)| self.has_number(*column, *row)));
+        lines
+    }
+
+    fn complete_rows(&self, called: &[Number]) -> usize {
+        (0..self.rows()).filter(|row| self.covers(&self.row_cells(*row), called)).count()
+    }
+
+    fn is_full(&self, called: &[Number]) -> bool {
+        let cells: Vec<(usize, usize)> = (0..self.columns.len()).flat_map(|column| (0..self.rows()).map(move |row| (column, row))).collect();
+        self.covers(&cells, called)
+    }
+
+    /// True when the called numbers win the main game: any row, column or diagonal of a 75-ball card, a full
+    /// house on a 90-ball ticket.
+    pub fn has_bingo(&self, called: &[Number]) -> bool {
+        if self.variant() == GameVariant::Ball90{
+            return self.is_full(called);
+        }
+        self.lines().iter().any(|cells| self.covers(cells, called))
+    }
+
+    /// The call that first completed the main game on this card, later calls don't change it.
+    pub fn winning_number(&self, called: &[Number]) -> Option<Number> {
+        (1..=called.len()).find(|calls| self.has_bingo(&called[..*calls])).map(|calls| called[calls - 1])
+    }
+
+    /// True when the called numbers cover the pattern, never for patterns the card's variant doesn't play.
+    pub fn has_pattern(&self, pattern: Pattern, called: &[Number]) -> bool {
+        if !self.variant().supports(pattern){
+            return false;
+        }
+        if self.variant() == GameVariant::Ball90{
+            return match pattern {
+                Pattern::Line => self.complete_rows(called) >= 1,
+                Pattern::TwoLines => self.complete_rows(called) >= 2,
+                _ => self.is_full(called),
+            };
+        }
+        let size = self.columns.len();
+        let last = size.saturating_sub(1);
+        match pattern {
+            Pattern::Line => self.has_bingo(called),
+            Pattern::FourCorners => self.covers(&[(0, 0), (0, last), (last, 0), (last, last)], called),
+            Pattern::LetterL => self.covers(&(0..size).flat_map(|i| [(0, i), (i, last)]).collect::<Vec<_>>(), called),
+            Pattern::LetterX => self.covers(&(0..size).flat_map(|i| [(i, i), (i, last - i)]).collect::<Vec<_>>(), called),
+            Pattern::TwoLines => false,
+            Pattern::Blackout => self.is_full(called),
+        }
+    }
+
+    fn open_cells(&self, cells: impl Iterator<Item = (usize, usize)>, called: &[Number]) -> usize {
+        cells.filter(|(column, row)| !self.is_marked(*column, *row, called)).count()
+    }
+
+    /// Uncovered numbers needed for the main game, the line closest to completion on 75-ball cards. 0 means
+    /// bingo and 1 one away.
+    pub fn numbers_to_go(&self, called: &[Number]) -> usize {
+        let size = self.columns.len();
+        if self.variant() == GameVariant::Ball90{
+            return self.open_cells((0..size).flat_map(|column| (0..self.rows()).map(move |row| (column, row))), called);
+        }
+        self.lines().into_iter().map(|cells| self.open_cells(cells.into_iter(), called)).min().unwrap_or(size)
+    }
+}
+
+pub fn generate_claim_code() -> String {
+    let mut rng = rng();
+    (0..CLAIM_CODE_LENGTH).map(|_| *CLAIM_CODE_ALPHABET.choose(&mut rng).unwrap() as char).collect()
+}
+
+/// Claim code as typed by a host, case and separators don't matter.
+pub fn normalize_claim_code(code: &str) -> String {
+    code.chars().filter(char::is_ascii_alphanumeric).map(|c| c.to_ascii_uppercase()).collect()
+}
+
+/// Card sales settings configured by the host.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct CardSettings{
+    pub max_cards_per_player: u32,
+    /// Price per card in the smallest currency unit, informational only.
+    pub price_cents: Option<u32>,
+    pub currency: Option<String>,
+    /// Cards a player may trade in for a fresh one each round, trade-ins are disabled at 0.
+    #[serde(default)]
+    pub trade_ins_per_round: u32,
+}
+
+impl Default for CardSettings{
+    fn default() -> Self {
+        Self{
+            max_cards_per_player: 1,
+            price_cents: None,
+            currency: None,
+            trade_ins_per_round: 0,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CardMessage<'a>{
+    r#type: String,
+    card: &'a Card,
+}
+
+impl<'a> CardMessage<'a>{
+    pub fn new(card: &'a Card) -> Self {
+        Self{
+            r#type: "card".to_string(),
+            card,
+        }
+    }
+}
+
+/// Confirms to the host that a card was assigned to a connection.
+#[derive(serde::Serialize)]
+pub struct CardAssignedMessage{
+    r#type: String,
+    client_id: SessionId,
+    card_id: CardId,
+}
+
+impl CardAssignedMessage{
+    pub fn new(client_id: SessionId, card_id: CardId) -> Self {
+        Self{
+            r#type: "card_assigned".to_string(),
+            client_id,
+            card_id,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct NewCardRequest{
+    /// Card to trade in, the most recent card when missing.
+    pub card_id: Option<CardId>,
+}
+
+/// Replacement for a traded in card, the voided card no longer counts for claims.
+#[derive(serde::Serialize)]
+pub struct CardTradedMessage<'a>{
+    r#type: String,
+    client_id: SessionId,
+    voided_card_id: CardId,
+    card: &'a Card,
+    /// Trade-ins left this round.
+    remaining: u32,
+}
+
+impl<'a> CardTradedMessage<'a>{
+    pub fn new(client_id: SessionId, voided_card_id: CardId, card: &'a Card, remaining: u32) -> Self {
+        Self{
+            r#type: "card_traded".to_string(),
+            client_id,
+            voided_card_id,
+            card,
+            remaining,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ClaimResultMessage{
+    r#type: String,
+    client_id: SessionId,
+    /// Card that won the main game.
+    card_id: Option<CardId>,
+    /// Secondary prizes awarded with this claim.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    prizes: Vec<Pattern>,
+    /// Calls made when the claim was checked.
+    calls: usize,
+    /// The main game win was within the jackpot call limit.
+    jackpot: bool,
+    /// The main game win was completed on a bonus ball.
+    bonus: bool,
+    valid: bool,
+}
+
+impl ClaimResultMessage{
+    pub fn new(client_id: SessionId, card_id: Option<CardId>, prizes: Vec<Pattern>, calls: usize, jackpot: bool, bonus: bool) -> Self {
+        Self{
+            r#type: "claim_result".to_string(),
+            client_id,
+            card_id,
+            valid: card_id.is_some() || !prizes.is_empty(),
+            prizes,
+            calls,
+            jackpot,
+            bonus,
+        }
+    }
+}
+
+/// Cards of the player the server marked a call on, speed rounds move too fast to daub by hand.
+#[derive(serde::Serialize)]
+pub struct DaubMessage{
+    r#type: String,
+    number: Number,
+    card_ids: Vec<CardId>,
+}
+
+impl DaubMessage{
+    pub fn new(number: Number, card_ids: Vec<CardId>) -> Self {
+        Self{
+            r#type: "daub".to_string(),
+            number,
+            card_ids,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CardStatus{
+    card_id: CardId,
+    numbers_to_go: usize,
+}
+
+/// Recomputed standing of a player's cards, sent when a call is undone.
+#[derive(serde::Serialize)]
+pub struct CardStatusMessage{
+    r#type: String,
+    cards: Vec<CardStatus>,
+}
+
+impl CardStatusMessage{
+    pub fn new(cards: &[Card], called: &[Number]) -> Self {
+        Self{
+            r#type: "card_status".to_string(),
+            cards: cards.iter().map(|card| CardStatus{ card_id: card.id, numbers_to_go: card.numbers_to_go(called) }).collect(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct VerifyClaimCodeRequest{
+    pub code: String,
+}
+
+impl VerifyClaimCodeRequest{
+    pub fn validate(&self) -> Result<(), String> {
+        if normalize_claim_code(&self.code).len() != CLAIM_CODE_LENGTH{
+            return Err(format!("Claim codes have {} characters", CLAIM_CODE_LENGTH));
+        }
+        Ok(())
+    }
+}
+
+/// A card looked up by its claim code and checked against the calls, for the host to confirm a win
+/// of a player without a device.
+#[derive(serde::Serialize)]
+pub struct ClaimCodeResultMessage{
+    r#type: String,
+    code: String,
+    /// Unset when no card of the room has the code.
+    card_id: Option<CardId>,
+    client_id: Option<SessionId>,
+    /// Patterns covered by the calls so far.
+    patterns: Vec<Pattern>,
+    numbers_to_go: Option<usize>,
+    calls: usize,
+}
+
+impl ClaimCodeResultMessage{
+    pub fn new(code: String, found: Option<(SessionId, &Card)>, called: &[Number]) -> Self {
+        Self{
+            r#type: "claim_code_result".to_string(),
+            code,
+            card_id: found.map(|(_, card)| card.id),
+            client_id: found.map(|(client_id, _)| client_id),
+            patterns: found.map(|(_, card)| Pattern::ALL.into_iter().filter(|pattern| card.has_pattern(*pattern, called)).collect()).unwrap_or_default(),
+            numbers_to_go: found.map(|(_, card)| card.numbers_to_go(called)),
+            calls: called.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn dealt_cards_follow_the_variant_layout(){
+        for _ in 0..50{
+            let card = Card::generate(1, &[], GameVariant::Ball90);
+            assert_eq!(card.variant(), GameVariant::Ball90);
+            assert!(card.is_valid_layout(GameVariant::Ball90));
+            assert!(!card.is_valid_layout(GameVariant::Ball75));
+            assert!(card.columns.iter().all(|column| column.iter().filter(|n| **n != FREE_SPACE).is_sorted()));
+            assert!(Card::generate(2, &[], GameVariant::Ball75).is_valid_layout(GameVariant::Ball75));
+        }
+    }
+
+    #[test]
+    fn tickets_win_lines_and_a_full_house(){
+        let card = Card::generate(1, &[], GameVariant::Ball90);
+        let row = |row: usize| -> Vec<Number> { card.columns.iter().map(|column| column[row]).filter(|n| *n != FREE_SPACE).collect() };
+        let (first, second) = (row(0), row(1));
+        let two_rows: Vec<Number> = first.iter().chain(&second).copied().collect();
+
+        assert!(card.has_pattern(Pattern::Line, &first));
+        assert!(!card.has_pattern(Pattern::TwoLines, &first));
+        assert!(card.has_pattern(Pattern::TwoLines, &two_rows));
+        assert!(!card.has_pattern(Pattern::FourCorners, &two_rows));
+        // The main game of 90-ball is a full house
+        assert!(!card.has_bingo(&two_rows));
+        assert_eq!(card.numbers_to_go(&two_rows), 5);
+        assert!(card.has_bingo(&GameVariant::Ball90.numbers().collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn the_winning_number_is_the_call_that_completed_the_card(){
+        let card = Card::generate(1, &[], GameVariant::Ball90);
+        let mut called: Vec<Number> = card.columns.iter().flatten().copied().filter(|n| *n != FREE_SPACE).collect();
+        let last = *called.last().unwrap();
+        assert_eq!(card.winning_number(&called[..called.len() - 1]), None);
+        // Numbers called after the full house don't move the winning call
+        called.extend(GameVariant::Ball90.numbers().filter(|n| !card.contains(*n)).take(3));
+        assert_eq!(card.winning_number(&called), Some(last));
+    }
+
+    #[test]
+    fn cards_from_a_small_pool_only_need_pool_numbers(){
+        let pool: Vec<Number> = (1..=50).collect();
+        for variant in [GameVariant::Ball75, GameVariant::Ball90]{
+            for _ in 0..50{
+                let card = Card::generate(1, &pool, variant);
+                assert!(card.columns.iter().flatten().all(|number| *number == FREE_SPACE || pool.contains(number)));
+                // Blank cells alone never win
+                assert!(!card.has_bingo(&[]));
+                assert!(Pattern::ALL.iter().all(|pattern| !card.has_pattern(*pattern, &[])));
+                assert!(card.numbers_to_go(&[]) > 0);
+                assert!(card.has_bingo(&pool));
+                assert_eq!(card.numbers_to_go(&pool), 0);
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use rand::{rng, Rng as _};
+use serde::{Deserialize, Serialize};
+
+use crate::room::SessionId;
+
+/// Longest delay that can be added to player messages.
+const MAX_LATENCY_MS: u64 = 10_000;
+
+/// Faults injected into the player traffic of a room, so the frontend's reconnection and resync logic can be
+/// exercised. Only operators, or hosts of rooms with the `chaos_mode` feature, can turn it on.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct ChaosSettings{
+    /// Delay added to every message sent to a player.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Share of the messages to each player that is dropped, 0 to 100.
+    #[serde(default)]
+    pub drop_percent: u8,
+}
+
+impl ChaosSettings{
+    pub fn validate(&self) -> Result<(), String> {
+        if self.latency_ms > MAX_LATENCY_MS{
+            return Err(format!("Latency can be at most {} milliseconds", MAX_LATENCY_MS));
+        }
+        if self.drop_percent > 100{
+            return Err("Drop rate must be between 0 and 100 percent".to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.latency_ms > 0 || self.drop_percent > 0
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        (self.latency_ms > 0).then(|| Duration::from_millis(self.latency_ms))
+    }
+
+    /// Rolls whether the next message is dropped.
+    pub fn should_drop(&self) -> bool {
+        self.drop_percent > 0 && rng().random_range(0..100) < self.drop_percent
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChaosRequest{
+    #[serde(flatten)]
+    pub settings: ChaosSettings,
+    /// Player sockets to close right away, as if their network dropped.
+    #[serde(default)]
+    pub disconnect: Vec<SessionId>,
+    /// Close every player socket of the room.
+    #[serde(default)]
+    pub disconnect_all: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChaosResult{
+    #[serde(flatten)]
+    pub settings: ChaosSettings,
+    /// Player sockets that were closed.
+    pub disconnected: usize,
+}
+
+/// Tells the hosts which faults are injected, sent whenever chaos mode changes.
+#[derive(Serialize)]
+pub struct ChaosMessage<'a>{
+    r#type: String,
+    #[serde(flatten)]
+    result: &'a ChaosResult,
+}
+
+impl<'a> ChaosMessage<'a>{
+    pub fn new(result: &'a ChaosResult) -> Self {
+        Self{
+            r#type: "chaos".to_string(),
+            result,
+        }
+    }
+}
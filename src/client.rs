@@ -1,54 +1,253 @@
-use actix_web::{web, get, Error, HttpRequest, HttpResponse};
-use tokio::task::spawn_local;
+use std::time::Duration;
 
-use crate::{room::{BingoServerHandle, RoomId, USER_CLIENT}, wshandler::{ws_handler, CommandHandler, ErrorMessage}};
+use actix_web::{web, get, http::header, Error, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use tokio::{task::spawn_local, time::timeout};
 
+use crate::{accessibility::{MessageFormat, SocketFormat}, card::NewCardRequest, config::WebSocketConfig, drain::maintenance_error, email::SetEmailRequest, players::{clean_display_name, generate_player_token, is_valid_player_token, load_preferences, update_preferences, validate_preferences, PlayerIdentity, Preferences, PlayerMessage, PreferencesMessage, SetPreferencesRequest, MAX_PREFERENCES}, room::{BingoServerHandle, RoomId, SessionId, UserType}, seats::Seat, sound_check::SoundCheckAck, subscription::SubscribeRequest, tickets::{redeem_ticket, requires_ticket}, versions::{ClientInfo, OutdatedClientMessage, VersionPolicy}, wshandler::{ws_handler, CommandHandler, ErrorMessage, WSMessage}};
 
+
+/// How long joining waits for the stored preferences before the player starts with the defaults.
+const PREFERENCES_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Applies a preference change and echoes the merged preferences back to the connection.
+async fn set_preferences(room: RoomId, server: &BingoServerHandle, database: &sqlx::PgPool, player_token: &str, conn: SessionId, request: SetPreferencesRequest) {
+    let response = match validate_preferences(&request.preferences) {
+        Ok(()) => match update_preferences(database, player_token, &request.preferences).await {
+            Ok(Some(preferences)) => serde_json::to_string(&PreferencesMessage::new(preferences)).unwrap(),
+            Ok(None) => ErrorMessage::new(format!("At most {} preferences can be stored", MAX_PREFERENCES)).to_string(),
+            Err(e) => {
+                log::error!("Failed to save preferences of player {}: {}", &player_token[..8], e);
+                ErrorMessage::new("Failed to save preferences".to_owned()).to_string()
+            }
+        },
+        Err(reason) => ErrorMessage::new(reason).to_string(),
+    };
+    server.send(room, conn, response).await;
+}
+
+/// Applies a message of a player socket, returns why a message was dropped or refused.
 pub async fn client_command_handler(
     room: RoomId,
     server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+    player_token: String,
+    versions: VersionPolicy,
+    conn: SessionId,
     msg: String
-) {
-    server.update(room, msg, USER_CLIENT).await;
+) -> Result<(), String> {
+    let message_type = serde_json::from_str::<WSMessage>(&msg)
+        .map(|message| message.r#type)
+        .unwrap_or_default();
+
+    match message_type.as_str() {
+        "request_card" => server.request_card(room, conn).await,
+        "claim_bingo" => server.claim_bingo(room, conn).await,
+        "new_card" => match serde_json::from_str::<NewCardRequest>(&msg) {
+            Ok(request) => server.new_card(room, conn, request.card_id).await,
+            Err(e) => return Err(format!("Invalid new_card message: {}", e)),
+        },
+        "subscribe" => match serde_json::from_str::<SubscribeRequest>(&msg) {
+            Ok(request) => server.subscribe(room, conn, request.channels).await,
+            Err(e) => return Err(format!("Invalid subscribe message: {}", e)),
+        },
+        "claim_seat" => match serde_json::from_str::<Seat>(&msg) {
+            Ok(seat) => server.take_seat(room, conn, seat, false).await,
+            Err(e) => return Err(format!("Invalid claim_seat message: {}", e)),
+        },
+        "client_info" => match serde_json::from_str::<ClientInfo>(&msg) {
+            Ok(info) => server.client_info(room, conn, info, versions).await,
+            Err(e) => return Err(format!("Invalid client_info message: {}", e)),
+        },
+        "sound_check_ack" => match serde_json::from_str::<SoundCheckAck>(&msg) {
+            Ok(ack) => server.sound_check_ack(room, conn, ack.id).await,
+            Err(e) => return Err(format!("Invalid sound_check_ack message: {}", e)),
+        },
+        "set_preferences" => match serde_json::from_str::<SetPreferencesRequest>(&msg) {
+            Ok(request) => set_preferences(room, &server, &database, &player_token, conn, request).await,
+            Err(e) => return Err(format!("Invalid set_preferences message: {}", e)),
+        },
+        "set_email" => match serde_json::from_str::<SetEmailRequest>(&msg) {
+            Ok(request) => match request.validate() {
+                Ok(()) => server.set_player_email(room, conn, request.email).await,
+                Err(error) => {
+                    server.send(room, conn, ErrorMessage::new(error.clone()).to_string()).await;
+                    return Err(error);
+                }
+            },
+            Err(e) => return Err(format!("Invalid set_email message: {}", e)),
+        },
+        _ => server.update(room, Some(conn), msg, UserType::Client).await,
+    }
+    Ok(())
 }
 
 fn create_command_handler(
     room: RoomId,
-    server: web::Data<BingoServerHandle>
+    server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+    player_token: String,
+    versions: VersionPolicy,
 ) -> CommandHandler {
-    Box::new(move |msg| Box::pin({
+    Box::new(move |conn, msg| Box::pin({
     let value = server.clone();
-    async move { client_command_handler(room, value, msg).await }
+    let database = database.clone();
+    let player_token = player_token.clone();
+    async move {
+        if let Err(e) = client_command_handler(room, value, database, player_token, versions, conn, msg).await{
+            log::warn!("Dropped a message of client {} in room {}: {}", conn, room, e);
+        }
+    }
     }))
 }
 
+#[derive(Deserialize)]
+struct JoinQuery {
+    /// Token from an earlier visit, a new one is issued when missing.
+    player_token: Option<String>,
+    name: Option<String>,
+    ticket: Option<String>,
+    /// One-time ticket from `/ws-ticket`, the ticket code was already checked when it was issued.
+    ws_ticket: Option<String>,
+    /// Reported again in the `client_info` message once connected.
+    app_version: Option<String>,
+    platform: Option<String>,
+    /// `plain` for simplified text events, e.g. for screen readers.
+    #[serde(default)]
+    format: MessageFormat,
+}
+
 #[get("/join/{room}")]
 async fn join(
     req: HttpRequest,
     payload: web::Payload,
     path: web::Path<(RoomId,)>,
+    query: web::Query<JoinQuery>,
     server: web::Data<BingoServerHandle>,
-    _datebase: web::Data<sqlx::PgPool>,
+    ws_config: web::Data<WebSocketConfig>,
+    datebase: web::Data<sqlx::PgPool>,
 ) -> Result<HttpResponse, Error> {
     let  (res, mut session, msg_stream ) = actix_ws::handle(&req, payload)?;
 
+    if server.is_draining().await {
+        return Err(maintenance_error());
+    }
+
     //Validate that the room exists
-    if !server.room_exists(path.0).await {
+    let Some(variant) = server.room_variant(path.0).await else {
         log::info!("Room not found {}", path.0);
-        let _ = session.text(ErrorMessage::new("Room not found".to_owned()).to_string());
+        let _ = session.text(ErrorMessage::new("Room not found".to_owned()).to_string()).await;
         return Err(actix_web::error::ErrorNotFound("Room not found"));
+    };
+
+    // Private rooms only admit players from the host's own sites, on top of the global CORS settings
+    let origin = req.headers().get(header::ORIGIN).and_then(|origin| origin.to_str().ok()).map(str::to_owned);
+    if !server.origin_allowed(path.0, origin.clone()).await {
+        log::info!("Rejected join to room {} from origin {:?}", path.0, origin);
+        return Err(actix_web::error::ErrorForbidden("This room does not accept players from this site"));
+    }
+
+    // A ws_ticket issued for a ticket holder carries the player token the ticket was bound to
+    let mut ticket_player = None;
+    if let Some(ticket) = &query.ws_ticket {
+        match server.redeem_ws_ticket(path.0, ticket.clone(), UserType::Client).await {
+            Some(identity) => ticket_player = Some(identity).filter(|identity| !identity.is_empty()),
+            None => return Err(actix_web::error::ErrorUnauthorized("Invalid or expired ws_ticket")),
+        }
+    }
+    let player_token = ticket_player
+        .or_else(|| query.player_token.clone())
+        .filter(|token| is_valid_player_token(token))
+        .unwrap_or_else(generate_player_token);
+
+    //Validate the ticket code when the room only admits ticket holders
+    let require_ticket = requires_ticket(&datebase, path.0).await.map_err(|e| {
+        log::error!("Failed to look up ticket requirement for room {}: {}", path.0, e);
+        actix_web::error::ErrorInternalServerError("Failed to validate ticket")
+    })?;
+    if require_ticket && query.ws_ticket.is_none() {
+        let redeemed = match &query.ticket {
+            Some(code) => redeem_ticket(&datebase, path.0, code, &player_token).await.map_err(|e| {
+                log::error!("Failed to redeem ticket for room {}: {}", path.0, e);
+                actix_web::error::ErrorInternalServerError("Failed to validate ticket")
+            })?,
+            None => false,
+        };
+        if !redeemed {
+            log::info!("Rejected join to room {} without a valid ticket", path.0);
+            return Err(actix_web::error::ErrorForbidden("A valid, unused ticket code is required"));
+        }
+    }
+
+    let client = ClientInfo{ app_version: query.app_version.clone(), platform: query.platform.clone() };
+    let outdated = ws_config.versions.is_outdated(&client);
+    if outdated && ws_config.versions.reject_outdated {
+        log::info!("Rejected outdated client {:?} joining room {}", client, path.0);
+        return Err(actix_web::error::ErrorUpgradeRequired("Client version is no longer supported, please reload the app"));
+    }
+
+    let ip = req.connection_info().realip_remote_addr().map(str::to_owned);
+    let name = query.name.as_deref().and_then(clean_display_name);
+    server.record_player(path.0, player_token.clone(), name.clone(), ip).await;
+    let format = SocketFormat{ format: query.format, variant };
+    if let Some(player) = format.render(&serde_json::to_string(&PlayerMessage::new(player_token.clone())).unwrap()) {
+        let _ = session.text(player.into_owned()).await;
+    }
+    if outdated {
+        if let Some(warning) = format.render(&serde_json::to_string(&OutdatedClientMessage::new(&ws_config.versions)).unwrap()) {
+            let _ = session.text(warning.into_owned()).await;
+        }
+    }
+    // A slow database doesn't hold up the join, the player starts with the defaults
+    let preferences = match timeout(PREFERENCES_TIMEOUT, load_preferences(&datebase, &player_token)).await {
+        Ok(Ok(preferences)) => preferences,
+        Ok(Err(e)) => {
+            log::error!("Failed to load preferences of player {}: {}", &player_token[..8], e);
+            Preferences::default()
+        }
+        Err(_) => {
+            log::warn!("Timed out loading preferences of player {}", &player_token[..8]);
+            Preferences::default()
+        }
+    };
+    if let Some(preferences) = format.render(&serde_json::to_string(&PreferencesMessage::new(preferences)).unwrap()) {
+        let _ = session.text(preferences.into_owned()).await;
     }
 
     log::info!("Client is joining room {}", path.0);
     // spawn websocket handler (and don't await it) so that the response is returned immediately
     spawn_local(ws_handler(
         server.clone(),
+        **ws_config,
+        format,
         path.0,
-        USER_CLIENT,
-        create_command_handler(path.0, server),
+        UserType::Client,
+        Some(PlayerIdentity{ token: player_token.clone(), name }),
+        create_command_handler(path.0, server, datebase, player_token, ws_config.versions),
         session,
         msg_stream,
     ));
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod tests{
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::{players::PlayerIdentity, room::UserType, variant::GameVariant, vectors::check_inbound};
+
+    #[tokio::test]
+    async fn client_messages_match_vectors(){
+        let (server, database) = BingoServerHandle::without_database();
+        let (server, database) = (web::Data::new(server), web::Data::new(database));
+        let room = server.create_room("vectors".to_owned(), GameVariant::default()).await.unwrap().id;
+        let player = PlayerIdentity{ token: "v".repeat(32), name: None };
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let conn = server.connect(room, tx, UserType::Client, Some(player.clone())).await.unwrap();
+        check_inbound("client.json", async |_, msg| {
+            client_command_handler(room, server.clone(), database.clone(), player.token.clone(), VersionPolicy::default(), conn, msg.to_owned()).await
+        }).await;
+    }
+}
@@ -0,0 +1,489 @@
+use std::{collections::BTreeMap, fmt::Display, net::SocketAddr, path::PathBuf, str::FromStr, time::Duration};
+
+use actix_web::cookie::SameSite;
+use anyhow::{anyhow, bail};
+#[cfg(feature = "shuttle")]
+use shuttle_runtime::SecretStore;
+
+use crate::admin::AdminConfig;
+use crate::analytics::{AnalyticsConfig, AnalyticsSinkConfig};
+use crate::archive::ArchiveConfig;
+use crate::cors::{CorsConfig, DEFAULT_ALLOWED_ORIGINS};
+use crate::crypto::Keyring;
+use crate::email::MailConfig;
+use crate::mqtt::MqttConfig;
+use crate::object_store::ObjectStoreConfig;
+use crate::sms::SmsConfig;
+use crate::versions::VersionPolicy;
+
+/// Deployment profile, selects the defaults used for every setting that is not configured explicitly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile{
+    Dev,
+    Staging,
+    Prod,
+}
+
+impl FromStr for Profile{
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "dev" | "development" => Ok(Profile::Dev),
+            "staging" => Ok(Profile::Staging),
+            "prod" | "production" => Ok(Profile::Prod),
+            _ => Err(anyhow!("Unknown profile {}", value)),
+        }
+    }
+}
+
+/// SameSite policy of the session cookie, selected with `COOKIE_SAME_SITE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CookieSameSite{
+    /// Cross-site frontend, the cookie is sent with credentialed CORS requests.
+    None,
+    /// Same-origin frontend.
+    Lax,
+    Strict,
+}
+
+impl FromStr for CookieSameSite{
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "none" => Ok(CookieSameSite::None),
+            "lax" => Ok(CookieSameSite::Lax),
+            "strict" => Ok(CookieSameSite::Strict),
+            _ => Err(anyhow!("Unknown SameSite policy {}", value)),
+        }
+    }
+}
+
+impl CookieSameSite{
+    pub fn same_site(self) -> SameSite {
+        match self {
+            CookieSameSite::None => SameSite::None,
+            CookieSameSite::Lax => SameSite::Lax,
+            CookieSameSite::Strict => SameSite::Strict,
+        }
+    }
+
+    /// Same-origin frontends don't need credentialed cross origin requests.
+    pub fn is_same_origin(self) -> bool {
+        self != CookieSameSite::None
+    }
+}
+
+/// Tunables of the websocket connections.
+#[derive(Debug, Clone, Copy)]
+pub struct WebSocketConfig{
+    pub heartbeat_interval: Duration,
+    /// Connections without a ping/pong for this long are closed.
+    pub client_timeout: Duration,
+    pub max_frame_size: usize,
+    pub max_continuation_size: usize,
+    pub versions: VersionPolicy,
+}
+
+/// Tunables of the room server.
+#[derive(Debug, Clone, Copy)]
+pub struct RoomConfig{
+    /// How often batched join/leave notifications are flushed to the host.
+    pub presence_flush_interval: Duration,
+    /// Lifetime of the one-time tickets issued by `/ws-ticket`.
+    pub ws_ticket_ttl: Duration,
+    /// Aggregate messages per second a room may relay before chat and reactions are dropped, 0 disables the limit.
+    pub max_messages_per_sec: u32,
+    /// Simultaneous host sockets per room token, 0 disables the limit.
+    pub max_host_connections: usize,
+    /// Queued commands from which player chat, reactions and statistics queries are shed, 0 disables shedding.
+    pub max_command_backlog: usize,
+    /// How long a player socket that timed out can be resumed under the same ID, 0 disables fast resume.
+    /// Hosts can override it per room.
+    pub resume_grace: Duration,
+    /// Whether email addresses and phone numbers are removed from chat before it is relayed or stored.
+    /// Hosts can override it per room.
+    pub redact_chat: bool,
+}
+
+/// Thresholds for switching non-critical database writes to memory-only operation.
+#[derive(Debug, Clone, Copy)]
+pub struct PersistenceConfig{
+    /// Writes slower than this count against the database.
+    pub latency_threshold: Duration,
+    /// Writes still running after this long are abandoned and retried.
+    pub write_timeout: Duration,
+    /// Consecutive slow or failed writes before the writer degrades.
+    pub error_threshold: u32,
+    /// How often a degraded writer probes the database.
+    pub retry_interval: Duration,
+    /// Writes kept in memory while degraded, the oldest are dropped beyond this.
+    pub max_queued_writes: usize,
+    /// Failed executions after which a write is dropped instead of retried.
+    pub max_write_attempts: u32,
+}
+
+/// Backoff and lockout of repeated failed host logins.
+#[derive(Debug, Clone, Copy)]
+pub struct LoginThrottleConfig{
+    /// Failures allowed before the backoff starts.
+    pub free_attempts: u32,
+    /// Wait after the first failure past the free attempts, doubled with every further failure.
+    pub backoff_base: Duration,
+    /// Failures after which the user or address is locked out.
+    pub lockout_threshold: u32,
+    /// Length of a lockout, failures older than this are forgotten.
+    pub lockout: Duration,
+    /// Count failures against the address in `X-Forwarded-For`/`Forwarded` instead of the peer, only
+    /// safe behind a reverse proxy that overwrites those headers.
+    pub trust_forwarded: bool,
+}
+
+/// HTTP chat translation service.
+#[derive(Clone)]
+pub struct TranslatorConfig{
+    pub url: String,
+    pub api_key: Option<String>,
+}
+
+impl std::fmt::Debug for TranslatorConfig{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TranslatorConfig").field("url", &self.url).finish()
+    }
+}
+
+/// Host authentication backend, selected with `AUTH_PROVIDER`.
+#[derive(Debug, Clone)]
+pub enum AuthConfig{
+    /// Accounts in the `users` table with argon2 hashed tokens.
+    Password{
+        /// How long looked up accounts and verified tokens are reused before the database is asked again.
+        cache_ttl: Duration,
+    },
+    /// OpenID Connect ID tokens, Google by default.
+    Oidc{
+        issuer: String,
+        client_id: String,
+        jwks_url: String,
+        allowed_users: Vec<String>,
+    },
+    /// GitHub OAuth access tokens.
+    Github{
+        allowed_users: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Config{
+    pub profile: Profile,
+    pub websocket: WebSocketConfig,
+    pub rooms: RoomConfig,
+    pub cors: CorsConfig,
+    pub auth: AuthConfig,
+    pub persistence: PersistenceConfig,
+    pub login: LoginThrottleConfig,
+    pub admin: AdminConfig,
+    /// Keys for room data persisted at rest, from `ENCRYPTION_KEY` and `ENCRYPTION_PREVIOUS_KEYS`.
+    pub encryption: Keyring,
+    /// Translates chat to the room language when set.
+    pub translator: Option<TranslatorConfig>,
+    /// Twilio account used to text calls to players without a smartphone.
+    pub sms: Option<SmsConfig>,
+    /// Broker that room events are mirrored to for venue hardware.
+    pub mqtt: Option<MqttConfig>,
+    /// SendGrid account used to email reports and prize-claim instructions.
+    pub mail: Option<MailConfig>,
+    /// Sink of the anonymized room events, disabled when `ANALYTICS_SINK` is unset.
+    pub analytics: Option<AnalyticsConfig>,
+    /// Bucket the reports and event logs of closed rooms are uploaded to.
+    pub archive: Option<ArchiveConfig>,
+    /// Address of the gRPC control plane, disabled when unset.
+    pub grpc_addr: Option<SocketAddr>,
+    /// Directory of the web client, served from the same origin as the API when set.
+    pub static_dir: Option<PathBuf>,
+    /// Lax by default when the web client is served by this server, None otherwise.
+    pub cookie_same_site: CookieSameSite,
+    /// Only send the session cookie over HTTPS, turned off for self-hosted servers on plain HTTP.
+    pub cookie_secure: bool,
+    /// Lifetime of the host login session.
+    pub session_ttl: Duration,
+    /// How often the overrides in the `config` table are checked for changes.
+    pub config_poll_interval: Duration,
+}
+
+/// Raw values the configuration is parsed from, the Shuttle secrets or the settings file of a self-hosted
+/// server. Not `Debug`, the values include tokens and keys.
+#[derive(Default)]
+pub struct Settings{
+    values: BTreeMap<String, String>,
+}
+
+impl Settings{
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.values.get(key).cloned()
+    }
+
+    /// Reads `KEY = "value"` lines as in `Secrets.toml`, unquoted `KEY=value` lines of an env file work too.
+    #[cfg(feature = "self-hosted")]
+    pub fn from_file(path: &std::path::Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let mut values = BTreeMap::new();
+        for (index, line) in text.lines().enumerate(){
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#'){
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                bail!("Line {} of {} is not a KEY = value setting", index + 1, path.display());
+            };
+            let value = value.trim();
+            let unquoted = value.strip_prefix('"').and_then(|value| value.strip_suffix('"'))
+                .or_else(|| value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')))
+                .unwrap_or(value);
+            values.insert(key.trim().to_owned(), unquoted.to_owned());
+        }
+        Ok(Self{ values })
+    }
+}
+
+#[cfg(feature = "shuttle")]
+impl From<SecretStore> for Settings{
+    fn from(secrets: SecretStore) -> Self {
+        Self{ values: secrets.into_iter().collect() }
+    }
+}
+
+/// Looks up a setting in the shuttle secrets or the settings file first and the environment second.
+fn lookup(secrets: &Settings, key: &str) -> Option<String> {
+    secrets.get(key).or_else(|| std::env::var(key).ok())
+}
+
+fn parse_or<T>(secrets: &Settings, key: &str, default: T) -> anyhow::Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match lookup(secrets, key) {
+        Some(value) => value.trim().parse::<T>().map_err(|e| anyhow!("Invalid value {} for {}: {}", value, key, e)),
+        None => Ok(default),
+    }
+}
+
+fn parse_optional<T>(secrets: &Settings, key: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    lookup(secrets, key)
+        .map(|value| value.trim().parse::<T>().map_err(|e| anyhow!("Invalid value {} for {}: {}", value, key, e)))
+        .transpose()
+}
+
+fn list(secrets: &Settings, key: &str) -> Option<Vec<String>> {
+    lookup(secrets, key).map(|value| {
+        value.split(',')
+            .map(|item| item.trim().to_owned())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+fn secs_or(secrets: &Settings, key: &str, default: u64) -> anyhow::Result<Duration> {
+    parse_or(secrets, key, default).map(Duration::from_secs)
+}
+
+impl Config{
+    pub fn load(secrets: &Settings) -> anyhow::Result<Self> {
+        let profile = parse_or(secrets, "PROFILE", Profile::Prod)?;
+        let dev = profile == Profile::Dev;
+
+        let websocket = WebSocketConfig{
+            heartbeat_interval: secs_or(secrets, "HEARTBEAT_INTERVAL_SECS", 5)?,
+            // Generous timeout in dev so a paused debugger doesn't drop every connection
+            client_timeout: secs_or(secrets, "CLIENT_TIMEOUT_SECS", if dev { 60 } else { 10 })?,
+            max_frame_size: parse_or(secrets, "WS_MAX_FRAME_SIZE", 128 * 1024)?,
+            max_continuation_size: parse_or(secrets, "WS_MAX_CONTINUATION_SIZE", 2 * 1024 * 1024)?,
+            versions: VersionPolicy{
+                min_version: parse_optional(secrets, "MIN_CLIENT_VERSION")?,
+                reject_outdated: parse_or(secrets, "REJECT_OUTDATED_CLIENTS", false)?,
+            },
+        };
+
+        let rooms = RoomConfig{
+            presence_flush_interval: Duration::from_millis(parse_or(secrets, "PRESENCE_FLUSH_INTERVAL_MS", 1000)?),
+            ws_ticket_ttl: secs_or(secrets, "WS_TICKET_TTL_SECS", 30)?,
+            max_messages_per_sec: parse_or(secrets, "ROOM_MAX_MESSAGES_PER_SEC", 200)?,
+            max_host_connections: parse_or(secrets, "HOST_MAX_CONNECTIONS", 3)?,
+            max_command_backlog: parse_or(secrets, "ROOM_MAX_COMMAND_BACKLOG", 1000)?,
+            resume_grace: secs_or(secrets, "RESUME_GRACE_SECS", 20)?,
+            redact_chat: parse_or(secrets, "REDACT_CHAT_PII", false)?,
+        };
+
+        let static_dir = lookup(secrets, "STATIC_DIR").filter(|dir| !dir.is_empty()).map(PathBuf::from);
+        let cookie_same_site = parse_or(secrets, "COOKIE_SAME_SITE", if static_dir.is_some() { CookieSameSite::Lax } else { CookieSameSite::None })?;
+
+        let cors = CorsConfig{
+            allowed_origins: list(secrets, "CORS_ALLOWED_ORIGINS")
+                .unwrap_or_else(|| DEFAULT_ALLOWED_ORIGINS.iter().map(|origin| origin.to_string()).collect()),
+            dev_mode: parse_or(secrets, "CORS_DEV_MODE", dev)?,
+            supports_credentials: !cookie_same_site.is_same_origin(),
+        };
+
+        let allowed_users = list(secrets, "AUTH_ALLOWED_USERS").unwrap_or_default();
+        let auth = match lookup(secrets, "AUTH_PROVIDER").as_deref() {
+            None | Some("password") => AuthConfig::Password{ cache_ttl: secs_or(secrets, "AUTH_CACHE_TTL_SECS", 300)? },
+            Some("oidc") => AuthConfig::Oidc{
+                issuer: lookup(secrets, "OIDC_ISSUER").unwrap_or_else(|| "https://accounts.google.com".to_owned()),
+                client_id: lookup(secrets, "OIDC_CLIENT_ID").ok_or_else(|| anyhow!("OIDC_CLIENT_ID is required for the oidc provider"))?,
+                jwks_url: lookup(secrets, "OIDC_JWKS_URL").unwrap_or_else(|| "https://www.googleapis.com/oauth2/v3/certs".to_owned()),
+                allowed_users,
+            },
+            Some("github") => AuthConfig::Github{ allowed_users },
+            Some(provider) => bail!("Unknown AUTH_PROVIDER {}", provider),
+        };
+
+        let persistence = PersistenceConfig{
+            latency_threshold: Duration::from_millis(parse_or(secrets, "DB_LATENCY_THRESHOLD_MS", 500)?),
+            write_timeout: secs_or(secrets, "DB_WRITE_TIMEOUT_SECS", 5)?,
+            error_threshold: parse_or(secrets, "DB_ERROR_THRESHOLD", 3)?,
+            retry_interval: secs_or(secrets, "DB_RETRY_INTERVAL_SECS", 10)?,
+            max_queued_writes: parse_or(secrets, "DB_MAX_QUEUED_WRITES", 10_000)?,
+            max_write_attempts: parse_or(secrets, "DB_MAX_WRITE_ATTEMPTS", 5)?,
+        };
+
+        let login = LoginThrottleConfig{
+            free_attempts: parse_or(secrets, "LOGIN_FREE_ATTEMPTS", 3)?,
+            backoff_base: secs_or(secrets, "LOGIN_BACKOFF_BASE_SECS", 1)?,
+            lockout_threshold: parse_or(secrets, "LOGIN_LOCKOUT_THRESHOLD", 10)?,
+            lockout: secs_or(secrets, "LOGIN_LOCKOUT_SECS", 15 * 60)?,
+            trust_forwarded: parse_or(secrets, "LOGIN_TRUST_FORWARDED", false)?,
+        };
+
+        let object_store = match (lookup(secrets, "S3_BUCKET"), lookup(secrets, "S3_ACCESS_KEY_ID"), lookup(secrets, "S3_SECRET_ACCESS_KEY")) {
+            (Some(bucket), Some(access_key_id), Some(secret_access_key)) => {
+                let region = lookup(secrets, "S3_REGION").unwrap_or_else(|| "us-east-1".to_owned());
+                let endpoint = lookup(secrets, "S3_ENDPOINT").unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+                Some(ObjectStoreConfig{ endpoint, region, bucket, access_key_id, secret_access_key })
+            }
+            _ => None,
+        };
+
+        let analytics_url = || lookup(secrets, "ANALYTICS_URL").ok_or_else(|| anyhow!("ANALYTICS_URL is required for the analytics sink"));
+        let analytics_sink = match lookup(secrets, "ANALYTICS_SINK").as_deref() {
+            None | Some("") => None,
+            Some("http") => Some(AnalyticsSinkConfig::Http{ url: analytics_url()?, token: lookup(secrets, "ANALYTICS_TOKEN") }),
+            Some("clickhouse") => Some(AnalyticsSinkConfig::ClickHouse{
+                url: analytics_url()?,
+                table: lookup(secrets, "ANALYTICS_CLICKHOUSE_TABLE").unwrap_or_else(|| "bingo_events".to_owned()),
+                user: lookup(secrets, "ANALYTICS_CLICKHOUSE_USER"),
+                password: lookup(secrets, "ANALYTICS_CLICKHOUSE_PASSWORD"),
+            }),
+            Some("s3") => Some(AnalyticsSinkConfig::S3{
+                store: object_store.clone().ok_or_else(|| anyhow!("S3_BUCKET, S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY are required for the s3 analytics sink"))?,
+                prefix: lookup(secrets, "ANALYTICS_S3_PREFIX").unwrap_or_else(|| "analytics".to_owned()),
+            }),
+            Some(sink) => bail!("Unknown ANALYTICS_SINK {}", sink),
+        };
+        let analytics = match analytics_sink {
+            Some(sink) => Some(AnalyticsConfig{
+                sink,
+                salt: lookup(secrets, "ANALYTICS_SALT").filter(|salt| !salt.is_empty()).ok_or_else(|| anyhow!("ANALYTICS_SALT is required to anonymize analytics events"))?,
+                batch_size: parse_or(secrets, "ANALYTICS_BATCH_SIZE", 500)?,
+                flush_interval: secs_or(secrets, "ANALYTICS_FLUSH_INTERVAL_SECS", 30)?,
+            }),
+            None => None,
+        };
+
+        let archive = match parse_or(secrets, "ARCHIVE_REPORTS", false)? {
+            true => Some(ArchiveConfig{
+                store: object_store.clone().ok_or_else(|| anyhow!("S3_BUCKET, S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY are required to archive reports"))?,
+                prefix: lookup(secrets, "ARCHIVE_S3_PREFIX").unwrap_or_else(|| "rooms".to_owned()),
+                prune: parse_or(secrets, "ARCHIVE_PRUNE_ROWS", false)?,
+            }),
+            false => None,
+        };
+
+        let config = Self{
+            profile,
+            websocket,
+            rooms,
+            cors,
+            auth,
+            persistence,
+            login,
+            admin: AdminConfig{ token: lookup(secrets, "ADMIN_TOKEN").filter(|token| !token.is_empty()) },
+            translator: lookup(secrets, "TRANSLATOR_URL").map(|url| TranslatorConfig{ url, api_key: lookup(secrets, "TRANSLATOR_API_KEY") }),
+            sms: match (lookup(secrets, "TWILIO_ACCOUNT_SID"), lookup(secrets, "TWILIO_AUTH_TOKEN"), lookup(secrets, "TWILIO_FROM_NUMBER")) {
+                (Some(account_sid), Some(auth_token), Some(from)) => Some(SmsConfig{ account_sid, auth_token, from, daily_budget: parse_or(secrets, "SMS_DAILY_BUDGET", 500)? }),
+                _ => None,
+            },
+            mail: match (lookup(secrets, "SENDGRID_API_KEY"), lookup(secrets, "MAIL_FROM")) {
+                (Some(api_key), Some(from)) => Some(MailConfig{ api_key, from }),
+                _ => None,
+            },
+            mqtt: lookup(secrets, "MQTT_URL").map(|url| MqttConfig{ url, topic_prefix: lookup(secrets, "MQTT_TOPIC_PREFIX").unwrap_or_else(|| "bingo".to_owned()) }),
+            analytics,
+            archive,
+            grpc_addr: parse_optional(secrets, "GRPC_ADDR")?,
+            static_dir,
+            cookie_same_site,
+            cookie_secure: parse_or(secrets, "COOKIE_SECURE", true)?,
+            encryption: Keyring::parse(lookup(secrets, "ENCRYPTION_KEY"), list(secrets, "ENCRYPTION_PREVIOUS_KEYS").unwrap_or_default())?,
+            session_ttl: secs_or(secrets, "SESSION_TTL_SECS", 5 * 60)?,
+            config_poll_interval: secs_or(secrets, "CONFIG_POLL_INTERVAL_SECS", 30)?,
+        };
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.websocket.heartbeat_interval.is_zero(){
+            bail!("HEARTBEAT_INTERVAL_SECS must be greater than zero");
+        }
+        if self.websocket.client_timeout <= self.websocket.heartbeat_interval{
+            bail!("CLIENT_TIMEOUT_SECS must be greater than HEARTBEAT_INTERVAL_SECS");
+        }
+        if self.websocket.max_frame_size == 0 || self.websocket.max_continuation_size < self.websocket.max_frame_size{
+            bail!("WS_MAX_CONTINUATION_SIZE must be at least WS_MAX_FRAME_SIZE and both greater than zero");
+        }
+        if self.rooms.presence_flush_interval.is_zero(){
+            bail!("PRESENCE_FLUSH_INTERVAL_MS must be greater than zero");
+        }
+        if self.rooms.ws_ticket_ttl.is_zero(){
+            bail!("WS_TICKET_TTL_SECS must be greater than zero");
+        }
+        if self.persistence.error_threshold == 0 || self.persistence.retry_interval.is_zero() || self.persistence.write_timeout.is_zero() || self.persistence.max_write_attempts == 0{
+            bail!("DB_ERROR_THRESHOLD, DB_RETRY_INTERVAL_SECS, DB_WRITE_TIMEOUT_SECS and DB_MAX_WRITE_ATTEMPTS must be greater than zero");
+        }
+        if self.login.lockout_threshold <= self.login.free_attempts || self.login.lockout.is_zero(){
+            bail!("LOGIN_LOCKOUT_THRESHOLD must be greater than LOGIN_FREE_ATTEMPTS and LOGIN_LOCKOUT_SECS greater than zero");
+        }
+        if let Some(dir) = self.static_dir.as_ref().filter(|dir| !dir.is_dir()){
+            bail!("STATIC_DIR {} is not a directory", dir.display());
+        }
+        if self.session_ttl.is_zero(){
+            bail!("SESSION_TTL_SECS must be greater than zero");
+        }
+        if !self.cors.dev_mode && self.cors.allowed_origins.is_empty(){
+            bail!("CORS_ALLOWED_ORIGINS must contain at least one origin outside of dev mode");
+        }
+        match &self.auth {
+            AuthConfig::Oidc { allowed_users, .. } | AuthConfig::Github { allowed_users } if allowed_users.is_empty() => {
+                bail!("AUTH_ALLOWED_USERS must list at least one user for external auth providers");
+            }
+            _ => {}
+        }
+        if !self.encryption.is_enabled() && self.profile == Profile::Prod{
+            log::warn!("ENCRYPTION_KEY is not set, room tokens and archived reports are stored in plaintext");
+        }
+        if self.cors.dev_mode && self.profile == Profile::Prod{
+            log::warn!("CORS dev mode is enabled in the prod profile");
+        }
+        if self.analytics.as_ref().is_some_and(|analytics| analytics.batch_size == 0 || analytics.flush_interval.is_zero()){
+            bail!("ANALYTICS_BATCH_SIZE and ANALYTICS_FLUSH_INTERVAL_SECS must be greater than zero");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use std::sync::{Arc, RwLock};
+
+use actix_cors::Cors;
+use actix_web::http;
+
+use crate::api_keys::API_KEY_HEADER;
+
+/// Most origins a host can allow for a single room.
+pub const MAX_ROOM_ORIGINS: usize = 20;
+
+pub const DEFAULT_ALLOWED_ORIGINS: [&str; 3] = [
+    "http://127.0.0.1:5500",
+    "http://10.0.0.199:5500",
+    "https://web2098.github.io",
+];
+
+/// Cross origin settings, loaded as part of the [`Config`](crate::config::Config).
+#[derive(Debug, Clone)]
+pub struct CorsConfig{
+    pub allowed_origins: Vec<String>,
+    /// Accept any origin, only meant for local development.
+    pub dev_mode: bool,
+    /// Allow cookies on cross origin requests, off when the frontend is same-origin.
+    pub supports_credentials: bool,
+}
+
+impl CorsConfig{
+    /// Builds the CORS middleware, preflight (OPTIONS) requests are answered by the middleware itself
+    /// so credentialed POST requests from the allowed origins work without extra routes.
+    pub fn build(&self, runtime: Arc<RuntimeOrigins>) -> Cors {
+        let mut cors = Cors::default();
+
+        if self.dev_mode{
+            // The request origin is echoed back, which keeps credentialed requests working
+            cors = cors.allow_any_origin();
+        }
+        else{
+            for origin in &self.allowed_origins{
+                cors = cors.allowed_origin(origin);
+            }
+            cors = cors.allowed_origin_fn(move |origin, _| origin.to_str().is_ok_and(|origin| runtime.allows(origin)));
+        }
+
+        if self.supports_credentials{
+            cors = cors.supports_credentials();
+        }
+
+        cors.allowed_methods(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+            .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
+            .allowed_header(http::header::CONTENT_TYPE)
+            .allowed_header(API_KEY_HEADER)
+            .max_age(3600)
+    }
+}
+
+/// Origins allowed on top of the configured ones, set from the `config` table while the server runs.
+#[derive(Debug, Default)]
+pub struct RuntimeOrigins{
+    origins: RwLock<Vec<String>>,
+}
+
+impl RuntimeOrigins{
+    pub fn set(&self, origins: Vec<String>){
+        *self.origins.write().unwrap() = origins;
+    }
+
+    pub fn allows(&self, origin: &str) -> bool {
+        self.origins.read().unwrap().iter().any(|allowed| allowed.eq_ignore_ascii_case(origin))
+    }
+}
+
+/// Web origins a host allows to join a room, checked on top of the global CORS settings.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct AllowedOrigins{
+    /// e.g. `https://games.example.com`, any origin may join when empty.
+    pub origins: Vec<String>,
+}
+
+impl AllowedOrigins{
+    /// Lowercases the origins, they must be `scheme://host[:port]` without a path.
+    pub fn normalize(self) -> Result<Self, String> {
+        if self.origins.len() > MAX_ROOM_ORIGINS{
+            return Err(format!("At most {} origins are allowed", MAX_ROOM_ORIGINS));
+        }
+        let mut origins = Vec::with_capacity(self.origins.len());
+        for origin in self.origins{
+            let normalized = origin.trim().trim_end_matches('/').to_lowercase();
+            let host = normalized.strip_prefix("https://").or_else(|| normalized.strip_prefix("http://"));
+            if !host.is_some_and(|host| !host.is_empty() && !host.contains(['/', '?', '#']) && !host.contains(char::is_whitespace)){
+                return Err(format!("Invalid origin {}, use the https://example.com format", origin));
+            }
+            if !origins.contains(&normalized){
+                origins.push(normalized);
+            }
+        }
+        Ok(Self{ origins })
+    }
+
+    /// Restricted rooms only admit browsers sending one of the origins, clients without an Origin header are refused.
+    pub fn allows(&self, origin: Option<&str>) -> bool {
+        self.origins.is_empty() || origin.is_some_and(|origin| self.origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin)))
+    }
+}
+
+/// Confirms the origins of a room to the hosts.
+#[derive(serde::Serialize)]
+pub struct AllowedOriginsMessage<'a>{
+    r#type: String,
+    origins: &'a [String],
+}
+
+impl<'a> AllowedOriginsMessage<'a>{
+    pub fn new(origins: &'a AllowedOrigins) -> Self {
+        Self{
+            r#type: "allowed_origins".to_string(),
+            origins: &origins.origins,
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::fmt;
+
+use aes_gcm::{aead::{Aead, KeyInit, Payload}, Aes256Gcm, Nonce};
+use anyhow::{anyhow, bail};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use hkdf::Hkdf;
+use rand::{rng, Rng as _};
+use sha2::Sha256;
+
+use crate::room::RoomId;
+
+/// Marks values sealed by the keyring, anything else is read back as plaintext.
+const PREFIX: &str = "enc1";
+
+const NONCE_SIZE: usize = 12;
+
+/// Master keys for data persisted per room. Values are sealed with AES-256-GCM under a key derived for
+/// the room, so a value copied to another room's row fails to decrypt. The first key seals new values,
+/// older keys only open existing ones until the rows have been rotated.
+///
+/// Sealed are the room and board tokens in the database and the reports and event logs archived to object
+/// storage. Cards are never persisted, they only live in the room server's memory. Archived objects
+/// aren't rotated, keep a key in `ENCRYPTION_PREVIOUS_KEYS` for as long as objects sealed with it are kept.
+#[derive(Clone, Default)]
+pub struct Keyring{
+    keys: Vec<(String, [u8; 32])>,
+}
+
+impl fmt::Debug for Keyring{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Keyring").field("key_ids", &self.keys.iter().map(|(id, _)| id).collect::<Vec<_>>()).finish()
+    }
+}
+
+/// Parses a `<id>:<64 hex digits>` key.
+fn parse_key(value: &str) -> anyhow::Result<(String, [u8; 32])> {
+    let (id, hex) = value.trim().split_once(':').ok_or_else(|| anyhow!("Encryption keys must look like <id>:<hex key>"))?;
+    if id.is_empty() || id.contains(':'){
+        bail!("Invalid encryption key id {}", id);
+    }
+    if hex.len() != 64{
+        bail!("Encryption key {} must be 32 bytes of hex", id);
+    }
+    let mut key = [0u8; 32];
+    for (index, byte) in key.iter_mut().enumerate(){
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16).map_err(|_| anyhow!("Encryption key {} is not valid hex", id))?;
+    }
+    Ok((id.to_owned(), key))
+}
+
+impl Keyring{
+    pub fn parse(current: Option<String>, previous: Vec<String>) -> anyhow::Result<Self> {
+        let current = match current {
+            Some(current) => parse_key(&current)?,
+            None if previous.is_empty() => return Ok(Self::default()),
+            None => bail!("ENCRYPTION_PREVIOUS_KEYS requires ENCRYPTION_KEY"),
+        };
+        let mut keys = vec![current];
+        for key in previous{
+            keys.push(parse_key(&key)?);
+        }
+        Ok(Self{ keys })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn room_cipher(master: &[u8; 32], room: RoomId) -> Aes256Gcm {
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, master)
+            .expand(format!("bingoserver room {}", room).as_bytes(), &mut key)
+            .unwrap();
+        Aes256Gcm::new(&key.into())
+    }
+
+    /// Seals a value for the room, values are stored as-is while no key is configured.
+    pub fn encrypt(&self, room: RoomId, plaintext: &str) -> String {
+        let (id, master) = match self.keys.first() {
+            Some(key) => key,
+            None => return plaintext.to_owned(),
+        };
+        let nonce = rng().random::<[u8; NONCE_SIZE]>();
+        let aad = room.to_be_bytes();
+        let ciphertext = Self::room_cipher(master, room)
+            .encrypt(Nonce::from_slice(&nonce), Payload{ msg: plaintext.as_bytes(), aad: &aad })
+            .unwrap();
+        format!("{}:{}:{}", PREFIX, id, STANDARD.encode([nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    pub fn decrypt(&self, room: RoomId, value: &str) -> anyhow::Result<String> {
+        let sealed = match value.strip_prefix(PREFIX).and_then(|rest| rest.strip_prefix(':')) {
+            Some(sealed) => sealed,
+            None => return Ok(value.to_owned()),
+        };
+        let (id, data) = sealed.split_once(':').ok_or_else(|| anyhow!("Malformed encrypted value"))?;
+        let (_, master) = self.keys.iter()
+            .find(|(key_id, _)| key_id == id)
+            .ok_or_else(|| anyhow!("Unknown encryption key {}", id))?;
+        let data = STANDARD.decode(data)?;
+        if data.len() < NONCE_SIZE{
+            bail!("Malformed encrypted value");
+        }
+        let (nonce, ciphertext) = data.split_at(NONCE_SIZE);
+        let aad = room.to_be_bytes();
+        let plaintext = Self::room_cipher(master, room)
+            .decrypt(Nonce::from_slice(nonce), Payload{ msg: ciphertext, aad: &aad })
+            .map_err(|_| anyhow!("Failed to decrypt value with key {}", id))?;
+        Ok(String::from_utf8(plaintext)?)
+    }
+
+    /// True when the value is plaintext or sealed with a previous key and should be written again.
+    pub fn needs_rotation(&self, value: &str) -> bool {
+        match self.keys.first() {
+            Some((id, _)) => !value.starts_with(&format!("{}:{}:", PREFIX, id)),
+            None => false,
+        }
+    }
+}
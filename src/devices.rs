@@ -0,0 +1,69 @@
+use crate::room::SessionId;
+
+/// What happens when a player token opens another socket while one is still connected, e.g. a second tab.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateSessionPolicy{
+    /// The newer socket takes over and the older one is closed.
+    #[default]
+    Replace,
+    /// Every socket stays open and shows the same cards, actions of any of them count for the player.
+    Mirror,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DuplicateSessionPolicyRequest{
+    pub policy: DuplicateSessionPolicy,
+}
+
+#[derive(serde::Serialize)]
+pub struct DuplicateSessionPolicyMessage{
+    r#type: String,
+    policy: DuplicateSessionPolicy,
+}
+
+impl DuplicateSessionPolicyMessage{
+    pub fn new(policy: DuplicateSessionPolicy) -> Self {
+        Self{
+            r#type: "duplicate_sessions".to_string(),
+            policy,
+        }
+    }
+}
+
+/// Tells a socket that it mirrors an earlier socket of the same player, its cards follow.
+#[derive(serde::Serialize)]
+pub struct SessionMirroredMessage{
+    r#type: String,
+    /// Connection the cards and claims of the player belong to.
+    client_id: SessionId,
+    devices: usize,
+}
+
+impl SessionMirroredMessage{
+    pub fn new(client_id: SessionId, devices: usize) -> Self {
+        Self{
+            r#type: "session_mirrored".to_string(),
+            client_id,
+            devices,
+        }
+    }
+}
+
+/// Tells the hosts how many sockets a player has open whenever a mirrored socket opens or closes.
+#[derive(serde::Serialize)]
+pub struct PlayerDevicesMessage{
+    r#type: String,
+    client_id: SessionId,
+    devices: usize,
+}
+
+impl PlayerDevicesMessage{
+    pub fn new(client_id: SessionId, devices: usize) -> Self {
+        Self{
+            r#type: "player_devices".to_string(),
+            client_id,
+            devices,
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use actix_web::{error, get, post, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::admin::Admin;
+use crate::room::BingoServerHandle;
+
+/// Body of the 503 responses returned while the server is draining.
+#[derive(serde::Serialize)]
+pub struct MaintenanceMessage{
+    r#type: String,
+    message: String,
+}
+
+impl MaintenanceMessage{
+    pub fn new() -> Self {
+        Self{
+            r#type: "maintenance".to_string(),
+            message: "The server is about to restart for maintenance, please try again shortly".to_string(),
+        }
+    }
+}
+
+pub fn maintenance_error() -> actix_web::Error {
+    error::InternalError::from_response("maintenance", HttpResponse::ServiceUnavailable().json(MaintenanceMessage::new())).into()
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DrainStatus{
+    pub draining: bool,
+    /// Rooms with at least one open socket.
+    pub active_rooms: usize,
+    /// Draining and no room is active anymore, safe to redeploy.
+    pub drained: bool,
+}
+
+#[derive(Deserialize)]
+struct DrainRequest{
+    enabled: bool,
+}
+
+/// Stops new rooms and joins while the running games continue.
+#[post("/admin/drain")]
+async fn set_draining(
+    _admin: Admin,
+    request: web::Json<DrainRequest>,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    server.set_draining(request.enabled).await;
+    HttpResponse::Ok().json(server.drain_status().await)
+}
+
+#[get("/admin/drain")]
+async fn drain_status(
+    _admin: Admin,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(server.drain_status().await)
+}
@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use rand::{rng, Rng as _};
+
+use crate::variant::GameVariant;
+
+pub type Number = u8;
+
+/// Number of recent draw request ids remembered per room for deduplication.
+const MAX_REMEMBERED_REQUESTS: usize = 64;
+
+/// The balls of a room, drawn without replacement.
+#[derive(Debug)]
+pub struct DrawPool{
+    remaining: Vec<Number>,
+    called: Vec<Number>,
+    /// When each number of `called` was called.
+    called_at: Vec<DateTime<Utc>>,
+    /// Recent `(request_id, number)` pairs, so a retried draw request returns the original number.
+    recent_requests: VecDeque<(String, Number)>,
+    /// Numbers are called in a predetermined order instead of randomly.
+    fixed_order: bool,
+}
+
+pub enum DrawResult{
+    Drawn(Number),
+    /// The request id was already handled, holds the number drawn at that time.
+    Duplicate(Number),
+    Exhausted,
+}
+
+pub enum ManualCallResult{
+    Called,
+    OutOfRange,
+    AlreadyCalled,
+}
+
+impl DrawPool{
+    /// Every ball of the variant.
+    pub fn new(variant: GameVariant) -> Self {
+        Self::with_numbers(variant.numbers().collect())
+    }
+
+    /// A pool calling the numbers in the given order, used for provably fair rounds.
+    pub fn with_order(mut order: Vec<Number>) -> Self {
+        order.reverse();
+        Self{
+            fixed_order: true,
+            ..Self::with_numbers(order)
+        }
+    }
+
+    /// A pool of only the given numbers, used for rounds with a custom pool.
+    pub fn with_numbers(numbers: Vec<Number>) -> Self {
+        Self{
+            remaining: numbers,
+            called: Vec::new(),
+            called_at: Vec::new(),
+            recent_requests: VecDeque::new(),
+            fixed_order: false,
+        }
+    }
+
+    pub fn draw(&mut self, request_id: Option<&str>) -> DrawResult {
+        if let Some(request_id) = request_id{
+            if let Some((_, number)) = self.recent_requests.iter().find(|(id, _)| id == request_id){
+                return DrawResult::Duplicate(*number);
+            }
+        }
+
+        if self.remaining.is_empty(){
+            return DrawResult::Exhausted;
+        }
+        let number = if self.fixed_order {
+            self.remaining.pop().unwrap()
+        } else {
+            let index = rng().random_range(0..self.remaining.len());
+            self.remaining.swap_remove(index)
+        };
+        self.called.push(number);
+        self.called_at.push(Utc::now());
+
+        if let Some(request_id) = request_id{
+            if self.recent_requests.len() == MAX_REMEMBERED_REQUESTS{
+                self.recent_requests.pop_front();
+            }
+            self.recent_requests.push_back((request_id.to_owned(), number));
+        }
+        DrawResult::Drawn(number)
+    }
+
+    /// Rebuilds a pool from an exported state. Calls exported without a time count as made at the restore.
+    pub fn restore(remaining: Vec<Number>, called: Vec<Number>, mut called_at: Vec<DateTime<Utc>>, fixed_order: bool) -> Self {
+        called_at.resize(called.len(), Utc::now());
+        Self{
+            remaining,
+            called,
+            called_at,
+            recent_requests: VecDeque::new(),
+            fixed_order,
+        }
+    }
+
+    pub fn remaining(&self) -> &[Number] {
+        &self.remaining
+    }
+
+    /// Records a number called from a physical cage, numbers outside the pool are out of range.
+    pub fn call(&mut self, number: Number) -> ManualCallResult {
+        match self.remaining.iter().position(|n| *n == number) {
+            Some(index) => {
+                self.remaining.swap_remove(index);
+                self.called.push(number);
+                self.called_at.push(Utc::now());
+                ManualCallResult::Called
+            }
+            None if self.called.contains(&number) => ManualCallResult::AlreadyCalled,
+            None => ManualCallResult::OutOfRange,
+        }
+    }
+
+    /// Takes back the most recent call, the number can be drawn again.
+    pub fn undo_last(&mut self) -> Option<Number> {
+        let number = self.called.pop()?;
+        self.called_at.pop();
+        // Fixed order pools call from the end, so pushing the number back restores the sequence
+        self.remaining.push(number);
+        self.recent_requests.retain(|(_, n)| *n != number);
+        Some(number)
+    }
+
+    /// True when the numbers are called in a predetermined order that manual calls would break.
+    pub fn is_fixed_order(&self) -> bool {
+        self.fixed_order
+    }
+
+    pub fn called(&self) -> &[Number] {
+        &self.called
+    }
+
+    /// When each number of [`DrawPool::called`] was called, in the same order.
+    pub fn call_times(&self) -> &[DateTime<Utc>] {
+        &self.called_at
+    }
+
+    /// Position of a called number in the call sequence, starting at 1.
+    pub fn call_index(&self, number: Number) -> Option<usize> {
+        self.called.iter().position(|n| *n == number).map(|index| index + 1)
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct DrawMessage{
+    r#type: String,
+    number: Number,
+    /// Position of the number in the call sequence, starting at 1.
+    call: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    duplicate: bool,
+    /// The number is one of the bonus balls of the round.
+    bonus: bool,
+}
+
+impl DrawMessage{
+    pub fn new(number: Number, call: usize, request_id: Option<String>, duplicate: bool, bonus: bool) -> Self {
+        Self{
+            r#type: "draw".to_string(),
+            number,
+            call,
+            request_id,
+            duplicate,
+            bonus,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct CallUndoneMessage{
+    r#type: String,
+    number: Number,
+    /// Corrected call sequence.
+    called: Vec<Number>,
+}
+
+impl CallUndoneMessage{
+    pub fn new(number: Number, called: &[Number]) -> Self {
+        Self{
+            r#type: "call_undone".to_string(),
+            number,
+            called: called.to_vec(),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::{fmt, sync::Arc};
+
+use anyhow::anyhow;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures_util::future::BoxFuture;
+
+use crate::room::RoomId;
+use crate::round::RoundId;
+
+const SENDGRID_API: &str = "https://api.sendgrid.com/v3/mail/send";
+
+/// Longest prize-claim instructions a host can set.
+const MAX_INSTRUCTIONS_LENGTH: usize = 2000;
+
+const MAX_EMAIL_LENGTH: usize = 254;
+
+/// SendGrid account, the integration is disabled unless `SENDGRID_API_KEY` and `MAIL_FROM` are set.
+#[derive(Clone)]
+pub struct MailConfig{
+    pub api_key: String,
+    /// Verified sender address of the account.
+    pub from: String,
+}
+
+impl fmt::Debug for MailConfig{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MailConfig").field("from", &self.from).finish()
+    }
+}
+
+/// Loose check that catches typos, the mail service has the final word.
+pub fn is_valid_email(email: &str) -> bool {
+    email.len() <= MAX_EMAIL_LENGTH
+        && !email.contains(char::is_whitespace)
+        && email.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.'))
+}
+
+#[derive(Debug)]
+pub struct Email{
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    /// File name and JSON content.
+    pub attachment: Option<(String, String)>,
+}
+
+impl Email{
+    /// Final report of a closed room, the full report is attached as JSON.
+    pub fn report(to: String, room: RoomId, summary: String, report: String) -> Self {
+        Self{
+            to,
+            subject: format!("Bingo report for room {}", room),
+            text: format!("Room {} has ended.\n\n{}", room, summary),
+            attachment: Some((format!("report-{}.json", room), report)),
+        }
+    }
+
+    pub fn prize_claim(to: String, room: RoomId, round: RoundId, instructions: Option<&str>) -> Self {
+        Self{
+            to,
+            subject: format!("You won round {} of bingo room {}", round, room),
+            text: format!("Congratulations, you won round {} of room {}!\n\n{}", round, room, instructions.unwrap_or("Show this email to the host to claim your prize.")),
+            attachment: None,
+        }
+    }
+}
+
+/// Sends transactional email, reports to hosts and prize-claim instructions to winners.
+pub trait Mailer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, anyhow::Result<()>>;
+}
+
+impl fmt::Debug for dyn Mailer{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Creates the mailer configured with `SENDGRID_API_KEY`, no email is sent without one.
+pub fn create_mailer(config: Option<&MailConfig>) -> Option<Arc<dyn Mailer>> {
+    config.map(|config| Arc::new(SendGridMailer{
+        config: config.clone(),
+        client: reqwest::Client::new(),
+    }) as Arc<dyn Mailer>)
+}
+
+/// Sends in the background, failures are only logged.
+pub fn send_email(mailer: &Arc<dyn Mailer>, email: Email){
+    let mailer = mailer.clone();
+    tokio::spawn(async move {
+        if let Err(e) = mailer.send(&email).await{
+            log::warn!("Failed to send \"{}\" using {}: {}", email.subject, mailer.name(), e);
+        }
+    });
+}
+
+pub struct SendGridMailer{
+    config: MailConfig,
+    client: reqwest::Client,
+}
+
+impl SendGridMailer{
+    async fn post(&self, email: &Email) -> anyhow::Result<()> {
+        let mut body = serde_json::json!({
+            "personalizations": [{ "to": [{ "email": email.to }] }],
+            "from": { "email": self.config.from },
+            "subject": email.subject,
+            "content": [{ "type": "text/plain", "value": email.text }],
+        });
+        if let Some((filename, content)) = &email.attachment{
+            body["attachments"] = serde_json::json!([{ "content": STANDARD.encode(content), "filename": filename, "type": "application/json" }]);
+        }
+        let response = self.client.post(SENDGRID_API)
+            .bearer_auth(&self.config.api_key)
+            .json(&body)
+            .send()
+            .await?;
+        if !response.status().is_success(){
+            return Err(anyhow!("SendGrid returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+impl Mailer for SendGridMailer{
+    fn name(&self) -> &'static str {
+        "sendgrid"
+    }
+
+    fn send<'a>(&'a self, email: &'a Email) -> BoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.post(email))
+    }
+}
+
+/// Where the report of a room goes and what winners are told, set by the host.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct EmailSettings{
+    /// Gets the final report when the room ends.
+    #[serde(default)]
+    pub report_to: Option<String>,
+    /// Emailed to winners that left an address, e.g. where to pick up the prize.
+    #[serde(default)]
+    pub claim_instructions: Option<String>,
+}
+
+impl EmailSettings{
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(to) = self.report_to.as_deref().filter(|to| !is_valid_email(to)){
+            return Err(format!("Invalid email address {}", to));
+        }
+        if self.claim_instructions.as_ref().is_some_and(|instructions| instructions.chars().count() > MAX_INSTRUCTIONS_LENGTH){
+            return Err(format!("Claim instructions are limited to {} characters", MAX_INSTRUCTIONS_LENGTH));
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct EmailSettingsMessage<'a>{
+    r#type: String,
+    #[serde(flatten)]
+    settings: &'a EmailSettings,
+}
+
+impl<'a> EmailSettingsMessage<'a>{
+    pub fn new(settings: &'a EmailSettings) -> Self {
+        Self{
+            r#type: "email_settings".to_string(),
+            settings,
+        }
+    }
+}
+
+/// Address a player wants prize-claim instructions sent to, unset to forget it.
+#[derive(Debug, serde::Deserialize)]
+pub struct SetEmailRequest{
+    pub email: Option<String>,
+}
+
+impl SetEmailRequest{
+    pub fn validate(&self) -> Result<(), String> {
+        match self.email.as_deref() {
+            Some(email) if !is_valid_email(email) => Err(format!("Invalid email address {}", email)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Confirms the address a player will be emailed at.
+#[derive(serde::Serialize)]
+pub struct EmailMessage<'a>{
+    r#type: String,
+    email: Option<&'a str>,
+}
+
+impl<'a> EmailMessage<'a>{
+    pub fn new(email: Option<&'a str>) -> Self {
+        Self{
+            r#type: "email".to_string(),
+            email,
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::{collections::HashMap, time::Duration};
+
+use actix_web::{get, web, HttpResponse};
+use futures_util::stream;
+use tokio::{sync::mpsc, time::timeout};
+
+use crate::admin::Admin;
+use crate::api_keys::HostIdentity;
+use crate::room::{BingoServerHandle, Msg, RoomId};
+
+/// Interval of the keep-alive comments sent on idle event streams.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Subscribers of the cross-room event streams, keyed by host username.
+#[derive(Debug, Default)]
+pub struct HostEvents{
+    subscribers: HashMap<String, Vec<mpsc::UnboundedSender<Msg>>>,
+    /// Admin streams, they get the events of every host.
+    admins: Vec<mpsc::UnboundedSender<Msg>>,
+}
+
+impl HostEvents{
+    pub fn subscribe(&mut self, host: String, tx: mpsc::UnboundedSender<Msg>){
+        self.subscribers.entry(host).or_default().push(tx);
+    }
+
+    pub fn subscribe_all(&mut self, tx: mpsc::UnboundedSender<Msg>){
+        self.admins.push(tx);
+    }
+
+    /// Forwards a room event to every stream of the room's host and the admin streams, closed streams are dropped.
+    pub fn publish(&mut self, host: &str, room: RoomId, event: &str){
+        if let Some(subscribers) = self.subscribers.get_mut(host){
+            let msg: Msg = format!(r#"{{"room":{},"event":{}}}"#, room, event).into();
+            subscribers.retain(|tx| tx.send(msg.clone()).is_ok());
+            if subscribers.is_empty(){
+                self.subscribers.remove(host);
+            }
+        }
+        if !self.admins.is_empty(){
+            let msg: Msg = format!(r#"{{"host":{},"room":{},"event":{}}}"#, serde_json::to_string(host).unwrap(), room, event).into();
+            self.admins.retain(|tx| tx.send(msg.clone()).is_ok());
+        }
+    }
+}
+
+#[get("/host/events")]
+async fn host_events(
+    user: HostIdentity,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    log::info!("Host {} subscribed to room events", user.username);
+    let (tx, rx) = mpsc::unbounded_channel();
+    server.subscribe_host_events(user.username, tx).await;
+    event_stream(rx)
+}
+
+/// Events of every room, each tagged with the room's host.
+#[get("/admin/events")]
+async fn admin_events(
+    _admin: Admin,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    log::info!("Admin subscribed to the events of all rooms");
+    let (tx, rx) = mpsc::unbounded_channel();
+    server.subscribe_all_events(tx).await;
+    event_stream(rx)
+}
+
+fn event_stream(rx: mpsc::UnboundedReceiver<Msg>) -> HttpResponse {
+    let events = stream::unfold(rx, |mut rx| async move {
+        let chunk = match timeout(KEEP_ALIVE_INTERVAL, rx.recv()).await {
+            Ok(Some(msg)) => format!("data: {}\n\n", msg),
+            Ok(None) => return None,
+            Err(_) => ": keep-alive\n\n".to_owned(),
+        };
+        Some((Ok::<_, actix_web::Error>(web::Bytes::from(chunk)), rx))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
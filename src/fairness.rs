@@ -0,0 +1,133 @@
+use actix_web::{error, get, web, HttpResponse};
+use rand::{rng, Rng as _};
+use sha2::{Digest, Sha256};
+
+use crate::draw::Number;
+use crate::persistence::PendingWrite;
+use crate::retention::DataClass;
+use crate::room::RoomId;
+use crate::round::RoundId;
+use crate::variant::GameVariant;
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect::<String>()
+}
+
+/// Secret seed of a provably fair round. The SHA-256 of the hex encoded seed is published when the round
+/// starts and the seed itself when it ends, so players can check the calls were fixed in advance.
+#[derive(Debug)]
+pub struct FairSeed{
+    seed: String,
+}
+
+impl FairSeed{
+    pub fn generate() -> Self {
+        Self{
+            seed: to_hex(&rng().random::<[u8; 32]>()),
+        }
+    }
+
+    pub fn from_seed(seed: String) -> Self {
+        Self{
+            seed,
+        }
+    }
+
+    pub fn seed(&self) -> &str {
+        &self.seed
+    }
+
+    pub fn commitment(&self) -> String {
+        to_hex(&Sha256::digest(self.seed.as_bytes()))
+    }
+
+    /// Order in which the numbers are called, a Fisher-Yates shuffle of 1..=75 (or 1..=90) where swap `i`
+    /// (from 74 or 89 down to 1) picks `j = u64::from_be_bytes(sha256("<seed>:<i>")[..8]) % (i + 1)`.
+    pub fn draw_order(&self, variant: GameVariant) -> Vec<Number> {
+        let mut numbers: Vec<Number> = variant.numbers().collect();
+        for i in (1..numbers.len()).rev() {
+            let digest = Sha256::digest(format!("{}:{}", self.seed, i).as_bytes());
+            let value = u64::from_be_bytes(digest[..8].try_into().unwrap());
+            numbers.swap(i, (value % (i as u64 + 1)) as usize);
+        }
+        numbers
+    }
+}
+
+/// Published audits are never replaced, a round number that already has one is logged as an error.
+pub fn record_commitment(room: RoomId, round: RoundId, variant: GameVariant, commitment: String) -> PendingWrite {
+    PendingWrite::new(
+        format!("seed commitment of round {} in room {}", round, room),
+        Box::new(move |database| {
+            let commitment = commitment.clone();
+            Box::pin(async move {
+                let result = sqlx::query("INSERT INTO round_audits (room_id, round, variant, commitment) VALUES ($1, $2, $3, $4) ON CONFLICT (room_id, round) DO NOTHING")
+                    .bind(room)
+                    .bind(round as i32)
+                    .bind(variant.name())
+                    .bind(commitment)
+                    .execute(&database)
+                    .await?;
+                if result.rows_affected() == 0{
+                    log::error!("Round {} of room {} already has a published audit, not recording its commitment", round, room);
+                }
+                Ok(())
+            })
+        }),
+    ).classified(DataClass::Calls)
+}
+
+pub fn record_reveal(room: RoomId, round: RoundId, seed: String, calls: Vec<Number>) -> PendingWrite {
+    let calls: Vec<i16> = calls.into_iter().map(i16::from).collect();
+    PendingWrite::new(
+        format!("seed reveal of round {} in room {}", round, room),
+        Box::new(move |database| {
+            let (seed, calls) = (seed.clone(), calls.clone());
+            Box::pin(async move {
+                let result = sqlx::query("UPDATE round_audits SET seed = $3, calls = $4 WHERE room_id = $1 AND round = $2 AND seed IS NULL")
+                    .bind(room)
+                    .bind(round as i32)
+                    .bind(seed)
+                    .bind(calls)
+                    .execute(&database)
+                    .await?;
+                if result.rows_affected() == 0{
+                    log::error!("Round {} of room {} has no open audit, not recording its seed", round, room);
+                }
+                Ok(())
+            })
+        }),
+    ).classified(DataClass::Calls)
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct RoundAudit {
+    /// The draw order of a seed depends on the variant, `75_ball` or `90_ball`.
+    variant: String,
+    commitment: String,
+    /// Revealed once the round has ended.
+    seed: Option<String>,
+    calls: Option<Vec<i16>>,
+}
+
+/// Public audit record of a provably fair round.
+#[get("/room/{room}/rounds/{round}/audit")]
+async fn round_audit(
+    path: web::Path<(RoomId, i32)>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let audit: Option<RoundAudit> = sqlx::query_as("SELECT variant, commitment, seed, calls FROM round_audits WHERE room_id = $1 AND round = $2")
+        .bind(path.0)
+        .bind(path.1)
+        .fetch_optional(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load audit of round {} in room {}: {}", path.1, path.0, e);
+            error::ErrorInternalServerError("Failed to load audit")
+        })?;
+
+    match audit {
+        Some(audit) => Ok(HttpResponse::Ok().json(audit)),
+        None => Err(error::ErrorNotFound("No provably fair round found")),
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::room::RoomId;
+
+/// Capabilities that can be rolled out gradually per host or per room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature{
+    /// Batched join/leave summaries sent to the host.
+    PresenceBatching,
+    /// Hosts of demo rooms may inject latency, dropped messages and disconnects.
+    ChaosMode,
+}
+
+impl Feature{
+    pub const ALL: [Feature; 2] = [Feature::PresenceBatching, Feature::ChaosMode];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Feature::PresenceBatching => "presence_batching",
+            Feature::ChaosMode => "chaos_mode",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|feature| feature.name() == name)
+    }
+
+    /// Value used when no row in the `feature_flags` table matches.
+    pub fn default_enabled(&self) -> bool {
+        match self {
+            Feature::PresenceBatching => true,
+            Feature::ChaosMode => false,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct FeatureFlagRow{
+    pub flag: String,
+    pub host: Option<String>,
+    pub room_id: Option<RoomId>,
+    pub enabled: bool,
+}
+
+impl FeatureFlagRow{
+    /// Specificity of the row for the given room, `None` when the row doesn't apply to it.
+    fn specificity(&self, host: &str, room_id: RoomId) -> Option<u8> {
+        if self.host.as_ref().is_some_and(|h| h != host) || self.room_id.is_some_and(|id| id != room_id){
+            return None;
+        }
+        Some(self.room_id.is_some() as u8 * 2 + self.host.is_some() as u8)
+    }
+}
+
+/// All feature flag rules, loaded from the database.
+#[derive(Debug, Default)]
+pub struct FeatureFlags{
+    rows: Vec<FeatureFlagRow>,
+    /// Defaults set in the `config` table, used over [`Feature::default_enabled`] when no row matches.
+    defaults: HashMap<Feature, bool>,
+}
+
+impl FeatureFlags{
+    pub async fn load(database: &sqlx::PgPool) -> Self {
+        let result = sqlx::query_as::<_, FeatureFlagRow>("SELECT flag, host, room_id, enabled FROM feature_flags")
+            .fetch_all(database)
+            .await;
+
+        match result {
+            Ok(rows) => {
+                for row in &rows{
+                    if Feature::from_name(&row.flag).is_none(){
+                        log::warn!("Ignoring unknown feature flag {}", row.flag);
+                    }
+                }
+                Self{ rows, defaults: HashMap::new() }
+            }
+            Err(e) => {
+                log::error!("Failed to load feature flags from database: {}", e);
+                Self::default()
+            }
+        }
+    }
+
+    pub fn set_defaults(&mut self, defaults: HashMap<Feature, bool>){
+        self.defaults = defaults;
+    }
+
+    /// Resolves the features enabled for a room, the most specific matching rule wins.
+    pub fn resolve(&self, host: &str, room_id: RoomId) -> RoomFeatures {
+        let mut enabled = HashSet::new();
+        for feature in Feature::ALL{
+            let rule = self.rows.iter()
+                .filter(|row| row.flag == feature.name())
+                .filter_map(|row| row.specificity(host, room_id).map(|score| (score, row.enabled)))
+                .max_by_key(|(score, _)| *score);
+
+            let is_enabled = match rule {
+                Some((_, is_enabled)) => is_enabled,
+                None => self.defaults.get(&feature).copied().unwrap_or_else(|| feature.default_enabled()),
+            };
+            if is_enabled{
+                enabled.insert(feature);
+            }
+        }
+        RoomFeatures{ enabled }
+    }
+}
+
+/// Features resolved for a single room.
+#[derive(Debug, Default, Clone)]
+pub struct RoomFeatures{
+    enabled: HashSet<Feature>,
+}
+
+impl RoomFeatures{
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.enabled.contains(&feature)
+    }
+}
@@ -0,0 +1,54 @@
+use crate::card::{Card, CardId};
+use crate::draw::Number;
+use crate::room::SessionId;
+use crate::round::RoundId;
+
+/// Checks a bingo claim against the cards the server dealt the player, what a client shows is never trusted.
+/// Returns the first card with a complete line.
+pub fn verify_claim(cards: &[Card], called: &[Number]) -> Option<CardId> {
+    cards.iter()
+        .find(|card| card.has_bingo(called))
+        .map(|card| card.id)
+}
+
+/// Sent to the room and mirrored to MQTT when a winner is recorded, round results alone are too late for buzzers.
+#[derive(serde::Serialize)]
+pub struct WinnerEvent{
+    r#type: String,
+    round: RoundId,
+    client_id: SessionId,
+    /// Card the server verified the win on, unset for winners the host recorded by hand.
+    card_id: Option<CardId>,
+    verified: bool,
+    calls: usize,
+    jackpot: bool,
+}
+
+impl WinnerEvent{
+    pub fn new(round: RoundId, client_id: SessionId, card_id: Option<CardId>, calls: usize, jackpot: bool) -> Self {
+        Self{
+            r#type: "winner".to_string(),
+            round,
+            client_id,
+            card_id,
+            verified: card_id.is_some(),
+            calls,
+            jackpot,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::variant::GameVariant;
+
+    #[test]
+    fn claims_need_a_covered_line_on_a_dealt_card(){
+        let card = Card::generate(3, &[], GameVariant::Ball75);
+        let top_row: Vec<Number> = card.columns.iter().map(|column| column[0]).collect();
+        assert_eq!(verify_claim(std::slice::from_ref(&card), &top_row[..4]), None);
+        assert_eq!(verify_claim(&[Card::generate(1, &[], GameVariant::Ball75), card], &top_row), Some(3));
+        assert_eq!(verify_claim(&[], &top_row), None);
+    }
+}
@@ -0,0 +1,39 @@
+use std::{sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+/// How often rooms are checked for sockets whose task is gone.
+pub const GHOST_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Connections the sweep removed because nothing reads their messages anymore, e.g. after a socket task
+/// ended without telling the room. Shared by the actor and the admin API.
+#[derive(Debug, Default)]
+pub struct GhostStatus{
+    sweeps: AtomicU64,
+    removed: AtomicU64,
+    last_removed: AtomicU64,
+}
+
+#[derive(serde::Serialize)]
+pub struct GhostReport{
+    sweeps: u64,
+    /// Ghost connections removed since the server started.
+    removed: u64,
+    /// Removed by the latest sweep.
+    last_removed: u64,
+}
+
+impl GhostStatus{
+    pub fn record(&self, removed: usize){
+        let removed = removed as u64;
+        self.sweeps.fetch_add(1, Ordering::Relaxed);
+        self.removed.fetch_add(removed, Ordering::Relaxed);
+        self.last_removed.store(removed, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> GhostReport {
+        GhostReport{
+            sweeps: self.sweeps.load(Ordering::Relaxed),
+            removed: self.removed.load(Ordering::Relaxed),
+            last_removed: self.last_removed.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use actix_web::{post, web, HttpResponse};
+use async_graphql::{ComplexObject, Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::room::RoomId;
+use crate::stats::{load_stats, StatsResult};
+
+pub type HistorySchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Nesting allowed in a query, deep enough for room -> rounds -> winners.
+const MAX_DEPTH: usize = 6;
+const MAX_COMPLEXITY: usize = 1000;
+const MAX_ROOMS: i64 = 200;
+
+/// Username of the host running the query, every resolver is limited to their data.
+struct QueryHost(String);
+
+fn host<'a>(ctx: &'a Context<'_>) -> &'a str {
+    &ctx.data_unchecked::<QueryHost>().0
+}
+
+fn database<'a>(ctx: &'a Context<'_>) -> &'a sqlx::PgPool {
+    ctx.data_unchecked::<sqlx::PgPool>()
+}
+
+/// Database errors are logged, clients only get a generic message.
+fn query_error(e: sqlx::Error) -> async_graphql::Error {
+    log::error!("GraphQL query failed: {}", e);
+    async_graphql::Error::new("Failed to load game history")
+}
+
+#[derive(sqlx::FromRow, SimpleObject)]
+#[graphql(complex)]
+struct Room{
+    id: RoomId,
+    valid_date: NaiveDate,
+    archived_at: Option<DateTime<Utc>>,
+    /// Object storage addresses of the final report and event log, once the room is archived there.
+    report_url: Option<String>,
+    event_log_url: Option<String>,
+}
+
+#[derive(sqlx::FromRow, SimpleObject)]
+struct RoundHistory{
+    round: i32,
+    calls: i64,
+    first_call_at: DateTime<Utc>,
+    last_call_at: DateTime<Utc>,
+    /// Seed commitment of provably fair rounds.
+    commitment: Option<String>,
+    seed: Option<String>,
+}
+
+#[derive(sqlx::FromRow, SimpleObject)]
+struct Call{
+    round: i32,
+    number: i16,
+    call_index: i32,
+    called_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow, SimpleObject)]
+struct Win{
+    round: i32,
+    pattern: String,
+    calls_to_win: i32,
+    jackpot: bool,
+    won_at: DateTime<Utc>,
+}
+
+#[ComplexObject]
+impl Room{
+    async fn rounds(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<RoundHistory>> {
+        sqlx::query_as("SELECT c.round, COUNT(*) AS calls, MIN(c.called_at) AS first_call_at, MAX(c.called_at) AS last_call_at, a.commitment, a.seed \
+                        FROM calls c LEFT JOIN round_audits a ON a.room_id = c.room_id AND a.round = c.round \
+                        WHERE c.room_id = $1 GROUP BY c.round, a.commitment, a.seed ORDER BY c.round")
+            .bind(self.id)
+            .fetch_all(database(ctx))
+            .await
+            .map_err(query_error)
+    }
+
+    /// Calls in order, limited to one round when given.
+    async fn calls(&self, ctx: &Context<'_>, round: Option<i32>) -> async_graphql::Result<Vec<Call>> {
+        sqlx::query_as("SELECT round, number, call_index, called_at FROM calls WHERE room_id = $1 AND ($2::integer IS NULL OR round = $2) ORDER BY round, call_index")
+            .bind(self.id)
+            .bind(round)
+            .fetch_all(database(ctx))
+            .await
+            .map_err(query_error)
+    }
+
+    async fn winners(&self, ctx: &Context<'_>, round: Option<i32>) -> async_graphql::Result<Vec<Win>> {
+        sqlx::query_as("SELECT round, pattern, calls_to_win, jackpot, won_at FROM wins WHERE room_id = $1 AND ($2::integer IS NULL OR round = $2) ORDER BY won_at")
+            .bind(self.id)
+            .bind(round)
+            .fetch_all(database(ctx))
+            .await
+            .map_err(query_error)
+    }
+
+    async fn stats(&self, ctx: &Context<'_>) -> async_graphql::Result<StatsResult> {
+        load_stats(database(ctx), host(ctx), Some(self.id)).await.map_err(query_error)
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot{
+    /// Rooms of the host, newest first.
+    async fn rooms(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 50)] limit: i64,
+        #[graphql(default)] offset: i64,
+    ) -> async_graphql::Result<Vec<Room>> {
+        sqlx::query_as("SELECT id, valid_date, archived_at, report_url, event_log_url FROM rooms WHERE host = $1 ORDER BY valid_date DESC, id DESC LIMIT $2 OFFSET $3")
+            .bind(host(ctx))
+            .bind(limit.clamp(0, MAX_ROOMS))
+            .bind(offset.max(0))
+            .fetch_all(database(ctx))
+            .await
+            .map_err(query_error)
+    }
+
+    async fn room(&self, ctx: &Context<'_>, id: RoomId) -> async_graphql::Result<Option<Room>> {
+        sqlx::query_as("SELECT id, valid_date, archived_at, report_url, event_log_url FROM rooms WHERE id = $1 AND host = $2")
+            .bind(id)
+            .bind(host(ctx))
+            .fetch_optional(database(ctx))
+            .await
+            .map_err(query_error)
+    }
+
+    /// Statistics over all rooms of the host.
+    async fn stats(&self, ctx: &Context<'_>) -> async_graphql::Result<StatsResult> {
+        load_stats(database(ctx), host(ctx), None).await.map_err(query_error)
+    }
+}
+
+pub fn build_schema(database: sqlx::PgPool) -> HistorySchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(database)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// Read-only game history for reporting pages.
+#[post("/graphql")]
+async fn graphql_query(
+    user: HostIdentity,
+    schema: web::Data<HistorySchema>,
+    request: web::Json<async_graphql::Request>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    let response = schema.execute(request.into_inner().data(QueryHost(user.username))).await;
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,179 @@
+use std::{net::SocketAddr, pin::Pin};
+
+use futures_util::{stream, Stream};
+use tokio::sync::mpsc;
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::api_keys::{verify_api_key, Scope, API_KEY_HEADER};
+use crate::room::{BingoServerHandle, RoomId};
+use crate::variant::GameVariant;
+
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/bingo.control.Control.rs"));
+}
+
+use generated::control_server::{Control, ControlServer};
+
+// Messages of proto/control.proto, the service itself is generated by build.rs
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CreateRoomRequest{
+    /// Empty for 75-ball.
+    #[prost(string, tag = "1")]
+    pub variant: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RoomCredentials{
+    #[prost(int32, tag = "1")]
+    pub room_id: RoomId,
+    #[prost(string, tag = "2")]
+    pub room_token: String,
+    #[prost(string, tag = "3")]
+    pub board_token: String,
+    #[prost(string, tag = "4")]
+    pub variant: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DrawRequest{
+    #[prost(int32, tag = "1")]
+    pub room_id: RoomId,
+    #[prost(string, tag = "2")]
+    pub room_token: String,
+    #[prost(string, optional, tag = "3")]
+    pub request_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DrawReply{}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListRoomsRequest{}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RoomSummary{
+    #[prost(int32, tag = "1")]
+    pub room_id: RoomId,
+    #[prost(uint32, tag = "2")]
+    pub players: u32,
+    #[prost(uint32, optional, tag = "3")]
+    pub round: Option<u32>,
+    #[prost(uint32, tag = "4")]
+    pub calls: u32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ListRoomsReply{
+    #[prost(message, repeated, tag = "1")]
+    pub rooms: Vec<RoomSummary>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StreamEventsRequest{}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RoomEvent{
+    #[prost(int32, tag = "1")]
+    pub room_id: RoomId,
+    #[prost(string, tag = "2")]
+    pub event: String,
+}
+
+/// Entry of the host event stream, see `HostEvents::publish`.
+#[derive(serde::Deserialize)]
+struct HostEvent{
+    room: RoomId,
+    event: serde_json::Value,
+}
+
+/// gRPC control plane sharing the room server with the HTTP API.
+struct ControlService{
+    server: BingoServerHandle,
+    database: sqlx::PgPool,
+}
+
+impl ControlService{
+    /// Resolves the `x-api-key` metadata to the host owning the key.
+    async fn authorize<T>(&self, request: &Request<T>) -> Result<String, Status> {
+        let key = request.metadata()
+            .get(API_KEY_HEADER.to_ascii_lowercase())
+            .and_then(|value| value.to_str().ok())
+            .ok_or_else(|| Status::unauthenticated("An API key is required"))?;
+        let (username, scopes) = verify_api_key(&self.database, key).await
+            .ok_or_else(|| Status::unauthenticated("Invalid API key"))?;
+        if !scopes.contains(&Scope::Host){
+            return Err(Status::permission_denied("API key lacks the host scope"));
+        }
+        Ok(username)
+    }
+}
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<RoomEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Control for ControlService{
+    async fn create_room(&self, request: Request<CreateRoomRequest>) -> Result<Response<RoomCredentials>, Status> {
+        let host = self.authorize(&request).await?;
+        let variant = match request.into_inner().variant {
+            variant if variant.is_empty() => GameVariant::default(),
+            variant => GameVariant::try_from(variant).map_err(|e| Status::invalid_argument(e.to_string()))?,
+        };
+        let room = self.server.create_room(host, variant).await
+            .ok_or_else(|| Status::unavailable("The server is about to restart for maintenance"))?;
+        log::info!("Created a room with id {} for {} over gRPC", room.id, room.host);
+
+        Ok(Response::new(RoomCredentials{ room_id: room.id, room_token: room.token, board_token: room.board_token, variant: room.variant.name().to_owned() }))
+    }
+
+    async fn draw(&self, request: Request<DrawRequest>) -> Result<Response<DrawReply>, Status> {
+        let request = request.into_inner();
+        if !self.server.has_room_host_privileges(request.room_id, request.room_token).await{
+            return Err(Status::not_found("Room not found"));
+        }
+        self.server.draw(request.room_id, request.request_id).await;
+        Ok(Response::new(DrawReply{}))
+    }
+
+    async fn list_rooms(&self, request: Request<ListRoomsRequest>) -> Result<Response<ListRoomsReply>, Status> {
+        let host = self.authorize(&request).await?;
+        let rooms = self.server.list_rooms(host).await.into_iter()
+            .map(|room| RoomSummary{
+                room_id: room.id,
+                players: room.players as u32,
+                round: room.round,
+                calls: room.calls as u32,
+            })
+            .collect();
+        Ok(Response::new(ListRoomsReply{ rooms }))
+    }
+
+    type StreamEventsStream = EventStream;
+
+    async fn stream_events(&self, request: Request<StreamEventsRequest>) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let host = self.authorize(&request).await?;
+        log::info!("Host {} subscribed to room events over gRPC", host);
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.server.subscribe_host_events(host, tx).await;
+
+        let events = stream::unfold(rx, |mut rx| async move {
+            loop {
+                let msg = rx.recv().await?;
+                match serde_json::from_str::<HostEvent>(&msg) {
+                    Ok(event) => return Some((Ok(RoomEvent{ room_id: event.room, event: event.event.to_string() }), rx)),
+                    Err(e) => log::warn!("Skipping malformed host event {}: {}", msg, e),
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+/// Serves the control plane on its own port until the process exits.
+pub async fn serve(addr: SocketAddr, server: BingoServerHandle, database: sqlx::PgPool){
+    log::info!("gRPC control plane listening on {}", addr);
+    let service = ControlServer::new(ControlService{ server, database });
+    if let Err(e) = Server::builder().add_service(service).serve(addr).await{
+        log::error!("gRPC control plane stopped: {}", e);
+    }
+}
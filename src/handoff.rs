@@ -0,0 +1,209 @@
+use std::{collections::HashSet, time::Duration};
+
+use actix_web::{error, post, web, HttpResponse};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::StreamExt as _;
+use serde::{Deserialize, Serialize};
+
+use crate::admin::Admin;
+use crate::announcements::Announcement;
+use crate::card::{Card, CardId, CardSettings};
+use crate::cors::AllowedOrigins;
+use crate::draw::Number;
+use crate::notes::ConnectionNote;
+use crate::offline::HostOfflinePolicy;
+use crate::devices::DuplicateSessionPolicy;
+use crate::retention::RetentionPolicy;
+use crate::email::EmailSettings;
+use crate::players::PlayerConnection;
+use crate::phase::RoomPhase;
+use crate::seats::{Seat, SeatMap};
+use crate::timezone::ZonedTime;
+use crate::room::{BingoServerHandle, RoomId, SessionId};
+use crate::variant::GameVariant;
+use crate::waitlist::PlayerLimit;
+use crate::round::{Prize, RoundId, RoundSettings};
+
+/// Bumped whenever the snapshot format changes incompatibly.
+const STATE_VERSION: u32 = 1;
+
+/// Largest state document accepted by the import.
+const MAX_IMPORT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Least time players of an imported room get to reconnect to this instance and pick up their cards.
+pub const HANDOFF_GRACE: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoundSnapshot{
+    pub id: RoundId,
+    pub settings: RoundSettings,
+    pub winners: Vec<SessionId>,
+    pub fair_seed: Option<String>,
+    #[serde(default)]
+    pub prizes: Vec<Prize>,
+    #[serde(default)]
+    pub jackpot_winners: Vec<SessionId>,
+    #[serde(default)]
+    pub bonus_winners: Vec<SessionId>,
+    #[serde(default)]
+    pub deadline: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub halfway_reached: bool,
+}
+
+/// Game state of a room, connections are not part of it and reconnect to the new instance.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomSnapshot{
+    pub id: RoomId,
+    pub host: String,
+    pub host_token: String,
+    pub board_token: String,
+    pub valid_date: NaiveDate,
+    pub round: Option<RoundSnapshot>,
+    pub rounds_played: RoundId,
+    pub remaining: Vec<Number>,
+    pub called: Vec<Number>,
+    #[serde(default)]
+    pub called_at: Vec<DateTime<Utc>>,
+    pub fixed_order: bool,
+    pub card_settings: CardSettings,
+    pub cards: Vec<(SessionId, Vec<Card>)>,
+    pub next_card_id: CardId,
+    #[serde(default)]
+    pub locale: Option<String>,
+    #[serde(default)]
+    pub sms_recipients: Vec<String>,
+    #[serde(default)]
+    pub settings_version: u64,
+    #[serde(default)]
+    pub milestones: bool,
+    #[serde(default)]
+    pub players_joined: usize,
+    /// Tokens of the players counted in `players_joined`.
+    #[serde(default)]
+    pub joined_players: Vec<String>,
+    #[serde(default)]
+    pub notes: Vec<(SessionId, ConnectionNote)>,
+    #[serde(default)]
+    pub phase: Option<RoomPhase>,
+    #[serde(default)]
+    pub seat_map: Option<SeatMap>,
+    #[serde(default)]
+    pub seats: Vec<(SessionId, Seat)>,
+    #[serde(default)]
+    pub players: Vec<(SessionId, PlayerConnection)>,
+    #[serde(default)]
+    pub allowed_origins: AllowedOrigins,
+    /// Missing before connection IDs were allocated in order.
+    #[serde(default)]
+    pub next_conn_id: SessionId,
+    #[serde(default)]
+    pub announcements: Vec<Announcement>,
+    #[serde(default)]
+    pub player_limit: Option<PlayerLimit>,
+    #[serde(default)]
+    pub host_offline_policy: HostOfflinePolicy,
+    #[serde(default)]
+    pub duplicate_session_policy: DuplicateSessionPolicy,
+    /// Set when the host overrides the server default.
+    #[serde(default)]
+    pub resume_grace_secs: Option<u64>,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
+    #[serde(default)]
+    pub redact_chat: Option<bool>,
+    #[serde(default)]
+    pub email_settings: EmailSettings,
+    #[serde(default)]
+    pub countdown: Option<ZonedTime>,
+    #[serde(default)]
+    pub variant: GameVariant,
+    /// Interval of the auto-caller, unset when it is not running.
+    #[serde(default)]
+    pub auto_call_secs: Option<u64>,
+    #[serde(default)]
+    pub auto_call_paused: bool,
+}
+
+impl RoomSnapshot{
+    /// Checks that the game state can be played on, the reason names the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        let numbers = self.variant.numbers();
+        let mut called = HashSet::new();
+        for number in &self.called{
+            if !numbers.contains(number){
+                return Err(format!("Room {}: called number {} is not a {} number", self.id, number, self.variant.name()));
+            }
+            if !called.insert(*number){
+                return Err(format!("Room {}: {} is called twice", self.id, number));
+            }
+        }
+        let mut remaining = HashSet::new();
+        for number in &self.remaining{
+            if !numbers.contains(number){
+                return Err(format!("Room {}: remaining number {} is not a {} number", self.id, number, self.variant.name()));
+            }
+            if called.contains(number) || !remaining.insert(*number){
+                return Err(format!("Room {}: {} is remaining more than once or was already called", self.id, number));
+            }
+        }
+        if let Some(card) = self.cards.iter().flat_map(|(_, cards)| cards).find(|card| !card.fits(self.variant)){
+            return Err(format!("Room {}: card {} is not a valid {} card", self.id, card.id, self.variant.name()));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StateExport{
+    version: u32,
+    rooms: Vec<RoomSnapshot>,
+}
+
+#[derive(Serialize)]
+struct ImportResult{
+    imported: usize,
+    skipped: usize,
+}
+
+/// Live state of every room, imported by the replacement instance before this one drains.
+#[post("/admin/export-state")]
+async fn export_state(
+    _admin: Admin,
+    server: web::Data<BingoServerHandle>,
+) -> HttpResponse {
+    let rooms = server.export_state().await;
+    log::info!("Exported the state of {} rooms", rooms.len());
+    HttpResponse::Ok().json(StateExport{ version: STATE_VERSION, rooms })
+}
+
+/// Loads exported rooms, rooms that already have open sockets here are left untouched.
+#[post("/admin/import-state")]
+async fn import_state(
+    _admin: Admin,
+    mut payload: web::Payload,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_IMPORT_SIZE{
+            return Err(error::ErrorPayloadTooLarge("State document is too large"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    let state: StateExport = serde_json::from_slice(&body)
+        .map_err(|e| error::ErrorBadRequest(format!("Invalid state document: {}", e)))?;
+    if state.version != STATE_VERSION{
+        return Err(error::ErrorBadRequest(format!("Unsupported state version {}", state.version)));
+    }
+    if let Some(reason) = state.rooms.iter().find_map(|room| room.validate().err()){
+        return Err(error::ErrorBadRequest(format!("Invalid state document: {}", reason)));
+    }
+
+    let total = state.rooms.len();
+    let imported = server.import_state(state.rooms).await;
+    log::info!("Imported the state of {} of {} rooms", imported, total);
+    Ok(HttpResponse::Ok().json(ImportResult{ imported, skipped: total - imported }))
+}
@@ -1,34 +1,30 @@
 
 
-use argon2::{
-    password_hash::{
-        PasswordHash, PasswordVerifier
-    },
-    Argon2
-};
 use actix_identity::Identity;
 use actix_web::{
     error, get, web, Error, HttpMessage as _, HttpRequest, HttpResponse, Responder
 };
-use base64::prelude::*;
 use serde::Deserialize;
-use sqlx::types::Uuid;
-use tokio::task::spawn_local;
-
-use crate::{room::{BingoServerHandle, ConnId, RoomCreds, RoomId, USER_HOST}, wshandler::{ws_handler, CommandHandler}};
+use actix_ws::{CloseCode, CloseReason, Message};
+use tokio::{task::spawn_local, time::timeout};
 
+use crate::{accessibility::SocketFormat, announcements::{CancelAnnouncementRequest, ScheduleAnnouncementRequest}, auto_call::AutoCallRequest, drain::maintenance_error, api_keys::{verify_api_key, Scope, API_KEY_HEADER}, auth::{AuthProvider, AuthenticatedUser}, config::{LoginThrottleConfig, WebSocketConfig}, lockout::{client_ip, LoginAttempt}, room::{BingoServerHandle, SessionId, RoomCreds, RoomId, UserType}, card::{Card, CardSettings, VerifyClaimCodeRequest}, chaos::ChaosRequest, cors::AllowedOrigins, devices::DuplicateSessionPolicyRequest, draw::Number, redact::RedactChatRequest, resume::ResumeGraceRequest, retention::RetentionPolicy, round::RoundSettings, email::EmailSettings, notes::TagConnectionRequest, offline::HostOfflinePolicyRequest, phase::SetPhaseRequest, seats::{AssignSeatRequest, SeatMap}, sound_check::SoundCheckRequest, settings::Versioned, timezone::SetCountdownRequest, versions::ForceRefreshRequest, variant::GameVariant, waitlist::PlayerLimitRequest, wshandler::{ws_handler, CommandHandler, ErrorMessage, WSMessage}};
 
-#[derive(sqlx::FromRow, serde::Deserialize, Debug)]
-pub struct AuthUser{
-    id: Uuid,
-    username: String,
-    token: String,
-}
 
 #[derive(serde::Serialize)]
 struct HostResult {
     room_id: RoomId,
     room_token: String,
+    /// Token for `/board/{room}` display connections.
+    board_token: String,
+    variant: GameVariant,
+}
+
+#[derive(serde::Deserialize)]
+struct HostQuery{
+    /// Game of a newly created room, an existing room of the day keeps its variant.
+    #[serde(default)]
+    variant: GameVariant,
 }
 
 impl Responder for HostResult {
@@ -43,73 +39,63 @@ impl Responder for HostResult {
 }
 
 
-pub fn verify_password(user_token: &str, hash_token: &str) -> Result<bool, argon2::password_hash::Error> {
-    let parsed_hash = PasswordHash::new(&hash_token)?;
-    Ok(Argon2::default().verify_password(user_token.as_bytes(), &parsed_hash).is_ok())
-}
-
 #[get("/host")]
 async fn host_room(
     req: HttpRequest,
+    query: web::Query<HostQuery>,
     server: web::Data<BingoServerHandle>,
+    auth_provider: web::Data<dyn AuthProvider>,
     datebase: web::Data<sqlx::PgPool>,
+    throttle: web::Data<LoginThrottleConfig>,
 ) -> actix_web::Result<impl Responder> {
 
     log::info!("Host request");
+    let ip = client_ip(&req, &throttle);
+
+    // Automation and kiosk devices authenticate with an API key instead of the interactive login
+    if let Some(key) = req.headers().get(API_KEY_HEADER).and_then(|value| value.to_str().ok()) {
+        let attempt = LoginAttempt::new(None, ip);
+        attempt.check(&datebase, &throttle).await?;
+        let Some((username, scopes)) = verify_api_key(&datebase, key).await else {
+            return Err(error::ErrorUnauthorized("Invalid API key"));
+        };
+        attempt.succeeded(&datebase, &throttle).await;
+        if !scopes.contains(&Scope::Host) {
+            return Err(error::ErrorForbidden("API key lacks the host scope"));
+        }
+        return create_host_room(&server, AuthenticatedUser{ username }, query.variant).await;
+    }
 
     //Check for Authorization header and error if not preset
     if !req.headers().contains_key("Authorization") {
         return Err(error::ErrorUnauthorized("Authorization header is required"));
     }
-    let auth = req.headers().get("Authorization").unwrap().to_str().unwrap();
+    let auth = req.headers().get("Authorization").unwrap().to_str()
+        .map_err(|_| error::ErrorUnauthorized("Invalid Authorization header, unexpected encoding"))?;
 
-    let decoded = BASE64_STANDARD.decode(auth);
-    if decoded.is_err() {
-        return Err(error::ErrorUnauthorized("Invalid Authorization header, unexpected encoding"));
-    }
-    let decoded = decoded.unwrap();
+    let attempt = LoginAttempt::new(auth_provider.claimed_user(auth), ip);
+    attempt.check(&datebase, &throttle).await?;
+    let user = auth_provider.authenticate(auth).await.map_err(error::ErrorUnauthorized)?;
+    attempt.succeeded(&datebase, &throttle).await;
+    log::info!("Host {} authenticated using {}", user.username, auth_provider.name());
 
-    let auth_token: Result<AuthUser, serde_json::Error> = serde_json::from_slice(&decoded);
-    if auth_token.is_err() {
-        log::warn!("Failed to parse Authorization header: {}", auth_token.unwrap_err());
-        return Err(error::ErrorUnauthorized("Invalid Authorization header, unexpected format"));
-    }
-    let auth_token = auth_token.unwrap();
-
-    log::info!("Host request from {}", auth_token.username);
-    // Check if token is valid in the database and matches the user
-    // if not return unauthorized
-    //Using auth_token.id look up the user in the database
-    let user_data: AuthUser = sqlx::query_as("SELECT * FROM users WHERE id = $1")
-        .bind(&auth_token.id)
-        .fetch_one(&**datebase)
-        .await
-        .map_err(|_| error::ErrorUnauthorized("Invalid Authorization header, user not found"))?;
+    // attach a verified user identity to the active session, API key callers never get one so a
+    // scoped key can't be traded for a session that mints unscoped keys
+    Identity::login(&req.extensions(), user.username.clone()).unwrap();
 
-    let result = verify_password(&auth_token.token, &user_data.token);
-    match result{
-        Ok(is_valid) => {
-            if !is_valid {
-                return Err(error::ErrorUnauthorized("Invalid Authorization header, token does not match"));
-            }
-        }
-        Err(err) => {
-            log::warn!("Failed to verify token: {}", err);
-            return Err(error::ErrorUnauthorized("Invalid Authorization header, token verification failed"));
-        }
-    }
+    create_host_room(&server, user, query.variant).await
+}
 
-    // attach a verified user identity to the active session
-    Identity::login(&req.extensions(), auth_token.username.clone()).unwrap();
+async fn create_host_room(server: &BingoServerHandle, user: AuthenticatedUser, variant: GameVariant) -> actix_web::Result<HostResult> {
 
     // Find if there is still a valid room of the day
-    // if there is no room create a new room
+    // if there is no room create a new room, rooms of previous days are archived nightly
     // return room id
 
-    let room: RoomCreds = server.create_room(auth_token.username.clone()).await;
+    let room: RoomCreds = server.create_room(user.username.clone(), variant).await.ok_or_else(maintenance_error)?;
     log::info!("Created a room with id {} and assigned to {}", room.id, room.host);
 
-    Ok(HostResult{room_id: room.id, room_token: room.token})
+    Ok(HostResult{room_id: room.id, room_token: room.token, board_token: room.board_token, variant: room.variant})
 }
 
 
@@ -118,40 +104,407 @@ async fn host_room(
 #[derive(serde::Serialize, serde::Deserialize)]
 struct ClientMessage{
     r#type: String,
-    client_id: ConnId,
+    client_id: SessionId,
+}
+
+#[derive(serde::Serialize)]
+struct UndeliverableMessage{
+    r#type: String,
+    client_id: SessionId,
+}
+
+impl UndeliverableMessage{
+    fn new(client_id: SessionId) -> Self {
+        Self{
+            r#type: "undeliverable".to_string(),
+            client_id,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct DrawRequest{
+    request_id: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ManualCallRequest{
+    number: Number,
+}
+
+#[derive(serde::Deserialize)]
+struct LanguageRequest{
+    /// Language code understood by the translator, unset to stop translating.
+    locale: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct SmsRecipientsRequest{
+    numbers: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ConfirmSmsRecipientRequest{
+    number: String,
+    /// Code the owner of the number was sent.
+    code: String,
+}
+
+#[derive(serde::Deserialize)]
+struct MilestonesRequest{
+    enabled: bool,
 }
 
+#[derive(serde::Deserialize)]
+struct AssignCardRequest{
+    client_id: SessionId,
+    card: Card,
+}
+
+#[derive(serde::Deserialize)]
+struct WinnerMessage{
+    client_id: SessionId,
+}
+
+/// Tells the host why its message was refused, returns the reason.
+async fn refuse(server: &BingoServerHandle, room: RoomId, error: String) -> String {
+    server.notify_host(room, ErrorMessage::new(error.clone()).to_string()).await;
+    error
+}
+
+/// Applies a message of a host socket. Messages that don't parse are dropped, refused messages are
+/// answered with an `error` message, either way the reason is returned.
 pub async fn host_command_handler(
     room: RoomId,
     server: web::Data<BingoServerHandle>,
     msg: String
-) {
+) -> Result<(), String> {
+    let message_type = serde_json::from_str::<WSMessage>(&msg)
+        .map(|message| message.r#type)
+        .unwrap_or_default();
+
+    match message_type.as_str() {
+        "start_round" => {
+            match serde_json::from_str::<RoundSettings>(&msg) {
+                Ok(settings) => server.start_round(room, settings).await,
+                Err(e) => return Err(format!("Invalid start_round message: {}", e)),
+            }
+            return Ok(());
+        }
+        "end_round" => {
+            server.end_round(room).await;
+            return Ok(());
+        }
+        "draw" => {
+            match serde_json::from_str::<DrawRequest>(&msg) {
+                Ok(request) => server.draw(room, request.request_id).await,
+                Err(e) => return Err(format!("Invalid draw message: {}", e)),
+            }
+            return Ok(());
+        }
+        "auto_call" => {
+            match serde_json::from_str::<AutoCallRequest>(&msg) {
+                Ok(request) => match request.validate() {
+                    Ok(()) => server.start_auto_call(room, request.interval()).await,
+                    Err(error) => return Err(refuse(&server, room, error).await),
+                },
+                Err(e) => return Err(format!("Invalid auto_call message: {}", e)),
+            }
+            return Ok(());
+        }
+        "pause_auto_call" => {
+            server.pause_auto_call(room, true).await;
+            return Ok(());
+        }
+        "resume_auto_call" => {
+            server.pause_auto_call(room, false).await;
+            return Ok(());
+        }
+        "manual_call" => {
+            match serde_json::from_str::<ManualCallRequest>(&msg) {
+                Ok(request) => server.manual_call(room, request.number).await,
+                Err(e) => return Err(format!("Invalid manual_call message: {}", e)),
+            }
+            return Ok(());
+        }
+        "set_language" => {
+            match serde_json::from_str::<Versioned<LanguageRequest>>(&msg) {
+                Ok(request) => server.set_language(room, request.update.locale.filter(|locale| !locale.is_empty()), request.version).await,
+                Err(e) => return Err(format!("Invalid set_language message: {}", e)),
+            }
+            return Ok(());
+        }
+        "sms_recipients" => {
+            match serde_json::from_str::<Versioned<SmsRecipientsRequest>>(&msg) {
+                Ok(request) => server.set_sms_recipients(room, request.update.numbers, request.version).await,
+                Err(e) => return Err(format!("Invalid sms_recipients message: {}", e)),
+            }
+            return Ok(());
+        }
+        "confirm_sms_recipient" => {
+            match serde_json::from_str::<ConfirmSmsRecipientRequest>(&msg) {
+                Ok(request) => server.confirm_sms_recipient(room, request.number, request.code).await,
+                Err(e) => return Err(format!("Invalid confirm_sms_recipient message: {}", e)),
+            }
+            return Ok(());
+        }
+        "undo_last_call" => {
+            server.undo_last_call(room).await;
+            return Ok(());
+        }
+        "card_settings" => {
+            match serde_json::from_str::<Versioned<CardSettings>>(&msg) {
+                Ok(request) => server.set_card_settings(room, request.update, request.version).await,
+                Err(e) => return Err(format!("Invalid card_settings message: {}", e)),
+            }
+            return Ok(());
+        }
+        "milestones" => {
+            match serde_json::from_str::<Versioned<MilestonesRequest>>(&msg) {
+                Ok(request) => server.set_milestones(room, request.update.enabled, request.version).await,
+                Err(e) => return Err(format!("Invalid milestones message: {}", e)),
+            }
+            return Ok(());
+        }
+        "assign_card" => {
+            match serde_json::from_str::<AssignCardRequest>(&msg) {
+                Ok(request) => server.assign_card(room, request.client_id, request.card).await,
+                Err(e) => return Err(format!("Invalid assign_card message: {}", e)),
+            }
+            return Ok(());
+        }
+        "get_settings" => {
+            server.get_settings(room).await;
+            return Ok(());
+        }
+        "report" => {
+            server.report(room).await;
+            return Ok(());
+        }
+        "force_refresh" => {
+            match serde_json::from_str::<ForceRefreshRequest>(&msg) {
+                Ok(request) => { server.force_refresh(Some(room), request).await; }
+                Err(e) => return Err(format!("Invalid force_refresh message: {}", e)),
+            }
+            return Ok(());
+        }
+        "set_phase" => {
+            match serde_json::from_str::<SetPhaseRequest>(&msg) {
+                Ok(request) => server.set_phase(room, request.phase).await,
+                Err(e) => return Err(format!("Invalid set_phase message: {}", e)),
+            }
+            return Ok(());
+        }
+        "chaos" => {
+            match serde_json::from_str::<ChaosRequest>(&msg) {
+                // Refusals are reported to the host by the room
+                Ok(request) => { let _ = server.set_chaos(room, request, false).await; }
+                Err(e) => return Err(format!("Invalid chaos message: {}", e)),
+            }
+            return Ok(());
+        }
+        "allowed_origins" => {
+            match serde_json::from_str::<AllowedOrigins>(&msg) {
+                Ok(origins) => server.set_allowed_origins(room, origins).await,
+                Err(e) => return Err(format!("Invalid allowed_origins message: {}", e)),
+            }
+            return Ok(());
+        }
+        "seat_map" => {
+            match serde_json::from_str::<SeatMap>(&msg) {
+                Ok(seat_map) => server.set_seat_map(room, seat_map).await,
+                Err(e) => return Err(format!("Invalid seat_map message: {}", e)),
+            }
+            return Ok(());
+        }
+        "assign_seat" => {
+            match serde_json::from_str::<AssignSeatRequest>(&msg) {
+                Ok(request) => server.take_seat(room, request.client_id, request.seat, true).await,
+                Err(e) => return Err(format!("Invalid assign_seat message: {}", e)),
+            }
+            return Ok(());
+        }
+        "tag_connection" => {
+            match serde_json::from_str::<TagConnectionRequest>(&msg) {
+                Ok(request) => server.tag_connection(room, request.client_id, request.note).await,
+                Err(e) => return Err(format!("Invalid tag_connection message: {}", e)),
+            }
+            return Ok(());
+        }
+        "client_versions" => {
+            server.client_versions(room).await;
+            return Ok(());
+        }
+        "schedule_announcement" => {
+            match serde_json::from_str::<ScheduleAnnouncementRequest>(&msg) {
+                Ok(request) => server.schedule_announcement(room, request).await,
+                Err(e) => return Err(format!("Invalid schedule_announcement message: {}", e)),
+            }
+            return Ok(());
+        }
+        "cancel_announcement" => {
+            match serde_json::from_str::<CancelAnnouncementRequest>(&msg) {
+                Ok(request) => server.cancel_announcement(room, request.id).await,
+                Err(e) => return Err(format!("Invalid cancel_announcement message: {}", e)),
+            }
+            return Ok(());
+        }
+        "list_announcements" => {
+            server.list_announcements(room).await;
+            return Ok(());
+        }
+        "verify_claim_code" => {
+            match serde_json::from_str::<VerifyClaimCodeRequest>(&msg) {
+                Ok(request) => match request.validate() {
+                    Ok(()) => server.verify_claim_code(room, request.code).await,
+                    Err(error) => return Err(refuse(&server, room, error).await),
+                },
+                Err(e) => return Err(format!("Invalid verify_claim_code message: {}", e)),
+            }
+            return Ok(());
+        }
+        "email_settings" => {
+            match serde_json::from_str::<EmailSettings>(&msg) {
+                Ok(settings) => match settings.validate() {
+                    Ok(()) => server.set_email_settings(room, settings).await,
+                    Err(error) => return Err(refuse(&server, room, error).await),
+                },
+                Err(e) => return Err(format!("Invalid email_settings message: {}", e)),
+            }
+            return Ok(());
+        }
+        "countdown" => {
+            match serde_json::from_str::<SetCountdownRequest>(&msg) {
+                Ok(request) => match request.validate() {
+                    Ok(()) => server.set_countdown(room, request.starts_at, request.time_zone).await,
+                    Err(error) => return Err(refuse(&server, room, error).await),
+                },
+                Err(e) => return Err(format!("Invalid countdown message: {}", e)),
+            }
+            return Ok(());
+        }
+        "redact_chat" => {
+            match serde_json::from_str::<RedactChatRequest>(&msg) {
+                Ok(request) => server.set_redact_chat(room, request.enabled).await,
+                Err(e) => return Err(format!("Invalid redact_chat message: {}", e)),
+            }
+            return Ok(());
+        }
+        "retention" => {
+            match serde_json::from_str::<RetentionPolicy>(&msg) {
+                Ok(policy) => server.set_retention(room, policy).await,
+                Err(e) => return Err(format!("Invalid retention message: {}", e)),
+            }
+            return Ok(());
+        }
+        "resume_grace" => {
+            match serde_json::from_str::<ResumeGraceRequest>(&msg) {
+                Ok(request) => match request.validate() {
+                    Ok(()) => server.set_resume_grace(room, request.secs.map(std::time::Duration::from_secs)).await,
+                    Err(error) => return Err(refuse(&server, room, error).await),
+                },
+                Err(e) => return Err(format!("Invalid resume_grace message: {}", e)),
+            }
+            return Ok(());
+        }
+        "duplicate_sessions" => {
+            match serde_json::from_str::<DuplicateSessionPolicyRequest>(&msg) {
+                Ok(request) => server.set_duplicate_session_policy(room, request.policy).await,
+                Err(e) => return Err(format!("Invalid duplicate_sessions message: {}", e)),
+            }
+            return Ok(());
+        }
+        "offline_policy" => {
+            match serde_json::from_str::<HostOfflinePolicyRequest>(&msg) {
+                Ok(request) => server.set_host_offline_policy(room, request.policy).await,
+                Err(e) => return Err(format!("Invalid offline_policy message: {}", e)),
+            }
+            return Ok(());
+        }
+        "player_limit" => {
+            match serde_json::from_str::<PlayerLimitRequest>(&msg) {
+                Ok(request) => match request.validate() {
+                    Ok(()) => server.set_player_limit(room, request.limit).await,
+                    Err(error) => return Err(refuse(&server, room, error).await),
+                },
+                Err(e) => return Err(format!("Invalid player_limit message: {}", e)),
+            }
+            return Ok(());
+        }
+        "sound_check" => {
+            match serde_json::from_str::<SoundCheckRequest>(&msg) {
+                Ok(request) => server.start_sound_check(room, request).await,
+                Err(e) => return Err(format!("Invalid sound_check message: {}", e)),
+            }
+            return Ok(());
+        }
+        "roster" => {
+            server.roster(room).await;
+            return Ok(());
+        }
+        "record_winner" => {
+            match serde_json::from_str::<WinnerMessage>(&msg) {
+                Ok(winner) => server.record_winner(room, winner.client_id).await,
+                Err(e) => return Err(format!("Invalid record_winner message: {}", e)),
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
     match serde_json::from_str::<ClientMessage>(&msg) {
         Ok(message) => {
-            server.send(room, message.client_id, msg).await;
+            if !server.send(room, message.client_id, msg).await {
+                // Let the host UI know the player is no longer connected instead of dropping the message
+                let response = serde_json::to_string(&UndeliverableMessage::new(message.client_id)).unwrap();
+                server.notify_host(room, response).await;
+            }
         }
-        Err(_) => {
-            server.update(room, msg, USER_HOST).await;
+        // Messages naming no player go to all of them
+        Err(_) if !message_type.is_empty() => {
+            server.update(room, None, msg, UserType::Host).await;
         }
+        Err(_) => return Err("Messages need a type".to_owned()),
     }
+    Ok(())
 }
 
 fn create_command_handler(
     room: RoomId,
     server: web::Data<BingoServerHandle>
 ) -> CommandHandler {
-    Box::new(move |msg| Box::pin({
+    Box::new(move |_conn, msg| Box::pin({
     let value = server.clone();
-    async move { host_command_handler(room, value, msg).await }
+    async move {
+        if let Err(e) = host_command_handler(room, value, msg).await{
+            log::warn!("Dropped a message of a host of room {}: {}", room, e);
+        }
+    }
     }))
 }
 
 #[derive(Deserialize)]
 struct StartQuery {
-    room_token: String,
+    room_token: Option<String>,
+    /// One-time ticket from `/ws-ticket`, replaces the session cookie and room token.
+    ws_ticket: Option<String>,
+    /// The host authenticates with an `authenticate` message as the first frame instead of the
+    /// session cookie, for native apps that can't keep cross-site cookies.
+    #[serde(default)]
+    frame_auth: bool,
+}
+
+/// First frame of a `/start` connection opened with `frame_auth`.
+#[derive(Deserialize)]
+struct AuthenticateMessage{
+    /// Same value as the `Authorization` header of `/host`.
+    authorization: String,
+    /// Overrides the `room_token` query parameter.
+    room_token: Option<String>,
 }
 
 
+#[allow(clippy::too_many_arguments)]
 #[get("/start/{room}")]
 async fn start(
     req: HttpRequest,
@@ -160,33 +513,170 @@ async fn start(
     path: web::Path<(RoomId,)>,
     query: web::Query<StartQuery>,
     server: web::Data<BingoServerHandle>,
-    _datebase: web::Data<sqlx::PgPool>,
+    ws_config: web::Data<WebSocketConfig>,
+    auth_provider: web::Data<dyn AuthProvider>,
+    database: web::Data<sqlx::PgPool>,
+    throttle: web::Data<LoginThrottleConfig>,
 ) -> Result<HttpResponse, Error> {
-    let user_id = if let Some(user) = user {
-        user.id().unwrap()
+    if query.frame_auth && query.ws_ticket.is_none() {
+        let (res, mut session, mut msg_stream) = actix_ws::handle(&req, payload)?;
+        let ip = client_ip(&req, &throttle);
+        let room = path.0;
+        let room_token = query.into_inner().room_token;
+
+        spawn_local(async move {
+            let authenticated = timeout(
+                ws_config.client_timeout,
+                authenticate_first_frame(&mut session, &mut msg_stream, ip, room, room_token, &server, &**auth_provider, &database, &throttle),
+            ).await.unwrap_or_else(|_| Err("Timed out waiting for the authenticate message".to_owned()));
+
+            let user_id = match authenticated {
+                Ok(user_id) => user_id,
+                Err(reason) => {
+                    log::info!("Refused host socket for room {}: {}", room, reason);
+                    if let Some(error) = SocketFormat::default().render(&ErrorMessage::new(reason.clone()).to_string()) {
+                        let _ = session.text(error.into_owned()).await;
+                    }
+                    let _ = session.close(Some(CloseReason{ code: CloseCode::Policy, description: Some(reason) })).await;
+                    return;
+                }
+            };
+
+            tracing::info!("Welcome {} as host for room {}", user_id, room);
+            ws_handler(
+                server.clone(),
+                **ws_config,
+        SocketFormat::default(),
+                room,
+                UserType::Host,
+                None,
+                create_command_handler(room, server),
+                session,
+                msg_stream,
+            ).await;
+        });
+        return Ok(res);
+    }
+
+    let user_id = if let Some(ticket) = &query.ws_ticket {
+        match server.redeem_ws_ticket(path.0, ticket.clone(), UserType::Host).await {
+            Some(user_id) => user_id,
+            None => return Err(error::ErrorUnauthorized("Invalid or expired ws_ticket")),
+        }
     } else {
-        log::warn!("Loging Denied no active session");
-        return Err(error::ErrorUnauthorized("Login required using /host endpoint"));
+        let user_id = if let Some(user) = user {
+            user.id().unwrap()
+        } else {
+            log::warn!("Loging Denied no active session");
+            return Err(error::ErrorUnauthorized("Login required using /host endpoint"));
+        };
+
+        //Validate that the room exists, and that the requestor has host privileges
+        if !server.has_room_host_privileges(path.0, query.room_token.clone().unwrap_or_default()).await {
+            log::info!("User {} does not have host privileges for room {} or the room does not exist", user_id, path.0);
+            return Err(actix_web::error::ErrorNotFound("Room not found"));
+        }
+        user_id
     };
 
     let (res, session, msg_stream ) = actix_ws::handle(&req, payload)?;
 
-    //Validate that the room exists, and that the requestor has host privileges
-    if !server.has_room_host_privileges(path.0, query.room_token.clone()).await {
-        log::info!("User {} does not have host privileges for room {} or the room does not exist", user_id, path.0);
-        return Err(actix_web::error::ErrorNotFound("Room not found"));
-    }
-
     tracing::info!("Welcome {} as host for room {}", user_id, path.0);
     // spawn websocket handler (and don't await it) so that the response is returned immediately
     spawn_local(ws_handler(
         server.clone(),
+        **ws_config,
+        SocketFormat::default(),
         path.0,
-        USER_HOST,
+        UserType::Host,
+        None,
         create_command_handler(path.0, server),
         session,
         msg_stream,
     ));
 
     Ok(res)
-}
\ No newline at end of file
+}
+
+/// Waits for the `authenticate` message of a `frame_auth` connection and checks it the same way as
+/// `/host` followed by the room token check of `/start`, returns the host or why it was refused.
+#[allow(clippy::too_many_arguments)]
+async fn authenticate_first_frame(
+    session: &mut actix_ws::Session,
+    msg_stream: &mut actix_ws::MessageStream,
+    ip: Option<String>,
+    room: RoomId,
+    room_token: Option<String>,
+    server: &BingoServerHandle,
+    auth_provider: &dyn AuthProvider,
+    database: &sqlx::PgPool,
+    throttle: &LoginThrottleConfig,
+) -> Result<String, String> {
+    let text = loop {
+        match msg_stream.recv().await {
+            Some(Ok(Message::Text(text))) => break text,
+            Some(Ok(Message::Ping(bytes))) => {
+                let _ = session.pong(&bytes).await;
+            }
+            Some(Ok(Message::Pong(_))) => {}
+            Some(Ok(Message::Close(_))) | None => return Err("Connection closed before authenticating".to_owned()),
+            _ => return Err("Expected an authenticate message".to_owned()),
+        }
+    };
+
+    let request = match serde_json::from_str::<WSMessage>(&text) {
+        Ok(message) if message.r#type == "authenticate" => serde_json::from_str::<AuthenticateMessage>(&text)
+            .map_err(|_| "Invalid authenticate message".to_owned())?,
+        _ => return Err("Expected an authenticate message".to_owned()),
+    };
+
+    let attempt = LoginAttempt::new(auth_provider.claimed_user(&request.authorization), ip);
+    if let Some(retry_after) = attempt.begin(database, throttle).await {
+        return Err(format!("Too many failed logins, try again in {} seconds", retry_after));
+    }
+    let user = auth_provider.authenticate(&request.authorization).await.map_err(str::to_owned)?;
+    attempt.succeeded(database, throttle).await;
+    log::info!("Host {} authenticated over the socket using {}", user.username, auth_provider.name());
+
+    if !server.has_room_host_privileges(room, request.room_token.or(room_token).unwrap_or_default()).await {
+        log::info!("User {} does not have host privileges for room {} or the room does not exist", user.username, room);
+        return Err("Room not found".to_owned());
+    }
+    Ok(user.username)
+}
+
+#[cfg(test)]
+mod tests{
+    use std::cell::Cell;
+
+    use super::*;
+    use chrono::Utc;
+    use crate::vectors::{check_inbound, parse};
+
+    #[tokio::test]
+    async fn host_messages_match_vectors(){
+        let (server, _) = BingoServerHandle::without_database();
+        let server = web::Data::new(server);
+        let rooms = Cell::new(0);
+        check_inbound("host.json", async |message_type, msg| {
+            // Sent before the socket is handed to the room
+            if message_type == "authenticate"{
+                return parse::<AuthenticateMessage>(msg).map(|_| ());
+            }
+            // A room per case, so a round started by one case doesn't refuse the next
+            rooms.set(rooms.get() + 1);
+            let room = server.create_room(format!("vectors{}", rooms.get()), GameVariant::default()).await.unwrap().id;
+            host_command_handler(room, server.clone(), msg.to_owned()).await?;
+            // The room checks these itself and answers the host with the same reasons
+            match message_type {
+                "start_round" => parse::<RoundSettings>(msg)?.validate(GameVariant::default()),
+                "chaos" => parse::<ChaosRequest>(msg)?.settings.validate(),
+                "allowed_origins" => parse::<AllowedOrigins>(msg)?.normalize().map(|_| ()),
+                "seat_map" => parse::<SeatMap>(msg)?.validate(),
+                "sound_check" => parse::<SoundCheckRequest>(msg)?.validate(),
+                "schedule_announcement" => parse::<ScheduleAnnouncementRequest>(msg)?.fire_at(Utc::now()).map(|_| ()),
+                _ => Ok(()),
+            }
+        }).await;
+    }
+}
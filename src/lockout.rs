@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use actix_web::{delete, error, get, http::header, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+
+use crate::admin::Admin;
+use crate::config::LoginThrottleConfig;
+
+const KIND_USER: &str = "user";
+const KIND_IP: &str = "ip";
+
+/// How often expired failures are removed.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// A `/host` login attempt, failures count against the claimed account and the client address.
+pub struct LoginAttempt{
+    user: Option<String>,
+    ip: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct LockedOutMessage{
+    error: String,
+    retry_after_secs: u64,
+}
+
+/// Address failures are counted against. Forwarding headers are set by the client unless a proxy
+/// rewrites them, so they are only used when `trust_forwarded` is configured.
+pub fn client_ip(req: &HttpRequest, config: &LoginThrottleConfig) -> Option<String> {
+    if config.trust_forwarded{
+        return req.connection_info().realip_remote_addr().map(str::to_owned);
+    }
+    req.peer_addr().map(|addr| addr.ip().to_string())
+}
+
+impl LoginAttempt{
+    pub fn new(user: Option<String>, ip: Option<String>) -> Self {
+        Self{ user, ip }
+    }
+
+    fn subjects(&self) -> Vec<(&'static str, &str)> {
+        [(KIND_USER, &self.user), (KIND_IP, &self.ip)].into_iter()
+            .filter_map(|(kind, subject)| subject.as_deref().map(|subject| (kind, subject)))
+            .collect()
+    }
+
+    /// Counts the attempt as a failure of the user and the address before the credentials are checked,
+    /// so parallel attempts can't all get past the backoff. Returns the seconds until the user or the
+    /// address may try again while either is backing off or locked out, the attempt isn't counted then.
+    /// The database being unavailable doesn't lock anyone out.
+    pub async fn begin(&self, database: &sqlx::PgPool, config: &LoginThrottleConfig) -> Option<u64> {
+        let locked_until = self.reserve(database, config).await.unwrap_or_else(|e| {
+            log::error!("Failed to count a login attempt: {}", e);
+            None
+        });
+
+        let retry_after = (locked_until? - Utc::now()).num_seconds().max(1) as u64;
+        log::warn!("Refused login of {} from {} for another {} seconds", self.user.as_deref().unwrap_or("unknown user"), self.ip.as_deref().unwrap_or("unknown address"), retry_after);
+        Some(retry_after)
+    }
+
+    /// Refuses the attempt with 429 while the user or the address is backing off or locked out.
+    pub async fn check(&self, database: &sqlx::PgPool, config: &LoginThrottleConfig) -> actix_web::Result<()> {
+        let Some(retry_after) = self.begin(database, config).await else {
+            return Ok(());
+        };
+        let response = HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+            .json(LockedOutMessage{ error: "Too many failed logins, try again later".to_owned(), retry_after_secs: retry_after });
+        Err(error::InternalError::from_response("locked out", response).into())
+    }
+
+    /// Checks and counts the attempt in one transaction, the row locks make concurrent attempts of the
+    /// same user or address wait for each other. Users are always locked before addresses.
+    async fn reserve(&self, database: &sqlx::PgPool, config: &LoginThrottleConfig) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let mut tx = database.begin().await?;
+        let mut counted = Vec::new();
+        for (kind, subject) in self.subjects(){
+            sqlx::query("INSERT INTO login_failures (kind, subject, failures) VALUES ($1, $2, 0) ON CONFLICT DO NOTHING")
+                .bind(kind)
+                .bind(subject)
+                .execute(&mut *tx)
+                .await?;
+            // Failures older than a lockout are forgotten
+            let (failures, expired, locked_until): (i32, bool, Option<DateTime<Utc>>) = sqlx::query_as("SELECT failures, \
+                last_failure_at < now() - make_interval(secs => $3), CASE WHEN locked_until > now() THEN locked_until END \
+                FROM login_failures WHERE kind = $1 AND subject = $2 FOR UPDATE")
+                .bind(kind)
+                .bind(subject)
+                .bind(config.lockout.as_secs_f64())
+                .fetch_one(&mut *tx)
+                .await?;
+            if locked_until.is_some(){
+                return Ok(locked_until);
+            }
+            counted.push((kind, subject, if expired { 1 } else { failures as u32 + 1 }));
+        }
+
+        for (kind, subject, failures) in counted{
+            let wait = backoff(config, failures);
+            if failures == config.lockout_threshold{
+                log::warn!("Locked out {} {} for {} seconds after {} failed logins", kind, subject, config.lockout.as_secs(), failures);
+            }
+            sqlx::query("UPDATE login_failures SET failures = $3, last_failure_at = now(), locked_until = now() + make_interval(secs => $4) \
+                WHERE kind = $1 AND subject = $2")
+                .bind(kind)
+                .bind(subject)
+                .bind(failures as i32)
+                .bind(wait.map(|wait| wait.as_secs_f64()))
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(None)
+    }
+
+    /// Takes back the failure counted by `begin`. The failures of the user are forgotten, those of the
+    /// address only drop by this attempt so one valid account can't reset the backoff of an address
+    /// guessing others.
+    pub async fn succeeded(&self, database: &sqlx::PgPool, config: &LoginThrottleConfig){
+        if let Err(e) = self.release(database, config).await{
+            log::error!("Failed to reset the login failures of {}: {}", self.user.as_deref().or(self.ip.as_deref()).unwrap_or("unknown user"), e);
+        }
+    }
+
+    async fn release(&self, database: &sqlx::PgPool, config: &LoginThrottleConfig) -> Result<(), sqlx::Error> {
+        let mut tx = database.begin().await?;
+        if let Some(user) = &self.user{
+            sqlx::query("DELETE FROM login_failures WHERE kind = $1 AND subject = $2")
+                .bind(KIND_USER)
+                .bind(user)
+                .execute(&mut *tx)
+                .await?;
+        }
+        if let Some(ip) = &self.ip{
+            let failures: Option<i32> = sqlx::query_scalar("SELECT failures FROM login_failures WHERE kind = $1 AND subject = $2 FOR UPDATE")
+                .bind(KIND_IP)
+                .bind(ip)
+                .fetch_optional(&mut *tx)
+                .await?;
+            if let Some(failures) = failures{
+                let failures = (failures as u32).saturating_sub(1);
+                let wait = backoff(config, failures).map(|wait| wait.as_secs_f64());
+                sqlx::query("UPDATE login_failures SET failures = $3, \
+                    locked_until = CASE WHEN $4::float8 IS NULL THEN NULL ELSE LEAST(locked_until, now() + make_interval(secs => $4)) END \
+                    WHERE kind = $1 AND subject = $2")
+                    .bind(KIND_IP)
+                    .bind(ip)
+                    .bind(failures as i32)
+                    .bind(wait)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        tx.commit().await
+    }
+}
+
+/// Wait before the next attempt after the given number of consecutive failures.
+fn backoff(config: &LoginThrottleConfig, failures: u32) -> Option<Duration> {
+    if failures >= config.lockout_threshold{
+        return Some(config.lockout);
+    }
+    let excess = failures.checked_sub(config.free_attempts).filter(|excess| *excess > 0)?;
+    Some(config.backoff_base.saturating_mul(1 << (excess - 1).min(16)).min(config.lockout))
+}
+
+/// Removes failures that are older than a lockout and no longer lock anyone out, runs for the life of the server.
+pub async fn prune_expired(database: sqlx::PgPool, lockout: Duration){
+    let mut ticks = tokio::time::interval(PRUNE_INTERVAL);
+    loop{
+        ticks.tick().await;
+        let result = sqlx::query("DELETE FROM login_failures WHERE last_failure_at < now() - make_interval(secs => $1) \
+            AND (locked_until IS NULL OR locked_until <= now())")
+            .bind(lockout.as_secs_f64())
+            .execute(&database)
+            .await;
+        match result {
+            Ok(result) if result.rows_affected() > 0 => log::info!("Removed {} expired login failures", result.rows_affected()),
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to remove expired login failures: {}", e),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct LoginFailures{
+    kind: String,
+    subject: String,
+    failures: i32,
+    last_failure_at: DateTime<Utc>,
+    /// Set while the subject is backing off or locked out.
+    locked_until: Option<DateTime<Utc>>,
+}
+
+/// Users and addresses with recent failed logins, locked out ones first.
+#[get("/admin/lockouts")]
+async fn list_lockouts(
+    _admin: Admin,
+    database: web::Data<sqlx::PgPool>,
+    config: web::Data<LoginThrottleConfig>,
+) -> actix_web::Result<HttpResponse> {
+    let failures: Vec<LoginFailures> = sqlx::query_as("SELECT kind, subject, failures, last_failure_at, \
+        CASE WHEN locked_until > now() THEN locked_until END AS locked_until \
+        FROM login_failures WHERE last_failure_at > now() - make_interval(secs => $1) \
+        ORDER BY locked_until DESC NULLS LAST, failures DESC")
+        .bind(config.lockout.as_secs_f64())
+        .fetch_all(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to list login failures: {}", e);
+            error::ErrorInternalServerError("Failed to list lockouts")
+        })?;
+    Ok(HttpResponse::Ok().json(failures))
+}
+
+/// Lifts the lockout of a user or address and forgets its failures.
+#[delete("/admin/lockouts/{kind}/{subject}")]
+async fn clear_lockout(
+    _admin: Admin,
+    path: web::Path<(String, String)>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let (kind, subject) = path.into_inner();
+    if kind != KIND_USER && kind != KIND_IP{
+        return Err(error::ErrorBadRequest("kind must be user or ip"));
+    }
+    let result = sqlx::query("DELETE FROM login_failures WHERE kind = $1 AND subject = $2")
+        .bind(&kind)
+        .bind(&subject)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to clear the lockout of {} {}: {}", kind, subject, e);
+            error::ErrorInternalServerError("Failed to clear lockout")
+        })?;
+    if result.rows_affected() == 0{
+        return Err(error::ErrorNotFound("No failed logins recorded"));
+    }
+    log::info!("Cleared the login failures of {} {}", kind, subject);
+    Ok(HttpResponse::NoContent().finish())
+}
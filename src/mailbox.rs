@@ -0,0 +1,106 @@
+use std::sync::{atomic::{AtomicU64, AtomicUsize, Ordering}, Arc};
+
+use tokio::sync::mpsc;
+
+/// Reply to a host query that was shed while the server is overloaded.
+pub const SERVER_BUSY: &str = "The server is busy, try again in a moment";
+
+/// Depth of the room server's command queue, shared by the actor and every handle so low priority
+/// commands can be shed before they are queued.
+#[derive(Debug, Default)]
+pub struct MailboxStatus{
+    /// Queued commands from which chat, reactions and statistics queries are shed, 0 disables shedding.
+    threshold: AtomicUsize,
+    depth: AtomicUsize,
+    peak: AtomicUsize,
+    shed: AtomicU64,
+}
+
+#[derive(serde::Serialize)]
+pub struct MailboxReport{
+    depth: usize,
+    peak: usize,
+    threshold: usize,
+    overloaded: bool,
+    shed: u64,
+}
+
+impl MailboxStatus{
+    pub fn new(threshold: usize) -> Self {
+        Self{
+            threshold: AtomicUsize::new(threshold),
+            ..Self::default()
+        }
+    }
+
+    /// Changes the threshold, e.g. when `max_command_backlog` is overridden at runtime.
+    pub fn set_threshold(&self, threshold: usize){
+        self.threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    /// Counts a command queued by a handle.
+    fn enqueued(&self){
+        let depth = self.depth.fetch_add(1, Ordering::Relaxed) + 1;
+        self.peak.fetch_max(depth, Ordering::Relaxed);
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        if threshold > 0 && depth == threshold{
+            log::warn!("Command queue at {} commands, shedding chat, reactions and statistics queries", depth);
+        }
+    }
+
+    /// Called by the actor each time it takes a command, and by handles when a send failed.
+    pub fn dequeued(&self){
+        let depth = self.depth.fetch_sub(1, Ordering::Relaxed).saturating_sub(1);
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        if threshold > 0 && depth + 1 == threshold{
+            log::info!("Command queue back to {} commands, {} commands shed so far", depth, self.shed.load(Ordering::Relaxed));
+        }
+    }
+
+    /// True while the queue is at or past the threshold.
+    pub fn is_overloaded(&self) -> bool {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        threshold > 0 && self.depth.load(Ordering::Relaxed) >= threshold
+    }
+
+    /// Counts a command dropped instead of queued.
+    pub fn shed(&self){
+        self.shed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> MailboxReport {
+        MailboxReport{
+            depth: self.depth.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            threshold: self.threshold.load(Ordering::Relaxed),
+            overloaded: self.is_overloaded(),
+            shed: self.shed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Sender of the actor's commands that counts each queued command, so the depth is current while the
+/// actor is still busy with an earlier command.
+#[derive(Debug)]
+pub struct CountingSender<T>{
+    tx: mpsc::UnboundedSender<T>,
+    status: Arc<MailboxStatus>,
+}
+
+impl<T> Clone for CountingSender<T>{
+    fn clone(&self) -> Self {
+        Self{ tx: self.tx.clone(), status: self.status.clone() }
+    }
+}
+
+impl<T> CountingSender<T>{
+    pub fn new(tx: mpsc::UnboundedSender<T>, status: Arc<MailboxStatus>) -> Self {
+        Self{ tx, status }
+    }
+
+    /// Counted before sending so the actor can never take the command before it is counted.
+    pub fn send(&self, value: T) -> Result<(), mpsc::error::SendError<T>> {
+        self.status.enqueued();
+        self.tx.send(value).inspect_err(|_| self.status.dequeued())
+    }
+}
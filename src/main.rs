@@ -1,18 +1,92 @@
 
+mod config;
+mod access_log;
+mod accessibility;
+mod admin;
+mod admin_ui;
+mod analytics;
+mod archive;
+mod attendance;
+mod auto_call;
+mod announcements;
+mod api_keys;
+mod auth;
+mod board;
+mod card;
+mod chaos;
+mod cors;
+mod crypto;
+mod devices;
+mod drain;
+mod email;
+mod draw;
+mod events;
+mod mailbox;
+mod message_log;
+mod milestones;
+mod notes;
+mod object_store;
+mod offline;
+mod overrides;
+mod mqtt;
+mod observer;
+mod fairness;
+mod grpc;
+mod game;
+mod ghosts;
+mod graphql;
+mod features;
+mod lockout;
+mod persistence;
+mod phase;
+mod players;
+mod privacy;
+mod replay;
+mod recent_errors;
+mod redact;
+mod resume;
+mod retention;
+mod report;
 mod room;
+mod schedule;
+mod seats;
+mod settings;
+mod sms;
+mod sound_check;
+mod stats;
+mod subscription;
+mod throttle;
+mod transfer;
+mod translate;
+mod tickets;
+mod timezone;
+mod tournaments;
+mod variant;
+mod versions;
+mod waitlist;
 mod wshandler;
+mod ws_ticket;
 mod client;
+mod handoff;
 mod host;
+mod presence;
+mod round;
+#[cfg(test)]
+mod vectors;
 
-use actix_cors::Cors;
+use std::sync::Arc;
+
+use actix_files::Files;
 use actix_identity::IdentityMiddleware;
 use actix_session::{config::PersistentSession, storage::CookieSessionStore, SessionMiddleware};
 use actix_web::{
-    cookie::{time::Duration, Key, SameSite}, http, middleware, web::{self, ServiceConfig}
+    cookie::{time::Duration, Key}, middleware, web::{self, ServiceConfig}
 };
-use host::AuthUser;
+use auth::AuthUser;
 use room::BingoServer;
+#[cfg(all(feature = "shuttle", not(feature = "self-hosted")))]
 use shuttle_actix_web::ShuttleActixWeb;
+#[cfg(all(feature = "shuttle", not(feature = "self-hosted")))]
 use shuttle_runtime::SecretStore;
 use sqlx::PgPool;
 use sqlx::types::Uuid;
@@ -20,10 +94,37 @@ use tokio::spawn;
 use crate::host::{host_room,start};
 use crate::room::RoomCreds;
 use crate::client::join;
+use crate::board::join_board;
+use crate::tickets::import_tickets;
+use crate::transfer::{accept_transfer, cancel_transfer, offer_transfer};
+use crate::attendance::attendance_csv;
+use crate::ws_ticket::issue_ws_ticket;
+use crate::events::{admin_events, host_events};
+use crate::players::{my_card, my_cards, search_players};
+use crate::schedule::{cancel_scheduled_room, list_schedule, schedule_feed, schedule_room};
+use crate::stats::host_stats;
+use crate::tournaments::{add_tournament_room, create_tournament, list_tournaments, tournament_leaderboard};
+use crate::graphql::graphql_query;
+use crate::fairness::round_audit;
+use crate::replay::room_replay;
+use crate::api_keys::{create_api_key, list_api_keys, revoke_api_key};
+use crate::config::{Config, Settings};
+use crate::admin::{client_versions, close_room, create_user, force_refresh, ghost_status, hash_token, host_connections, list_recent_errors, list_users, live_rooms, mailbox_status, message_log_levels, persistence_status, room_chaos, rotate_user_token, set_message_log_level, set_room_message_log_level};
+use crate::admin_ui::admin_page;
+use crate::cors::RuntimeOrigins;
+use crate::overrides::{list_config, remove_config, set_config};
+use crate::drain::{drain_status, set_draining};
+use crate::observer::observe_room;
+use crate::lockout::{clear_lockout, list_lockouts};
+use crate::handoff::{export_state, import_state};
+use crate::persistence::Persistence;
+use crate::sms::SmsBridge;
+use crate::mqtt::MqttBridge;
+use crate::analytics::Analytics;
+use crate::archive::Archive;
+use crate::privacy::{admin_delete_host_data, delete_host_data, delete_player_data};
 
-const FIVE_MINUTES: Duration = Duration::minutes(5);
-
-async fn load_accounts(pool: &sqlx::PgPool, secrets: &SecretStore) {
+async fn load_accounts(pool: &sqlx::PgPool, secrets: &Settings) {
 
     let mut count = 0;
     loop
@@ -59,11 +160,55 @@ async fn load_accounts(pool: &sqlx::PgPool, secrets: &SecretStore) {
     }
 }
 
+#[cfg(not(any(feature = "shuttle", feature = "self-hosted")))]
+compile_error!("Enable the shuttle or the self-hosted feature");
+
+#[cfg(all(feature = "shuttle", not(feature = "self-hosted")))]
 #[shuttle_runtime::main]
 async fn main(
     #[shuttle_shared_db::Postgres] pool: PgPool,
     #[shuttle_runtime::Secrets] secrets: SecretStore,
 ) -> ShuttleActixWeb<impl FnOnce(&mut ServiceConfig) + Send + Clone + 'static> {
+    let service_config = setup(pool, Settings::from(secrets)).await.map_err(shuttle_runtime::Error::from)?;
+    Ok(service_config.into())
+}
+
+/// Runs without Shuttle, e.g. on a machine at the venue. Settings come from the file named by
+/// `SETTINGS_FILE`, `Secrets.toml` in the working directory when present, and the environment. The
+/// database is `DATABASE_URL` and the server listens on `BIND_ADDR`, `0.0.0.0:8000` by default.
+#[cfg(feature = "self-hosted")]
+#[actix_web::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let settings_file = std::env::var("SETTINGS_FILE").ok().map(std::path::PathBuf::from)
+        .or_else(|| Some(std::path::PathBuf::from("Secrets.toml")).filter(|path| path.exists()));
+    let secrets = match settings_file {
+        Some(path) => {
+            log::info!("Reading settings from {}", path.display());
+            Settings::from_file(&path)?
+        }
+        None => Settings::default(),
+    };
+    let lookup = |key: &str| secrets.get(key).or_else(|| std::env::var(key).ok());
+    let database_url = lookup("DATABASE_URL").ok_or_else(|| anyhow::anyhow!("DATABASE_URL is required when self-hosted"))?;
+    let bind_addr = lookup("BIND_ADDR").unwrap_or_else(|| "0.0.0.0:8000".to_owned());
+
+    let pool = sqlx::postgres::PgPoolOptions::new().connect(&database_url).await?;
+    let service_config = setup(pool, secrets).await?;
+
+    log::info!("Listening on {}", bind_addr);
+    actix_web::HttpServer::new(move || actix_web::App::new().configure(service_config.clone()))
+        .bind(&bind_addr)?
+        .run()
+        .await?;
+    Ok(())
+}
+
+/// Runs the migrations, starts the room server and its background tasks, and returns the routes.
+async fn setup(pool: PgPool, secrets: Settings) -> anyhow::Result<impl FnOnce(&mut ServiceConfig) + Send + Clone + 'static> {
+    let config = Config::load(&secrets)?;
+    log::info!("Effective configuration: {:?}", config);
 
     sqlx::migrate!()
         .run(&pool)
@@ -75,8 +220,7 @@ async fn main(
 
     let rows: Vec<RoomCreds> = sqlx::query_as("SELECT * FROM rooms")
         .fetch_all(&pool)
-        .await
-        .map_err(|e| shuttle_runtime::Error::from(anyhow::Error::new(e)))?;
+        .await?;
 
     for row in rows {
         println!("{:?}", row);
@@ -85,52 +229,139 @@ async fn main(
 
     let users: Vec<AuthUser> = sqlx::query_as("SELECT * FROM users")
         .fetch_all(&pool)
-        .await
-        .map_err(|e| shuttle_runtime::Error::from(anyhow::Error::new(e)))?;
+        .await?;
 
     for user in users {
         println!("{:?}", user);
     }
 
     let secret_key = Key::generate();
+    let session_ttl = Duration::seconds(config.session_ttl.as_secs() as i64);
+
+    let auth_provider = auth::create_provider(&config.auth, pool.clone());
+
+    let persistence = Persistence::start(pool.clone(), config.persistence);
+    let db_status = persistence.status();
 
-    let (mut server, server_tx) = BingoServer::new(pool.clone());
+    let translator = translate::create_translator(config.translator.as_ref());
+    let mqtt = config.mqtt.as_ref().map(MqttBridge::start).transpose()?;
+    let analytics = config.analytics.as_ref().map(Analytics::start);
+    let archive = config.archive.clone().map(|archive| Archive::new(archive, config.encryption.clone()));
+    let mailer = email::create_mailer(config.mail.as_ref());
+    let (mut server, server_tx) = BingoServer::new(pool.clone(), config.rooms, persistence, config.encryption.clone(), translator, config.sms.clone().map(SmsBridge::new), mqtt, analytics, archive.clone(), mailer);
     server.populate_rooms().await;
+    let mailbox = server_tx.mailbox();
+    let ghosts = server_tx.ghosts();
+    let errors = server_tx.errors();
+    let message_log = server_tx.message_log();
     let _server = spawn(server.run());
 
-    let config = move |cfg: &mut ServiceConfig| {
+    let runtime_origins = Arc::new(RuntimeOrigins::default());
+    spawn(overrides::watch(pool.clone(), server_tx.clone(), runtime_origins.clone(), config.config_poll_interval));
+    spawn(lockout::prune_expired(pool.clone(), config.login.lockout));
+
+    if let Some(addr) = config.grpc_addr {
+        spawn(grpc::serve(addr, server_tx.clone(), pool.clone()));
+    }
+
+    let history_schema = graphql::build_schema(pool.clone());
+
+    let service_config = move |cfg: &mut ServiceConfig| {
         cfg.service(
             web::scope("")
                 .app_data(web::Data::new(server_tx.clone()))
+                .app_data(web::Data::new(config.websocket))
+                .app_data(web::Data::new(config.login))
+                .app_data(web::Data::from(auth_provider.clone()))
                 .app_data(web::Data::new(pool.clone()))
+                .app_data(web::Data::new(config.admin.clone()))
+                .app_data(web::Data::from(db_status.clone()))
+                .app_data(web::Data::from(mailbox.clone()))
+                .app_data(web::Data::from(ghosts.clone()))
+                .app_data(web::Data::from(errors.clone()))
+                .app_data(web::Data::from(message_log.clone()))
+                .app_data(web::Data::new(history_schema.clone()))
+                .app_data(web::Data::new(archive.clone()))
                 .service(host_room)
                 .service(start)
                 .service(join)
+                .service(my_cards)
+                .service(my_card)
+                .service(join_board)
+                .service(import_tickets)
+                .service(offer_transfer)
+                .service(accept_transfer)
+                .service(cancel_transfer)
+                .service(attendance_csv)
+                .service(issue_ws_ticket)
+                .service(host_events)
+                .service(host_stats)
+                .service(search_players)
+                .service(schedule_feed)
+                .service(schedule_room)
+                .service(list_schedule)
+                .service(cancel_scheduled_room)
+                .service(create_tournament)
+                .service(list_tournaments)
+                .service(add_tournament_room)
+                .service(tournament_leaderboard)
+                .service(graphql_query)
+                .service(round_audit)
+                .service(room_replay)
+                .service(create_api_key)
+                .service(list_api_keys)
+                .service(revoke_api_key)
+                .service(persistence_status)
+                .service(mailbox_status)
+                .service(ghost_status)
+                .service(list_recent_errors)
+                .service(live_rooms)
+                .service(admin_page)
+                .service(message_log_levels)
+                .service(set_message_log_level)
+                .service(set_room_message_log_level)
+                .service(host_connections)
+                .service(client_versions)
+                .service(force_refresh)
+                .service(room_chaos)
+                .service(observe_room)
+                .service(close_room)
+                .service(admin_events)
+                .service(list_users)
+                .service(create_user)
+                .service(rotate_user_token)
+                .service(hash_token)
+                .service(list_config)
+                .service(set_config)
+                .service(remove_config)
+                .service(list_lockouts)
+                .service(clear_lockout)
+                .service(set_draining)
+                .service(drain_status)
+                .service(export_state)
+                .service(import_state)
+                .service(delete_player_data)
+                .service(delete_host_data)
+                .service(admin_delete_host_data)
+                // Registered last so the API routes take precedence over files of the same name
+                .configure(|cfg| if let Some(dir) = &config.static_dir {
+                    cfg.service(Files::new("/", dir).index_file("index.html"));
+                })
                 .wrap(IdentityMiddleware::default())
                 .wrap(
                     SessionMiddleware::builder(CookieSessionStore::default(), secret_key.clone())
                         .cookie_name("JSESSIONID".to_owned())
-                        .cookie_secure(true)
+                        .cookie_secure(config.cookie_secure)
                         .cookie_http_only(true)
-                        .cookie_same_site(SameSite::None)
-                        .session_lifecycle(PersistentSession::default().session_ttl(FIVE_MINUTES))
+                        .cookie_same_site(config.cookie_same_site.same_site())
+                        .session_lifecycle(PersistentSession::default().session_ttl(session_ttl))
                         .build(),
                 )
                 .wrap(middleware::NormalizePath::trim())
-                .wrap(middleware::Logger::default())
-                .wrap(
-                    Cors::default()
-                        .allowed_origin("http://127.0.0.1:5500") // Replace with your allowed origin
-                        .allowed_origin("http://10.0.0.199:5500") // Replace with your allowed origin
-                        .allowed_origin("https://web2098.github.io") // Replace with your allowed origin
-                        .allowed_methods(vec!["GET", "POST"])
-                        .allowed_headers(vec![http::header::AUTHORIZATION, http::header::ACCEPT])
-                        .allowed_header(http::header::CONTENT_TYPE)
-                        .supports_credentials()
-                        .max_age(3600),
-                ),
+                .wrap(access_log::logger())
+                .wrap(config.cors.build(runtime_origins.clone())),
         );
     };
 
-    Ok(config.into())
+    Ok(service_config)
 }
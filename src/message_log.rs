@@ -0,0 +1,158 @@
+use std::{collections::HashMap, sync::{atomic::{AtomicBool, Ordering}, RwLock}};
+
+use serde_json::Value;
+
+use crate::room::{RoomId, SessionId, UserType};
+
+/// Keys whose values never reach the log, credentials and anything naming a player.
+const REDACTED_KEYS: &[&str] = &[
+    "token", "room_token", "board_token", "player_token", "ws_ticket", "authorization", "claim_code",
+    "name", "display_name", "username", "player", "text", "original_text", "note", "phone", "numbers", "email",
+    "report_to",
+];
+const REDACTED: &str = "[redacted]";
+
+/// How much of the socket traffic of a room is logged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageLogLevel{
+    #[default]
+    Off,
+    /// Direction, connection and message type only.
+    Types,
+    /// The whole message with tokens and player names redacted.
+    Redacted,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction{
+    Inbound,
+    Outbound,
+}
+
+/// Logging of socket messages, off unless enabled through the admin API for all rooms or single ones.
+/// Shared by every socket, which checks it for each message.
+#[derive(Debug, Default)]
+pub struct MessageLog{
+    /// Set while any level is not `Off`, so sockets skip the locks in the common case.
+    active: AtomicBool,
+    levels: RwLock<MessageLogLevels>,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct MessageLogLevels{
+    default: MessageLogLevel,
+    rooms: HashMap<RoomId, MessageLogLevel>,
+}
+
+impl MessageLog{
+    pub fn level(&self, room: RoomId) -> MessageLogLevel {
+        if !self.active.load(Ordering::Relaxed){
+            return MessageLogLevel::Off;
+        }
+        let levels = self.levels.read().unwrap();
+        levels.rooms.get(&room).copied().unwrap_or(levels.default)
+    }
+
+    pub fn set_default(&self, level: MessageLogLevel){
+        let mut levels = self.levels.write().unwrap();
+        levels.default = level;
+        self.update_active(&levels);
+    }
+
+    /// Overrides the default for one room, `None` goes back to the default.
+    pub fn set_room(&self, room: RoomId, level: Option<MessageLogLevel>){
+        let mut levels = self.levels.write().unwrap();
+        match level {
+            Some(level) => levels.rooms.insert(room, level),
+            None => levels.rooms.remove(&room),
+        };
+        self.update_active(&levels);
+    }
+
+    fn update_active(&self, levels: &MessageLogLevels){
+        let active = levels.default != MessageLogLevel::Off || levels.rooms.values().any(|level| *level != MessageLogLevel::Off);
+        self.active.store(active, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> MessageLogLevels {
+        self.levels.read().unwrap().clone()
+    }
+
+    pub fn record(&self, room: RoomId, conn_id: SessionId, user_type: UserType, direction: Direction, msg: &str){
+        let level = self.level(room);
+        if level == MessageLogLevel::Off{
+            return;
+        }
+        let Ok(mut message) = serde_json::from_str::<Value>(msg) else {
+            tracing::info!(target: "messages", room, conn_id = %conn_id, role = ?user_type, ?direction, bytes = msg.len(), "unparseable message");
+            return;
+        };
+        let message_type = message.get("type").and_then(Value::as_str).unwrap_or_default().to_owned();
+        match level {
+            MessageLogLevel::Off => {}
+            MessageLogLevel::Types => {
+                tracing::info!(target: "messages", room, conn_id = %conn_id, role = ?user_type, ?direction, message_type, bytes = msg.len());
+            }
+            MessageLogLevel::Redacted => {
+                redact(&mut message);
+                tracing::info!(target: "messages", room, conn_id = %conn_id, role = ?user_type, ?direction, message_type, %message);
+            }
+        }
+    }
+}
+
+fn redact(value: &mut Value){
+    match value {
+        Value::Object(fields) => {
+            for (key, value) in fields.iter_mut(){
+                if REDACTED_KEYS.contains(&key.as_str()){
+                    *value = Value::String(REDACTED.to_owned());
+                }
+                else{
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn redacts_tokens_and_player_names(){
+        let mut message: Value = serde_json::json!({
+            "type": "roster",
+            "room_token": "f3a9",
+            "players": [{ "client_id": 7, "name": "Alice", "preferences": { "sound": true } }],
+        });
+        redact(&mut message);
+        assert_eq!(message, serde_json::json!({
+            "type": "roster",
+            "room_token": REDACTED,
+            "players": [{ "client_id": 7, "name": REDACTED, "preferences": { "sound": true } }],
+        }));
+    }
+
+    #[test]
+    fn redacts_both_texts_of_translated_chat(){
+        let mut message: Value = serde_json::json!({
+            "type": "chat",
+            "text": "Bonne chance",
+            "original_text": "Good luck",
+            "translated_to": "fr",
+        });
+        redact(&mut message);
+        assert_eq!(message, serde_json::json!({
+            "type": "chat",
+            "text": REDACTED,
+            "original_text": REDACTED,
+            "translated_to": "fr",
+        }));
+    }
+}
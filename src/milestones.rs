@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use crate::card::Card;
+use crate::draw::Number;
+use crate::room::SessionId;
+
+/// Joins that are celebrated, then every thousandth player.
+const PLAYER_MILESTONES: [usize; 6] = [10, 25, 50, 100, 250, 500];
+
+/// Players one number away from a bingo that make the room "tense".
+pub const WAITING_MILESTONE: usize = 5;
+
+pub fn is_player_milestone(joined: usize) -> bool {
+    PLAYER_MILESTONES.contains(&joined) || (joined > 0 && joined.is_multiple_of(1000))
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Milestone{
+    /// `value` players have joined the room.
+    PlayersJoined,
+    /// Half of the ball pool has been called, `value` is the call count.
+    HalfwayThroughPool,
+    /// `value` players are one number away from a bingo.
+    PlayersWaiting,
+}
+
+/// Crowd engagement hook, only sent in rooms where the host enabled milestones.
+#[derive(serde::Serialize)]
+pub struct MilestoneMessage{
+    r#type: String,
+    milestone: Milestone,
+    value: usize,
+}
+
+impl MilestoneMessage{
+    pub fn new(milestone: Milestone, value: usize) -> Self {
+        Self{
+            r#type: "milestone".to_string(),
+            milestone,
+            value,
+        }
+    }
+}
+
+/// Counts the players holding a card that is one number away.
+pub fn players_waiting(cards: &HashMap<SessionId, Vec<Card>>, called: &[Number]) -> usize {
+    cards.values().filter(|cards| cards.iter().any(|card| card.numbers_to_go(called) == 1)).count()
+}
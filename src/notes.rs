@@ -0,0 +1,64 @@
+use crate::room::SessionId;
+
+/// Most tags kept for a connection.
+const MAX_TAGS: usize = 10;
+
+const MAX_TAG_LENGTH: usize = 40;
+
+const MAX_NOTE_LENGTH: usize = 500;
+
+/// Tags and a free text note the host keeps on a connection, e.g. `table 5` and `paid cash`.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct ConnectionNote{
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+}
+
+impl ConnectionNote{
+    /// Trims the tags and note to their limits, dropping empty and repeated tags.
+    pub fn clean(self) -> Self {
+        let mut tags: Vec<String> = Vec::new();
+        for tag in self.tags {
+            let tag: String = tag.trim().chars().filter(|c| !c.is_control()).take(MAX_TAG_LENGTH).collect();
+            if !tag.is_empty() && !tags.contains(&tag){
+                tags.push(tag);
+            }
+        }
+        tags.truncate(MAX_TAGS);
+        let note = self.note
+            .map(|note| note.trim().chars().take(MAX_NOTE_LENGTH).collect::<String>())
+            .filter(|note| !note.is_empty());
+        Self{ tags, note }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.note.is_none()
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TagConnectionRequest{
+    pub client_id: SessionId,
+    #[serde(flatten)]
+    pub note: ConnectionNote,
+}
+
+/// Confirms the stored tags and note of a connection to the hosts.
+#[derive(serde::Serialize)]
+pub struct ConnectionTaggedMessage<'a>{
+    r#type: String,
+    client_id: SessionId,
+    #[serde(flatten)]
+    note: &'a ConnectionNote,
+}
+
+impl<'a> ConnectionTaggedMessage<'a>{
+    pub fn new(client_id: SessionId, note: &'a ConnectionNote) -> Self {
+        Self{
+            r#type: "connection_tagged".to_string(),
+            client_id,
+            note,
+        }
+    }
+}
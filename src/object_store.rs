@@ -0,0 +1,118 @@
+use std::{fmt, time::Duration};
+
+use anyhow::anyhow;
+use chrono::Utc;
+use hkdf::hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Reports and event logs are small, an upload taking longer than this has stalled.
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// S3 compatible bucket, from `S3_BUCKET`, `S3_REGION`, `S3_ENDPOINT`, `S3_ACCESS_KEY_ID` and `S3_SECRET_ACCESS_KEY`.
+#[derive(Clone)]
+pub struct ObjectStoreConfig{
+    /// e.g. `https://s3.eu-west-1.amazonaws.com` or the address of a MinIO server, objects are addressed path style.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+}
+
+impl fmt::Debug for ObjectStoreConfig{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObjectStoreConfig").field("endpoint", &self.endpoint).field("bucket", &self.bucket).finish()
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect::<String>()
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent encodes an object key the way SigV4 expects, slashes are kept.
+fn encode_key(key: &str) -> String {
+    key.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+        _ => format!("%{:02X}", b),
+    }).collect()
+}
+
+/// Uploads objects with AWS Signature Version 4, without pulling in an SDK.
+#[derive(Debug, Clone)]
+pub struct ObjectStore{
+    config: ObjectStoreConfig,
+    client: reqwest::Client,
+}
+
+impl ObjectStore{
+    pub fn new(config: ObjectStoreConfig) -> Self {
+        Self{
+            config,
+            client: reqwest::Client::builder().connect_timeout(CONNECT_TIMEOUT).timeout(UPLOAD_TIMEOUT).build().unwrap(),
+        }
+    }
+
+    /// Address of an object in the bucket.
+    pub fn url(&self, key: &str) -> String {
+        format!("{}/{}/{}", self.config.endpoint.trim_end_matches('/'), self.config.bucket, encode_key(key))
+    }
+
+    /// Stores an object, replacing any object with the same key, and returns its address.
+    pub async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> anyhow::Result<String> {
+        let response = self.signed(reqwest::Method::PUT, key, body)?
+            .header("Content-Type", content_type)
+            .send()
+            .await?;
+        if !response.status().is_success(){
+            return Err(anyhow!("Object storage returned {} for {}", response.status(), key));
+        }
+        Ok(self.url(key))
+    }
+
+    /// Removes an object, succeeds when there was none.
+    pub async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let response = self.signed(reqwest::Method::DELETE, key, Vec::new())?
+            .send()
+            .await?;
+        if !response.status().is_success() && response.status() != reqwest::StatusCode::NOT_FOUND{
+            return Err(anyhow!("Object storage returned {} deleting {}", response.status(), key));
+        }
+        Ok(())
+    }
+
+    /// Request for the object signed with AWS Signature Version 4.
+    fn signed(&self, method: reqwest::Method, key: &str, body: Vec<u8>) -> anyhow::Result<reqwest::RequestBuilder> {
+        let url = self.url(key);
+        let host = reqwest::Url::parse(&url)?.host_str().ok_or_else(|| anyhow!("S3_ENDPOINT has no host"))?.to_owned();
+        let now = Utc::now();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = to_hex(&Sha256::digest(&body));
+
+        let path = format!("/{}/{}", self.config.bucket, encode_key(key));
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!("{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}", method, path, host, payload_hash, timestamp, signed_headers, payload_hash);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.config.region);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", timestamp, scope, to_hex(&Sha256::digest(canonical_request.as_bytes())));
+
+        let key_date = hmac(format!("AWS4{}", self.config.secret_access_key).as_bytes(), &date);
+        let key_region = hmac(&key_date, &self.config.region);
+        let key_service = hmac(&key_region, "s3");
+        let signing_key = hmac(&key_service, "aws4_request");
+        let signature = to_hex(&hmac(&signing_key, &string_to_sign));
+        let authorization = format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.config.access_key_id, scope, signed_headers, signature);
+
+        Ok(self.client.request(method, &url)
+            .header("Authorization", authorization)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", timestamp)
+            .body(body))
+    }
+}
@@ -0,0 +1,92 @@
+use actix_web::{error, get, web, Error, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::task::spawn_local;
+
+use crate::admin::{audit, Admin};
+use crate::accessibility::SocketFormat;
+use crate::config::WebSocketConfig;
+use crate::room::{BingoServerHandle, RoomId, UserType};
+use crate::wshandler::{ws_handler, CommandHandler};
+
+/// Longest reason stored in the audit log.
+const MAX_REASON_LENGTH: usize = 500;
+
+/// A message the hosts or the players of a room received, relayed to the admins observing it.
+#[derive(serde::Serialize)]
+pub struct ObservedMessage{
+    r#type: String,
+    /// Who the message went to, `host` or `client`.
+    to: UserType,
+    message: Value,
+}
+
+impl ObservedMessage{
+    pub fn new(to: UserType, msg: &str) -> Self {
+        Self{
+            r#type: "observed".to_string(),
+            to,
+            message: serde_json::from_str(msg).unwrap_or_else(|_| Value::String(msg.to_owned())),
+        }
+    }
+}
+
+fn create_command_handler(room: RoomId) -> CommandHandler {
+    // Observers only watch, nothing they send reaches the room
+    Box::new(move |conn, msg| Box::pin(async move {
+        log::debug!("Ignoring message from observer {} in room {}: {}", conn, room, msg);
+    }))
+}
+
+#[derive(Deserialize)]
+struct ObserveQuery{
+    /// Why the room is observed, e.g. a support ticket, stored in the audit log.
+    reason: Option<String>,
+}
+
+/// Attaches a read-only socket to a live room for support, without the room's join link. The socket gets
+/// the board and every message the hosts and players are sent to the room, wrapped in `observed`.
+/// Every attach is written to the audit log first and refused when that fails.
+#[allow(clippy::too_many_arguments)]
+#[get("/admin/rooms/{room}/observe")]
+async fn observe_room(
+    _admin: Admin,
+    req: HttpRequest,
+    payload: web::Payload,
+    path: web::Path<(RoomId,)>,
+    query: web::Query<ObserveQuery>,
+    server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+    ws_config: web::Data<WebSocketConfig>,
+) -> Result<HttpResponse, Error> {
+    if !server.room_exists(path.0).await{
+        return Err(error::ErrorNotFound("Room not found"));
+    }
+
+    let reason = query.reason.as_deref()
+        .map(|reason| reason.trim().chars().take(MAX_REASON_LENGTH).collect::<String>())
+        .filter(|reason| !reason.is_empty());
+    let ip = req.connection_info().realip_remote_addr().map(str::to_owned);
+    audit(&database, "observe_room", Some(path.0), reason.as_deref(), ip.as_deref()).await
+        .map_err(|e| {
+            log::error!("Failed to audit observing room {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to write the audit log")
+        })?;
+
+    let (res, session, msg_stream) = actix_ws::handle(&req, payload)?;
+
+    log::warn!("Admin from {:?} is observing room {}, reason: {:?}", ip, path.0, reason);
+    spawn_local(ws_handler(
+        server.clone(),
+        **ws_config,
+        SocketFormat::default(),
+        path.0,
+        UserType::Observer,
+        None,
+        create_command_handler(path.0),
+        session,
+        msg_stream,
+    ));
+
+    Ok(res)
+}
@@ -0,0 +1,139 @@
+use std::{collections::BTreeMap, sync::Mutex, time::Instant};
+
+use crate::draw::Number;
+
+/// What speed rounds and the auto-caller do while no host socket is open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HostOfflinePolicy{
+    /// The server keeps calling, the host catches up from the digest.
+    #[default]
+    Continue,
+    /// Calling stops until a host socket is open again.
+    Freeze,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct HostOfflinePolicyRequest{
+    pub policy: HostOfflinePolicy,
+}
+
+#[derive(serde::Serialize)]
+pub struct HostOfflinePolicyMessage{
+    r#type: String,
+    policy: HostOfflinePolicy,
+}
+
+impl HostOfflinePolicyMessage{
+    pub fn new(policy: HostOfflinePolicy) -> Self {
+        Self{
+            r#type: "offline_policy".to_string(),
+            policy,
+        }
+    }
+}
+
+/// Host messages of a live room that had no host socket open. Only a count and the latest message of
+/// each type are kept, plus the numbers called, so a long absence stays small.
+#[derive(Debug)]
+pub struct HostBacklog{
+    since: Instant,
+    frozen: bool,
+    /// The caller skipped a call because of the freeze and has to be restarted.
+    stalled: bool,
+    events: Mutex<BacklogEvents>,
+}
+
+#[derive(Debug, Default)]
+struct BacklogEvents{
+    counts: BTreeMap<String, usize>,
+    latest: BTreeMap<String, serde_json::Value>,
+    calls: Vec<Number>,
+}
+
+impl HostBacklog{
+    pub fn new(frozen: bool) -> Self {
+        Self{
+            since: Instant::now(),
+            frozen,
+            stalled: false,
+            events: Mutex::default(),
+        }
+    }
+
+    /// The room stopped calling until the host is back.
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn stall(&mut self){
+        self.stalled = true;
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    pub fn record(&self, msg: &str){
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(msg) else {
+            return;
+        };
+        let kind = value["type"].as_str().unwrap_or("unknown").to_owned();
+        let mut events = self.events.lock().unwrap();
+        if kind == "draw" && value["duplicate"] != true{
+            if let Some(number) = value["number"].as_u64().and_then(|number| Number::try_from(number).ok()){
+                events.calls.push(number);
+            }
+        }
+        *events.counts.entry(kind.clone()).or_default() += 1;
+        events.latest.insert(kind, value);
+    }
+
+    pub fn into_digest(self) -> HostDigestMessage {
+        let events = self.events.into_inner().unwrap();
+        HostDigestMessage{
+            r#type: "host_digest".to_string(),
+            offline_secs: self.since.elapsed().as_secs(),
+            frozen: self.frozen,
+            calls: events.calls,
+            counts: events.counts,
+            latest: events.latest,
+        }
+    }
+}
+
+/// Catch-up sent to the first host socket that opens after the room was left without one.
+#[derive(serde::Serialize)]
+pub struct HostDigestMessage{
+    r#type: String,
+    offline_secs: u64,
+    /// Calling was stopped while the host was away and has been resumed.
+    frozen: bool,
+    /// Numbers called while the host was away, in order.
+    calls: Vec<Number>,
+    /// Number of messages missed per type.
+    counts: BTreeMap<String, usize>,
+    /// Last missed message of each type.
+    latest: BTreeMap<String, serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn digest_keeps_calls_and_the_latest_message_per_type(){
+        let backlog = HostBacklog::new(false);
+        backlog.record(r#"{"type":"draw","number":7,"call":1,"duplicate":false,"bonus":false}"#);
+        backlog.record(r#"{"type":"presence","joined":2,"left":0,"connected":2}"#);
+        backlog.record(r#"{"type":"draw","number":7,"call":1,"duplicate":true,"bonus":false}"#);
+        backlog.record(r#"{"type":"draw","number":41,"call":2,"duplicate":false,"bonus":false}"#);
+        backlog.record(r#"{"type":"presence","joined":0,"left":1,"connected":1}"#);
+
+        let digest = backlog.into_digest();
+        assert_eq!(digest.calls, vec![7, 41]);
+        assert_eq!(digest.counts["draw"], 3);
+        assert_eq!(digest.counts["presence"], 2);
+        assert_eq!(digest.latest["presence"]["connected"], 1);
+    }
+}
@@ -0,0 +1,212 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use actix_web::{delete, error, get, put, web, HttpResponse};
+use serde_json::Value;
+
+use crate::admin::Admin;
+use crate::config::RoomConfig;
+use crate::cors::{AllowedOrigins, RuntimeOrigins};
+use crate::features::Feature;
+use crate::resume::ResumeGraceRequest;
+use crate::room::BingoServerHandle;
+
+/// Prefix of the keys setting the default of a feature flag, e.g. `feature.chaos_mode`.
+const FEATURE_PREFIX: &str = "feature.";
+
+/// Tunables stored in the `config` table, applied on top of the deployment settings without a redeploy.
+/// Unset fields keep the value of the secrets or the environment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConfigOverrides{
+    pub max_messages_per_sec: Option<u32>,
+    pub max_host_connections: Option<usize>,
+    pub max_command_backlog: Option<usize>,
+    pub resume_grace_secs: Option<u64>,
+    pub redact_chat: Option<bool>,
+    /// Origins allowed on top of `CORS_ALLOWED_ORIGINS`.
+    pub cors_origins: Vec<String>,
+    /// Defaults of the feature flags, rows of the `feature_flags` table still take precedence.
+    pub features: HashMap<Feature, bool>,
+}
+
+fn expect<T: serde::de::DeserializeOwned>(key: &str, value: &Value, expected: &str) -> Result<T, String> {
+    serde_json::from_value(value.clone()).map_err(|_| format!("{} must be {}", key, expected))
+}
+
+impl ConfigOverrides{
+    /// Sets the override of a key, e.g. `max_host_connections` to `5`.
+    pub fn set(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match key {
+            "max_messages_per_sec" => self.max_messages_per_sec = Some(expect(key, value, "a number")?),
+            "max_host_connections" => self.max_host_connections = Some(expect(key, value, "a number")?),
+            "max_command_backlog" => self.max_command_backlog = Some(expect(key, value, "a number")?),
+            "resume_grace_secs" => {
+                // Capped like the grace period a host sets for its room
+                let request = ResumeGraceRequest{ secs: Some(expect(key, value, "a number of seconds")?) };
+                request.validate()?;
+                self.resume_grace_secs = request.secs;
+            }
+            "redact_chat" => self.redact_chat = Some(expect(key, value, "true or false")?),
+            "cors_origins" => {
+                let origins = expect(key, value, "a list of origins")?;
+                self.cors_origins = AllowedOrigins{ origins }.normalize()?.origins;
+            }
+            key => {
+                let feature = key.strip_prefix(FEATURE_PREFIX)
+                    .and_then(Feature::from_name)
+                    .ok_or_else(|| format!("Unknown configuration key {}", key))?;
+                self.features.insert(feature, expect(key, value, "true or false")?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the overrides from the database, invalid rows are logged and skipped.
+    pub async fn load(database: &sqlx::PgPool) -> Result<Self, sqlx::Error> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT key, value::text FROM config")
+            .fetch_all(database)
+            .await?;
+
+        let mut overrides = Self::default();
+        for (key, value) in rows{
+            let result = serde_json::from_str(&value)
+                .map_err(|e| e.to_string())
+                .and_then(|value| overrides.set(&key, &value));
+            if let Err(e) = result{
+                log::warn!("Ignoring configuration override {}: {}", key, e);
+            }
+        }
+        Ok(overrides)
+    }
+
+    /// The room settings of the deployment with the overrides applied.
+    pub fn apply(&self, defaults: &RoomConfig) -> RoomConfig {
+        RoomConfig{
+            max_messages_per_sec: self.max_messages_per_sec.unwrap_or(defaults.max_messages_per_sec),
+            max_host_connections: self.max_host_connections.unwrap_or(defaults.max_host_connections),
+            max_command_backlog: self.max_command_backlog.unwrap_or(defaults.max_command_backlog),
+            resume_grace: self.resume_grace_secs.map(Duration::from_secs).unwrap_or(defaults.resume_grace),
+            redact_chat: self.redact_chat.unwrap_or(defaults.redact_chat),
+            ..*defaults
+        }
+    }
+}
+
+/// Polls the `config` table and hands changed overrides to the room server and the CORS middleware, so
+/// every instance picks up edits within `CONFIG_POLL_INTERVAL_SECS`.
+pub async fn watch(database: sqlx::PgPool, server: BingoServerHandle, origins: Arc<RuntimeOrigins>, poll_interval: Duration){
+    let mut current = ConfigOverrides::default();
+    let mut ticks = tokio::time::interval(poll_interval);
+    loop{
+        ticks.tick().await;
+        match ConfigOverrides::load(&database).await {
+            Ok(overrides) if overrides != current => {
+                log::info!("Applying configuration overrides {:?}", overrides);
+                origins.set(overrides.cors_origins.clone());
+                server.apply_config(overrides.clone());
+                current = overrides;
+            }
+            Ok(_) => {}
+            Err(e) => log::error!("Failed to load configuration overrides: {}", e),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ConfigRow{
+    key: String,
+    value: String,
+    updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Lists the overrides stored in the `config` table.
+#[get("/admin/config")]
+async fn list_config(_admin: Admin, database: web::Data<sqlx::PgPool>) -> actix_web::Result<HttpResponse> {
+    let rows: Vec<ConfigRow> = sqlx::query_as("SELECT key, value::text AS value, updated_at FROM config ORDER BY key")
+        .fetch_all(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to list configuration overrides: {}", e);
+            error::ErrorInternalServerError("Failed to list configuration")
+        })?;
+    let rows: Vec<Value> = rows.into_iter()
+        .map(|row| serde_json::json!({
+            "key": row.key,
+            "value": serde_json::from_str::<Value>(&row.value).unwrap_or(Value::Null),
+            "updated_at": row.updated_at,
+        }))
+        .collect();
+    Ok(HttpResponse::Ok().json(rows))
+}
+
+/// Stores the override of a key, the body is its JSON value, e.g. `5` or `["https://example.com"]`.
+#[put("/admin/config/{key}")]
+async fn set_config(
+    _admin: Admin,
+    path: web::Path<(String,)>,
+    value: web::Json<Value>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let key = &path.0;
+    ConfigOverrides::default().set(key, &value).map_err(error::ErrorBadRequest)?;
+
+    sqlx::query("INSERT INTO config (key, value, updated_at) VALUES ($1, $2::jsonb, now()) \
+        ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value, updated_at = now()")
+        .bind(key)
+        .bind(value.to_string())
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to store configuration override {}: {}", key, e);
+            error::ErrorInternalServerError("Failed to store configuration")
+        })?;
+
+    log::info!("Configuration override {} set to {}", key, value.0);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Removes the override of a key, the deployment setting applies again.
+#[delete("/admin/config/{key}")]
+async fn remove_config(
+    _admin: Admin,
+    path: web::Path<(String,)>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let result = sqlx::query("DELETE FROM config WHERE key = $1")
+        .bind(&path.0)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to remove configuration override {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to remove configuration")
+        })?;
+    if result.rows_affected() == 0{
+        return Err(error::ErrorNotFound("No override for this key"));
+    }
+    log::info!("Configuration override {} removed", path.0);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn validates_keys_and_values(){
+        let mut overrides = ConfigOverrides::default();
+        overrides.set("max_host_connections", &json!(5)).unwrap();
+        overrides.set("feature.chaos_mode", &json!(true)).unwrap();
+        overrides.set("cors_origins", &json!(["https://Games.example.com/"])).unwrap();
+        assert_eq!(overrides.max_host_connections, Some(5));
+        assert_eq!(overrides.features.get(&Feature::ChaosMode), Some(&true));
+        assert_eq!(overrides.cors_origins, ["https://games.example.com"]);
+
+        assert!(overrides.set("max_host_connections", &json!("five")).is_err());
+        assert!(overrides.set("feature.unknown", &json!(true)).is_err());
+        assert!(overrides.set("database_url", &json!("postgres://")).is_err());
+        assert!(overrides.set("cors_origins", &json!(["example.com"])).is_err());
+        assert!(overrides.set("resume_grace_secs", &json!(301)).is_err());
+        overrides.set("resume_grace_secs", &json!(300)).unwrap();
+        assert_eq!(overrides.resume_grace_secs, Some(300));
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::VecDeque,
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc},
+    time::Instant,
+};
+
+use futures_util::future::BoxFuture;
+use tokio::{sync::mpsc, time::{interval, timeout, MissedTickBehavior}};
+
+use crate::config::PersistenceConfig;
+use crate::recent_errors::RecentErrors;
+use crate::retention::{DataClass, RetentionPolicy};
+
+pub type WriteFn = Box<dyn Fn(sqlx::PgPool) -> BoxFuture<'static, Result<(), sqlx::Error>> + Send + Sync>;
+
+/// A database write that can be delayed and retried without affecting gameplay.
+pub struct PendingWrite{
+    pub description: String,
+    pub run: WriteFn,
+    /// Set for writes a room's retention policy can rule out.
+    pub class: Option<DataClass>,
+    /// Kept when the backlog is full, see `essential`.
+    essential: bool,
+    /// Failed executions so far, the write is given up after `max_write_attempts`.
+    attempts: u32,
+}
+
+impl PendingWrite{
+    pub fn new(description: String, run: WriteFn) -> Self {
+        Self{
+            description,
+            run,
+            class: None,
+            essential: false,
+            attempts: 0,
+        }
+    }
+
+    pub fn classified(self, class: DataClass) -> Self {
+        Self{
+            class: Some(class),
+            ..self
+        }
+    }
+
+    /// Marks a write that nothing else recreates, e.g. a scheduled announcement. A full backlog drops the
+    /// oldest other write to make room, essential writes are only given up after failing.
+    pub fn essential(self) -> Self {
+        Self{
+            essential: true,
+            ..self
+        }
+    }
+}
+
+/// Health of the database as seen by the background writer, shared with the admin API.
+#[derive(Debug, Default)]
+pub struct PersistenceStatus{
+    degraded: AtomicBool,
+    queued: AtomicU64,
+    written: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+    last_latency_ms: AtomicU64,
+}
+
+#[derive(serde::Serialize)]
+pub struct PersistenceReport{
+    degraded: bool,
+    queued: u64,
+    written: u64,
+    failed: u64,
+    dropped: u64,
+    last_latency_ms: u64,
+}
+
+impl PersistenceStatus{
+    /// True while writes are kept in memory because the database is slow or failing.
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    pub fn report(&self) -> PersistenceReport {
+        PersistenceReport{
+            degraded: self.is_degraded(),
+            queued: self.queued.load(Ordering::Relaxed),
+            written: self.written.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            last_latency_ms: self.last_latency_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Handle used by the room server to submit non-critical writes without waiting on the database.
+#[derive(Debug, Clone)]
+pub struct Persistence{
+    write_tx: mpsc::UnboundedSender<PendingWrite>,
+    status: Arc<PersistenceStatus>,
+    /// Failed writes are recorded here, the room server and the sockets record theirs too.
+    errors: Arc<RecentErrors>,
+}
+
+impl Persistence{
+    pub fn start(database: sqlx::PgPool, config: PersistenceConfig) -> Self {
+        let (write_tx, write_rx) = mpsc::unbounded_channel();
+        let status = Arc::new(PersistenceStatus::default());
+        let errors = Arc::new(RecentErrors::default());
+        tokio::spawn(run_writer(database, config, write_rx, status.clone(), errors.clone()));
+
+        Self{
+            write_tx,
+            status,
+            errors,
+        }
+    }
+
+    pub fn submit(&self, write: PendingWrite){
+        self.status.queued.fetch_add(1, Ordering::Relaxed);
+        if self.write_tx.send(write).is_err(){
+            log::error!("Persistence writer stopped, dropping write");
+        }
+    }
+
+    /// Submits a write of a room, dropping it when the retention policy of the room doesn't keep its data.
+    pub fn submit_for(&self, policy: &RetentionPolicy, write: PendingWrite){
+        if write.class.is_some_and(|class| !policy.allows(class)){
+            log::debug!("Not persisting {}, the room doesn't retain it", write.description);
+            return;
+        }
+        self.submit(write);
+    }
+
+    pub fn status(&self) -> Arc<PersistenceStatus> {
+        self.status.clone()
+    }
+
+    pub fn errors(&self) -> Arc<RecentErrors> {
+        self.errors.clone()
+    }
+}
+
+enum Outcome{
+    Ok,
+    Slow,
+    Failed,
+}
+
+async fn execute(database: &sqlx::PgPool, config: &PersistenceConfig, status: &PersistenceStatus, errors: &RecentErrors, write: &PendingWrite) -> Outcome {
+    let started = Instant::now();
+    let result = timeout(config.write_timeout, (write.run)(database.clone())).await;
+    let latency = started.elapsed();
+    status.last_latency_ms.store(latency.as_millis() as u64, Ordering::Relaxed);
+
+    match result {
+        Ok(Ok(())) if latency > config.latency_threshold => Outcome::Slow,
+        Ok(Ok(())) => Outcome::Ok,
+        Ok(Err(e)) => {
+            log::error!("Failed to persist {}: {}", write.description, e);
+            errors.record("persistence", format!("Failed to persist {}: {}", write.description, e));
+            Outcome::Failed
+        }
+        Err(_) => {
+            log::error!("Timed out persisting {}", write.description);
+            errors.record("persistence", format!("Timed out persisting {}", write.description));
+            Outcome::Failed
+        }
+    }
+}
+
+/// Takes the oldest write out of a full backlog that can be dropped, `None` when all of them are essential.
+fn evict(backlog: &mut VecDeque<PendingWrite>) -> Option<PendingWrite> {
+    let index = backlog.iter().position(|write| !write.essential)?;
+    backlog.remove(index)
+}
+
+/// Executes writes in order. After too many slow or failed writes the writer switches to degraded mode,
+/// keeping writes in memory and retrying the oldest one periodically until the database recovers.
+/// A write that keeps failing is given up so it doesn't hold back the ones queued after it.
+async fn run_writer(
+    database: sqlx::PgPool,
+    config: PersistenceConfig,
+    mut write_rx: mpsc::UnboundedReceiver<PendingWrite>,
+    status: Arc<PersistenceStatus>,
+    errors: Arc<RecentErrors>,
+){
+    let mut backlog: VecDeque<PendingWrite> = VecDeque::new();
+    let mut strikes = 0;
+    // Probes run on a fixed schedule, a busy room submitting writes mustn't keep postponing them
+    let mut probe = interval(config.retry_interval);
+    probe.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let degraded = status.is_degraded();
+        let mut next = if degraded {
+            tokio::select! {
+                write = write_rx.recv() => match write {
+                    Some(write) => {
+                        if backlog.len() >= config.max_queued_writes{
+                            if let Some(dropped) = evict(&mut backlog){
+                                log::warn!("Persistence backlog full, dropping {}", dropped.description);
+                                status.dropped.fetch_add(1, Ordering::Relaxed);
+                                status.queued.fetch_sub(1, Ordering::Relaxed);
+                            }
+                        }
+                        backlog.push_back(write);
+                        continue;
+                    }
+                    None => break,
+                },
+                // Retry interval elapsed, probe the database with the oldest write
+                _ = probe.tick() => match backlog.pop_front() {
+                    Some(write) => write,
+                    None => {
+                        status.degraded.store(false, Ordering::Relaxed);
+                        continue;
+                    }
+                },
+            }
+        } else {
+            match backlog.pop_front() {
+                Some(write) => write,
+                None => match write_rx.recv().await {
+                    Some(write) => write,
+                    None => break,
+                },
+            }
+        };
+
+        match execute(&database, &config, &status, &errors, &next).await {
+            Outcome::Failed => {
+                status.failed.fetch_add(1, Ordering::Relaxed);
+                strikes += 1;
+                next.attempts += 1;
+                if next.attempts >= config.max_write_attempts{
+                    log::error!("Giving up on {} after {} failed attempts", next.description, next.attempts);
+                    errors.record("persistence", format!("Gave up on {} after {} failed attempts", next.description, next.attempts));
+                    status.dropped.fetch_add(1, Ordering::Relaxed);
+                    status.queued.fetch_sub(1, Ordering::Relaxed);
+                }
+                else{
+                    backlog.push_front(next);
+                }
+            }
+            outcome => {
+                status.written.fetch_add(1, Ordering::Relaxed);
+                status.queued.fetch_sub(1, Ordering::Relaxed);
+                if matches!(outcome, Outcome::Slow){
+                    strikes += 1;
+                }
+                else{
+                    strikes = 0;
+                    if degraded{
+                        log::info!("Database recovered, flushing {} queued writes", backlog.len());
+                        status.degraded.store(false, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+
+        if strikes >= config.error_threshold && !status.is_degraded(){
+            log::warn!("Database is slow or failing, switching to memory-only writes");
+            status.degraded.store(true, Ordering::Relaxed);
+            probe.reset();
+            strikes = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(description: &str) -> PendingWrite {
+        PendingWrite::new(description.to_owned(), Box::new(|_| Box::pin(async { Ok(()) })))
+    }
+
+    #[test]
+    fn full_backlog_keeps_essential_writes(){
+        let mut backlog = VecDeque::from([write("announcement").essential(), write("call"), write("chat")]);
+
+        assert_eq!(evict(&mut backlog).map(|write| write.description), Some("call".to_owned()));
+        assert_eq!(evict(&mut backlog).map(|write| write.description), Some("chat".to_owned()));
+        assert!(evict(&mut backlog).is_none());
+        assert_eq!(backlog.len(), 1);
+    }
+}
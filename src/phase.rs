@@ -0,0 +1,58 @@
+/// Lifecycle of a room, enforced by the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomPhase{
+    /// Players gather and get cards, nothing is called yet.
+    #[default]
+    Lobby,
+    /// Rounds are played and numbers called.
+    Live,
+    /// Break between rounds, cards can still be bought.
+    Intermission,
+    /// The event is over, players can no longer join.
+    Ended,
+}
+
+impl RoomPhase{
+    pub fn can_transition_to(self, next: RoomPhase) -> bool {
+        matches!(
+            (self, next),
+            (RoomPhase::Lobby, RoomPhase::Live)
+                | (RoomPhase::Live, RoomPhase::Intermission)
+                | (RoomPhase::Intermission, RoomPhase::Live)
+                | (RoomPhase::Lobby | RoomPhase::Live | RoomPhase::Intermission, RoomPhase::Ended)
+        )
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RoomPhase::Lobby => "lobby",
+            RoomPhase::Live => "live",
+            RoomPhase::Intermission => "intermission",
+            RoomPhase::Ended => "ended",
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SetPhaseRequest{
+    pub phase: RoomPhase,
+}
+
+/// Broadcast to every connection of the room on each transition.
+#[derive(serde::Serialize)]
+pub struct PhaseMessage{
+    r#type: String,
+    phase: RoomPhase,
+    previous: RoomPhase,
+}
+
+impl PhaseMessage{
+    pub fn new(phase: RoomPhase, previous: RoomPhase) -> Self {
+        Self{
+            r#type: "phase".to_string(),
+            phase,
+            previous,
+        }
+    }
+}
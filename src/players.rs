@@ -0,0 +1,313 @@
+use actix_web::{error, get, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use rand::{rng, Rng as _};
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::card::{Card, Pattern};
+use crate::draw::Number;
+use crate::mailbox::SERVER_BUSY;
+use crate::persistence::PendingWrite;
+use crate::retention::DataClass;
+use crate::phase::RoomPhase;
+use crate::room::{BingoServerHandle, RoomId, SessionId};
+use crate::round::RoundId;
+use crate::seats::Seat;
+
+/// Longest display name kept for a player.
+const MAX_NAME_LENGTH: usize = 40;
+
+/// Most preferences stored for a player.
+pub const MAX_PREFERENCES: usize = 32;
+
+const MAX_PREFERENCE_KEY_LENGTH: usize = 40;
+
+/// Largest serialized value of a single preference.
+const MAX_PREFERENCE_VALUE_SIZE: usize = 256;
+
+/// Shortest name a host can search for.
+const MIN_SEARCH_LENGTH: usize = 2;
+
+pub const MAX_SEARCH_RESULTS: usize = 50;
+
+/// Departed connections a room remembers for search and attendance, the earliest without cards are
+/// forgotten first.
+pub const MAX_DEPARTED_PLAYERS: usize = 500;
+
+/// Header to send the player token in, instead of the `player_token` query parameter.
+pub const PLAYER_TOKEN_HEADER: &str = "X-Player-Token";
+
+pub type Preferences = Map<String, Value>;
+
+/// Player behind a client socket, from the join query.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlayerIdentity{
+    pub token: String,
+    pub name: Option<String>,
+}
+
+/// A client socket of a player, kept after it closes so hosts can still look it up.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlayerConnection{
+    pub player: PlayerIdentity,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>,
+}
+
+/// Tells the newer socket of a player that it replaced an older one, which is closed.
+#[derive(serde::Serialize)]
+pub struct SessionTakeoverMessage{
+    r#type: String,
+    replaced_client_id: SessionId,
+}
+
+impl SessionTakeoverMessage{
+    pub fn new(replaced_client_id: SessionId) -> Self {
+        Self{
+            r#type: "session_takeover".to_string(),
+            replaced_client_id,
+        }
+    }
+}
+
+/// Connection found by a host player search.
+#[derive(Debug, serde::Serialize)]
+pub struct PlayerMatch{
+    pub room: RoomId,
+    pub client_id: SessionId,
+    pub name: Option<String>,
+    pub connected: bool,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>,
+    pub cards: usize,
+    pub seat: Option<Seat>,
+}
+
+/// A card of the player with the calls it covers.
+#[derive(Debug, serde::Serialize)]
+pub struct PlayerCard{
+    /// Connection the card was dealt to, cards stay with the socket that got them.
+    pub client_id: SessionId,
+    #[serde(flatten)]
+    pub card: Card,
+    pub marked: Vec<Number>,
+    pub numbers_to_go: usize,
+}
+
+impl PlayerCard{
+    pub fn new(client_id: SessionId, card: &Card, called: &[Number]) -> Self {
+        Self{
+            client_id,
+            marked: card.marked(called),
+            numbers_to_go: card.numbers_to_go(called),
+            card: card.clone(),
+        }
+    }
+}
+
+/// Where the player's claims of the current round stand.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ClaimStatus{
+    pub won: bool,
+    /// Verified, the win is announced when the claim window closes.
+    pub pending: bool,
+    pub prizes: Vec<Pattern>,
+}
+
+/// Cards and claims of a player in a room, so a client can rebuild its screen while the socket is unstable.
+#[derive(Debug, serde::Serialize)]
+pub struct PlayerCards{
+    pub room: RoomId,
+    /// Socket of the player that is currently connected, if any.
+    pub client_id: Option<SessionId>,
+    pub phase: RoomPhase,
+    pub round: Option<RoundId>,
+    pub calls: Vec<Number>,
+    pub cards: Vec<PlayerCard>,
+    pub claim: ClaimStatus,
+}
+
+#[derive(serde::Deserialize)]
+struct PlayerCardsQuery{
+    player_token: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct PlayerSearchQuery{
+    name: String,
+}
+
+pub fn generate_player_token() -> String {
+    rng().random::<[u8; 16]>().iter().map(|x| format!("{:02x}", x)).collect::<String>()
+}
+
+/// Accepts tokens in the format issued by the server, anything else gets a new token.
+pub fn is_valid_player_token(token: &str) -> bool {
+    token.len() == 32 && token.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+pub fn clean_display_name(name: &str) -> Option<String> {
+    let name: String = name.trim().chars().filter(|c| !c.is_control()).take(MAX_NAME_LENGTH).collect();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Creates or refreshes the player's record, a missing name keeps the stored one.
+pub fn record_player(token: String, display_name: Option<String>, ip: Option<String>) -> PendingWrite {
+    PendingWrite::new(
+        format!("player {}", &token[..8]),
+        Box::new(move |database| {
+            let (token, display_name, ip) = (token.clone(), display_name.clone(), ip.clone());
+            Box::pin(async move {
+                sqlx::query("INSERT INTO players (token, display_name, last_ip) VALUES ($1, $2, $3) ON CONFLICT (token) DO UPDATE SET display_name = COALESCE($2, players.display_name), last_ip = $3, last_seen_at = now()")
+                    .bind(token)
+                    .bind(display_name)
+                    .bind(ip)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).classified(DataClass::Roster)
+}
+
+/// Tells a joining client the token to present on later visits.
+#[derive(serde::Serialize)]
+pub struct PlayerMessage{
+    r#type: String,
+    player_token: String,
+}
+
+impl PlayerMessage{
+    pub fn new(player_token: String) -> Self {
+        Self{
+            r#type: "player".to_string(),
+            player_token,
+        }
+    }
+}
+
+/// Preferences stored for the player, empty for unknown players.
+pub async fn load_preferences(database: &sqlx::PgPool, token: &str) -> Result<Preferences, sqlx::Error> {
+    let preferences: Option<Json<Preferences>> = sqlx::query_scalar("SELECT preferences FROM players WHERE token = $1")
+        .bind(token)
+        .fetch_optional(database)
+        .await?;
+    Ok(preferences.map(|preferences| preferences.0).unwrap_or_default())
+}
+
+/// Checks a change of preferences on its own, a `null` value removes the key.
+pub fn validate_preferences(changes: &Preferences) -> Result<(), String> {
+    for (key, value) in changes {
+        if key.is_empty() || key.len() > MAX_PREFERENCE_KEY_LENGTH{
+            return Err(format!("Preference keys must be 1 to {} bytes", MAX_PREFERENCE_KEY_LENGTH));
+        }
+        if !value.is_null() && value.to_string().len() > MAX_PREFERENCE_VALUE_SIZE{
+            return Err(format!("Preference {} is larger than {} bytes", key, MAX_PREFERENCE_VALUE_SIZE));
+        }
+    }
+    if changes.values().filter(|value| !value.is_null()).count() > MAX_PREFERENCES{
+        return Err(format!("At most {} preferences can be stored", MAX_PREFERENCES));
+    }
+    Ok(())
+}
+
+/// Applies a validated change in a single statement, so changes made on several devices at once don't overwrite
+/// each other. `None` when the player would end up with more than `MAX_PREFERENCES`.
+pub async fn update_preferences(database: &sqlx::PgPool, token: &str, changes: &Preferences) -> Result<Option<Preferences>, sqlx::Error> {
+    let removed: Vec<String> = changes.iter().filter(|(_, value)| value.is_null()).map(|(key, _)| key.clone()).collect();
+    let set: Preferences = changes.iter().filter(|(_, value)| !value.is_null()).map(|(key, value)| (key.clone(), value.clone())).collect();
+    let preferences: Option<Json<Preferences>> = sqlx::query_scalar("INSERT INTO players (token, preferences) VALUES ($1, $2) \
+        ON CONFLICT (token) DO UPDATE SET preferences = (players.preferences || $2) - $3::text[], last_seen_at = now() \
+        WHERE (SELECT count(*) FROM jsonb_object_keys((players.preferences || $2) - $3::text[])) <= $4 \
+        RETURNING preferences")
+        .bind(token)
+        .bind(Json(&set))
+        .bind(&removed)
+        .bind(MAX_PREFERENCES as i64)
+        .fetch_optional(database)
+        .await?;
+    Ok(preferences.map(|preferences| preferences.0))
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SetPreferencesRequest{
+    pub preferences: Preferences,
+}
+
+/// Current preferences of the player, sent on join and after every change.
+#[derive(serde::Serialize)]
+pub struct PreferencesMessage{
+    r#type: String,
+    preferences: Preferences,
+}
+
+impl PreferencesMessage{
+    pub fn new(preferences: Preferences) -> Self {
+        Self{
+            r#type: "preferences".to_string(),
+            preferences,
+        }
+    }
+}
+
+/// Finds current and earlier connections of a player across the host's rooms, e.g. when a player reports
+/// an issue without knowing their room.
+#[get("/host/players")]
+async fn search_players(
+    user: HostIdentity,
+    query: web::Query<PlayerSearchQuery>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    let name = query.name.trim();
+    if name.chars().count() < MIN_SEARCH_LENGTH{
+        return Err(error::ErrorBadRequest(format!("Search for at least {} characters", MIN_SEARCH_LENGTH)));
+    }
+    let players = server.find_players(user.username.clone(), name.to_owned()).await
+        .ok_or_else(|| error::ErrorServiceUnavailable(SERVER_BUSY))?;
+    Ok(HttpResponse::Ok().json(players))
+}
+
+fn player_token(req: &HttpRequest, query: &PlayerCardsQuery) -> actix_web::Result<String> {
+    req.headers().get(PLAYER_TOKEN_HEADER).and_then(|value| value.to_str().ok()).map(str::to_owned)
+        .or_else(|| query.player_token.clone())
+        .filter(|token| is_valid_player_token(token))
+        .ok_or_else(|| error::ErrorUnauthorized("A player token is required"))
+}
+
+/// Current cards, marks and claim status of the player presenting its token, for a recovery screen.
+#[get("/join/{room}/me")]
+async fn my_cards(
+    req: HttpRequest,
+    path: web::Path<(RoomId,)>,
+    query: web::Query<PlayerCardsQuery>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    let token = player_token(&req, &query)?;
+    let cards = server.player_cards(path.0, token).await
+        .ok_or_else(|| error::ErrorServiceUnavailable(SERVER_BUSY))?
+        .ok_or_else(|| error::ErrorNotFound("Player not found in this room"))?;
+    Ok(HttpResponse::Ok().json(cards))
+}
+
+/// The card of the player presenting its token, dealt by the server when it holds none yet. Asking again
+/// returns the same card, so a client that lost its state gets back the card it plays. The player needs
+/// an open socket in the room, the card is also sent there.
+#[get("/join/{room}/card")]
+async fn my_card(
+    req: HttpRequest,
+    path: web::Path<(RoomId,)>,
+    query: web::Query<PlayerCardsQuery>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    let token = player_token(&req, &query)?;
+    let card = server.player_card(path.0, token).await
+        .ok_or_else(|| error::ErrorServiceUnavailable(SERVER_BUSY))?;
+    match card {
+        Some(Ok(card)) => Ok(HttpResponse::Ok().json(card)),
+        Some(Err(error)) => Err(error::ErrorConflict(error)),
+        None => Err(error::ErrorNotFound("The player is not connected to this room")),
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::notes::ConnectionNote;
+use crate::room::{DisconnectReason, SessionId};
+
+/// Join and leave events collected since the last flush to the host.
+#[derive(Debug, Default)]
+pub struct PresenceBatch{
+    joined: Vec<SessionId>,
+    left: Vec<Departure>,
+}
+
+/// A client that left the room and why.
+#[derive(Debug, Clone, Copy, serde::Serialize)]
+pub struct Departure{
+    client_id: SessionId,
+    reason: DisconnectReason,
+}
+
+impl PresenceBatch{
+    pub fn join(&mut self, conn_id: SessionId){
+        self.joined.push(conn_id);
+    }
+
+    pub fn leave(&mut self, conn_id: SessionId, reason: DisconnectReason){
+        // A client that joined and left within the same batch is not reported at all
+        if let Some(index) = self.joined.iter().position(|id| *id == conn_id){
+            self.joined.swap_remove(index);
+            return;
+        }
+        self.left.push(Departure{ client_id: conn_id, reason });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.joined.is_empty() && self.left.is_empty()
+    }
+
+    /// Drains the batch into a summary, the batch is empty afterwards.
+    pub fn take_summary(&mut self, connected: usize) -> PresenceMessage {
+        let message = PresenceMessage{
+            r#type: "presence".to_string(),
+            joined: self.joined.len(),
+            left: self.left.len(),
+            connected,
+            departures: std::mem::take(&mut self.left),
+        };
+        self.joined.clear();
+        message
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct PresenceMessage{
+    r#type: String,
+    joined: usize,
+    left: usize,
+    connected: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    departures: Vec<Departure>,
+}
+
+impl PresenceMessage{
+    /// A single client that left, reported right away in rooms that don't batch presence.
+    pub fn departure(conn_id: SessionId, reason: DisconnectReason, connected: usize) -> Self {
+        Self{
+            r#type: "presence".to_string(),
+            joined: 0,
+            left: 1,
+            connected,
+            departures: vec![Departure{ client_id: conn_id, reason }],
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RosterMessage{
+    r#type: String,
+    clients: Vec<SessionId>,
+    /// Host tags and notes of the listed clients that have any.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    notes: HashMap<SessionId, ConnectionNote>,
+    /// Seated clients per table when the room has a seat map.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tables: BTreeMap<String, Vec<SessionId>>,
+}
+
+impl RosterMessage{
+    pub fn new(mut clients: Vec<SessionId>, notes: HashMap<SessionId, ConnectionNote>, tables: BTreeMap<String, Vec<SessionId>>) -> Self {
+        clients.sort_unstable();
+        Self{
+            r#type: "roster".to_string(),
+            clients,
+            notes,
+            tables,
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use actix_identity::Identity;
+use actix_web::{delete, error, web, HttpResponse};
+
+use crate::admin::Admin;
+use crate::archive::Archive;
+use crate::players::is_valid_player_token;
+use crate::room::BingoServerHandle;
+
+/// Rows removed by a purge, per table.
+#[derive(Debug, Default, serde::Serialize)]
+struct PurgeReport{
+    players: u64,
+    rooms: u64,
+    tickets: u64,
+    calls: u64,
+    wins: u64,
+    round_audits: u64,
+    round_starts: u64,
+    card_trade_ins: u64,
+    api_keys: u64,
+    feature_flags: u64,
+    tournaments: u64,
+    tournament_points: u64,
+    chat_messages: u64,
+    announcements: u64,
+    scheduled_rooms: u64,
+    sms_opt_ins: u64,
+    /// Rooms whose archived report and event log were deleted from object storage.
+    archived_rooms: u64,
+}
+
+async fn purge_player(database: &sqlx::PgPool, token: &str) -> Result<PurgeReport, sqlx::Error> {
+    let mut tx = database.begin().await?;
+    let tournament_points = sqlx::query("DELETE FROM tournament_points WHERE player_token = $1")
+        .bind(token)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    // The ticket stays used, it just no longer points at the player
+    sqlx::query("UPDATE tickets SET player_token = NULL WHERE player_token = $1")
+        .bind(token)
+        .execute(&mut *tx)
+        .await?;
+    let chat_messages = sqlx::query("DELETE FROM chat_messages WHERE player_token = $1")
+        .bind(token)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    let players = sqlx::query("DELETE FROM players WHERE token = $1")
+        .bind(token)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+
+    tx.commit().await?;
+    Ok(PurgeReport{ players, tournament_points, chat_messages, ..PurgeReport::default() })
+}
+
+/// Deletes the archived objects of the host's rooms. Runs before the rows go, so a failed purge still
+/// knows which rooms have objects left.
+async fn purge_archives(database: &sqlx::PgPool, archive: &Archive, host: &str) -> anyhow::Result<u64> {
+    let rooms: Vec<i32> = sqlx::query_scalar("SELECT id FROM rooms WHERE host = $1 AND (report_url IS NOT NULL OR event_log_url IS NOT NULL)")
+        .bind(host)
+        .fetch_all(database)
+        .await?;
+    for room in &rooms{
+        archive.delete_room(*room).await?;
+    }
+    Ok(rooms.len() as u64)
+}
+
+/// Removes every room of the host and everything recorded for those rooms.
+async fn purge_host(database: &sqlx::PgPool, host: &str, archived_rooms: u64) -> Result<PurgeReport, sqlx::Error> {
+    let mut tx = database.begin().await?;
+    let mut report = PurgeReport{ archived_rooms, ..PurgeReport::default() };
+
+    let rooms: Vec<i32> = sqlx::query_scalar("SELECT id FROM rooms WHERE host = $1")
+        .bind(host)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    report.tickets = sqlx::query("DELETE FROM tickets WHERE room_id = ANY($1)").bind(&rooms).execute(&mut *tx).await?.rows_affected();
+    report.round_audits = sqlx::query("DELETE FROM round_audits WHERE room_id = ANY($1)").bind(&rooms).execute(&mut *tx).await?.rows_affected();
+    report.announcements = sqlx::query("DELETE FROM announcements WHERE room_id = ANY($1)").bind(&rooms).execute(&mut *tx).await?.rows_affected();
+    report.scheduled_rooms = sqlx::query("DELETE FROM scheduled_rooms WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.sms_opt_ins = sqlx::query("DELETE FROM sms_opt_ins WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.chat_messages = sqlx::query("DELETE FROM chat_messages WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.calls = sqlx::query("DELETE FROM calls WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.wins = sqlx::query("DELETE FROM wins WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.round_starts = sqlx::query("DELETE FROM round_starts WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.card_trade_ins = sqlx::query("DELETE FROM card_trade_ins WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.api_keys = sqlx::query("DELETE FROM api_keys WHERE username = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.feature_flags = sqlx::query("DELETE FROM feature_flags WHERE host = $1 OR room_id = ANY($2)").bind(host).bind(&rooms).execute(&mut *tx).await?.rows_affected();
+    report.tournament_points = sqlx::query("DELETE FROM tournament_points WHERE room_id = ANY($1)").bind(&rooms).execute(&mut *tx).await?.rows_affected();
+    // Tournament rooms go with the tournaments
+    report.tournaments = sqlx::query("DELETE FROM tournaments WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+    report.rooms = sqlx::query("DELETE FROM rooms WHERE host = $1").bind(host).execute(&mut *tx).await?.rows_affected();
+
+    tx.commit().await?;
+    Ok(report)
+}
+
+/// Deletes a player's stored data, the token itself is the proof of identity.
+#[delete("/players/{token}/data")]
+async fn delete_player_data(
+    path: web::Path<(String,)>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    if !is_valid_player_token(&path.0){
+        return Err(error::ErrorNotFound("Player not found"));
+    }
+    let report = purge_player(&database, &path.0).await.map_err(|e| {
+        log::error!("Failed to delete player data: {}", e);
+        error::ErrorInternalServerError("Failed to delete player data")
+    })?;
+
+    log::info!("Deleted player data on request: {:?}", report);
+    Ok(HttpResponse::Ok().json(report))
+}
+
+async fn purge_host_data(host: &str, server: &BingoServerHandle, database: &sqlx::PgPool, archive: Option<&Archive>) -> actix_web::Result<HttpResponse> {
+    // Close the live rooms first so nothing is written for them after the purge
+    server.remove_host_rooms(host.to_owned()).await;
+    let archived_rooms = match archive {
+        Some(archive) => purge_archives(database, archive, host).await.map_err(|e| {
+            log::error!("Failed to delete the archives of host {}: {}", host, e);
+            error::ErrorInternalServerError("Failed to delete host data")
+        })?,
+        None => 0,
+    };
+    let report = purge_host(database, host, archived_rooms).await.map_err(|e| {
+        log::error!("Failed to purge data of host {}: {}", host, e);
+        error::ErrorInternalServerError("Failed to delete host data")
+    })?;
+
+    log::info!("Purged data of host {}: {:?}", host, report);
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Deletes the logged in host's rooms and records, API keys can't be used for this.
+#[delete("/host/data")]
+async fn delete_host_data(
+    user: Option<Identity>,
+    server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+    archive: web::Data<Option<Archive>>,
+) -> actix_web::Result<HttpResponse> {
+    let host = match user.and_then(|user| user.id().ok()) {
+        Some(host) => host,
+        None => return Err(error::ErrorUnauthorized("Login required using /host endpoint")),
+    };
+    purge_host_data(&host, &server, &database, archive.get_ref().as_ref()).await
+}
+
+/// Purges a host on behalf of the organization running the server.
+#[delete("/admin/hosts/{host}/data")]
+async fn admin_delete_host_data(
+    _admin: Admin,
+    path: web::Path<(String,)>,
+    server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+    archive: web::Data<Option<Archive>>,
+) -> actix_web::Result<HttpResponse> {
+    purge_host_data(&path.0, &server, &database, archive.get_ref().as_ref()).await
+}
@@ -0,0 +1,57 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::{DateTime, Utc};
+
+/// Errors kept for the admin API, older ones are dropped.
+const MAX_RECENT_ERRORS: usize = 50;
+
+/// Latest server side failures, e.g. database writes that failed and socket tasks that panicked. They are
+/// logged as well, this only saves operators of a one-off event from digging through the logs.
+#[derive(Debug, Default)]
+pub struct RecentErrors{
+    errors: Mutex<VecDeque<RecentError>>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RecentError{
+    pub at: DateTime<Utc>,
+    /// Part of the server the error happened in.
+    pub source: &'static str,
+    pub message: String,
+}
+
+impl RecentErrors{
+    pub fn record(&self, source: &'static str, message: String){
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() >= MAX_RECENT_ERRORS{
+            errors.pop_front();
+        }
+        errors.push_back(RecentError{
+            at: Utc::now(),
+            source,
+            message,
+        });
+    }
+
+    /// Newest first.
+    pub fn list(&self) -> Vec<RecentError> {
+        self.errors.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_errors(){
+        let errors = RecentErrors::default();
+        for i in 0..MAX_RECENT_ERRORS + 2{
+            errors.record("test", format!("error {}", i));
+        }
+        let list = errors.list();
+        assert_eq!(list.len(), MAX_RECENT_ERRORS);
+        assert_eq!(list[0].message, format!("error {}", MAX_RECENT_ERRORS + 1));
+        assert_eq!(list[MAX_RECENT_ERRORS - 1].message, "error 2");
+    }
+}
@@ -0,0 +1,166 @@
+use serde_json::Value;
+
+const EMAIL_PLACEHOLDER: &str = "[email]";
+const PHONE_PLACEHOLDER: &str = "[phone]";
+
+/// Digits of the shortest local and the longest international (E.164) phone number.
+const MIN_PHONE_DIGITS: usize = 7;
+const MAX_PHONE_DIGITS: usize = 15;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct RedactChatRequest{
+    /// Unset for the server default.
+    pub enabled: Option<bool>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RedactChatMessage{
+    r#type: String,
+    enabled: bool,
+}
+
+impl RedactChatMessage{
+    pub fn new(enabled: bool) -> Self {
+        Self{
+            r#type: "redact_chat".to_string(),
+            enabled,
+        }
+    }
+}
+
+/// Replaces email addresses and phone numbers in the `text` of a chat message, `None` when there was
+/// nothing to redact so the message is relayed as sent.
+pub fn redact_chat(msg: &str) -> Option<String> {
+    let mut message: Value = serde_json::from_str(msg).ok()?;
+    let text = message.get("text").and_then(Value::as_str)?;
+    let redacted = redact_text(text);
+    if redacted == text{
+        return None;
+    }
+    message["text"] = Value::String(redacted);
+    Some(message.to_string())
+}
+
+pub fn redact_text(text: &str) -> String {
+    redact_phone_numbers(&redact_emails(text))
+}
+
+fn redact_emails(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()){
+        redacted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let word = &rest[..end];
+        // Addresses are often wrapped in brackets or end a sentence
+        let trimmed = word.trim_start_matches(['(', '<', '"', '\'']);
+        let leading = &word[..word.len() - trimmed.len()];
+        let core = trimmed.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '>', '"', '\'']);
+        if is_email(core){
+            redacted.push_str(leading);
+            redacted.push_str(EMAIL_PLACEHOLDER);
+            redacted.push_str(&trimmed[core.len()..]);
+        }
+        else{
+            redacted.push_str(word);
+        }
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+fn is_email(word: &str) -> bool {
+    let Some((local, domain)) = word.split_once('@') else {
+        return false;
+    };
+    let Some((_, tld)) = domain.rsplit_once('.') else {
+        return false;
+    };
+    !local.is_empty()
+        && local.chars().all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-'))
+        && domain.split('.').all(|label| !label.is_empty() && label.chars().all(|c| c.is_alphanumeric() || c == '-'))
+        && tld.len() >= 2
+        && tld.chars().all(char::is_alphabetic)
+}
+
+/// Runs of digits joined by single spaces, dashes, dots or brackets. Bingo players chat numbers too, so
+/// a run only counts as a phone number with enough digits and either a leading `+` or a group of at
+/// least three digits, which keeps lists of called numbers like `12 34 56 78` readable.
+fn redact_phone_numbers(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut redacted = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len(){
+        let starts_number = matches!(chars[i], '+' | '(') || chars[i].is_ascii_digit();
+        let after_word = i > 0 && chars[i - 1].is_alphanumeric();
+        if starts_number && !after_word{
+            if let Some(end) = phone_number_end(&chars[i..]){
+                redacted.push_str(PHONE_PLACEHOLDER);
+                i += end;
+                continue;
+            }
+        }
+        redacted.push(chars[i]);
+        i += 1;
+    }
+    redacted
+}
+
+/// Length of the phone number at the start of `chars`, if there is one.
+fn phone_number_end(chars: &[char]) -> Option<usize> {
+    let mut digits = 0;
+    let mut group = 0;
+    let mut longest_group = 0;
+    let mut end = 0;
+    for (i, c) in chars.iter().enumerate(){
+        match c {
+            '0'..='9' => {
+                digits += 1;
+                group += 1;
+                longest_group = longest_group.max(group);
+                end = i + 1;
+            }
+            '+' if i == 0 => {}
+            ' ' | '-' | '.' | '(' | ')' => {
+                // Separators never come in pairs within a number, except a bracket next to a space
+                if i > 0 && matches!(chars[i - 1], ' ' | '-' | '.') && !matches!(c, '(' | ')'){
+                    break;
+                }
+                group = 0;
+            }
+            _ => break,
+        }
+    }
+    // A number glued to letters is a code or a name, not something to dial
+    if chars.get(end).is_some_and(|c| c.is_alphanumeric()){
+        return None;
+    }
+    let plausible = chars[0] == '+' || longest_group >= 3;
+    (plausible && (MIN_PHONE_DIGITS..=MAX_PHONE_DIGITS).contains(&digits)).then_some(end)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn redacts_emails_and_phone_numbers(){
+        assert_eq!(redact_text("mail me at jo.doe+bingo@example.co.uk."), "mail me at [email].");
+        assert_eq!(redact_text("(jo@example.com) or call +44 20 7946 0958!"), "([email]) or call [phone]!");
+        assert_eq!(redact_text("text 555-123-4567 or (030) 1234567"), "text [phone] or [phone]");
+        assert_eq!(redact_text("I need 12 34 56 78 and 90"), "I need 12 34 56 78 and 90");
+        assert_eq!(redact_text("@everyone room 12345 is fun, see you at 19.30"), "@everyone room 12345 is fun, see you at 19.30");
+        assert_eq!(redact_text("card ABC1234567 won"), "card ABC1234567 won");
+    }
+
+    #[test]
+    fn redacts_only_the_chat_text(){
+        let msg = r#"{"type":"chat","client_id":4155550123,"text":"call 415 555 0123"}"#;
+        let redacted: Value = serde_json::from_str(&redact_chat(msg).unwrap()).unwrap();
+        assert_eq!(redacted["text"], "call [phone]");
+        assert_eq!(redacted["client_id"], 4155550123u64);
+        assert_eq!(redact_chat(r#"{"type":"chat","text":"Bingo!"}"#), None);
+    }
+}
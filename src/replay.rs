@@ -0,0 +1,126 @@
+use actix_web::{error, get, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::room::{BingoServerHandle, RoomId};
+
+#[derive(Deserialize)]
+struct ReplayQuery {
+    /// Limits the replay to one round, the whole game otherwise.
+    round: Option<i32>,
+    /// Token of a room that is still open, its host can sign in instead.
+    room_token: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct JournalEntry {
+    kind: String,
+    round: i32,
+    number: Option<i16>,
+    card: Option<i32>,
+    at: DateTime<Utc>,
+}
+
+/// A replay step, kept small since a game can have hundreds of calls.
+#[derive(serde::Serialize)]
+struct ReplayEvent {
+    /// Milliseconds since the start of the replay.
+    t: i64,
+    r#type: String,
+    round: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    number: Option<i16>,
+    /// Card a player got for a traded in one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    card: Option<i32>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Replay {
+    room: RoomId,
+    started_at: Option<DateTime<Utc>>,
+    events: Vec<ReplayEvent>,
+}
+
+impl Replay{
+    /// Loads the calls, wins and card trade-ins of a room, `None` when it has none.
+    pub async fn load(database: &sqlx::PgPool, room: RoomId, round: Option<i32>) -> sqlx::Result<Option<Self>> {
+        // Fair rounds played before round starts were recorded have their start in the audit record
+        let journal: Vec<JournalEntry> = sqlx::query_as(
+            "SELECT 'round_started' AS kind, round, NULL::smallint AS number, NULL::integer AS card, started_at AS at FROM round_starts WHERE room_id = $1 AND ($2::integer IS NULL OR round = $2) \
+             UNION ALL SELECT 'round_started', round, NULL, NULL, started_at FROM round_audits a WHERE room_id = $1 AND ($2::integer IS NULL OR round = $2) \
+                AND NOT EXISTS (SELECT 1 FROM round_starts s WHERE s.room_id = a.room_id AND s.round = a.round) \
+             UNION ALL SELECT 'card_traded', round, NULL, card, traded_at FROM card_trade_ins WHERE room_id = $1 AND ($2::integer IS NULL OR round = $2) \
+             UNION ALL SELECT 'draw', round, number, NULL, called_at FROM calls WHERE room_id = $1 AND ($2::integer IS NULL OR round = $2) \
+             UNION ALL SELECT 'win', round, NULL, NULL, won_at FROM wins WHERE room_id = $1 AND ($2::integer IS NULL OR round = $2) \
+             ORDER BY at, round")
+            .bind(room)
+            .bind(round)
+            .fetch_all(database)
+            .await?;
+
+        if journal.is_empty(){
+            return Ok(None);
+        }
+
+        let started_at = journal.first().map(|entry| entry.at);
+        let events = journal.into_iter()
+            .map(|entry| ReplayEvent{
+                t: started_at.map_or(0, |start| (entry.at - start).num_milliseconds()),
+                r#type: entry.kind,
+                round: entry.round,
+                number: entry.number,
+                card: entry.card,
+            })
+            .collect();
+        Ok(Some(Self{ room, started_at, events }))
+    }
+}
+
+/// Whether the signed in host owns the room, archived rooms included.
+async fn is_room_host(database: &sqlx::PgPool, room: RoomId, host: &str) -> sqlx::Result<bool> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM rooms WHERE id = $1 AND host = $2)")
+        .bind(room)
+        .bind(host)
+        .fetch_one(database)
+        .await
+}
+
+/// Time ordered calls and wins of a room for animating a finished game, for its host or with the room token.
+#[get("/room/{room}/replay")]
+async fn room_replay(
+    user: Option<HostIdentity>,
+    path: web::Path<(RoomId,)>,
+    query: web::Query<ReplayQuery>,
+    server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let room = path.0;
+    let allowed = match (&query.room_token, user) {
+        (Some(token), _) => server.has_room_host_privileges(room, token.clone()).await,
+        (None, Some(user)) => {
+            user.require_scope(Scope::Host)?;
+            is_room_host(&database, room, &user.username).await.map_err(|e| {
+                log::error!("Failed to look up the host of room {}: {}", room, e);
+                error::ErrorInternalServerError("Failed to load replay")
+            })?
+        }
+        (None, None) => return Err(error::ErrorUnauthorized("Login required using /host endpoint or the room token")),
+    };
+    if !allowed{
+        return Err(error::ErrorNotFound("Room not found"));
+    }
+
+    let replay = Replay::load(&database, room, query.round)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load replay of room {}: {}", room, e);
+            error::ErrorInternalServerError("Failed to load replay")
+        })?;
+
+    match replay {
+        Some(replay) => Ok(HttpResponse::Ok().json(replay)),
+        None => Err(error::ErrorNotFound("Nothing to replay")),
+    }
+}
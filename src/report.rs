@@ -0,0 +1,67 @@
+use std::collections::BTreeMap;
+
+use crate::card::CardSettings;
+use crate::notes::ConnectionNote;
+use crate::room::SessionId;
+use crate::seats::Seat;
+
+#[derive(serde::Serialize)]
+pub struct PlayerReport{
+    pub client_id: SessionId,
+    /// Number of cards the player holds.
+    pub cards: usize,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub note: Option<ConnectionNote>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seat: Option<Seat>,
+}
+
+/// Summary of the room sent to the host on request.
+#[derive(serde::Serialize)]
+pub struct ReportMessage{
+    r#type: String,
+    rounds_played: u32,
+    card_settings: CardSettings,
+    cards_sold: usize,
+    /// Total of the card prices, when a price is configured.
+    revenue_cents: Option<u64>,
+    players: Vec<PlayerReport>,
+    /// Players per table, for delivering prizes in the hall.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    tables: BTreeMap<String, Vec<SessionId>>,
+}
+
+impl ReportMessage{
+    pub fn new(rounds_played: u32, card_settings: CardSettings, players: Vec<PlayerReport>, tables: BTreeMap<String, Vec<SessionId>>) -> Self {
+        let cards_sold = players.iter().map(|player| player.cards).sum::<usize>();
+        let revenue_cents = card_settings.price_cents.map(|price| price as u64 * cards_sold as u64);
+        Self{
+            r#type: "report".to_string(),
+            rounds_played,
+            card_settings,
+            cards_sold,
+            revenue_cents,
+            players,
+            tables,
+        }
+    }
+
+    /// Keeps the counts but drops what tells players apart, the host's notes and the seating.
+    pub fn aggregate(mut self) -> Self {
+        for player in &mut self.players{
+            player.note = None;
+            player.seat = None;
+        }
+        self.tables.clear();
+        self
+    }
+
+    /// Plain text overview for the report email.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("Rounds played: {}\nPlayers: {}\nCards sold: {}", self.rounds_played, self.players.len(), self.cards_sold);
+        if let Some(revenue_cents) = self.revenue_cents{
+            summary.push_str(&format!("\nRevenue: {}.{:02}", revenue_cents / 100, revenue_cents % 100));
+        }
+        summary
+    }
+}
@@ -0,0 +1,64 @@
+use std::time::{Duration, Instant};
+
+use crate::room::SessionId;
+
+/// Longest grace period a host can set.
+const MAX_RESUME_GRACE_SECS: u64 = 300;
+
+/// Client socket that missed its heartbeats, e.g. while a phone switched networks. The player keeps its
+/// connection ID and nobody is told it left until the grace period runs out.
+#[derive(Debug)]
+pub struct SuspendedConnection{
+    pub player_token: String,
+    pub since: Instant,
+    /// Primary socket this one mirrored, restored when it resumes.
+    pub mirrored: Option<SessionId>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ResumeGraceRequest{
+    /// Unset for the server default, 0 turns fast resume off.
+    pub secs: Option<u64>,
+}
+
+impl ResumeGraceRequest{
+    pub fn validate(&self) -> Result<(), String> {
+        if self.secs.is_some_and(|secs| secs > MAX_RESUME_GRACE_SECS){
+            return Err(format!("The resume grace period is limited to {} seconds", MAX_RESUME_GRACE_SECS));
+        }
+        Ok(())
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ResumeGraceMessage{
+    r#type: String,
+    secs: u64,
+}
+
+impl ResumeGraceMessage{
+    pub fn new(grace: Duration) -> Self {
+        Self{
+            r#type: "resume_grace".to_string(),
+            secs: grace.as_secs(),
+        }
+    }
+}
+
+/// Tells a player its socket picked up the earlier connection, followed by the board and its cards.
+#[derive(serde::Serialize)]
+pub struct ResumedMessage{
+    r#type: String,
+    client_id: SessionId,
+    offline_ms: u64,
+}
+
+impl ResumedMessage{
+    pub fn new(client_id: SessionId, offline: Duration) -> Self {
+        Self{
+            r#type: "resumed".to_string(),
+            client_id,
+            offline_ms: offline.as_millis().try_into().unwrap_or(u64::MAX),
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use crate::persistence::PendingWrite;
+use crate::room::{RoomId, UserType};
+
+/// Kind of data a database write stores, checked against the retention policy of the room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataClass{
+    Chat,
+    /// Calls, wins and everything derived from them, e.g. round audits.
+    Calls,
+    /// Records naming a player, e.g. tournament points.
+    Roster,
+}
+
+/// What is kept about the players of a room.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RosterRetention{
+    /// Player records with names, and the player list in archived reports.
+    #[default]
+    Full,
+    /// Only counts, archived reports leave out the player list.
+    Aggregate,
+}
+
+/// What the server stores of a room, set by its host. The persistence layer drops writes of data the
+/// policy doesn't keep, gameplay is the same either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct RetentionPolicy{
+    #[serde(default)]
+    pub chat: bool,
+    #[serde(default = "keep_calls")]
+    pub calls: bool,
+    #[serde(default)]
+    pub roster: RosterRetention,
+}
+
+fn keep_calls() -> bool {
+    true
+}
+
+impl Default for RetentionPolicy{
+    fn default() -> Self {
+        Self{
+            chat: false,
+            calls: keep_calls(),
+            roster: RosterRetention::default(),
+        }
+    }
+}
+
+impl RetentionPolicy{
+    pub fn allows(&self, class: DataClass) -> bool {
+        match class {
+            DataClass::Chat => self.chat,
+            DataClass::Calls => self.calls,
+            DataClass::Roster => self.roster == RosterRetention::Full,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RetentionPolicyMessage{
+    r#type: String,
+    #[serde(flatten)]
+    policy: RetentionPolicy,
+}
+
+impl RetentionPolicyMessage{
+    pub fn new(policy: RetentionPolicy) -> Self {
+        Self{
+            r#type: "retention".to_string(),
+            policy,
+        }
+    }
+}
+
+/// Stores the policy with the room so it still applies after a restart.
+pub fn save_retention(room_id: RoomId, policy: RetentionPolicy) -> PendingWrite {
+    let policy = serde_json::to_string(&policy).unwrap();
+    PendingWrite::new(
+        format!("retention policy of room {}", room_id),
+        Box::new(move |database| {
+            let policy = policy.clone();
+            Box::pin(async move {
+                sqlx::query("UPDATE rooms SET retention = $2::jsonb WHERE id = $1")
+                    .bind(room_id)
+                    .bind(policy)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    )
+}
+
+/// Stores a chat message as relayed, only for rooms that keep chat. Player messages keep the player
+/// token of the sender so they are erased with the player's data.
+pub fn record_chat(room_id: RoomId, host: String, sender: UserType, player_token: Option<String>, message: String) -> PendingWrite {
+    PendingWrite::new(
+        format!("chat of room {}", room_id),
+        Box::new(move |database| {
+            let (host, player_token, message) = (host.clone(), player_token.clone(), message.clone());
+            Box::pin(async move {
+                sqlx::query("INSERT INTO chat_messages (room_id, host, sender, player_token, message) VALUES ($1, $2, $3, $4, $5::jsonb)")
+                    .bind(room_id)
+                    .bind(host)
+                    .bind(if sender == UserType::Host { "host" } else { "client" })
+                    .bind(player_token)
+                    .bind(message)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).classified(DataClass::Chat)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn missing_fields_keep_calls_but_not_chat(){
+        let policy: RetentionPolicy = serde_json::from_str(r#"{ "roster": "aggregate" }"#).unwrap();
+        assert!(policy.allows(DataClass::Calls));
+        assert!(!policy.allows(DataClass::Chat));
+        assert!(!policy.allows(DataClass::Roster));
+        assert!(RetentionPolicy::default().allows(DataClass::Roster));
+    }
+}
@@ -1,392 +1,5173 @@
-use std::{collections::HashMap, io};
+use std::{collections::{HashMap, HashSet, VecDeque}, io, sync::Arc, time::{Duration, Instant}};
 
+use chrono::{DateTime, NaiveDate, Utc};
 use rand::{rng, Rng as _};
-use tokio::sync::{mpsc, oneshot};
+use sqlx::types::Uuid;
+use tokio::{sync::{mpsc, oneshot}, time::{interval, sleep}};
+
+use crate::analytics::{Analytics, AnalyticsEventKind};
+use crate::archive::Archive;
+use crate::attendance::AttendanceRow;
+use crate::announcements::{load_announcements, remove_announcement, save_announcement, Announcement, AnnouncementMessage, ScheduleAnnouncementRequest, ScheduledAnnouncementsMessage, ANNOUNCEMENT_CHECK_INTERVAL, MAX_LATENESS, MAX_SCHEDULED};
+use crate::auto_call::{AutoCallMessage, AutoCaller};
+use crate::board::BoardMessage;
+use crate::card::{generate_claim_code, normalize_claim_code, Card, CardAssignedMessage, ClaimCodeResultMessage, CardId, CardMessage, CardSettings, CardStatusMessage, CardTradedMessage, ClaimResultMessage, DaubMessage};
+use crate::config::RoomConfig;
+use crate::chaos::{ChaosMessage, ChaosRequest, ChaosResult, ChaosSettings};
+use crate::cors::{AllowedOrigins, AllowedOriginsMessage};
+use crate::crypto::Keyring;
+use crate::devices::{DuplicateSessionPolicy, DuplicateSessionPolicyMessage, PlayerDevicesMessage, SessionMirroredMessage};
+use crate::persistence::{PendingWrite, Persistence};
+use crate::players::{record_player, ClaimStatus, PlayerCard, PlayerCards, PlayerConnection, PlayerIdentity, PlayerMatch, SessionTakeoverMessage, MAX_DEPARTED_PLAYERS, MAX_SEARCH_RESULTS};
+use crate::drain::DrainStatus;
+use crate::email::{send_email, Email, EmailMessage, EmailSettings, EmailSettingsMessage, Mailer};
+use crate::draw::{CallUndoneMessage, DrawMessage, DrawPool, DrawResult, ManualCallResult, Number};
+use crate::events::HostEvents;
+use crate::fairness::{record_commitment, record_reveal};
+use crate::fairness::FairSeed;
+use crate::game::{verify_claim, WinnerEvent};
+use crate::ghosts::{GhostStatus, GHOST_SWEEP_INTERVAL};
+use crate::handoff::{RoomSnapshot, RoundSnapshot, HANDOFF_GRACE};
+use crate::features::{Feature, FeatureFlags, RoomFeatures};
+use crate::mailbox::{CountingSender, MailboxStatus, SERVER_BUSY};
+use crate::message_log::MessageLog;
+use crate::presence::{PresenceBatch, PresenceMessage, RosterMessage};
+use crate::recent_errors::RecentErrors;
+use crate::report::{PlayerReport, ReportMessage};
+use crate::redact::{redact_chat, RedactChatMessage};
+use crate::retention::{record_chat, save_retention, RetentionPolicy, RetentionPolicyMessage, RosterRetention};
+use crate::resume::{ResumeGraceMessage, ResumedMessage, SuspendedConnection};
+use crate::stats::{record_call, record_round_start, record_trade_in, record_win, remove_call, LINE_PATTERN};
+use crate::subscription::{Channel, SubscribedMessage};
+use crate::notes::{ConnectionNote, ConnectionTaggedMessage};
+use crate::observer::ObservedMessage;
+use crate::overrides::ConfigOverrides;
+use crate::offline::{HostBacklog, HostOfflinePolicy, HostOfflinePolicyMessage};
+use crate::phase::{PhaseMessage, RoomPhase};
+use crate::seats::{group_by_table, seat_holder, Seat, SeatMap, SeatMapMessage, SeatMessage};
+use crate::milestones::{is_player_milestone, players_waiting, Milestone, MilestoneMessage, WAITING_MILESTONE};
+use crate::mqtt::MqttBridge;
+use crate::settings::{SettingsChangedMessage, SettingsConflictMessage, SettingsDelta, SettingsMessage};
+use crate::sound_check::{SoundCheck, SoundCheckMessage, SoundCheckRequest};
+use crate::sms::{generate_opt_in_code, is_valid_phone_number, SmsBridge, SmsOptInPendingMessage, MAX_RECIPIENTS};
+use crate::transfer::{TransferCancelledMessage, TransferError, TransferOffer, TransferOfferedMessage};
+use crate::variant::GameVariant;
+use crate::waitlist::{AdmittedMessage, PlayerLimit, WaitingConnection, WaitlistMessage, WaitlistedMessage};
+use crate::translate::{is_chat, TranslationQueue, Translator};
+use crate::versions::{count_versions, ClientInfo, ClientVersionsMessage, ForceRefreshMessage, ForceRefreshRequest, ForceRefreshResult, OutdatedClientMessage, RoomClientVersions, VersionCount, VersionPolicy, UNKNOWN_CLIENT};
+use crate::throttle::{ThrottledMessage, ThroughputLimiter};
+use crate::timezone::{CountdownMessage, StartTime, ZonedTime};
+use crate::tournaments::record_points;
+use crate::round::{ClaimWindowClosedMessage, ClaimWindowOpenedMessage, CollectedClaim, PrizeWonMessage, Round, RoundEndReason, RoundEndedMessage, RoundId, RoundSettings, RoundStartedMessage};
+use crate::wshandler::{ConnectionRejectedMessage, ErrorMessage, SessionClosedMessage, CLOSE_ROOM_TRANSFERRED, CLOSE_SESSION_REPLACED};
+use crate::ws_ticket::{generate_ticket, WsTicket};
 
 
 pub type RoomId = i32;
-pub type ConnId = u32;
-pub type Msg = String;
+/// Outgoing socket payload, shared by every recipient of a broadcast instead of copied per connection.
+pub type Msg = Arc<str>;
+
+/// ID of a host, client or board socket within its room, sent to the frontend as `conn_id` or `client_id`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct SessionId(u32);
+
+impl SessionId{
+    /// First ID a room hands out.
+    const FIRST: Self = Self(1);
+
+    fn next(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+
+    /// IDs are only handed out by rooms, tests of other modules make their own.
+    #[cfg(test)]
+    pub fn new(id: u32) -> Self {
+        Self(id)
+    }
+}
+
+impl std::fmt::Display for SessionId{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Kind of socket, decides what a connection receives and which commands it may send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserType{
+    Host,
+    Client,
+    /// Display-only connection that receives the board state, see `/board/{room}`.
+    Board,
+    /// Read-only support socket of an admin, see `/admin/rooms/{room}/observe`.
+    Observer,
+}
+
+/// Why a connection left its room, reported to the hosts with the presence summary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason{
+    /// The client closed its socket.
+    ClientClose,
+    /// The socket missed its heartbeats, and didn't resume within the grace period for players.
+    Timeout,
+    /// The server closed the socket, e.g. an outdated app or chaos mode.
+    Kicked,
+    /// The socket task failed or vanished without a goodbye.
+    Error,
+    /// A newer socket of the same player took over.
+    Superseded,
+}
+
+/// Phases in which players can get and trade in cards.
+const CARD_PHASES: [RoomPhase; 3] = [RoomPhase::Lobby, RoomPhase::Live, RoomPhase::Intermission];
 
-pub const USER_HOST : ConnId = 0;
-pub const USER_CLIENT : ConnId = 1;
+const SPEED_ROUND_CALLS: &str = "The server makes the calls in speed rounds";
 
 #[derive(sqlx::FromRow, Debug)]
 pub struct RoomCreds{
     pub id: RoomId,
     pub host: String,
     pub token: String,
+    /// Day the room was created for, rooms are archived after it.
+    pub valid_date: NaiveDate,
+    /// Token of the display boards, kept separate so it can be given to venue staff.
+    pub board_token: String,
+    #[sqlx(try_from = "String")]
+    pub variant: GameVariant,
 }
 
 impl RoomCreds{
-    pub fn new(id: RoomId, host: String, token: String) -> Self {
+    pub fn new(id: RoomId, host: String, token: String, valid_date: NaiveDate, board_token: String, variant: GameVariant) -> Self {
         Self{
             id,
             host,
             token,
+            valid_date,
+            board_token,
+            variant,
+        }
+    }
+}
+
+fn generate_token() -> String {
+    rng().random::<[u8; 32]>().to_vec().iter().map(|x| format!("{:02x}", x)).collect::<String>()
+}
+
+/// Reason a websocket was refused by the room.
+#[derive(Debug, Clone, Copy)]
+pub enum ConnectError{
+    /// The room already has the configured number of host sockets open.
+    HostConnectionLimit(usize),
+    /// The room was removed after the upgrade was accepted.
+    RoomClosed,
+    /// The room has its player limit and no waitlist.
+    RoomFull(usize),
+    /// Not a refusal, the player waits under this id until a slot frees up.
+    Waitlisted(SessionId),
+}
+
+impl ConnectError{
+    pub fn reason(&self) -> &'static str {
+        match self {
+            ConnectError::HostConnectionLimit(_) => "host_connection_limit",
+            ConnectError::RoomClosed => "room_closed",
+            ConnectError::RoomFull(_) => "room_full",
+            ConnectError::Waitlisted(_) => "waitlisted",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct HostConnection{
+    tx: mpsc::UnboundedSender<Msg>,
+    connected_at: Instant,
+}
+
+/// Open host sockets of a room, listed by the admin API to spot leaked tokens.
+#[derive(Debug, serde::Serialize)]
+pub struct HostConnections{
+    pub room: RoomId,
+    pub host: String,
+    /// Age of each open socket in seconds.
+    pub connection_ages_secs: Vec<u64>,
+}
+
+/// Open connections and progress of a room, listed by the admin API.
+#[derive(Debug, serde::Serialize)]
+pub struct LiveRoom{
+    pub room: RoomId,
+    pub host: String,
+    pub variant: GameVariant,
+    pub phase: RoomPhase,
+    pub round: Option<RoundId>,
+    pub calls: usize,
+    pub hosts: usize,
+    pub players: usize,
+    pub waiting: usize,
+    pub boards: usize,
+    pub observers: usize,
+}
+
+/// State of a room at a glance, for integrations that list a host's rooms.
+#[derive(Debug)]
+pub struct RoomOverview{
+    pub id: RoomId,
+    pub players: usize,
+    pub round: Option<RoundId>,
+    pub calls: usize,
+}
+
+/// Current room of the day, rooms rotate at midnight UTC.
+pub fn room_date() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+/// A command received by the [`ChatServer`].
+#[derive(Debug)]
+enum Command {
+    Create{
+        host: String,
+        variant: GameVariant,
+        res_tx: tokio::sync::oneshot::Sender<Option<RoomCreds>>,
+    },
+
+    RoomExists{
+        room_id: RoomId,
+        res_tx: tokio::sync::oneshot::Sender<bool>,
+    },
+
+    RoomVariant{
+        room_id: RoomId,
+        res_tx: oneshot::Sender<Option<GameVariant>>,
+    },
+
+    RoomHostAuth{
+        room_id: RoomId,
+        host_token: String,
+        res_tx: tokio::sync::oneshot::Sender<bool>,
+    },
+
+    RoomBoardAuth{
+        room_id: RoomId,
+        board_token: String,
+        res_tx: tokio::sync::oneshot::Sender<bool>,
+    },
+
+    Connect {
+        room: RoomId,
+        conn_tx: mpsc::UnboundedSender<Msg>,
+        res_tx: tokio::sync::oneshot::Sender<Result<SessionId, ConnectError>>,
+        user_type: UserType,
+        player: Option<PlayerIdentity>,
+    },
+
+    Disconnect {
+        room: RoomId,
+        conn: SessionId,
+        user_type: UserType,
+        /// Players that timed out can resume the connection for a while.
+        reason: DisconnectReason,
+    },
+
+    ExpireSuspended{
+        room: RoomId,
+        conn: SessionId,
+        since: Instant,
+    },
+
+    SetResumeGrace{
+        room: RoomId,
+        grace: Option<Duration>,
+    },
+
+    SetRetention{
+        room: RoomId,
+        policy: RetentionPolicy,
+    },
+
+    SetRedactChat{
+        room: RoomId,
+        enabled: Option<bool>,
+    },
+
+    Update{
+        room: RoomId,
+        /// Client connection that sent the message, `None` for the host.
+        sender: Option<SessionId>,
+        msg: String,
+        user_type: UserType,
+    },
+
+    Relay{
+        room: RoomId,
+        msg: String,
+        user_type: UserType,
+    },
+
+    SetLanguage{
+        room: RoomId,
+        locale: Option<String>,
+        version: Option<u64>,
+    },
+
+    SetSmsRecipients{
+        room: RoomId,
+        numbers: Vec<String>,
+        version: Option<u64>,
+    },
+
+    ConfirmSmsRecipient{
+        room: RoomId,
+        number: String,
+        code: String,
+    },
+
+    /// Opt-ins of the numbers a host set were looked up.
+    SmsOptInsLoaded{
+        room: RoomId,
+        numbers: Vec<String>,
+        opted_in: Result<Vec<String>, sqlx::Error>,
+        version: Option<u64>,
+    },
+
+    /// The opt-in of a number was saved, or failed to.
+    SmsOptInSaved{
+        room: RoomId,
+        number: String,
+        code: String,
+        saved: Result<(), sqlx::Error>,
+    },
+
+    SetMilestones{
+        room: RoomId,
+        enabled: bool,
+        version: Option<u64>,
+    },
+
+    GetSettings{
+        room: RoomId,
+    },
+
+    Send{
+        room: RoomId,
+        conn: SessionId,
+        msg: String,
+        res_tx: tokio::sync::oneshot::Sender<bool>,
+    },
+
+    NotifyHost{
+        room: RoomId,
+        msg: String,
+    },
+
+    StartRound{
+        room: RoomId,
+        settings: RoundSettings,
+    },
+
+    EndRound{
+        room: RoomId,
+        reason: RoundEndReason,
+    },
+
+    CloseClaimWindow{
+        room: RoomId,
+        round: RoundId,
+        window: u32,
+    },
+
+    RoundTimeout{
+        room: RoomId,
+        round: RoundId,
+    },
+
+    SpeedCall{
+        room: RoomId,
+        round: RoundId,
+    },
+
+    StartAutoCall{
+        room: RoomId,
+        interval: Duration,
+    },
+
+    PauseAutoCall{
+        room: RoomId,
+        paused: bool,
+    },
+
+    AutoCall{
+        room: RoomId,
+        generation: u32,
+    },
+
+    RecordWinner{
+        room: RoomId,
+        conn: SessionId,
+    },
+
+    Draw{
+        room: RoomId,
+        request_id: Option<String>,
+    },
+
+    ManualCall{
+        room: RoomId,
+        number: Number,
+    },
+
+    UndoLastCall{
+        room: RoomId,
+    },
+
+    SetCardSettings{
+        room: RoomId,
+        settings: CardSettings,
+        version: Option<u64>,
+    },
+
+    RequestCard{
+        room: RoomId,
+        conn: SessionId,
+    },
+
+    NewCard{
+        room: RoomId,
+        conn: SessionId,
+        card_id: Option<CardId>,
+    },
+
+    AssignCard{
+        room: RoomId,
+        conn: SessionId,
+        card: Card,
+    },
+
+    ClaimBingo{
+        room: RoomId,
+        conn: SessionId,
+    },
+
+    Report{
+        room: RoomId,
+    },
+
+    IssueWsTicket{
+        room: RoomId,
+        user_type: UserType,
+        identity: String,
+        res_tx: tokio::sync::oneshot::Sender<(String, Duration)>,
+    },
+
+    RedeemWsTicket{
+        room: RoomId,
+        ticket: String,
+        user_type: UserType,
+        res_tx: tokio::sync::oneshot::Sender<Option<String>>,
+    },
+
+    SubscribeHostEvents{
+        host: String,
+        tx: mpsc::UnboundedSender<Msg>,
+    },
+
+    SubscribeAllEvents{
+        tx: mpsc::UnboundedSender<Msg>,
+    },
+
+    CloseRoom{
+        room: RoomId,
+        res_tx: oneshot::Sender<Option<bool>>,
+    },
+
+    ApplyConfig{
+        overrides: ConfigOverrides,
+    },
+
+    FlushPresence,
+
+    SweepGhosts,
+
+    RotateRooms,
+
+    Roster{
+        room: RoomId,
+    },
+
+    Subscribe{
+        room: RoomId,
+        conn: SessionId,
+        channels: Vec<Channel>,
+    },
+
+    HostConnections{
+        res_tx: oneshot::Sender<Vec<HostConnections>>,
+    },
+
+    LiveRooms{
+        res_tx: oneshot::Sender<Vec<LiveRoom>>,
+    },
+
+    FindPlayers{
+        host: String,
+        name: String,
+        res_tx: oneshot::Sender<Vec<PlayerMatch>>,
+    },
+
+    PlayerCards{
+        room: RoomId,
+        player_token: String,
+        res_tx: oneshot::Sender<Option<PlayerCards>>,
+    },
+
+    PlayerCard{
+        room: RoomId,
+        player_token: String,
+        res_tx: oneshot::Sender<Option<Result<PlayerCard, String>>>,
+    },
+
+    Attendance{
+        room: RoomId,
+        host: String,
+        res_tx: oneshot::Sender<Option<Vec<AttendanceRow>>>,
+    },
+
+    ListRooms{
+        host: String,
+        res_tx: oneshot::Sender<Vec<RoomOverview>>,
+    },
+
+    ExportState{
+        res_tx: oneshot::Sender<Vec<RoomSnapshot>>,
+    },
+
+    ImportState{
+        rooms: Vec<RoomSnapshot>,
+        res_tx: oneshot::Sender<usize>,
+    },
+
+    SetDraining{
+        draining: bool,
+        res_tx: oneshot::Sender<()>,
+    },
+
+    DrainStatus{
+        res_tx: oneshot::Sender<DrainStatus>,
+    },
+
+    ClientInfo{
+        room: RoomId,
+        conn: SessionId,
+        info: ClientInfo,
+        policy: VersionPolicy,
+    },
+
+    ClientVersions{
+        room: RoomId,
+    },
+
+    SetPhase{
+        room: RoomId,
+        phase: RoomPhase,
+    },
+
+    TagConnection{
+        room: RoomId,
+        conn: SessionId,
+        note: ConnectionNote,
+    },
+
+    SetSeatMap{
+        room: RoomId,
+        seat_map: SeatMap,
+    },
+
+    SetAllowedOrigins{
+        room: RoomId,
+        origins: AllowedOrigins,
+    },
+
+    SetChaos{
+        room: RoomId,
+        request: ChaosRequest,
+        by_admin: bool,
+        res_tx: oneshot::Sender<Option<Result<ChaosResult, String>>>,
+    },
+
+    OriginAllowed{
+        room: RoomId,
+        origin: Option<String>,
+        res_tx: oneshot::Sender<bool>,
+    },
+
+    TakeSeat{
+        room: RoomId,
+        conn: SessionId,
+        seat: Seat,
+        /// Assigned by the host rather than claimed by the player.
+        by_host: bool,
+    },
+
+    ForceRefresh{
+        /// Every room when unset.
+        room: Option<RoomId>,
+        request: ForceRefreshRequest,
+        res_tx: oneshot::Sender<ForceRefreshResult>,
+    },
+
+    AllClientVersions{
+        res_tx: oneshot::Sender<Vec<RoomClientVersions>>,
+    },
+
+    RecordPlayer{
+        room: RoomId,
+        token: String,
+        display_name: Option<String>,
+        ip: Option<String>,
+    },
+
+    RemoveHostRooms{
+        host: String,
+        res_tx: oneshot::Sender<()>,
+    },
+
+    StartSoundCheck{
+        room: RoomId,
+        request: SoundCheckRequest,
+    },
+
+    SoundCheckAck{
+        room: RoomId,
+        conn: SessionId,
+        id: u32,
+    },
+
+    FinishSoundCheck{
+        room: RoomId,
+        id: u32,
+    },
+
+    ScheduleAnnouncement{
+        room: RoomId,
+        request: ScheduleAnnouncementRequest,
+    },
+
+    CancelAnnouncement{
+        room: RoomId,
+        id: Uuid,
+    },
+
+    ListAnnouncements{
+        room: RoomId,
+    },
+
+    FireAnnouncements,
+
+    OfferTransfer{
+        room: RoomId,
+        host_token: String,
+        from: String,
+        to: String,
+        res_tx: oneshot::Sender<Result<TransferOffer, TransferError>>,
+    },
+
+    AcceptTransfer{
+        room: RoomId,
+        user: String,
+        res_tx: oneshot::Sender<Result<RoomCreds, TransferError>>,
+    },
+
+    /// The new owner of a transferred room was written to the database, or failed to.
+    FinishTransfer{
+        room: RoomId,
+        offer: TransferOffer,
+        creds: RoomCreds,
+        saved: Result<(), sqlx::Error>,
+        res_tx: oneshot::Sender<Result<RoomCreds, TransferError>>,
+    },
+
+    CancelTransfer{
+        room: RoomId,
+        user: String,
+        res_tx: oneshot::Sender<Result<(), TransferError>>,
+    },
+
+    SetPlayerLimit{
+        room: RoomId,
+        limit: Option<PlayerLimit>,
+    },
+
+    VerifyClaimCode{
+        room: RoomId,
+        code: String,
+    },
+
+    SetHostOfflinePolicy{
+        room: RoomId,
+        policy: HostOfflinePolicy,
+    },
+
+    SetEmailSettings{
+        room: RoomId,
+        settings: EmailSettings,
+    },
+
+    SetPlayerEmail{
+        room: RoomId,
+        conn: SessionId,
+        email: Option<String>,
+    },
+
+    SetDuplicateSessionPolicy{
+        room: RoomId,
+        policy: DuplicateSessionPolicy,
+    },
+
+    SetCountdown{
+        room: RoomId,
+        starts_at: Option<StartTime>,
+        time_zone: String,
+    },
+}
+
+
+#[derive(Debug)]
+struct Room{
+    id: RoomId,
+    host: String,
+    host_token: String,
+    /// Open host sockets, host messages are delivered to all of them.
+    host_pipes: HashMap<SessionId, HostConnection>,
+    /// Maximum simultaneous host sockets, 0 for no limit.
+    max_host_connections: usize,
+    /// Map of connection IDs to their message receivers.
+    sessions: HashMap<SessionId, mpsc::UnboundedSender<Msg>>,
+    /// Decides which host and player actions are accepted.
+    phase: RoomPhase,
+    /// Tables and seats of the hall, players can't take seats without one.
+    seat_map: Option<SeatMap>,
+    /// Seats taken by connections, kept after the client leaves until someone else takes the seat.
+    seats: HashMap<SessionId, Seat>,
+    /// Host tags and notes, kept for the lifetime of the room even after the client leaves.
+    notes: HashMap<SessionId, ConnectionNote>,
+    /// App versions and platforms reported by the clients.
+    clients: HashMap<SessionId, ClientInfo>,
+    /// Web origins players may join from, any origin when empty.
+    allowed_origins: AllowedOrigins,
+    /// Players behind the client sockets, kept after they leave so hosts can still find them.
+    /// Wins score tournament points for these players.
+    players: HashMap<SessionId, PlayerConnection>,
+    /// Channels of the clients that subscribed to a subset of the broadcasts.
+    subscriptions: HashMap<SessionId, Vec<Channel>>,
+    board_token: String,
+    /// Connected display boards.
+    boards: HashMap<SessionId, mpsc::UnboundedSender<Msg>>,
+    /// Support sockets of admins, they get what the hosts and players of the room get.
+    observers: HashMap<SessionId, mpsc::UnboundedSender<Msg>>,
+    /// Language chat is translated to, e.g. `es`.
+    locale: Option<String>,
+    /// Started with the first chat message that needs translating.
+    translations: Option<TranslationQueue>,
+    /// Phone numbers that opted in to get every call and the winners.
+    sms_recipients: Vec<String>,
+    /// Opt-in codes sent to numbers the host added, by phone number.
+    sms_pending: HashMap<String, String>,
+    /// Bumped on every settings change so co-hosts can't overwrite each other's edits.
+    settings_version: u64,
+    /// Emit milestone events for crowd engagement.
+    milestones: bool,
+    /// Distinct players that joined since the room was created, a player reconnecting or opening a
+    /// second tab counts once. Sockets without a player token each count.
+    players_joined: usize,
+    /// Tokens of the players counted in `players_joined`.
+    joined_players: HashSet<String>,
+    /// Players one number away after the last call.
+    players_waiting: usize,
+    /// Round currently being played, if any.
+    round: Option<Round>,
+    /// Number of rounds started in this room.
+    rounds_played: RoundId,
+    /// Joins and leaves not yet reported to the host.
+    presence: PresenceBatch,
+    /// Features enabled for this room.
+    features: RoomFeatures,
+    /// Faults injected into the player traffic while testing.
+    chaos: ChaosSettings,
+    /// Aggregate message rate ceiling of the room.
+    throughput: ThroughputLimiter,
+    /// Game played in the room, fixed when the room is created.
+    variant: GameVariant,
+    /// Numbers drawn by the server, reset at the start of every round.
+    draws: DrawPool,
+    card_settings: CardSettings,
+    /// Cards held by each connection.
+    cards: HashMap<SessionId, Vec<Card>>,
+    /// Cards traded in by each connection since the last round ended.
+    trade_ins: HashMap<SessionId, u32>,
+    next_card_id: CardId,
+    /// Connection IDs are handed out in order and never reused, so a new socket can't take over the cards,
+    /// seat or notes of an earlier one.
+    next_conn_id: SessionId,
+    /// Diagnostic broadcast waiting for acknowledgements.
+    sound_check: Option<SoundCheck>,
+    sound_checks_run: u32,
+    /// Host announcements waiting to be sent, by time.
+    announcements: Vec<Announcement>,
+    /// Ownership offered to another host account.
+    transfer: Option<TransferOffer>,
+    player_limit: Option<PlayerLimit>,
+    /// Players waiting for a slot, in the order they joined.
+    waitlist: VecDeque<WaitingConnection>,
+    host_offline_policy: HostOfflinePolicy,
+    duplicate_session_policy: DuplicateSessionPolicy,
+    /// Extra sockets of players mirroring their first socket, by the socket they mirror.
+    mirrors: HashMap<SessionId, SessionId>,
+    /// What the server stores of the room.
+    retention: RetentionPolicy,
+    /// Whether personal details are removed from chat, unset for the server default.
+    redact_chat: Option<bool>,
+    /// Player sockets that timed out and can still be resumed.
+    suspended: HashMap<SessionId, SuspendedConnection>,
+    /// Overrides the server wide grace period for resuming.
+    resume_grace: Option<Duration>,
+    /// Draws the calls of the round on a cadence when the host turned it on.
+    auto_caller: Option<AutoCaller>,
+    /// Auto-caller timers started so far, see [`AutoCaller::generation`].
+    auto_call_generation: u32,
+    email_settings: EmailSettings,
+    /// Start of the next game, until a round starts.
+    countdown: Option<ZonedTime>,
+    /// Addresses players left for prize-claim instructions, never persisted.
+    player_emails: HashMap<SessionId, String>,
+    /// Host messages missed since the last host socket of a live room closed.
+    host_backlog: Option<HostBacklog>,
+    /// Day the room was created for.
+    valid_date: NaiveDate,
+}
+
+impl Room{
+    pub fn new(host: String, variant: GameVariant) -> Self {
+        let id = rng().random::<RoomId>();
+        //Generated HOST ID has a 256 bit length UUID
+        let host_token = generate_token();
+
+        Self::create_from_entry(host, id, host_token, room_date(), generate_token(), variant)
+    }
+
+    pub fn create_from_entry(host: String, id: RoomId, host_token: String, valid_date: NaiveDate, board_token: String, variant: GameVariant) -> Self {
+        let sessions = HashMap::new();
+        Self{
+            id,
+            host,
+            host_token,
+            host_pipes: HashMap::new(),
+            max_host_connections: 0,
+            sessions,
+            phase: RoomPhase::Lobby,
+            seat_map: None,
+            seats: HashMap::new(),
+            notes: HashMap::new(),
+            players: HashMap::new(),
+            allowed_origins: AllowedOrigins::default(),
+            clients: HashMap::new(),
+            subscriptions: HashMap::new(),
+            board_token,
+            boards: HashMap::new(),
+            observers: HashMap::new(),
+            locale: None,
+            translations: None,
+            sms_recipients: Vec::new(),
+            sms_pending: HashMap::new(),
+            settings_version: 0,
+            milestones: false,
+            players_joined: 0,
+            joined_players: HashSet::new(),
+            players_waiting: 0,
+            round: None,
+            rounds_played: 0,
+            presence: PresenceBatch::default(),
+            features: RoomFeatures::default(),
+            chaos: ChaosSettings::default(),
+            throughput: ThroughputLimiter::new(0),
+            variant,
+            draws: DrawPool::new(variant),
+            card_settings: CardSettings::default(),
+            cards: HashMap::new(),
+            trade_ins: HashMap::new(),
+            next_card_id: 1,
+            next_conn_id: SessionId::FIRST,
+            sound_check: None,
+            sound_checks_run: 0,
+            announcements: Vec::new(),
+            transfer: None,
+            player_limit: None,
+            waitlist: VecDeque::new(),
+            host_offline_policy: HostOfflinePolicy::default(),
+            duplicate_session_policy: DuplicateSessionPolicy::default(),
+            mirrors: HashMap::new(),
+            suspended: HashMap::new(),
+            retention: RetentionPolicy::default(),
+            redact_chat: None,
+            resume_grace: None,
+            auto_caller: None,
+            auto_call_generation: 0,
+            email_settings: EmailSettings::default(),
+            countdown: None,
+            player_emails: HashMap::new(),
+            host_backlog: None,
+            valid_date,
+        }
+    }
+
+    pub async fn add_client(&mut self, tx: mpsc::UnboundedSender<Msg>, user_type: UserType, player: Option<PlayerIdentity>) -> Result<SessionId, ConnectError> {
+
+        if user_type == UserType::Host
+        {
+            if self.max_host_connections > 0 && self.host_pipes.len() >= self.max_host_connections{
+                log::warn!("Rejected host socket for room {}, {} already open", self.id, self.host_pipes.len());
+                return Err(ConnectError::HostConnectionLimit(self.max_host_connections));
+            }
+            let id = self.allocate_conn_id();
+            tracing::info!("Adding host connection {} to room {}", id, self.id);
+            if let Some(backlog) = self.host_backlog.take(){
+                let _ = tx.send(serde_json::to_string(&backlog.into_digest()).unwrap().into());
+            }
+            self.host_pipes.insert(id, HostConnection{ tx, connected_at: Instant::now() });
+            return Ok(id);
+        }
+        if user_type == UserType::Board
+        {
+            let id = self.allocate_conn_id();
+            tracing::info!("Adding board {} to room {}", id, self.id);
+            let _ = tx.send(self.board_message());
+            if let Some(countdown) = &self.countdown{
+                let _ = tx.send(serde_json::to_string(&CountdownMessage::new(Some(countdown))).unwrap().into());
+            }
+            self.boards.insert(id, tx);
+            return Ok(id);
+        }
+        if self.phase == RoomPhase::Ended{
+            return Err(ConnectError::RoomClosed);
+        }
+        if user_type == UserType::Observer
+        {
+            let id = self.allocate_conn_id();
+            log::warn!("Adding observer {} to room {}", id, self.id);
+            let _ = tx.send(self.board_message());
+            self.observers.insert(id, tx);
+            return Ok(id);
+        }
+        if let Some(id) = self.suspended_conn(player.as_ref()){
+            self.resume_client(id, tx).await;
+            return Ok(id);
+        }
+        // A player token still connected means the link was opened again, e.g. in a new tab or by someone it was shared with
+        let replaced = player.as_ref().and_then(|player| self.players.iter()
+            .find(|(conn_id, connection)| connection.player.token == player.token && self.sessions.contains_key(*conn_id))
+            .map(|(conn_id, _)| *conn_id));
+
+        // A player taking over its own session frees its old slot
+        if let Some(limit) = self.player_limit.filter(|limit| replaced.is_none() && self.player_count() >= limit.max_players){
+            if !limit.waitlist{
+                log::info!("Refused a player in room {}, {} players are in", self.id, self.player_count());
+                return Err(ConnectError::RoomFull(limit.max_players));
+            }
+            let id = self.allocate_conn_id();
+            let _ = tx.send(serde_json::to_string(&WaitlistedMessage::new(self.waitlist.len() + 1)).unwrap().into());
+            self.waitlist.push_back(WaitingConnection{ id, tx, player });
+            log::info!("Client {} is waiting for a slot in room {} at position {}", id, self.id, self.waitlist.len());
+            self.send_waitlist().await;
+            return Err(ConnectError::Waitlisted(id));
+        }
+
+        let id = self.allocate_conn_id();
+        self.admit_client(id, tx, player).await;
+
+        if let Some(replaced) = replaced.filter(|_| self.duplicate_session_policy == DuplicateSessionPolicy::Mirror){
+            let primary = self.mirrors.get(&replaced).copied().unwrap_or(replaced);
+            self.mirrors.insert(id, primary);
+            let devices = self.devices(primary);
+            log::info!("Client {} mirrors client {} in room {}, the player has {} devices open", id, primary, self.id, devices);
+            self.send(id, &serde_json::to_string(&SessionMirroredMessage::new(primary, devices)).unwrap()).await;
+            for card in self.cards.get(&primary).into_iter().flatten(){
+                self.send(id, &serde_json::to_string(&CardMessage::new(card)).unwrap()).await;
+            }
+            self.send_host(&serde_json::to_string(&PlayerDevicesMessage::new(primary, devices)).unwrap()).await;
+        }
+        else if let Some(replaced) = replaced{
+            log::warn!("Client {} took over the session of client {} in room {}, closing the older socket", id, replaced, self.id);
+            self.send(id, &serde_json::to_string(&SessionTakeoverMessage::new(replaced)).unwrap()).await;
+            // The new socket plays on with the cards, seat and address of the session it took over
+            self.hand_over(replaced, id);
+            for card in self.cards.get(&id).into_iter().flatten(){
+                self.send(id, &serde_json::to_string(&CardMessage::new(card)).unwrap()).await;
+            }
+            let closed = SessionClosedMessage::new(CLOSE_SESSION_REPLACED, "Session taken over by a newer connection");
+            self.send(replaced, &serde_json::to_string(&closed).unwrap()).await;
+            // Dropping the sender closes the socket after the queued messages
+            self.remove_client(replaced, UserType::Client, DisconnectReason::Superseded).await;
+        }
+
+        Ok(id)
+    }
+
+    /// Moves what a player holds from a connection to the one that took over its session.
+    fn hand_over(&mut self, from: SessionId, to: SessionId){
+        if let Some(cards) = self.cards.remove(&from){
+            self.cards.insert(to, cards);
+        }
+        if let Some(trade_ins) = self.trade_ins.remove(&from){
+            self.trade_ins.insert(to, trade_ins);
+        }
+        if let Some(seat) = self.seats.remove(&from){
+            self.seats.insert(to, seat);
+        }
+        if let Some(email) = self.player_emails.remove(&from){
+            self.player_emails.insert(to, email);
+        }
+        for primary in self.mirrors.values_mut().filter(|primary| **primary == from){
+            *primary = to;
+        }
+    }
+
+    /// Suspended connection the player can pick up again.
+    fn suspended_conn(&self, player: Option<&PlayerIdentity>) -> Option<SessionId> {
+        let player = player?;
+        self.suspended.iter().find(|(_, suspended)| suspended.player_token == player.token).map(|(conn_id, _)| *conn_id)
+    }
+
+    /// Keeps a timed out player socket in the room without telling anyone it left, returns when it was suspended.
+    fn suspend_client(&mut self, conn_id: SessionId) -> Option<Instant> {
+        let player_token = self.players.get(&conn_id)?.player.token.clone();
+        self.sessions.remove(&conn_id)?;
+        let since = Instant::now();
+        let mirrored = self.mirrors.remove(&conn_id);
+        tracing::info!("Suspending client {} of room {}", conn_id, self.id);
+        self.suspended.insert(conn_id, SuspendedConnection{ player_token, since, mirrored });
+        Some(since)
+    }
+
+    /// Hands a suspended connection to the new socket and catches it up with the board and its cards.
+    async fn resume_client(&mut self, id: SessionId, tx: mpsc::UnboundedSender<Msg>){
+        let Some(suspended) = self.suspended.remove(&id) else {
+            return;
+        };
+        let offline = suspended.since.elapsed();
+        log::info!("Client {} resumed in room {} after {:?}", id, self.id, offline);
+        let _ = tx.send(serde_json::to_string(&ResumedMessage::new(id, offline)).unwrap().into());
+        let _ = tx.send(self.board_message());
+        for card in self.cards.get(&id).into_iter().flatten(){
+            let _ = tx.send(serde_json::to_string(&CardMessage::new(card)).unwrap().into());
+        }
+        if let Some(primary) = suspended.mirrored{
+            self.mirrors.insert(id, primary);
+        }
+        self.sessions.insert(id, tx);
+    }
+
+    /// Players taking up a slot, mirrored sockets don't count but suspended players keep theirs.
+    fn player_count(&self) -> usize {
+        let suspended = self.suspended.values().filter(|suspended| suspended.mirrored.is_none()).count();
+        self.sessions.keys().filter(|conn_id| !self.mirrors.contains_key(conn_id)).count() + suspended
+    }
+
+    /// Open sockets of the player whose first socket is `primary`.
+    fn devices(&self, primary: SessionId) -> usize {
+        usize::from(self.sessions.contains_key(&primary)) + self.mirrors.values().filter(|mirrored| **mirrored == primary).count()
+    }
+
+    /// Next free connection ID, IDs the room still knows about are skipped in case the counter wrapped around.
+    fn allocate_conn_id(&mut self) -> SessionId {
+        loop {
+            let id = self.next_conn_id;
+            self.next_conn_id = id.next();
+            if !self.is_known_conn_id(id){
+                return id;
+            }
+        }
+    }
+
+    fn is_known_conn_id(&self, id: SessionId) -> bool {
+        self.host_pipes.contains_key(&id) || self.sessions.contains_key(&id) || self.boards.contains_key(&id) || self.observers.contains_key(&id)
+            || self.cards.contains_key(&id) || self.players.contains_key(&id) || self.notes.contains_key(&id) || self.seats.contains_key(&id)
+            || self.waitlist.iter().any(|waiting| waiting.id == id)
+    }
+
+    async fn admit_client(&mut self, id: SessionId, tx: mpsc::UnboundedSender<Msg>, player: Option<PlayerIdentity>){
+        tracing::info!("Adding client {} to room {}", id, self.id);
+        if let Some(seat_map) = &self.seat_map{
+            let _ = tx.send(serde_json::to_string(&SeatMapMessage::new(seat_map)).unwrap().into());
+        }
+        if let Some(countdown) = &self.countdown{
+            let _ = tx.send(serde_json::to_string(&CountdownMessage::new(Some(countdown))).unwrap().into());
+        }
+        self.sessions.insert(id, tx);
+        let first_join = player.as_ref().is_none_or(|player| self.joined_players.insert(player.token.clone()));
+        if let Some(player) = player{
+            self.players.insert(id, PlayerConnection{ player, joined_at: Utc::now(), left_at: None });
+        }
+        if self.features.is_enabled(Feature::PresenceBatching){
+            self.presence.join(id);
+        }
+        if !first_join{
+            return;
+        }
+        self.players_joined += 1;
+        if self.milestones && is_player_milestone(self.players_joined){
+            self.announce_milestone(Milestone::PlayersJoined, self.players_joined).await;
+        }
+    }
+
+    /// Lets waiting players in while there are free slots and tells the rest their new positions,
+    /// true when anyone was admitted.
+    async fn admit_waiting(&mut self) -> bool {
+        if self.waitlist.is_empty(){
+            return false;
+        }
+        let max_players = self.player_limit.map_or(usize::MAX, |limit| limit.max_players);
+        let mut admitted = 0;
+        while self.player_count() < max_players{
+            let Some(waiting) = self.waitlist.pop_front() else {
+                break;
+            };
+            log::info!("Admitting waiting client {} to room {}", waiting.id, self.id);
+            let _ = waiting.tx.send(serde_json::to_string(&AdmittedMessage::new(waiting.id)).unwrap().into());
+            self.admit_client(waiting.id, waiting.tx, waiting.player).await;
+            admitted += 1;
+        }
+        if admitted == 0{
+            return false;
+        }
+        for (index, waiting) in self.waitlist.iter().enumerate(){
+            let _ = waiting.tx.send(serde_json::to_string(&WaitlistedMessage::new(index + 1)).unwrap().into());
+        }
+        true
+    }
+
+    async fn send_waitlist(&self){
+        let waiting = self.waitlist.iter().map(|waiting| waiting.id).collect();
+        self.send_host(&serde_json::to_string(&WaitlistMessage::new(self.player_limit, self.player_count(), waiting)).unwrap()).await;
+    }
+
+    /// Changes the player limit, players beyond a lowered limit stay but no one else is let in until
+    /// enough of them leave.
+    pub async fn set_player_limit(&mut self, limit: Option<PlayerLimit>){
+        self.player_limit = limit;
+        self.admit_waiting().await;
+        // Without a waitlist nobody can wait, dropping the senders closes their sockets
+        if let Some(limit) = limit.filter(|limit| !limit.waitlist){
+            let rejected: Msg = serde_json::to_string(&ConnectionRejectedMessage::new(ConnectError::RoomFull(limit.max_players))).unwrap().into();
+            for waiting in self.waitlist.drain(..){
+                let _ = waiting.tx.send(rejected.clone());
+            }
+        }
+        self.send_waitlist().await;
+    }
+
+    pub async fn remove_client(&mut self, conn_id: SessionId, user_type: UserType, reason: DisconnectReason){
+        if user_type == UserType::Host
+        {
+            // A live room left without a host keeps what the host misses for when it is back
+            if self.host_pipes.remove(&conn_id).is_some() && self.host_pipes.is_empty() && self.phase == RoomPhase::Live{
+                let frozen = self.host_offline_policy == HostOfflinePolicy::Freeze && (self.is_speed_round() || self.auto_caller.is_some());
+                log::info!("Last host socket of live room {} closed, buffering host messages{}", self.id, if frozen { " and freezing the caller" } else { "" });
+                self.host_backlog = Some(HostBacklog::new(frozen));
+            }
+            return;
+        }
+        if user_type == UserType::Board
+        {
+            self.boards.remove(&conn_id);
+            return;
+        }
+        if user_type == UserType::Observer
+        {
+            log::warn!("Observer {} left room {}", conn_id, self.id);
+            self.observers.remove(&conn_id);
+            return;
+        }
+        if let Some(index) = self.waitlist.iter().position(|waiting| waiting.id == conn_id){
+            self.waitlist.remove(index);
+            for (index, waiting) in self.waitlist.iter().enumerate().skip(index){
+                let _ = waiting.tx.send(serde_json::to_string(&WaitlistedMessage::new(index + 1)).unwrap().into());
+            }
+            self.send_waitlist().await;
+            return;
+        }
+        tracing::info!("Removing client {} from room {}: {:?}", conn_id, self.id, reason);
+        self.subscriptions.remove(&conn_id);
+        self.clients.remove(&conn_id);
+        // Kicked sockets and sockets taken over by a newer one of the player aren't worth finding again
+        if matches!(reason, DisconnectReason::Kicked | DisconnectReason::Superseded){
+            self.players.remove(&conn_id);
+        }
+        else if let Some(player) = self.players.get_mut(&conn_id){
+            player.left_at = Some(Utc::now());
+            self.prune_departed_players();
+        }
+        let suspended = self.suspended.remove(&conn_id);
+        let connected = self.sessions.remove(&conn_id).is_some() || suspended.is_some();
+        if connected && self.features.is_enabled(Feature::PresenceBatching){
+            self.presence.leave(conn_id, reason);
+        }
+        let mirrored = self.mirrors.remove(&conn_id)
+            .or(suspended.and_then(|suspended| suspended.mirrored))
+            .or_else(|| self.mirrors.values().any(|mirrored| *mirrored == conn_id).then_some(conn_id));
+        if let Some(primary) = mirrored{
+            self.send_host(&serde_json::to_string(&PlayerDevicesMessage::new(primary, self.devices(primary))).unwrap()).await;
+        }
+        if self.admit_waiting().await{
+            self.send_waitlist().await;
+        }
+    }
+
+    /// Keeps at most `MAX_DEPARTED_PLAYERS` departed connections, connections still holding cards are kept.
+    fn prune_departed_players(&mut self){
+        let departed = self.players.values().filter(|player| player.left_at.is_some()).count();
+        if departed <= MAX_DEPARTED_PLAYERS{
+            return;
+        }
+        let mut prunable: Vec<(DateTime<Utc>, SessionId)> = self.players.iter()
+            .filter(|(conn_id, _)| !self.cards.contains_key(*conn_id))
+            .filter_map(|(conn_id, player)| player.left_at.map(|left_at| (left_at, *conn_id)))
+            .collect();
+        prunable.sort();
+        for (_, conn_id) in prunable.into_iter().take(departed - MAX_DEPARTED_PLAYERS){
+            self.players.remove(&conn_id);
+        }
+    }
+
+    pub async fn broadcast(&self, msg: &str, user_type: UserType){
+        if user_type == UserType::Client
+        {
+            self.send_host(msg).await;
+            return;
+        }
+        self.broadcast_clients(msg);
+    }
+
+    /// Sends a message to every client subscribed to its channel.
+    fn broadcast_clients(&self, msg: &str){
+        self.observe(UserType::Client, msg);
+        // Only parse the message type when someone actually filters
+        let channel = if self.subscriptions.is_empty() { None } else { Some(Channel::of_message(msg)) };
+        let msg = Msg::from(msg);
+        for (conn_id, tx) in &self.sessions{
+            let subscribed = match (channel, self.subscriptions.get(conn_id)) {
+                (Some(channel), Some(channels)) => channels.contains(&channel),
+                _ => true,
+            };
+            if subscribed{
+                self.deliver(tx, &msg);
+            }
+        }
+    }
+
+    /// Limits the broadcasts a client receives to the given channels.
+    pub async fn subscribe(&mut self, conn_id: SessionId, channels: Vec<Channel>){
+        if !self.sessions.contains_key(&conn_id){
+            return;
+        }
+        let msg = serde_json::to_string(&SubscribedMessage::new(channels.clone())).unwrap();
+        self.subscriptions.insert(conn_id, channels);
+        self.send(conn_id, &msg).await;
+    }
+
+    /// Sends a message to a client and the sockets mirroring it, returns false when none of them is still connected.
+    pub async fn send(&self, conn_id: SessionId, msg: &str) -> bool {
+        let msg = Msg::from(msg);
+        let mut delivered = match self.sessions.get(&conn_id) {
+            Some(tx) => self.deliver(tx, &msg),
+            None => false,
+        };
+        for (mirror, _) in self.mirrors.iter().filter(|(_, mirrored)| **mirrored == conn_id){
+            if let Some(tx) = self.sessions.get(mirror){
+                delivered |= self.deliver(tx, &msg);
+            }
+        }
+        delivered
+    }
+
+    /// Deals a new card to a client within the card limit of the room, returns the card message to send.
+    fn deal_card(&mut self, conn_id: SessionId) -> Result<String, String> {
+        let held = self.cards.get(&conn_id).map_or(0, |cards| cards.len());
+        if held >= self.card_settings.max_cards_per_player as usize{
+            return Err(format!("Card limit of {} reached", self.card_settings.max_cards_per_player));
+        }
+        let card = Card::generate(self.next_card_id, &self.card_pool(), self.variant);
+        self.next_card_id += 1;
+        let msg = serde_json::to_string(&CardMessage::new(&card)).unwrap();
+        self.cards.entry(conn_id).or_default().push(card);
+        Ok(msg)
+    }
+
+    /// Connections whose socket task is gone, their messages can't be sent anymore.
+    fn ghosts(&self) -> Vec<(SessionId, UserType)> {
+        let hosts = self.host_pipes.iter().map(|(id, connection)| (*id, &connection.tx, UserType::Host));
+        let clients = self.sessions.iter().map(|(id, tx)| (*id, tx, UserType::Client))
+            .chain(self.waitlist.iter().map(|waiting| (waiting.id, &waiting.tx, UserType::Client)));
+        let boards = self.boards.iter().map(|(id, tx)| (*id, tx, UserType::Board));
+        let observers = self.observers.iter().map(|(id, tx)| (*id, tx, UserType::Observer));
+        hosts.chain(clients).chain(boards).chain(observers)
+            .filter(|(_, tx, _)| tx.is_closed())
+            .map(|(id, _, user_type)| (id, user_type))
+            .collect()
+    }
+
+    /// Queues a message for a client, delayed or dropped while chaos mode is on.
+    fn deliver(&self, tx: &mpsc::UnboundedSender<Msg>, msg: &Msg) -> bool {
+        if !self.chaos.is_active(){
+            return tx.send(msg.clone()).is_ok();
+        }
+        if self.chaos.should_drop(){
+            return !tx.is_closed();
+        }
+        match self.chaos.latency() {
+            Some(latency) => {
+                let (delayed_tx, msg) = (tx.clone(), msg.clone());
+                tokio::spawn(async move {
+                    sleep(latency).await;
+                    let _ = delayed_tx.send(msg);
+                });
+                !tx.is_closed()
+            }
+            None => tx.send(msg.clone()).is_ok(),
+        }
+    }
+
+    /// Tells the requester, or the hosts when unset, that the action is not allowed in the current phase.
+    async fn check_phase(&self, allowed: &[RoomPhase], action: &str, requester: Option<SessionId>) -> bool {
+        if allowed.contains(&self.phase){
+            return true;
+        }
+        let error = ErrorMessage::new(format!("Cannot {} while the room is in the {} phase", action, self.phase.name())).to_string();
+        match requester {
+            Some(conn_id) => { self.send(conn_id, &error).await; }
+            None => self.send_host(&error).await,
+        }
+        false
+    }
+
+    fn is_speed_round(&self) -> bool {
+        self.round.as_ref().is_some_and(|round| round.settings.speed_interval().is_some())
+    }
+
+    /// Marks a call on the cards holding it, players of speed rounds don't daub by hand.
+    async fn auto_daub(&self, number: Number){
+        for (conn_id, cards) in &self.cards{
+            let card_ids: Vec<CardId> = cards.iter().filter(|card| card.contains(number)).map(|card| card.id).collect();
+            if !card_ids.is_empty(){
+                self.send(*conn_id, &serde_json::to_string(&DaubMessage::new(number, card_ids)).unwrap()).await;
+            }
+        }
+    }
+
+    /// Numbers new cards are drawn from, empty unless the current round uses a custom pool.
+    fn card_pool(&self) -> Vec<Number> {
+        match &self.round {
+            Some(round) if round.settings.has_custom_pool(self.variant) => round.settings.numbers(self.variant),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Report as stored in the archive, without notes and seats unless the room keeps its roster.
+    fn archived_report(&self) -> ReportMessage {
+        match self.retention.roster {
+            RosterRetention::Full => self.report(),
+            RosterRetention::Aggregate => self.report().aggregate(),
+        }
+    }
+
+    fn report(&self) -> ReportMessage {
+        // Tagged players are listed even without cards, e.g. a cash payment noted at the door
+        let mut players: Vec<PlayerReport> = self.cards.iter()
+            .map(|(client_id, cards)| PlayerReport{ client_id: *client_id, cards: cards.len(), note: self.notes.get(client_id).cloned(), seat: self.seats.get(client_id).cloned() })
+            .collect();
+        players.extend(self.notes.iter()
+            .filter(|(client_id, _)| !self.cards.contains_key(client_id))
+            .map(|(client_id, note)| PlayerReport{ client_id: *client_id, cards: 0, note: Some(note.clone()), seat: self.seats.get(client_id).cloned() }));
+        ReportMessage::new(self.rounds_played, self.card_settings.clone(), players, group_by_table(self.seats.iter()))
+    }
+
+    /// Switches the phase and announces it to every connection regardless of subscriptions.
+    async fn change_phase(&mut self, phase: RoomPhase){
+        let previous = std::mem::replace(&mut self.phase, phase);
+        log::info!("Room {} moved from the {} to the {} phase", self.id, previous.name(), phase.name());
+        let msg: Msg = serde_json::to_string(&PhaseMessage::new(phase, previous)).unwrap().into();
+        self.send_host(&msg).await;
+        for tx in self.sessions.values().chain(self.boards.values()){
+            let _ = tx.send(msg.clone());
+        }
+    }
+
+    /// Sends a message to the host and every client in the room.
+    pub async fn broadcast_all(&self, msg: &str){
+        self.send_host(msg).await;
+        self.broadcast_clients(msg);
+    }
+
+    /// Relays a message to the admins observing the room.
+    fn observe(&self, to: UserType, msg: &str){
+        if self.observers.is_empty(){
+            return;
+        }
+        let msg = Msg::from(serde_json::to_string(&ObservedMessage::new(to, msg)).unwrap());
+        for tx in self.observers.values(){
+            let _ = tx.send(msg.clone());
+        }
+    }
+
+    pub async fn send_host(&self, msg: &str){
+        self.observe(UserType::Host, msg);
+        if let (true, Some(backlog)) = (self.host_pipes.is_empty(), &self.host_backlog){
+            backlog.record(msg);
+        }
+        let msg = Msg::from(msg);
+        for connection in self.host_pipes.values(){
+            let _ = connection.tx.send(msg.clone());
+        }
+    }
+
+    fn settings_message(&self) -> Msg {
+        let msg = SettingsMessage::new(self.settings_version, &self.card_settings, self.locale.as_deref(), &self.sms_recipients, self.milestones);
+        serde_json::to_string(&msg).unwrap().into()
+    }
+
+    /// False when a host changed settings it saw at an older version, the hosts get the conflict and the current settings.
+    pub async fn check_settings_version(&self, expected: Option<u64>) -> bool {
+        match expected {
+            Some(expected) if expected != self.settings_version => {
+                log::info!("Rejected settings change of room {} at version {}, current version is {}", self.id, expected, self.settings_version);
+                self.send_host(&serde_json::to_string(&SettingsConflictMessage::new(expected, self.settings_version)).unwrap()).await;
+                self.send_host(&self.settings_message()).await;
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Bumps the settings version, hosts get the new settings and everyone else the player visible changes.
+    pub async fn settings_changed(&mut self, delta: SettingsDelta){
+        self.settings_version += 1;
+        self.send_host(&self.settings_message()).await;
+        if !delta.is_empty(){
+            let msg: Msg = serde_json::to_string(&SettingsChangedMessage::new(self.settings_version, delta)).unwrap().into();
+            // Sent regardless of subscriptions, clients can't render the game correctly without it
+            for tx in self.sessions.values().chain(self.boards.values()){
+                let _ = tx.send(msg.clone());
+            }
+        }
+    }
+
+    async fn announce_milestone(&self, milestone: Milestone, value: usize){
+        log::info!("Room {} reached milestone {:?} at {}", self.id, milestone, value);
+        self.broadcast_all(&serde_json::to_string(&MilestoneMessage::new(milestone, value)).unwrap()).await;
+    }
+
+    async fn check_call_milestones(&mut self){
+        let called = self.draws.called();
+        let calls = called.len();
+        let pool_size = calls + self.draws.remaining().len();
+        let waiting = players_waiting(&self.cards, called);
+        let previously_waiting = std::mem::replace(&mut self.players_waiting, waiting);
+        let halfway = calls >= pool_size / 2 && self.round.as_mut().is_some_and(|round| !std::mem::replace(&mut round.halfway_reached, true));
+        if !self.milestones{
+            return;
+        }
+
+        if halfway{
+            self.announce_milestone(Milestone::HalfwayThroughPool, calls).await;
+        }
+        if waiting >= WAITING_MILESTONE && previously_waiting < WAITING_MILESTONE{
+            self.announce_milestone(Milestone::PlayersWaiting, waiting).await;
+        }
+    }
+
+    /// Broadcasts the latest call to everyone in the room, returns the message and the write recording it for the statistics.
+    pub async fn announce_call(&mut self, number: Number, request_id: Option<String>) -> (Msg, PendingWrite) {
+        let call = self.draws.called().len();
+        let bonus = self.round.as_ref().is_some_and(|round| round.is_bonus(number));
+        let msg: Msg = serde_json::to_string(&DrawMessage::new(number, call, request_id, false, bonus)).unwrap().into();
+        self.broadcast_all(&msg).await;
+        if self.is_speed_round(){
+            self.auto_daub(number).await;
+        }
+        self.update_boards().await;
+        self.check_call_milestones().await;
+        let round = self.round.as_ref().map_or(0, |round| round.id);
+        (msg, record_call(self.id, self.host.clone(), round, number, call))
+    }
+
+    pub fn snapshot(&self) -> RoomSnapshot {
+        RoomSnapshot{
+            id: self.id,
+            host: self.host.clone(),
+            host_token: self.host_token.clone(),
+            board_token: self.board_token.clone(),
+            valid_date: self.valid_date,
+            round: self.round.as_ref().map(|round| RoundSnapshot{
+                id: round.id,
+                settings: round.settings.clone(),
+                winners: round.all_winners(),
+                fair_seed: round.fair_seed.as_ref().map(|seed| seed.seed().to_owned()),
+                prizes: round.prizes.clone(),
+                jackpot_winners: round.jackpot_winners.clone(),
+                bonus_winners: round.bonus_winners.clone(),
+                deadline: round.deadline,
+                halfway_reached: round.halfway_reached,
+            }),
+            rounds_played: self.rounds_played,
+            remaining: self.draws.remaining().to_vec(),
+            called: self.draws.called().to_vec(),
+            called_at: self.draws.call_times().to_vec(),
+            fixed_order: self.draws.is_fixed_order(),
+            card_settings: self.card_settings.clone(),
+            cards: self.cards.iter().map(|(conn_id, cards)| (*conn_id, cards.clone())).collect(),
+            next_card_id: self.next_card_id,
+            next_conn_id: self.next_conn_id,
+            locale: self.locale.clone(),
+            sms_recipients: self.sms_recipients.clone(),
+            settings_version: self.settings_version,
+            milestones: self.milestones,
+            players_joined: self.players_joined,
+            joined_players: self.joined_players.iter().cloned().collect(),
+            notes: self.notes.iter().map(|(conn_id, note)| (*conn_id, note.clone())).collect(),
+            phase: Some(self.phase),
+            seat_map: self.seat_map.clone(),
+            seats: self.seats.iter().map(|(conn_id, seat)| (*conn_id, seat.clone())).collect(),
+            players: self.players.iter().map(|(conn_id, player)| (*conn_id, player.clone())).collect(),
+            allowed_origins: self.allowed_origins.clone(),
+            announcements: self.announcements.clone(),
+            player_limit: self.player_limit,
+            host_offline_policy: self.host_offline_policy,
+            duplicate_session_policy: self.duplicate_session_policy,
+            resume_grace_secs: self.resume_grace.map(|grace| grace.as_secs()),
+            retention: self.retention,
+            redact_chat: self.redact_chat,
+            email_settings: self.email_settings.clone(),
+            countdown: self.countdown.clone(),
+            variant: self.variant,
+            auto_call_secs: self.auto_caller.map(|caller| caller.interval.as_secs()),
+            auto_call_paused: self.auto_caller.is_some_and(|caller| caller.paused),
+        }
+    }
+
+    pub fn from_snapshot(snapshot: RoomSnapshot) -> Self {
+        let mut room = Self::create_from_entry(snapshot.host, snapshot.id, snapshot.host_token, snapshot.valid_date, snapshot.board_token, snapshot.variant);
+        room.round = snapshot.round.map(|round| Round{
+            id: round.id,
+            settings: round.settings,
+            winners: round.winners,
+            fair_seed: round.fair_seed.map(FairSeed::from_seed),
+            prizes: round.prizes,
+            jackpot_winners: round.jackpot_winners,
+            bonus_winners: round.bonus_winners,
+            claim_window: None,
+            claim_windows_opened: 0,
+            deadline: round.deadline,
+            halfway_reached: round.halfway_reached,
+        });
+        room.rounds_played = snapshot.rounds_played;
+        room.draws = DrawPool::restore(snapshot.remaining, snapshot.called, snapshot.called_at, snapshot.fixed_order);
+        room.card_settings = snapshot.card_settings;
+        room.cards = snapshot.cards.into_iter().collect();
+        room.next_card_id = snapshot.next_card_id;
+        room.locale = snapshot.locale;
+        room.sms_recipients = snapshot.sms_recipients;
+        room.settings_version = snapshot.settings_version;
+        room.milestones = snapshot.milestones;
+        room.players_joined = snapshot.players_joined;
+        room.joined_players = snapshot.joined_players.into_iter().collect();
+        room.notes = snapshot.notes.into_iter().collect();
+        room.players = snapshot.players.into_iter().collect();
+        room.allowed_origins = snapshot.allowed_origins;
+        room.announcements = snapshot.announcements;
+        room.player_limit = snapshot.player_limit;
+        room.host_offline_policy = snapshot.host_offline_policy;
+        room.duplicate_session_policy = snapshot.duplicate_session_policy;
+        room.resume_grace = snapshot.resume_grace_secs.map(Duration::from_secs);
+        room.retention = snapshot.retention;
+        room.redact_chat = snapshot.redact_chat;
+        room.email_settings = snapshot.email_settings;
+        room.countdown = snapshot.countdown;
+        room.auto_caller = snapshot.auto_call_secs.map(|secs| {
+            room.auto_call_generation += 1;
+            AutoCaller{ interval: Duration::from_secs(secs), paused: snapshot.auto_call_paused, generation: room.auto_call_generation }
+        });
+        // Rooms exported before phases existed were always live
+        room.phase = snapshot.phase.unwrap_or(RoomPhase::Live);
+        room.seat_map = snapshot.seat_map;
+        room.seats = snapshot.seats.into_iter().collect();
+        // Players still connected to the old instance get their cards, seat and notes back when they reconnect here
+        let handed_off = Instant::now();
+        room.suspended = room.players.iter()
+            .filter(|(_, connection)| connection.left_at.is_none())
+            .map(|(conn_id, connection)| (*conn_id, SuspendedConnection{ player_token: connection.player.token.clone(), since: handed_off, mirrored: None }))
+            .collect();
+        // Snapshots of older versions have no counter, continue after the IDs they still reference
+        room.next_conn_id = room.cards.keys().chain(room.players.keys()).chain(room.notes.keys()).chain(room.seats.keys())
+            .map(|id| id.next())
+            .fold(snapshot.next_conn_id.max(SessionId::FIRST), SessionId::max);
+        room
+    }
+
+    /// True while any host, client or board socket is open.
+    pub fn is_active(&self) -> bool {
+        !self.host_pipes.is_empty() || !self.sessions.is_empty() || !self.boards.is_empty()
+    }
+
+    fn board_message(&self) -> Msg {
+        serde_json::to_string(&BoardMessage::new(self.variant, self.round.as_ref(), self.round.is_some(), &self.draws)).unwrap().into()
+    }
+
+    /// Sends the current board state to every connected display board.
+    pub async fn update_boards(&self){
+        if self.boards.is_empty(){
+            return;
+        }
+        let msg = self.board_message();
+        for tx in self.boards.values(){
+            let _ = tx.send(msg.clone());
+        }
+    }
+
+    /// Sends the batched join/leave summary to the host, if anything changed since the last flush.
+    pub async fn flush_presence(&mut self) -> Option<String> {
+        if self.presence.is_empty(){
+            return None;
+        }
+        let summary = serde_json::to_string(&self.presence.take_summary(self.sessions.len())).unwrap();
+        self.send_host(&summary).await;
+        Some(summary)
+    }
+}
+
+
+#[derive(Debug)]
+pub struct BingoServer {
+
+    /// Map of room name to participant IDs in that room.
+    rooms: HashMap<RoomId, Room>,
+
+    /// Command receiver.
+    cmd_rx: mpsc::UnboundedReceiver<Command>,
+
+    /// Postgres database pool
+    database: sqlx::PgPool,
+
+    /// Command sender used by the server to schedule commands for itself (e.g. round timers).
+    cmd_tx: CountingSender<Command>,
+
+    config: RoomConfig,
+
+    /// Settings of the deployment, `config` is these with the overrides of the `config` table applied.
+    defaults: RoomConfig,
+
+    /// Feature flag rules, resolved for each room when it is loaded or created.
+    feature_flags: FeatureFlags,
+
+    /// Outstanding one-time websocket tickets.
+    ws_tickets: HashMap<String, WsTicket>,
+
+    /// Cross-room event streams of the hosts.
+    host_events: HostEvents,
+
+    /// Background writer for room records, keeps the actor loop off a slow database.
+    persistence: Persistence,
+
+    /// Seals the room tokens persisted in the database.
+    keyring: Keyring,
+
+    /// No new rooms or joins are accepted, set ahead of a redeploy.
+    draining: bool,
+
+    /// Translates chat in rooms that have a language set.
+    translator: Option<Arc<dyn Translator>>,
+
+    /// Texts calls to the SMS list of a room when Twilio is configured.
+    sms: Option<SmsBridge>,
+
+    /// Mirrors room events to venue hardware when a broker is configured.
+    mqtt: Option<MqttBridge>,
+
+    /// Ships anonymized room events to the organizer's analytics store when configured.
+    analytics: Option<Analytics>,
+
+    /// Uploads the reports of closed rooms to object storage when configured.
+    archive: Option<Archive>,
+
+    /// Emails reports and prize-claim instructions when configured.
+    mailer: Option<Arc<dyn Mailer>>,
+
+    /// Depth of the command queue, read by the handles to shed low priority commands.
+    mailbox: Arc<MailboxStatus>,
+
+    /// Ghost connections removed by the periodic sweep, read by the admin API.
+    ghosts: Arc<GhostStatus>,
+}
+
+impl BingoServer{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(database: sqlx::PgPool, config: RoomConfig, persistence: Persistence, keyring: Keyring, translator: Option<Arc<dyn Translator>>, sms: Option<SmsBridge>, mqtt: Option<MqttBridge>, analytics: Option<Analytics>, archive: Option<Archive>, mailer: Option<Arc<dyn Mailer>>) -> (Self, BingoServerHandle){
+        let rooms = HashMap::with_capacity(0);
+        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
+        let mailbox = Arc::new(MailboxStatus::new(config.max_command_backlog));
+        let cmd_tx = CountingSender::new(cmd_tx, mailbox.clone());
+        let ghosts = Arc::new(GhostStatus::default());
+        let errors = persistence.errors();
+        (
+            Self{
+                rooms,
+                cmd_rx,
+                database,
+                cmd_tx: cmd_tx.clone(),
+                config,
+                defaults: config,
+                feature_flags: FeatureFlags::default(),
+                ws_tickets: HashMap::new(),
+                host_events: HostEvents::default(),
+                persistence,
+                keyring,
+                draining: false,
+                translator,
+                sms,
+                mqtt,
+                analytics,
+                archive,
+                mailer,
+                mailbox: mailbox.clone(),
+                ghosts: ghosts.clone(),
+            },
+            BingoServerHandle{
+                cmd_tx: cmd_tx.clone(),
+                mailbox,
+                ghosts,
+                errors,
+                message_log: Arc::default(),
+            }
+        )
+    }
+
+    /// Applies the server wide settings and feature flags to a loaded or newly created room.
+    fn configure_room(&self, room: &mut Room){
+        room.features = self.feature_flags.resolve(&room.host, room.id);
+        room.throughput = ThroughputLimiter::new(self.config.max_messages_per_sec);
+        room.max_host_connections = self.config.max_host_connections;
+    }
+
+    pub async fn populate_rooms(&mut self){
+
+        self.feature_flags = FeatureFlags::load(&self.database).await;
+
+        let result = sqlx::query_as::<_, RoomCreds>("SELECT * FROM rooms WHERE archived_at IS NULL")
+        .fetch_all(&self.database)
+        .await;
+
+        match result {
+            Ok(rows) =>
+            {
+                for row in rows
+                {
+                    let rotate = self.keyring.needs_rotation(&row.token) || self.keyring.needs_rotation(&row.board_token);
+                    let row = match self.open_creds(row) {
+                        Some(row) => row,
+                        None => continue,
+                    };
+                    if rotate{
+                        self.persistence.submit(self.seal_tokens(&row));
+                    }
+                    let mut room = Room::create_from_entry(row.host, row.id, row.token, row.valid_date, row.board_token, row.variant);
+                    self.configure_room(&mut room);
+                    self.rooms.insert(row.id, room);
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to load rooms from database: {}", e)
+            },
+        }
+
+        match load_announcements(&self.database).await {
+            Ok(rows) => {
+                for row in rows{
+                    if let Some(room) = self.rooms.get_mut(&row.room_id){
+                        room.announcements.push(row.announcement);
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to load scheduled announcements: {}", e),
+        }
+
+        let result = sqlx::query_as::<_, (RoomId, String)>("SELECT id, retention::text FROM rooms WHERE archived_at IS NULL AND retention IS NOT NULL")
+            .fetch_all(&self.database)
+            .await;
+        match result {
+            Ok(rows) => {
+                for (room_id, policy) in rows{
+                    match (self.rooms.get_mut(&room_id), serde_json::from_str(&policy)) {
+                        (Some(room), Ok(policy)) => room.retention = policy,
+                        (_, Err(e)) => log::error!("Invalid retention policy of room {}: {}", room_id, e),
+                        (None, Ok(_)) => {}
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to load retention policies: {}", e),
+        }
+
+        // Round numbers continue after the audited rounds so a restart never reuses a published audit
+        let result = sqlx::query_as::<_, (RoomId, i32)>("SELECT room_id, MAX(round) FROM (SELECT room_id, round FROM round_audits UNION ALL SELECT room_id, round FROM round_starts) r GROUP BY room_id")
+            .fetch_all(&self.database)
+            .await;
+        match result {
+            Ok(rows) => {
+                for (room_id, round) in rows{
+                    if let Some(room) = self.rooms.get_mut(&room_id){
+                        room.rounds_played = room.rounds_played.max(round as RoundId);
+                    }
+                }
+            }
+            Err(e) => log::error!("Failed to load the audited rounds: {}", e),
+        }
+
+    }
+
+    /// Returns the host's room of the day, creating it for the variant unless the server is draining. A room
+    /// that already exists keeps its variant.
+    pub async fn create_room(&mut self, host: String, variant: GameVariant) -> Option<RoomCreds> {
+
+        let today = room_date();
+
+        // Check if rooms contains a room of the day with the same host
+        for room in self.rooms.values(){
+            if room.host == host && room.valid_date == today{
+                return Some(RoomCreds::new(room.id, host, room.host_token.clone(), today, room.board_token.clone(), room.variant));
+            }
+        }
+        if self.draining{
+            log::info!("Refused a new room for {} while draining", host);
+            return None;
+        }
+
+        //CHeck if host is already created a room of the day in the database look up using the host
+        //Skipped while the database is degraded, every persisted room is loaded into memory at startup anyway
+        if !self.persistence.status().is_degraded(){
+            let result = sqlx::query_as::<_, RoomCreds>("SELECT * FROM rooms WHERE host = $1 AND valid_date = $2 AND archived_at IS NULL")
+                .bind(host.clone())
+                .bind(today)
+                .fetch_optional(&self.database)
+                .await;
+
+            match result {
+                Ok(room) => {
+                    if let Some(room) = room.and_then(|room| self.open_creds(room)){
+                        return Some(room);
+                    }
+                }
+                Err(e) => log::error!("Failed to look up room for host {}: {}", host, e),
+            }
+        }
+
+        let mut room= Room::new(host.clone(), variant);
+        self.configure_room(&mut room);
+        let room_id = room.id;
+        let room_token = room.host_token.clone();
+        let board_token = room.board_token.clone();
+        self.rooms.insert(room_id, room);
+        if let Some(analytics) = &self.analytics{
+            analytics.record(room_id, &host, AnalyticsEventKind::RoomCreated);
+        }
+
+        //Insert room creds into the rooms table, the room is playable from memory even if the write is delayed
+        let (db_host, db_token, db_board_token) = (host.clone(), self.keyring.encrypt(room_id, &room_token), self.keyring.encrypt(room_id, &board_token));
+        self.persistence.submit(PendingWrite::new(
+            format!("room {}", room_id),
+            Box::new(move |database| {
+                let (host, token, board_token) = (db_host.clone(), db_token.clone(), db_board_token.clone());
+                Box::pin(async move {
+                    sqlx::query("INSERT INTO rooms (id, host, token, valid_date, board_token, variant) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING")
+                        .bind(room_id)
+                        .bind(host)
+                        .bind(token)
+                        .bind(today)
+                        .bind(board_token)
+                        .bind(variant.name())
+                        .execute(&database)
+                        .await
+                        .map(|_| log::info!("Added room {} to database", room_id))
+                })
+            }),
+        ));
+
+        Some(RoomCreds::new(room_id, host, room_token, today, board_token, variant))
+    }
+
+    /// Decrypts the tokens of a stored room, rooms that can't be decrypted are skipped.
+    fn open_creds(&self, row: RoomCreds) -> Option<RoomCreds> {
+        let token = self.keyring.decrypt(row.id, &row.token);
+        let board_token = self.keyring.decrypt(row.id, &row.board_token);
+        match (token, board_token) {
+            (Ok(token), Ok(board_token)) => Some(RoomCreds{ token, board_token, ..row }),
+            (Err(e), _) | (_, Err(e)) => {
+                log::error!("Failed to decrypt tokens of room {}: {}", row.id, e);
+                None
+            }
+        }
+    }
+
+    /// Writes the tokens of a room again under the current encryption key.
+    fn seal_tokens(&self, creds: &RoomCreds) -> PendingWrite {
+        let room_id = creds.id;
+        let token = self.keyring.encrypt(room_id, &creds.token);
+        let board_token = self.keyring.encrypt(room_id, &creds.board_token);
+        PendingWrite::new(
+            format!("key rotation of room {}", room_id),
+            Box::new(move |database| {
+                let (token, board_token) = (token.clone(), board_token.clone());
+                Box::pin(async move {
+                    sqlx::query("UPDATE rooms SET token = $2, board_token = $3 WHERE id = $1")
+                        .bind(room_id)
+                        .bind(token)
+                        .bind(board_token)
+                        .execute(&database)
+                        .await
+                        .map(|_| ())
+                })
+            }),
+        )
+    }
+
+    /// Writes the new host and host token of a transferred room.
+    /// Archives the rooms of previous days. Rooms with connected players are kept until the next rotation.
+    pub async fn rotate_rooms(&mut self){
+        let today = room_date();
+        let expired: Vec<RoomId> = self.rooms.values()
+            .filter(|room| room.valid_date < today && !room.is_active())
+            .map(|room| room.id)
+            .collect();
+        if expired.is_empty(){
+            return;
+        }
+
+        for room_id in &expired{
+            let Some(room) = self.rooms.remove(room_id) else {
+                continue;
+            };
+            // Rooms that were never ended are archived when they expire
+            if room.phase != RoomPhase::Ended{
+                self.record_room_ended(&room);
+                if let Some(archive) = &self.archive{
+                    self.persistence.submit(archive.archive_room(room.id, serde_json::to_string(&room.archived_report()).unwrap()));
+                }
+            }
+        }
+        log::info!("Archived {} expired rooms", expired.len());
+
+        self.persistence.submit(PendingWrite::new(
+            format!("archive of {} rooms", expired.len()),
+            Box::new(move |database| {
+                let expired = expired.clone();
+                Box::pin(async move {
+                    sqlx::query("UPDATE rooms SET archived_at = now() WHERE id = ANY($1) AND archived_at IS NULL")
+                        .bind(expired)
+                        .execute(&database)
+                        .await
+                        .map(|_| ())
+                })
+            }),
+        ));
+    }
+
+    pub async fn room_exists(&self, room_id: RoomId) -> bool {
+        self.rooms.contains_key(&room_id)
+    }
+
+    pub async fn has_room_host_privileges(&self, room_id: RoomId, host_token: String) -> bool {
+        let room = self.rooms.get(&room_id);
+        match room {
+            None => {
+                log::error!("Room {} not found", room_id);
+                false
+            }
+            Some(room) => {
+                let result = room.host_token == host_token;
+                if !result{
+                    log::error!("Host token mismatch for room {}", room_id);
+                }
+                result
+            }
+        }
+    }
+
+    pub async fn has_board_access(&self, room_id: RoomId, board_token: String) -> bool {
+        self.rooms.get(&room_id).is_some_and(|room| room.board_token == board_token)
+    }
+
+    pub async fn add_client(&mut self, room_id: RoomId, tx: mpsc::UnboundedSender<Msg>, user_type: UserType, player: Option<PlayerIdentity>) -> Result<SessionId, ConnectError> {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return Err(ConnectError::RoomClosed);
+        };
+        let stalled = user_type == UserType::Host && room.host_backlog.as_ref().is_some_and(HostBacklog::is_stalled);
+        let resuming = user_type == UserType::Client && room.suspended_conn(player.as_ref()).is_some();
+        let result = room.add_client(tx, user_type, player).await;
+        if let (Some(analytics), UserType::Client, Ok(_), false) = (&self.analytics, user_type, &result, resuming){
+            analytics.record(room_id, &room.host, AnalyticsEventKind::PlayerJoined{ players: room.sessions.len() });
+        }
+        // The caller of a frozen speed round or auto-called round picks up again with the host back
+        let resumed = stalled && result.is_ok() && room.phase == RoomPhase::Live;
+        let resume = room.round.as_ref()
+            .filter(|_| resumed)
+            .and_then(|round| round.settings.speed_interval().map(|interval| (round.id, interval)));
+        let auto_caller = room.auto_caller.filter(|caller| resumed && !caller.paused);
+        if let Some((round_id, interval)) = resume{
+            log::info!("Host is back in room {}, resuming the caller", room_id);
+            self.schedule_speed_call(room_id, round_id, interval);
+        }
+        else if let Some(caller) = auto_caller{
+            log::info!("Host is back in room {}, resuming the auto-caller", room_id);
+            self.schedule_auto_call(room_id, caller);
+        }
+        result
+    }
+
+    /// Removes connections whose socket task ended without disconnecting, as if they had left.
+    pub async fn sweep_ghosts(&mut self){
+        let ghosts: Vec<(RoomId, SessionId, UserType)> = self.rooms.values()
+            .flat_map(|room| room.ghosts().into_iter().map(|(conn_id, user_type)| (room.id, conn_id, user_type)))
+            .collect();
+        for (room_id, conn_id, user_type) in &ghosts{
+            log::warn!("Removing ghost {:?} connection {} from room {}", user_type, conn_id, room_id);
+            self.remove_client(*room_id, *conn_id, *user_type, DisconnectReason::Error).await;
+        }
+        self.ghosts.record(ghosts.len());
+    }
+
+    pub async fn remove_client(&mut self, room_id: RoomId, conn_id: SessionId, user_type: UserType, reason: DisconnectReason){
+        // The room may have been purged while the connection was open
+        if let Some(room) = self.rooms.get_mut(&room_id){
+            let was_player = user_type == UserType::Client && (room.sessions.contains_key(&conn_id) || room.suspended.contains_key(&conn_id));
+            // Taken before the player may be forgotten
+            let joined_at = room.players.get(&conn_id).map(|player| player.joined_at);
+            room.remove_client(conn_id, user_type, reason).await;
+            if let (Some(analytics), true) = (&self.analytics, was_player){
+                let connected_secs = joined_at.map(|joined_at| (Utc::now() - joined_at).num_seconds());
+                analytics.record(room_id, &room.host, AnalyticsEventKind::PlayerLeft{ players: room.sessions.len(), connected_secs, reason });
+            }
+            // Batched departures reach the hosts and their event streams with the next presence summary
+            if was_player && !room.features.is_enabled(Feature::PresenceBatching){
+                let departure = serde_json::to_string(&PresenceMessage::departure(conn_id, reason, room.sessions.len())).unwrap();
+                room.send_host(&departure).await;
+                self.host_events.publish(&room.host, room_id, &departure);
+            }
+            if self.draining && !room.is_active() && self.active_rooms() == 0{
+                log::warn!("Last room closed while draining, the server can be restarted");
+            }
+        }
+    }
+
+    /// Suspends a timed out player socket for the grace period of its room, false when it has to leave now.
+    fn suspend_client(&mut self, room_id: RoomId, conn_id: SessionId) -> bool {
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return false;
+        };
+        let grace = room.resume_grace.unwrap_or(self.config.resume_grace);
+        if grace.is_zero(){
+            return false;
+        }
+        let Some(since) = room.suspend_client(conn_id) else {
+            return false;
+        };
+        self.expire_suspended_after(room_id, conn_id, since, grace);
+        true
+    }
+
+    /// Removes the suspended connection unless it resumed within `grace`.
+    fn expire_suspended_after(&self, room_id: RoomId, conn_id: SessionId, since: Instant, grace: Duration){
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            sleep(grace).await;
+            let _ = cmd_tx.send(Command::ExpireSuspended{ room: room_id, conn: conn_id, since });
+        });
+    }
+
+    pub async fn export_state(&self) -> Vec<RoomSnapshot> {
+        self.rooms.values().map(Room::snapshot).collect()
+    }
+
+    /// Replaces rooms with the imported state, rooms with open sockets on this instance are kept.
+    pub async fn import_state(&mut self, snapshots: Vec<RoomSnapshot>) -> usize {
+        let mut imported = 0;
+        for snapshot in snapshots{
+            if self.rooms.get(&snapshot.id).is_some_and(Room::is_active){
+                log::warn!("Skipped import of room {}, it is already active", snapshot.id);
+                continue;
+            }
+            let mut room = Room::from_snapshot(snapshot);
+            self.configure_room(&mut room);
+            let grace = room.resume_grace.unwrap_or(self.config.resume_grace).max(HANDOFF_GRACE);
+            for (conn_id, suspended) in &room.suspended{
+                self.expire_suspended_after(room.id, *conn_id, suspended.since, grace);
+            }
+            // Timers of the round pick up on this instance, a deadline that passed during the handoff ends the round right away
+            if let Some(round) = room.round.as_ref(){
+                if let Some(deadline) = round.deadline{
+                    self.schedule_round_timeout(room.id, round.id, (deadline - Utc::now()).to_std().unwrap_or_default());
+                }
+            }
+            if let Some(round) = room.round.as_ref().filter(|_| room.phase == RoomPhase::Live){
+                if let Some(interval) = round.settings.speed_interval(){
+                    self.schedule_speed_call(room.id, round.id, interval);
+                }
+                else if let Some(caller) = room.auto_caller.filter(|caller| !caller.paused){
+                    self.schedule_auto_call(room.id, caller);
+                }
+            }
+            self.rooms.insert(room.id, room);
+            imported += 1;
+        }
+        imported
+    }
+
+    fn active_rooms(&self) -> usize {
+        self.rooms.values().filter(|room| room.is_active()).count()
+    }
+
+    pub async fn set_draining(&mut self, draining: bool){
+        log::warn!("Draining mode {} with {} active rooms", if draining { "enabled" } else { "disabled" }, self.active_rooms());
+        self.draining = draining;
+    }
+
+    pub async fn drain_status(&self) -> DrainStatus {
+        let active_rooms = self.active_rooms();
+        DrainStatus{ draining: self.draining, active_rooms, drained: self.draining && active_rooms == 0 }
+    }
+
+    /// Drops every room of the host from memory, connected sockets stop receiving messages.
+    pub async fn remove_host_rooms(&mut self, host: &str){
+        let removed: Vec<RoomId> = self.rooms.values().filter(|room| room.host == host).map(|room| room.id).collect();
+        for room_id in removed{
+            if let Some(room) = self.rooms.remove(&room_id).filter(|room| room.phase != RoomPhase::Ended){
+                self.record_room_ended(&room);
+            }
+        }
+    }
+
+    /// Rooms end with the ended phase, or when they expire or are deleted before that.
+    fn record_room_ended(&self, room: &Room){
+        if let Some(analytics) = &self.analytics{
+            analytics.record(room.id, &room.host, AnalyticsEventKind::RoomEnded{ rounds: room.rounds_played, players_joined: room.players_joined });
+        }
+    }
+
+    /// Sends a diagnostic payload to every client, the hosts get the round trips once all clients
+    /// acknowledged it or the timeout passed.
+    pub async fn start_sound_check(&mut self, room_id: RoomId, request: SoundCheckRequest){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if let Err(error) = request.validate(){
+            room.send_host(&ErrorMessage::new(error).to_string()).await;
+            return;
+        }
+        if room.sound_check.is_some(){
+            room.send_host(&ErrorMessage::new("A sound check is already running".to_owned()).to_string()).await;
+            return;
+        }
+
+        room.sound_checks_run += 1;
+        let id = room.sound_checks_run;
+        let check = SoundCheck::new(id, room.sessions.keys().copied());
+        log::info!("Sound check {} of room {} sent to {} clients", id, room_id, room.sessions.len());
+        if check.is_complete(){
+            room.send_host(&serde_json::to_string(&check.report()).unwrap()).await;
+            return;
+        }
+
+        let msg: Msg = serde_json::to_string(&SoundCheckMessage::new(id, &request)).unwrap().into();
+        for tx in room.sessions.values(){
+            room.deliver(tx, &msg);
+        }
+        room.sound_check = Some(check);
+
+        let cmd_tx = self.cmd_tx.clone();
+        let timeout = request.timeout_duration();
+        tokio::spawn(async move {
+            sleep(timeout).await;
+            let _ = cmd_tx.send(Command::FinishSoundCheck { room: room_id, id });
+        });
+    }
+
+    pub async fn sound_check_ack(&mut self, room_id: RoomId, conn_id: SessionId, id: u32){
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        let complete = match room.sound_check.as_mut() {
+            Some(check) => check.acknowledge(conn_id, id) && check.is_complete(),
+            None => false,
+        };
+        if complete{
+            self.finish_sound_check(room_id, id).await;
+        }
+    }
+
+    pub async fn offer_transfer(&mut self, room_id: RoomId, host_token: String, from: String, to: String) -> Result<TransferOffer, TransferError> {
+        let room = self.rooms.get_mut(&room_id)
+            .filter(|room| room.host_token == host_token && room.host == from)
+            .ok_or(TransferError::RoomNotFound)?;
+        if to == from{
+            return Err(TransferError::SameHost);
+        }
+        let offer = TransferOffer::new(from, to);
+        log::info!("Host {} offered room {} to {}", offer.from, room_id, offer.to);
+        room.send_host(&serde_json::to_string(&TransferOfferedMessage::new(&offer)).unwrap()).await;
+        room.transfer = Some(offer.clone());
+        Ok(offer)
+    }
+
+    /// Hands the room to the recipient of the pending offer, the previous host token stops working.
+    /// Saves the recipient of the offer as the owner of the room, the room changes hands once the database has it.
+    pub fn accept_transfer(&mut self, room_id: RoomId, user: String, res_tx: oneshot::Sender<Result<RoomCreds, TransferError>>){
+        let (offer, creds) = match self.claim_transfer(room_id, user) {
+            Ok(claimed) => claimed,
+            Err(error) => {
+                let _ = res_tx.send(Err(error));
+                return;
+            }
+        };
+        let token = self.keyring.encrypt(room_id, &creds.token);
+        let (database, cmd_tx) = (self.database.clone(), self.cmd_tx.clone());
+        tokio::spawn(async move {
+            let saved = sqlx::query("UPDATE rooms SET host = $2, token = $3 WHERE id = $1")
+                .bind(room_id)
+                .bind(&creds.host)
+                .bind(token)
+                .execute(&database)
+                .await
+                .map(|_| ());
+            let _ = cmd_tx.send(Command::FinishTransfer{ room: room_id, offer, creds, saved, res_tx });
+        });
+    }
+
+    /// Takes the offer out of the room while the new owner is saved, along with the credentials of the new host.
+    fn claim_transfer(&mut self, room_id: RoomId, user: String) -> Result<(TransferOffer, RoomCreds), TransferError> {
+        let room = self.rooms.get(&room_id).ok_or(TransferError::RoomNotFound)?;
+        let offer = room.transfer.as_ref().filter(|offer| !offer.is_expired()).ok_or(TransferError::NoOffer)?;
+        if offer.to != user{
+            return Err(TransferError::NotRecipient);
+        }
+        let valid_date = room.valid_date;
+        if self.rooms.values().any(|other| other.host == user && other.valid_date == valid_date){
+            return Err(TransferError::AlreadyHosting);
+        }
+
+        let room = self.rooms.get_mut(&room_id).ok_or(TransferError::RoomNotFound)?;
+        let offer = room.transfer.take().ok_or(TransferError::NoOffer)?;
+        let creds = RoomCreds::new(room.id, user, generate_token(), room.valid_date, room.board_token.clone(), room.variant);
+        Ok((offer, creds))
+    }
+
+    /// Hands the room to its new host, or puts the offer back when the new owner couldn't be saved.
+    pub async fn finish_transfer(&mut self, room_id: RoomId, offer: TransferOffer, creds: RoomCreds, saved: Result<(), sqlx::Error>) -> Result<RoomCreds, TransferError> {
+        let room = self.rooms.get_mut(&room_id).ok_or(TransferError::RoomNotFound)?;
+        if let Err(e) = saved{
+            log::error!("Failed to save the transfer of room {}: {}", room_id, e);
+            self.persistence.errors().record("transfer", format!("Failed to save the transfer of room {}: {}", room_id, e));
+            room.transfer = Some(offer);
+            return Err(TransferError::NotSaved);
+        }
+        let previous = std::mem::replace(&mut room.host, creds.host.clone());
+        room.host_token = creds.token.clone();
+        log::warn!("Room {} transferred from {} to {}", room_id, previous, room.host);
+
+        // Dropping the senders closes the previous host's sockets after the message
+        let closed = SessionClosedMessage::new(CLOSE_ROOM_TRANSFERRED, "Room transferred to another host");
+        room.send_host(&serde_json::to_string(&closed).unwrap()).await;
+        room.host_pipes.clear();
+
+        self.ws_tickets.retain(|_, ticket| ticket.room != room_id || ticket.user_type != UserType::Host);
+        Ok(creds)
+    }
+
+    /// Withdraws the offer as the owner or declines it as the recipient.
+    pub async fn cancel_transfer(&mut self, room_id: RoomId, user: &str) -> Result<(), TransferError> {
+        let room = self.rooms.get_mut(&room_id).ok_or(TransferError::RoomNotFound)?;
+        let offer = room.transfer.as_ref().ok_or(TransferError::NoOffer)?;
+        if room.host != user && offer.to != user{
+            return Err(TransferError::NotRecipient);
+        }
+        room.transfer = None;
+        log::info!("Transfer of room {} cancelled by {}", room_id, user);
+        room.send_host(&serde_json::to_string(&TransferCancelledMessage::new(user)).unwrap()).await;
+        Ok(())
+    }
+
+    pub async fn schedule_announcement(&mut self, room_id: RoomId, request: ScheduleAnnouncementRequest){
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        let fire_at = match request.fire_at(Utc::now()) {
+            Ok(fire_at) => fire_at,
+            Err(error) => {
+                room.send_host(&ErrorMessage::new(error).to_string()).await;
+                return;
+            }
+        };
+        if room.announcements.len() >= MAX_SCHEDULED{
+            room.send_host(&ErrorMessage::new(format!("At most {} announcements can be scheduled", MAX_SCHEDULED)).to_string()).await;
+            return;
+        }
+
+        let announcement = Announcement::new(request, fire_at);
+        log::info!("Announcement {} scheduled in room {} for {}", announcement.id, room_id, fire_at);
+        self.persistence.submit(save_announcement(room_id, &announcement));
+        let index = room.announcements.partition_point(|scheduled| scheduled.fire_at <= fire_at);
+        room.announcements.insert(index, announcement);
+        room.send_host(&serde_json::to_string(&ScheduledAnnouncementsMessage::new(&room.announcements)).unwrap()).await;
+    }
+
+    pub async fn cancel_announcement(&mut self, room_id: RoomId, id: Uuid){
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        let Some(index) = room.announcements.iter().position(|announcement| announcement.id == id) else {
+            room.send_host(&ErrorMessage::new("No such announcement is scheduled".to_owned()).to_string()).await;
+            return;
+        };
+        room.announcements.remove(index);
+        self.persistence.submit(remove_announcement(room_id, id));
+        room.send_host(&serde_json::to_string(&ScheduledAnnouncementsMessage::new(&room.announcements)).unwrap()).await;
+    }
+
+    pub async fn list_announcements(&self, room_id: RoomId){
+        if let Some(room) = self.rooms.get(&room_id){
+            room.send_host(&serde_json::to_string(&ScheduledAnnouncementsMessage::new(&room.announcements)).unwrap()).await;
+        }
+    }
+
+    /// Sends the announcements that are due to every connection of their room.
+    pub async fn fire_announcements(&mut self){
+        let now = Utc::now();
+        for room in self.rooms.values_mut(){
+            let due = room.announcements.partition_point(|announcement| announcement.fire_at <= now);
+            if due == 0{
+                continue;
+            }
+            for announcement in room.announcements.drain(..due).collect::<Vec<_>>(){
+                self.persistence.submit(remove_announcement(room.id, announcement.id));
+                if now - announcement.fire_at > MAX_LATENESS{
+                    log::warn!("Dropped announcement {} of room {} missed at {}", announcement.id, room.id, announcement.fire_at);
+                    continue;
+                }
+                log::info!("Sending announcement {} in room {}", announcement.id, room.id);
+                let msg: Msg = serde_json::to_string(&AnnouncementMessage::new(&announcement)).unwrap().into();
+                room.send_host(&msg).await;
+                for tx in room.sessions.values().chain(room.boards.values()){
+                    room.deliver(tx, &msg);
+                }
+            }
+            room.send_host(&serde_json::to_string(&ScheduledAnnouncementsMessage::new(&room.announcements)).unwrap()).await;
+        }
+    }
+
+    /// Reports the sound check to the hosts, unless it already finished.
+    pub async fn finish_sound_check(&mut self, room_id: RoomId, id: u32){
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        let Some(check) = room.sound_check.take_if(|check| check.id == id) else {
+            return;
+        };
+        room.send_host(&serde_json::to_string(&check.report()).unwrap()).await;
+    }
+
+    pub async fn broadcast(&mut self, room_id: RoomId, sender: Option<SessionId>, msg: &str, user_type: UserType){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        // Speed rounds leave no time for chat
+        if room.is_speed_round() && is_chat(msg){
+            return;
+        }
+        let decision = room.throughput.check(msg);
+        if decision.alert{
+            log::warn!("Room {} exceeded {} messages per second, dropping non-critical messages", room_id, room.throughput.limit());
+            let alert = ThrottledMessage::new(room.throughput.limit());
+            room.send_host(&serde_json::to_string(&alert).unwrap()).await;
+        }
+        if !decision.deliver{
+            return;
+        }
+        let chat = is_chat(msg);
+        // Redacted before anything else sees the message, including storage and the translator
+        let redacted = if chat && room.redact_chat.unwrap_or(self.config.redact_chat) { redact_chat(msg) } else { None };
+        let msg = redacted.as_deref().unwrap_or(msg);
+        if chat{
+            // Stored with the player token so the player's chat can be erased with the rest of their data
+            // Player chat that can't be erased that way isn't stored at all
+            let player_token = sender.and_then(|conn| room.players.get(&conn)).map(|connection| connection.player.token.clone());
+            if user_type == UserType::Host || player_token.is_some(){
+                self.persistence.submit_for(&room.retention, record_chat(room_id, room.host.clone(), user_type, player_token, msg.to_owned()));
+            }
+        }
+
+        // Translation runs off the actor loop in the room's queue, the results are relayed with follow-up commands
+        if let (Some(locale), Some(translator), true) = (room.locale.clone(), &self.translator, chat){
+            let cmd_tx = self.cmd_tx.clone();
+            room.translations
+                .get_or_insert_with(|| TranslationQueue::spawn(translator.clone(), move |msg, user_type| {
+                    let _ = cmd_tx.send(Command::Relay{ room: room_id, msg, user_type });
+                }))
+                .push(msg.to_owned(), locale, user_type);
+            return;
+        }
+        room.broadcast(msg, user_type).await;
+    }
+
+    /// Relays a message that already passed the throughput check.
+    pub async fn relay(&self, room_id: RoomId, msg: &str, user_type: UserType){
+        if let Some(room) = self.rooms.get(&room_id){
+            room.broadcast(msg, user_type).await;
+        }
+    }
+
+    pub async fn set_language(&mut self, room_id: RoomId, locale: Option<String>, version: Option<u64>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if room.check_settings_version(version).await{
+            log::info!("Room {} chat language set to {:?}", room_id, locale);
+            room.locale = locale.clone();
+            room.settings_changed(SettingsDelta::locale(locale)).await;
+        }
+    }
+
+    pub async fn set_sms_recipients(&mut self, room_id: RoomId, numbers: Vec<String>, version: Option<u64>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.check_settings_version(version).await{
+            return;
+        }
+        if self.sms.is_none(){
+            room.send_host(&ErrorMessage::new("SMS announcements are not configured on this server".to_owned()).to_string()).await;
+            return;
+        }
+        if numbers.len() > MAX_RECIPIENTS{
+            room.send_host(&ErrorMessage::new(format!("At most {} SMS recipients are allowed", MAX_RECIPIENTS)).to_string()).await;
+            return;
+        }
+        if let Some(invalid) = numbers.iter().find(|number| !is_valid_phone_number(number)){
+            room.send_host(&ErrorMessage::new(format!("Invalid phone number {}, use the +15551234567 format", invalid)).to_string()).await;
+            return;
+        }
+
+        // Only numbers that opted in to the host's announcements get them, the others are sent a code first
+        let (database, cmd_tx, host) = (self.database.clone(), self.cmd_tx.clone(), room.host.clone());
+        tokio::spawn(async move {
+            let opted_in = sqlx::query_scalar("SELECT phone FROM sms_opt_ins WHERE host = $1 AND phone = ANY($2)")
+                .bind(host)
+                .bind(&numbers)
+                .fetch_all(&database)
+                .await;
+            let _ = cmd_tx.send(Command::SmsOptInsLoaded{ room: room_id, numbers, opted_in, version });
+        });
+    }
+
+    /// Announces to the numbers that opted in and sends the others a code.
+    pub async fn apply_sms_recipients(&mut self, room_id: RoomId, numbers: Vec<String>, opted_in: Result<Vec<String>, sqlx::Error>, version: Option<u64>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        let Some(sms) = &self.sms else {
+            return;
+        };
+        let opted_in = match opted_in {
+            Ok(opted_in) => opted_in,
+            Err(e) => {
+                log::error!("Failed to load the SMS opt-ins of room {}: {}", room_id, e);
+                room.send_host(&ErrorMessage::new("Failed to look up SMS opt-ins".to_owned()).to_string()).await;
+                return;
+            }
+        };
+        // Another co-host may have changed the settings during the lookup
+        if !room.check_settings_version(version).await{
+            return;
+        }
+        room.sms_pending.retain(|number, _| numbers.contains(number));
+        let invites: Vec<String> = numbers.iter()
+            .filter(|number| !opted_in.contains(number) && !room.sms_pending.contains_key(*number))
+            .cloned()
+            .collect();
+        for number in invites{
+            let code = generate_opt_in_code();
+            if !sms.request_opt_in(&room.host, &number, &code){
+                room.send_host(&ErrorMessage::new("The SMS budget of the day is used up, no more opt-in codes can be sent".to_owned()).to_string()).await;
+                break;
+            }
+            room.sms_pending.insert(number, code);
+        }
+
+        room.sms_recipients = numbers.into_iter().filter(|number| opted_in.contains(number)).collect();
+        log::info!("Room {} announces to {} numbers, {} have yet to opt in", room_id, room.sms_recipients.len(), room.sms_pending.len());
+        if !room.sms_pending.is_empty(){
+            room.send_host(&serde_json::to_string(&SmsOptInPendingMessage::new(room.sms_pending.keys().map(String::as_str).collect())).unwrap()).await;
+        }
+        // Phone numbers stay with the hosts
+        room.settings_changed(SettingsDelta::default()).await;
+    }
+
+    /// Adds a number to the announcements once the host enters the code its owner was sent. A wrong code drops
+    /// the pending opt-in so codes can't be guessed, the host has to add the number again for a new one.
+    pub async fn confirm_sms_recipient(&mut self, room_id: RoomId, number: String, code: String){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        let Some(expected) = room.sms_pending.remove(&number) else {
+            room.send_host(&ErrorMessage::new(format!("No opt-in code was sent to {}", number)).to_string()).await;
+            return;
+        };
+        if expected != code{
+            room.send_host(&ErrorMessage::new(format!("Wrong opt-in code for {}, add the number again to send a new one", number)).to_string()).await;
+            return;
+        }
+        let (database, cmd_tx, host) = (self.database.clone(), self.cmd_tx.clone(), room.host.clone());
+        tokio::spawn(async move {
+            let saved = sqlx::query("INSERT INTO sms_opt_ins (host, phone) VALUES ($1, $2) ON CONFLICT DO NOTHING")
+                .bind(host)
+                .bind(&number)
+                .execute(&database)
+                .await
+                .map(|_| ());
+            let _ = cmd_tx.send(Command::SmsOptInSaved{ room: room_id, number, code, saved });
+        });
+    }
+
+    /// Adds a number whose opt-in was saved, a failed save keeps the code pending so the host can retry.
+    pub async fn finish_sms_opt_in(&mut self, room_id: RoomId, number: String, code: String, saved: Result<(), sqlx::Error>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if let Err(e) = saved{
+            log::error!("Failed to save an SMS opt-in of room {}: {}", room_id, e);
+            room.send_host(&ErrorMessage::new("Failed to save the SMS opt-in".to_owned()).to_string()).await;
+            room.sms_pending.insert(number, code);
+            return;
+        }
+        log::info!("A number opted in to the announcements of room {}", room_id);
+        if !room.sms_recipients.contains(&number) && room.sms_recipients.len() < MAX_RECIPIENTS{
+            room.sms_recipients.push(number);
+        }
+        room.settings_changed(SettingsDelta::default()).await;
+    }
+
+    pub async fn set_email_settings(&mut self, room_id: RoomId, settings: EmailSettings){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if self.mailer.is_none(){
+            room.send_host(&ErrorMessage::new("Email is not configured on this server".to_owned()).to_string()).await;
+            return;
+        }
+        log::info!("Room {} {} its report by email", room_id, if settings.report_to.is_some() { "sends" } else { "doesn't send" });
+        room.email_settings = settings;
+        room.send_host(&serde_json::to_string(&EmailSettingsMessage::new(&room.email_settings)).unwrap()).await;
+    }
+
+    /// Starts the countdown to the next game in the lobby or intermission, with the start shown in the
+    /// time zone of the venue.
+    pub async fn set_countdown(&mut self, room_id: RoomId, starts_at: Option<StartTime>, time_zone: String){
+        if !self.rooms.contains_key(&room_id){
+            return;
+        }
+        let countdown = match starts_at {
+            Some(starts_at) => match ZonedTime::resolve(starts_at, &time_zone) {
+                Some(countdown) => Some(countdown),
+                None => {
+                    self.notify_host(room_id, &ErrorMessage::new(format!("Unknown time zone {}", time_zone)).to_string()).await;
+                    return;
+                }
+            },
+            None => None,
+        };
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        if !room.check_phase(&[RoomPhase::Lobby, RoomPhase::Intermission], "start a countdown", None).await{
+            return;
+        }
+        match &countdown {
+            Some(countdown) => log::info!("Room {} starts at {}", room_id, countdown.display),
+            None => log::info!("Room {} stopped its countdown", room_id),
+        }
+        room.countdown = countdown;
+        let msg = serde_json::to_string(&CountdownMessage::new(room.countdown.as_ref())).unwrap();
+        room.broadcast_all(&msg).await;
+        for tx in room.boards.values(){
+            let _ = tx.send(Msg::from(msg.as_str()));
+        }
+    }
+
+    /// Remembers where a player is sent prize-claim instructions, only for the lifetime of the room.
+    pub async fn set_player_email(&mut self, room_id: RoomId, conn_id: SessionId, email: Option<String>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if self.mailer.is_none(){
+            room.send(conn_id, &ErrorMessage::new("Email is not configured on this server".to_owned()).to_string()).await;
+            return;
+        }
+        match &email {
+            Some(email) => room.player_emails.insert(conn_id, email.clone()),
+            None => room.player_emails.remove(&conn_id),
+        };
+        room.send(conn_id, &serde_json::to_string(&EmailMessage::new(email.as_deref())).unwrap()).await;
+    }
+
+    pub async fn set_milestones(&mut self, room_id: RoomId, enabled: bool, version: Option<u64>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if room.check_settings_version(version).await{
+            room.milestones = enabled;
+            room.settings_changed(SettingsDelta::default()).await;
+        }
+    }
+
+    pub async fn get_settings(&self, room_id: RoomId){
+        if let Some(room) = self.rooms.get(&room_id){
+            room.send_host(&room.settings_message()).await;
+        }
+    }
+
+    pub async fn send(&self, room_id: RoomId, conn_id: SessionId, msg: &str) -> bool {
+        match self.rooms.get(&room_id) {
+            Some(room) => room.send(conn_id, msg).await,
+            None => false,
+        }
+    }
+
+    /// Cards and claims of a mirrored socket belong to the socket it mirrors.
+    fn card_holder(&self, room_id: RoomId, conn_id: SessionId) -> SessionId {
+        self.rooms.get(&room_id).and_then(|room| room.mirrors.get(&conn_id).copied()).unwrap_or(conn_id)
+    }
+
+    pub async fn notify_host(&self, room_id: RoomId, msg: &str){
+        if let Some(room) = self.rooms.get(&room_id){
+            room.send_host(msg).await;
+        }
+    }
+
+    pub async fn start_round(&mut self, room_id: RoomId, settings: RoundSettings){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if room.round.is_some(){
+            room.send_host(&ErrorMessage::new("A round is already in progress".to_owned()).to_string()).await;
+            return;
+        }
+        if let Err(error) = settings.validate(room.variant){
+            room.send_host(&ErrorMessage::new(error).to_string()).await;
+            return;
+        }
+        if !room.check_phase(&[RoomPhase::Lobby, RoomPhase::Live, RoomPhase::Intermission], "start a round", None).await{
+            return;
+        }
+        // Starting a round is what takes a room out of the lobby or intermission
+        if room.phase != RoomPhase::Live{
+            room.change_phase(RoomPhase::Live).await;
+        }
+
+        room.countdown = None;
+        room.rounds_played += 1;
+        let round = Round::new(room.rounds_played, settings, room.variant);
+        log::info!("Starting round {} in room {}", round.id, room_id);
+
+        let msg = serde_json::to_string(&RoundStartedMessage::new(&round, room.variant)).unwrap();
+        let timeout = round.settings.max_duration().map(|duration| (round.id, duration));
+        let speed = round.settings.speed_interval().map(|interval| (round.id, interval));
+        let numbers = round.settings.numbers(room.variant);
+        room.draws = match &round.fair_seed {
+            Some(seed) => {
+                self.persistence.submit_for(&room.retention, record_commitment(room_id, round.id, room.variant, seed.commitment()));
+                DrawPool::with_order(seed.draw_order(room.variant).into_iter().filter(|number| numbers.contains(number)).collect())
+            }
+            None => DrawPool::with_numbers(numbers),
+        };
+        self.persistence.submit_for(&room.retention, record_round_start(room_id, room.host.clone(), round.id));
+        room.players_waiting = 0;
+        room.round = Some(round);
+        room.broadcast_all(&msg).await;
+        room.update_boards().await;
+        if let Some(mqtt) = &self.mqtt{
+            mqtt.publish(room_id, &msg);
+        }
+        if let (Some(analytics), Some(round)) = (&self.analytics, &room.round){
+            analytics.record(room_id, &room.host, AnalyticsEventKind::RoundStarted{ round: round.id, speed: speed.is_some() });
+        }
+        self.host_events.publish(&room.host, room_id, &msg);
+
+        if let Some((round_id, duration)) = timeout{
+            self.schedule_round_timeout(room_id, round_id, duration);
+        }
+        if let Some((round_id, interval)) = speed{
+            self.schedule_speed_call(room_id, round_id, interval);
+        }
+    }
+
+    pub async fn end_round(&mut self, room_id: RoomId, reason: RoundEndReason){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if let Some(mut round) = room.round.take(){
+            log::info!("Round {} in room {} ended: {:?}", round.id, room_id, reason);
+            // Claims of a window still open when the round ends are winners too
+            round.winners = round.all_winners();
+            round.claim_window = None;
+            if room.phase == RoomPhase::Live{
+                room.change_phase(RoomPhase::Intermission).await;
+            }
+            room.trade_ins.clear();
+            if room.auto_caller.take().is_some(){
+                room.send_host(&serde_json::to_string(&AutoCallMessage::new(None)).unwrap()).await;
+            }
+            let msg = serde_json::to_string(&RoundEndedMessage::new(&round, reason)).unwrap();
+            room.broadcast_all(&msg).await;
+            if let Some(sms) = &self.sms{
+                sms.announce(&room.host, &room.sms_recipients, &msg, room.variant);
+            }
+            if let Some(mqtt) = &self.mqtt{
+                mqtt.publish(room_id, &msg);
+            }
+            if let Some(seed) = &round.fair_seed{
+                self.persistence.submit_for(&room.retention, record_reveal(room_id, round.id, seed.seed().to_owned(), room.draws.called().to_vec()));
+            }
+            if let Some(mailer) = &self.mailer{
+                for email in round.winners.iter().filter_map(|winner| room.player_emails.get(winner)){
+                    send_email(mailer, Email::prize_claim(email.clone(), room_id, round.id, room.email_settings.claim_instructions.as_deref()));
+                }
+            }
+            if let Some(analytics) = &self.analytics{
+                let kind = AnalyticsEventKind::RoundEnded{ round: round.id, reason, calls: room.draws.called().len(), winners: round.winners.len() };
+                analytics.record(room_id, &room.host, kind);
+            }
+            // Boards keep showing the winners of the finished round
+            let board: Msg = serde_json::to_string(&BoardMessage::new(room.variant, Some(&round), false, &room.draws)).unwrap().into();
+            for tx in room.boards.values(){
+                let _ = tx.send(board.clone());
+            }
+            self.host_events.publish(&room.host, room_id, &msg);
+        }
+    }
+
+    fn is_current_round(&self, room_id: RoomId, round_id: RoundId) -> bool {
+        self.rooms.get(&room_id)
+            .and_then(|room| room.round.as_ref())
+            .is_some_and(|round| round.id == round_id)
+    }
+
+    pub async fn round_timeout(&mut self, room_id: RoomId, round_id: RoundId){
+        if self.is_current_round(room_id, round_id){
+            self.end_round(room_id, RoundEndReason::Timeout).await;
+        }
+    }
+
+    /// Schedules the automatic end of the round, the round id guards against ending a later round.
+    fn schedule_round_timeout(&self, room_id: RoomId, round_id: RoundId, duration: Duration){
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            sleep(duration).await;
+            let _ = cmd_tx.send(Command::RoundTimeout { room: room_id, round: round_id });
+        });
+    }
+
+    fn schedule_speed_call(&self, room_id: RoomId, round_id: RoundId, interval: Duration){
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            sleep(interval).await;
+            let _ = cmd_tx.send(Command::SpeedCall { room: room_id, round: round_id });
+        });
+    }
+
+    /// Makes the next call of a speed round, then verifies every card and ends the round on the first win.
+    pub async fn speed_call(&mut self, room_id: RoomId, round_id: RoundId){
+        // A frozen room stops the caller until the host is back
+        if let Some(backlog) = self.rooms.get_mut(&room_id).and_then(|room| room.host_backlog.as_mut()).filter(|backlog| backlog.is_frozen()){
+            log::info!("Speed round of room {} is frozen without its host", room_id);
+            backlog.stall();
+            return;
+        }
+        // A paused or ended room stops the caller
+        let interval = self.rooms.get(&room_id)
+            .filter(|room| room.phase == RoomPhase::Live)
+            .and_then(|room| room.round.as_ref())
+            .filter(|round| round.id == round_id)
+            .and_then(|round| round.settings.speed_interval());
+        let Some(interval) = interval else {
+            return;
+        };
+
+        self.next_draw(room_id, None).await;
+
+        let Some(room) = self.rooms.get(&room_id) else {
+            return;
+        };
+        let called = room.draws.called();
+        let winners: Vec<SessionId> = room.cards.iter()
+            .filter(|(_, cards)| cards.iter().any(|card| card.has_bingo(called)))
+            .map(|(conn_id, _)| *conn_id)
+            .collect();
+        let exhausted = room.draws.remaining().is_empty();
+
+        if winners.is_empty(){
+            if exhausted{
+                log::info!("Speed round {} of room {} called every number without a win", round_id, room_id);
+                self.end_round(room_id, RoundEndReason::NumbersExhausted).await;
+            }
+            else{
+                self.schedule_speed_call(room_id, round_id, interval);
+            }
+            return;
+        }
+        // Every card completed on this call wins, unless a winner limit ends the round first
+        for conn_id in winners{
+            if !self.is_current_round(room_id, round_id){
+                return;
+            }
+            self.claim_bingo(room_id, conn_id).await;
+        }
+        self.end_round(room_id, RoundEndReason::FirstWin).await;
+    }
+
+    /// Starts calling the round hands-free, or changes the interval of a running auto-caller.
+    pub async fn start_auto_call(&mut self, room_id: RoomId, interval: Duration){
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        if !room.check_phase(&[RoomPhase::Live], "start the auto-caller", None).await{
+            return;
+        }
+        if room.round.is_none(){
+            room.send_host(&ErrorMessage::new("Start a round before the auto-caller".to_owned()).to_string()).await;
+            return;
+        }
+        if room.is_speed_round(){
+            room.send_host(&ErrorMessage::new(SPEED_ROUND_CALLS.to_owned()).to_string()).await;
+            return;
+        }
+
+        room.auto_call_generation += 1;
+        let caller = AutoCaller{ interval, paused: false, generation: room.auto_call_generation };
+        room.auto_caller = Some(caller);
+        log::info!("Room {} calls a number every {:?}", room_id, interval);
+        room.send_host(&serde_json::to_string(&AutoCallMessage::new(Some(&caller))).unwrap()).await;
+        self.schedule_auto_call(room_id, caller);
+    }
+
+    /// Pauses or resumes the auto-caller, a resumed caller waits a full interval before its next call.
+    pub async fn pause_auto_call(&mut self, room_id: RoomId, paused: bool){
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        let Some(caller) = room.auto_caller.as_mut() else {
+            room.send_host(&ErrorMessage::new("The auto-caller is not running".to_owned()).to_string()).await;
+            return;
+        };
+        if caller.paused == paused{
+            return;
+        }
+        caller.paused = paused;
+        if !paused{
+            room.auto_call_generation += 1;
+            caller.generation = room.auto_call_generation;
+        }
+        let caller = *caller;
+        log::info!("Auto-caller of room {} {}", room_id, if paused { "paused" } else { "resumed" });
+        room.send_host(&serde_json::to_string(&AutoCallMessage::new(Some(&caller))).unwrap()).await;
+        if !paused{
+            self.schedule_auto_call(room_id, caller);
+        }
+    }
+
+    fn schedule_auto_call(&self, room_id: RoomId, caller: AutoCaller){
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            sleep(caller.interval).await;
+            let _ = cmd_tx.send(Command::AutoCall { room: room_id, generation: caller.generation });
+        });
+    }
+
+    /// Makes the next call of the auto-caller. A paused room holds the calls, the caller stops once every
+    /// number is called.
+    pub async fn auto_call(&mut self, room_id: RoomId, generation: u32){
+        let caller = self.rooms.get(&room_id)
+            .and_then(|room| room.auto_caller)
+            .filter(|caller| caller.generation == generation && !caller.paused);
+        let Some(caller) = caller else {
+            return;
+        };
+        // A frozen room stops the caller until the host is back
+        if let Some(backlog) = self.rooms.get_mut(&room_id).and_then(|room| room.host_backlog.as_mut()).filter(|backlog| backlog.is_frozen()){
+            log::info!("Auto-caller of room {} is frozen without its host", room_id);
+            backlog.stall();
+            return;
+        }
+        if self.rooms.get(&room_id).is_some_and(|room| room.phase == RoomPhase::Live){
+            self.next_draw(room_id, None).await;
+        }
+
+        // A round ended by the call drops the caller
+        let Some(room) = self.rooms.get_mut(&room_id).filter(|room| room.auto_caller.is_some()) else {
+            return;
+        };
+        if room.phase == RoomPhase::Ended || room.draws.remaining().is_empty(){
+            log::info!("Auto-caller of room {} stopped", room_id);
+            room.auto_caller = None;
+            room.send_host(&serde_json::to_string(&AutoCallMessage::new(None)).unwrap()).await;
+            return;
+        }
+        self.schedule_auto_call(room_id, caller);
+    }
+
+    /// Adds a winner to the round, `card_id` is the card the server verified the claim on and unset for
+    /// winners the host records by hand.
+    pub async fn record_winner(&mut self, room_id: RoomId, conn_id: SessionId, card_id: Option<CardId>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        let Some(round) = room.round.as_mut() else {
+            log::warn!("Winner {} recorded in room {} without an active round", conn_id, room_id);
+            return;
+        };
+        let announcement = (!round.winners.contains(&conn_id)).then(|| {
+            let event = WinnerEvent::new(round.id, conn_id, card_id, room.draws.called().len(), round.jackpot_winners.contains(&conn_id));
+            serde_json::to_string(&event).unwrap()
+        });
+        let max_reached = round.add_winner(conn_id);
+        if let Some(msg) = &announcement{
+            room.broadcast_all(msg).await;
+            if let Some(mqtt) = &self.mqtt{
+                mqtt.publish(room_id, msg);
+            }
+        }
+        if max_reached{
+            self.end_round(room_id, RoundEndReason::MaxWinners).await;
+        }
+        else{
+            room.update_boards().await;
+        }
+    }
+
+    pub async fn draw(&mut self, room_id: RoomId, request_id: Option<String>){
+        if let Some(room) = self.rooms.get(&room_id).filter(|room| room.is_speed_round()){
+            room.send_host(&ErrorMessage::new(SPEED_ROUND_CALLS.to_owned()).to_string()).await;
+            return;
+        }
+        self.next_draw(room_id, request_id).await;
+    }
+
+    async fn next_draw(&mut self, room_id: RoomId, request_id: Option<String>){
+        self.close_open_claim_window(room_id).await;
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.check_phase(&[RoomPhase::Live], "draw", None).await{
+            return;
+        }
+
+        match room.draws.draw(request_id.as_deref()) {
+            DrawResult::Drawn(number) => {
+                log::info!("Drew {} as call {} in room {}", number, room.draws.called().len(), room_id);
+                let (msg, write) = room.announce_call(number, request_id).await;
+                self.persistence.submit_for(&room.retention, write);
+                if let Some(sms) = &self.sms{
+                    sms.announce(&room.host, &room.sms_recipients, &msg, room.variant);
+                }
+                if let Some(mqtt) = &self.mqtt{
+                    mqtt.publish(room_id, &msg);
+                }
+            }
+            DrawResult::Duplicate(number) => {
+                // A retried request only gets the original result back, nothing is broadcast again
+                let call = room.draws.call_index(number).unwrap_or_default();
+                let bonus = room.round.as_ref().is_some_and(|round| round.is_bonus(number));
+                let msg = DrawMessage::new(number, call, request_id, true, bonus);
+                room.send_host(&serde_json::to_string(&msg).unwrap()).await;
+            }
+            DrawResult::Exhausted => {
+                room.send_host(&ErrorMessage::new("All numbers have been called".to_owned()).to_string()).await;
+            }
+        }
+    }
+
+    pub async fn manual_call(&mut self, room_id: RoomId, number: Number){
+        self.close_open_claim_window(room_id).await;
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.check_phase(&[RoomPhase::Live], "call a number", None).await{
+            return;
+        }
+        if room.is_speed_round(){
+            room.send_host(&ErrorMessage::new(SPEED_ROUND_CALLS.to_owned()).to_string()).await;
+            return;
+        }
+
+        if room.draws.is_fixed_order(){
+            room.send_host(&ErrorMessage::new("Manual calls are not allowed in provably fair rounds".to_owned()).to_string()).await;
+            return;
+        }
+        match room.draws.call(number) {
+            ManualCallResult::Called => {
+                log::info!("Host called {} as call {} in room {}", number, room.draws.called().len(), room_id);
+                let (msg, write) = room.announce_call(number, None).await;
+                self.persistence.submit_for(&room.retention, write);
+                if let Some(sms) = &self.sms{
+                    sms.announce(&room.host, &room.sms_recipients, &msg, room.variant);
+                }
+                if let Some(mqtt) = &self.mqtt{
+                    mqtt.publish(room_id, &msg);
+                }
+            }
+            ManualCallResult::OutOfRange => {
+                room.send_host(&ErrorMessage::new(format!("Number {} is not in the pool of this round", number)).to_string()).await;
+            }
+            ManualCallResult::AlreadyCalled => {
+                room.send_host(&ErrorMessage::new(format!("Number {} was already called", number)).to_string()).await;
+            }
+        }
+    }
+
+    pub async fn undo_last_call(&mut self, room_id: RoomId){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.check_phase(&[RoomPhase::Live], "undo a call", None).await{
+            return;
+        }
+        if room.is_speed_round(){
+            room.send_host(&ErrorMessage::new(SPEED_ROUND_CALLS.to_owned()).to_string()).await;
+            return;
+        }
+
+        let call = room.draws.called().len();
+        let number = match room.draws.undo_last() {
+            Some(number) => number,
+            None => {
+                room.send_host(&ErrorMessage::new("No number has been called".to_owned()).to_string()).await;
+                return;
+            }
+        };
+        log::info!("Undid call {} of {} in room {}", call, number, room_id);
+
+        let called = room.draws.called();
+        let msg = serde_json::to_string(&CallUndoneMessage::new(number, called)).unwrap();
+        room.broadcast_all(&msg).await;
+        if let Some(mqtt) = &self.mqtt{
+            mqtt.publish(room_id, &msg);
+        }
+        // Daubs are derived from the calls, so players only need their corrected standing
+        for (conn_id, cards) in &room.cards{
+            let status = serde_json::to_string(&CardStatusMessage::new(cards, called)).unwrap();
+            room.send(*conn_id, &status).await;
+        }
+        room.update_boards().await;
+
+        let round = room.round.as_ref().map_or(0, |round| round.id);
+        self.persistence.submit(remove_call(room_id, round, call));
+    }
+
+    pub async fn set_card_settings(&mut self, room_id: RoomId, settings: CardSettings, version: Option<u64>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if room.check_settings_version(version).await{
+            log::info!("Room {} allows {} cards per player", room_id, settings.max_cards_per_player);
+            room.card_settings = settings.clone();
+            room.settings_changed(SettingsDelta::card_settings(settings)).await;
+        }
+    }
+
+    pub async fn request_card(&mut self, room_id: RoomId, conn_id: SessionId){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.check_phase(&CARD_PHASES, "get a card", Some(conn_id)).await{
+            return;
+        }
+        match room.deal_card(conn_id) {
+            Ok(msg) => { room.send(conn_id, &msg).await; }
+            Err(error) => { room.send(conn_id, &ErrorMessage::new(error).to_string()).await; }
+        }
+    }
+
+    /// The first card of a connected player, dealt when it holds none yet, see `/join/{room}/card`.
+    /// `None` when the token has no open socket in the room.
+    pub async fn player_card(&mut self, room_id: RoomId, player_token: &str) -> Option<Result<PlayerCard, String>> {
+        let room = self.rooms.get(&room_id)?;
+        let conn_id = room.players.iter()
+            .filter(|(conn_id, connection)| connection.player.token == player_token && room.sessions.contains_key(conn_id))
+            .map(|(conn_id, _)| *conn_id)
+            .max()?;
+        let conn_id = self.card_holder(room_id, conn_id);
+        let room = self.rooms.get_mut(&room_id)?;
+
+        if room.cards.get(&conn_id).is_none_or(Vec::is_empty){
+            if !CARD_PHASES.contains(&room.phase){
+                return Some(Err(format!("Cannot get a card while the room is in the {} phase", room.phase.name())));
+            }
+            match room.deal_card(conn_id) {
+                Ok(msg) => { room.send(conn_id, &msg).await; }
+                Err(error) => return Some(Err(error)),
+            }
+        }
+        let card = room.cards.get(&conn_id).and_then(|cards| cards.first())?;
+        Some(Ok(PlayerCard::new(conn_id, card, room.draws.called())))
+    }
+
+    /// Voids one of the client's cards and issues a fresh one, only before the first call of the round.
+    pub async fn new_card(&mut self, room_id: RoomId, conn_id: SessionId, card_id: Option<CardId>){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.check_phase(&CARD_PHASES, "trade in a card", Some(conn_id)).await{
+            return;
+        }
+
+        let allowed = room.card_settings.trade_ins_per_round;
+        let used = room.trade_ins.get(&conn_id).copied().unwrap_or_default();
+        let error = if allowed == 0 {
+            Some("Card trade-ins are not allowed in this room".to_owned())
+        } else if !room.draws.called().is_empty() {
+            Some("Cards can only be traded in before the first call".to_owned())
+        } else if used >= allowed {
+            Some(format!("Trade-in limit of {} reached for this round", allowed))
+        } else {
+            None
+        };
+        if let Some(error) = error{
+            room.send(conn_id, &ErrorMessage::new(error).to_string()).await;
+            return;
+        }
+
+        let pool = room.card_pool();
+        let cards = room.cards.entry(conn_id).or_default();
+        let position = match card_id {
+            Some(card_id) => cards.iter().position(|card| card.id == card_id),
+            None => cards.len().checked_sub(1),
+        };
+        let Some(position) = position else {
+            room.send(conn_id, &ErrorMessage::new("No card to trade in".to_owned()).to_string()).await;
+            return;
+        };
+
+        let card = Card::generate(room.next_card_id, &pool, room.variant);
+        room.next_card_id += 1;
+        let voided = std::mem::replace(&mut cards[position], card);
+        room.trade_ins.insert(conn_id, used + 1);
+        log::info!("Client {} traded in card {} for card {} in room {}", conn_id, voided.id, cards[position].id, room_id);
+        // Trade-ins between rounds count towards the next one
+        let round = room.round.as_ref().map_or(room.rounds_played + 1, |round| round.id);
+        self.persistence.submit_for(&room.retention, record_trade_in(room_id, room.host.clone(), round, voided.id, cards[position].id));
+
+        let msg = serde_json::to_string(&CardTradedMessage::new(conn_id, voided.id, &cards[position], allowed - used - 1)).unwrap();
+        room.send(conn_id, &msg).await;
+        room.send_host(&msg).await;
+        self.host_events.publish(&room.host, room_id, &msg);
+    }
+
+    /// Gives the client a card printed by the host, e.g. a paper card sold at the door, ignoring the card limit.
+    pub async fn assign_card(&mut self, room_id: RoomId, conn_id: SessionId, card: Card){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        let error = if !room.sessions.contains_key(&conn_id) {
+            Some(format!("Client {} is not connected", conn_id))
+        } else if !card.is_valid_layout(room.variant) {
+            Some(format!("Card {} is not a valid {} card", card.id, room.variant.name()))
+        } else if room.cards.iter().any(|(holder, cards)| *holder != conn_id && cards.iter().any(|held| held.id == card.id)) {
+            Some(format!("Card {} is already held by another player", card.id))
+        } else {
+            None
+        };
+        if let Some(error) = error{
+            room.send_host(&ErrorMessage::new(error).to_string()).await;
+            return;
+        }
+
+        // Printed cards get a code of the server, codes chosen by the host could be guessed
+        let mut card = card;
+        card.claim_code = Some(generate_claim_code());
+        // Keep issued ids clear of the printed batch
+        room.next_card_id = room.next_card_id.max(card.id.saturating_add(1));
+        log::info!("Host assigned card {} to client {} in room {}", card.id, conn_id, room_id);
+        let msg = serde_json::to_string(&CardMessage::new(&card)).unwrap();
+        let assigned = serde_json::to_string(&CardAssignedMessage::new(conn_id, card.id)).unwrap();
+        let cards = room.cards.entry(conn_id).or_default();
+        cards.retain(|held| held.id != card.id);
+        cards.push(card);
+        room.send(conn_id, &msg).await;
+        room.send_host(&assigned).await;
+        self.host_events.publish(&room.host, room_id, &assigned);
+    }
+
+    /// Looks up the card with the claim code and reports to the hosts what it covers, recording the
+    /// winner is left to the host.
+    pub async fn verify_claim_code(&self, room_id: RoomId, code: String){
+        let Some(room) = self.rooms.get(&room_id) else {
+            return;
+        };
+        let normalized = normalize_claim_code(&code);
+        let found = room.cards.iter()
+            .flat_map(|(holder, cards)| cards.iter().map(move |card| (*holder, card)))
+            .find(|(_, card)| card.claim_code.as_deref() == Some(normalized.as_str()));
+        match found {
+            Some((holder, card)) => log::info!("Host looked up card {} of client {} in room {} by its claim code", card.id, holder, room_id),
+            None => log::info!("Host entered an unknown claim code in room {}", room_id),
+        }
+        let msg = ClaimCodeResultMessage::new(normalized, found, room.draws.called());
+        room.send_host(&serde_json::to_string(&msg).unwrap()).await;
+    }
+
+    /// Validates a claim against every card held by the client, a valid claim counts as a round winner.
+    pub async fn claim_bingo(&mut self, room_id: RoomId, conn_id: SessionId){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.check_phase(&[RoomPhase::Live], "claim bingo", Some(conn_id)).await{
+            return;
+        }
+
+        let called = room.draws.called();
+        let cards = room.cards.get(&conn_id).map(Vec::as_slice).unwrap_or_default();
+        let winning_card = verify_claim(cards, called);
+        let prizes = match room.round.as_mut() {
+            Some(round) => round.award_prizes(conn_id, cards, called),
+            None => Vec::new(),
+        };
+        let jackpot = winning_card.is_some() && room.round.as_mut().is_some_and(|round| round.award_jackpot(conn_id, called.len()));
+        let bonus = winning_card.is_some() && room.round.as_mut().is_some_and(|round| round.award_bonus(conn_id, cards, called));
+
+        log::info!("Claim from client {} in room {} is {}", conn_id, room_id, if winning_card.is_some() || !prizes.is_empty() { "valid" } else { "invalid" });
+        if jackpot{
+            log::info!("Client {} won the jackpot in room {} after {} calls", conn_id, room_id, called.len());
+        }
+        if bonus{
+            log::info!("Client {} won on a bonus ball in room {}", conn_id, room_id);
+        }
+        let msg = serde_json::to_string(&ClaimResultMessage::new(conn_id, winning_card, prizes.iter().map(|prize| prize.pattern).collect(), called.len(), jackpot, bonus)).unwrap();
+        room.send(conn_id, &msg).await;
+        room.send_host(&msg).await;
+        self.host_events.publish(&room.host, room_id, &msg);
+
+        if let Some(round) = &room.round{
+            for prize in &prizes{
+                log::info!("Client {} won the {} prize of round {} in room {}", conn_id, prize.pattern.name(), round.id, room_id);
+                let announcement = serde_json::to_string(&PrizeWonMessage::new(round.id, prize)).unwrap();
+                room.broadcast_all(&announcement).await;
+                self.host_events.publish(&room.host, room_id, &announcement);
+                if let Some(sms) = &self.sms{
+                    sms.announce(&room.host, &room.sms_recipients, &announcement, room.variant);
+                }
+                if let Some(mqtt) = &self.mqtt{
+                    mqtt.publish(room_id, &announcement);
+                }
+                self.persistence.submit_for(&room.retention, record_win(room_id, room.host.clone(), round.id, prize.pattern.name(), called.len(), false));
+                if let Some(player) = room.players.get(&conn_id){
+                    self.persistence.submit_for(&room.retention, record_points(room_id, player.player.token.clone(), round.id, prize.pattern.name(), false));
+                }
+            }
+        }
+
+        if let (Some(card_id), Some(round)) = (winning_card, room.round.as_mut()){
+            let claim_window = round.settings.claim_window();
+            let collected = claim_window.map(|_| round.collect_claim(conn_id, card_id));
+            // Repeated claims are answered, but the win is only recorded once
+            let new_win = match collected {
+                Some(collected) => collected != CollectedClaim::Repeated,
+                None => !round.winners.contains(&conn_id),
+            };
+            if new_win{
+                self.persistence.submit_for(&room.retention, record_win(room_id, room.host.clone(), round.id, LINE_PATTERN, called.len(), jackpot));
+                if let Some(player) = room.players.get(&conn_id){
+                    self.persistence.submit_for(&room.retention, record_points(room_id, player.player.token.clone(), round.id, LINE_PATTERN, jackpot));
+                }
+            }
+            let (Some(duration), Some(collected)) = (claim_window, collected) else {
+                self.record_winner(room_id, conn_id, Some(card_id)).await;
+                return;
+            };
+            // Later claims join the open window, the first one opens it and schedules its end
+            if let CollectedClaim::Opened(window) = collected{
+                let round_id = round.id;
+                log::info!("Claim window {} of round {} in room {} opened by client {}", window, round_id, room_id, conn_id);
+                room.broadcast_all(&serde_json::to_string(&ClaimWindowOpenedMessage::new(round_id, duration)).unwrap()).await;
+                let cmd_tx = self.cmd_tx.clone();
+                tokio::spawn(async move {
+                    sleep(duration).await;
+                    let _ = cmd_tx.send(Command::CloseClaimWindow { room: room_id, round: round_id, window });
+                });
+            }
+        }
+    }
+
+    /// Closes the open claim window of the room, if any, before the next call.
+    async fn close_open_claim_window(&mut self, room_id: RoomId){
+        let open = self.rooms.get(&room_id)
+            .and_then(|room| room.round.as_ref())
+            .and_then(|round| round.claim_window.as_ref().map(|window| (round.id, window.id)));
+        if let Some((round_id, window)) = open{
+            self.close_claim_window(room_id, round_id, window).await;
+        }
+    }
+
+    /// Makes every claim of the window a winner at once, ignored when that window is already closed.
+    pub async fn close_claim_window(&mut self, room_id: RoomId, round_id: RoundId, window_id: u32){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        let Some(round) = room.round.as_mut().filter(|round| round.id == round_id) else {
+            return;
+        };
+        let Some(window) = round.claim_window.take_if(|window| window.id == window_id) else {
+            return;
+        };
+
+        let mut max_reached = false;
+        let mut winners = Vec::new();
+        for (conn_id, card_id) in &window.claims{
+            if !round.winners.contains(conn_id){
+                let calls = room.draws.called().len();
+                winners.push(serde_json::to_string(&WinnerEvent::new(round.id, *conn_id, Some(*card_id), calls, round.jackpot_winners.contains(conn_id))).unwrap());
+            }
+            max_reached |= round.add_winner(*conn_id);
+        }
+        for msg in &winners{
+            room.broadcast_all(msg).await;
+            if let Some(mqtt) = &self.mqtt{
+                mqtt.publish(room_id, msg);
+            }
+        }
+        log::info!("Claim window {} of round {} in room {} closed with {} winners", window.id, round_id, room_id, window.claims.len());
+        let msg = serde_json::to_string(&ClaimWindowClosedMessage::new(round_id, &window)).unwrap();
+        room.broadcast_all(&msg).await;
+        self.host_events.publish(&room.host, room_id, &msg);
+
+        if max_reached{
+            self.end_round(room_id, RoundEndReason::MaxWinners).await;
+        }
+        else{
+            room.update_boards().await;
+        }
+    }
+
+    pub async fn report(&self, room_id: RoomId){
+        if let Some(room) = self.rooms.get(&room_id){
+            room.send_host(&serde_json::to_string(&room.report()).unwrap()).await;
+        }
+    }
+
+    pub async fn issue_ws_ticket(&mut self, room: RoomId, user_type: UserType, identity: String) -> (String, Duration) {
+        let now = Instant::now();
+        self.ws_tickets.retain(|_, ticket| ticket.expires > now);
+
+        let ticket = generate_ticket();
+        let ttl = self.config.ws_ticket_ttl;
+        self.ws_tickets.insert(ticket.clone(), WsTicket{ room, user_type, identity, expires: now + ttl });
+        (ticket, ttl)
+    }
+
+    /// Consumes a websocket ticket, returns the identity it was issued to when it is valid for the room and role.
+    pub async fn redeem_ws_ticket(&mut self, room: RoomId, ticket: &str, user_type: UserType) -> Option<String> {
+        let ticket = self.ws_tickets.remove(ticket)?;
+        if ticket.room != room || ticket.user_type != user_type || ticket.expires <= Instant::now(){
+            log::warn!("Rejected websocket ticket for room {}", room);
+            return None;
+        }
+        Some(ticket.identity)
+    }
+
+    pub async fn flush_presence(&mut self){
+        for room in self.rooms.values_mut(){
+            if let Some(summary) = room.flush_presence().await{
+                self.host_events.publish(&room.host, room.id, &summary);
+            }
+        }
+    }
+
+    pub async fn roster(&self, room_id: RoomId){
+        if let Some(room) = self.rooms.get(&room_id){
+            let notes = room.notes.iter()
+                .filter(|(conn_id, _)| room.sessions.contains_key(conn_id))
+                .map(|(conn_id, note)| (*conn_id, note.clone()))
+                .collect();
+            let tables = group_by_table(room.seats.iter().filter(|(conn_id, _)| room.sessions.contains_key(conn_id)));
+            let roster = RosterMessage::new(room.sessions.keys().copied().collect(), notes, tables);
+            room.send_host(&serde_json::to_string(&roster).unwrap()).await;
+        }
+    }
+
+    /// Client versions of a room, connected clients that never reported count as unknown.
+    fn room_client_versions(room: &Room) -> Vec<VersionCount> {
+        count_versions(room.sessions.keys().map(|conn_id| room.clients.get(conn_id).unwrap_or(&UNKNOWN_CLIENT)))
+    }
+
+    /// Records the reported version, outdated clients are warned or closed depending on the policy.
+    pub async fn client_info(&mut self, room_id: RoomId, conn_id: SessionId, info: ClientInfo, policy: VersionPolicy){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.sessions.contains_key(&conn_id){
+            return;
+        }
+
+        let outdated = policy.is_outdated(&info);
+        room.clients.insert(conn_id, info);
+        if outdated{
+            room.send(conn_id, &serde_json::to_string(&OutdatedClientMessage::new(&policy)).unwrap()).await;
+            if policy.reject_outdated{
+                log::info!("Closing outdated client {} in room {}", conn_id, room_id);
+                // Dropping the sender ends the socket, its disconnect finds nothing left to remove
+                room.remove_client(conn_id, UserType::Client, DisconnectReason::Kicked).await;
+            }
+        }
+    }
+
+    /// Tells every client of the rooms to reload, returns the number of rooms and of closed sockets.
+    pub async fn force_refresh(&mut self, room_id: Option<RoomId>, request: ForceRefreshRequest) -> ForceRefreshResult {
+        let msg: Msg = serde_json::to_string(&ForceRefreshMessage::new(&request)).unwrap().into();
+        let policy = request.policy();
+        let mut result = ForceRefreshResult{ rooms: 0, closed: 0 };
+        for room in self.rooms.values_mut().filter(|room| room_id.is_none_or(|id| room.id == id)){
+            result.rooms += 1;
+            room.send_host(&msg).await;
+            // Sent regardless of subscriptions
+            for tx in room.sessions.values().chain(room.boards.values()){
+                let _ = tx.send(msg.clone());
+            }
+            if !policy.reject_outdated{
+                continue;
+            }
+            let outdated: Vec<SessionId> = room.sessions.keys()
+                .filter(|conn_id| policy.is_outdated(room.clients.get(conn_id).unwrap_or(&UNKNOWN_CLIENT)))
+                .copied()
+                .collect();
+            for conn_id in outdated{
+                room.remove_client(conn_id, UserType::Client, DisconnectReason::Kicked).await;
+                result.closed += 1;
+            }
+        }
+        log::info!("Forced a refresh in {} rooms, closed {} outdated clients: {:?}", result.rooms, result.closed, request);
+        result
+    }
+
+    /// Moves the room to another phase, ending the room also ends the round in progress.
+    pub async fn set_phase(&mut self, room_id: RoomId, phase: RoomPhase){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.phase.can_transition_to(phase){
+            let error = format!("Cannot move from the {} to the {} phase", room.phase.name(), phase.name());
+            room.send_host(&ErrorMessage::new(error).to_string()).await;
+            return;
+        }
+        if phase == RoomPhase::Intermission && room.round.is_some(){
+            room.send_host(&ErrorMessage::new("End the round before the intermission".to_owned()).to_string()).await;
+            return;
+        }
+
+        if phase == RoomPhase::Ended{
+            self.end_round(room_id, RoundEndReason::Host).await;
+        }
+        if let Some(room) = self.rooms.get_mut(&room_id){
+            room.change_phase(phase).await;
+            if let (Some(analytics), RoomPhase::Ended) = (&self.analytics, phase){
+                analytics.record(room_id, &room.host, AnalyticsEventKind::RoomEnded{ rounds: room.rounds_played, players_joined: room.players_joined });
+            }
+            if phase == RoomPhase::Ended{
+                let report = room.report();
+                if let Some(archive) = &self.archive{
+                    self.persistence.submit(archive.archive_room(room_id, serde_json::to_string(&room.archived_report()).unwrap()));
+                }
+                if let (Some(mailer), Some(to)) = (&self.mailer, &room.email_settings.report_to){
+                    send_email(mailer, Email::report(to.clone(), room_id, report.summary(), serde_json::to_string(&report).unwrap()));
+                }
+            }
+        }
+    }
+
+    /// Ends a room like its host would, `None` when there is no such room and false when it already ended.
+    pub async fn close_room(&mut self, room_id: RoomId) -> Option<bool> {
+        let phase = self.rooms.get(&room_id)?.phase;
+        if phase == RoomPhase::Ended{
+            return Some(false);
+        }
+        self.set_phase(room_id, RoomPhase::Ended).await;
+        log::info!("Closed room {} on behalf of an admin", room_id);
+        Some(true)
+    }
+
+    /// Applies changed overrides of the `config` table to the server and every loaded room.
+    pub async fn apply_config(&mut self, overrides: ConfigOverrides){
+        self.config = overrides.apply(&self.defaults);
+        self.feature_flags.set_defaults(overrides.features);
+        self.mailbox.set_threshold(self.config.max_command_backlog);
+
+        let mut rooms = std::mem::take(&mut self.rooms);
+        for room in rooms.values_mut(){
+            self.configure_room(room);
+        }
+        self.rooms = rooms;
+    }
+
+    /// Replaces the host tags and note of a connection, empty ones remove it.
+    pub async fn tag_connection(&mut self, room_id: RoomId, conn_id: SessionId, note: ConnectionNote){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if !room.sessions.contains_key(&conn_id) && !room.notes.contains_key(&conn_id) && !room.cards.contains_key(&conn_id){
+            room.send_host(&ErrorMessage::new(format!("Client {} is not known in this room", conn_id)).to_string()).await;
+            return;
+        }
+
+        let note = note.clean();
+        let msg = serde_json::to_string(&ConnectionTaggedMessage::new(conn_id, &note)).unwrap();
+        if note.is_empty(){
+            room.notes.remove(&conn_id);
+        }
+        else{
+            room.notes.insert(conn_id, note);
+        }
+        room.send_host(&msg).await;
+    }
+
+    /// Changes the faults injected into the room and closes the requested player sockets.
+    /// Hosts can only do this in rooms with the chaos mode feature, operators in any room.
+    pub async fn set_chaos(&mut self, room_id: RoomId, request: ChaosRequest, by_admin: bool) -> Option<Result<ChaosResult, String>> {
+        let room = self.rooms.get_mut(&room_id)?;
+        let check = if !by_admin && !room.features.is_enabled(Feature::ChaosMode) {
+            Err("Chaos mode is not available in this room".to_owned())
+        } else {
+            request.settings.validate()
+        };
+        if let Err(error) = check{
+            if !by_admin{
+                room.send_host(&ErrorMessage::new(error.clone()).to_string()).await;
+            }
+            return Some(Err(error));
+        }
+
+        let targets: Vec<SessionId> = if request.disconnect_all {
+            room.sessions.keys().copied().collect()
+        } else {
+            request.disconnect.into_iter().filter(|conn_id| room.sessions.contains_key(conn_id)).collect()
+        };
+        // Dropping the sockets without a goodbye looks like a lost network to the client
+        for conn_id in &targets{
+            room.remove_client(*conn_id, UserType::Client, DisconnectReason::Kicked).await;
+        }
+        room.chaos = request.settings;
+        log::warn!("Chaos mode in room {}: {:?}, disconnected {} clients", room_id, room.chaos, targets.len());
+
+        let result = ChaosResult{ settings: room.chaos, disconnected: targets.len() };
+        room.send_host(&serde_json::to_string(&ChaosMessage::new(&result)).unwrap()).await;
+        Some(Ok(result))
+    }
+
+    /// Restricts the web origins players may join from, an empty list lifts the restriction.
+    pub async fn set_allowed_origins(&mut self, room_id: RoomId, origins: AllowedOrigins){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        let origins = match origins.normalize() {
+            Ok(origins) => origins,
+            Err(error) => {
+                room.send_host(&ErrorMessage::new(error).to_string()).await;
+                return;
+            }
+        };
+
+        log::info!("Room {} admits players from {:?}", room_id, origins.origins);
+        room.send_host(&serde_json::to_string(&AllowedOriginsMessage::new(&origins)).unwrap()).await;
+        room.allowed_origins = origins;
+    }
+
+    /// Replaces the seat map, seats that no longer exist are released and an empty map turns seating off.
+    pub async fn set_seat_map(&mut self, room_id: RoomId, seat_map: SeatMap){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+        if let Err(error) = seat_map.validate(){
+            room.send_host(&ErrorMessage::new(error).to_string()).await;
+            return;
+        }
+
+        log::info!("Seat map of room {} set to {} tables", room_id, seat_map.tables.len());
+        room.seats.retain(|_, seat| seat_map.contains(seat));
+        let msg: Msg = serde_json::to_string(&SeatMapMessage::new(&seat_map)).unwrap().into();
+        room.seat_map = (!seat_map.tables.is_empty()).then_some(seat_map);
+        room.send_host(&msg).await;
+        for tx in room.sessions.values(){
+            let _ = tx.send(msg.clone());
+        }
+    }
+
+    /// Seats a client, a seat held by someone who already left is handed over.
+    pub async fn take_seat(&mut self, room_id: RoomId, conn_id: SessionId, seat: Seat, by_host: bool){
+        let room = match self.rooms.get_mut(&room_id) {
+            Some(room) => room,
+            None => return,
+        };
+
+        let holder = seat_holder(&room.seats, &seat);
+        let error = if !room.sessions.contains_key(&conn_id) {
+            Some(format!("Client {} is not connected", conn_id))
+        } else if !room.seat_map.as_ref().is_some_and(|seat_map| seat_map.contains(&seat)) {
+            Some(format!("Seat {} at table {} does not exist", seat.seat, seat.table))
+        } else if holder.is_some_and(|holder| holder != conn_id && room.sessions.contains_key(&holder)) {
+            Some(format!("Seat {} at table {} is taken", seat.seat, seat.table))
+        } else {
+            None
+        };
+        if let Some(error) = error{
+            let error = ErrorMessage::new(error).to_string();
+            if by_host{
+                room.send_host(&error).await;
+            }
+            else{
+                room.send(conn_id, &error).await;
+            }
+            return;
+        }
+
+        if let Some(holder) = holder{
+            room.seats.remove(&holder);
+        }
+        log::info!("Client {} took seat {} at table {} in room {}", conn_id, seat.seat, seat.table, room_id);
+        let msg = serde_json::to_string(&SeatMessage::new(conn_id, &seat)).unwrap();
+        room.seats.insert(conn_id, seat);
+        room.send(conn_id, &msg).await;
+        room.send_host(&msg).await;
+    }
+
+    pub async fn client_versions(&self, room_id: RoomId){
+        if let Some(room) = self.rooms.get(&room_id){
+            let msg = ClientVersionsMessage::new(Self::room_client_versions(room));
+            room.send_host(&serde_json::to_string(&msg).unwrap()).await;
+        }
+    }
+
+    pub async fn all_client_versions(&self) -> Vec<RoomClientVersions> {
+        let mut rooms: Vec<RoomClientVersions> = self.rooms.values()
+            .filter(|room| !room.sessions.is_empty())
+            .map(|room| RoomClientVersions{
+                room: room.id,
+                host: room.host.clone(),
+                versions: Self::room_client_versions(room),
+            })
+            .collect();
+        rooms.sort_by_key(|room| room.room);
+        rooms
+    }
+
+    pub async fn subscribe(&mut self, room_id: RoomId, conn_id: SessionId, channels: Vec<Channel>){
+        if let Some(room) = self.rooms.get_mut(&room_id){
+            room.subscribe(conn_id, channels).await;
+        }
+    }
+
+    pub async fn host_connections(&self) -> Vec<HostConnections> {
+        let mut connections: Vec<HostConnections> = self.rooms.values()
+            .filter(|room| !room.host_pipes.is_empty())
+            .map(|room| HostConnections{
+                room: room.id,
+                host: room.host.clone(),
+                connection_ages_secs: room.host_pipes.values().map(|connection| connection.connected_at.elapsed().as_secs()).collect(),
+            })
+            .collect();
+        connections.sort_by_key(|room| std::cmp::Reverse(room.connection_ages_secs.len()));
+        connections
+    }
+
+    /// Rooms with at least one open connection, busiest first.
+    pub fn live_rooms(&self) -> Vec<LiveRoom> {
+        let mut rooms: Vec<LiveRoom> = self.rooms.values()
+            .map(|room| LiveRoom{
+                room: room.id,
+                host: room.host.clone(),
+                variant: room.variant,
+                phase: room.phase,
+                round: room.round.as_ref().map(|round| round.id),
+                calls: room.draws.called().len(),
+                hosts: room.host_pipes.len(),
+                players: room.sessions.len(),
+                waiting: room.waitlist.len(),
+                boards: room.boards.len(),
+                observers: room.observers.len(),
+            })
+            .filter(|room| room.hosts + room.players + room.waiting + room.boards + room.observers > 0)
+            .collect();
+        rooms.sort_by_key(|room| (std::cmp::Reverse(room.players + room.waiting), room.room));
+        rooms
+    }
+
+    /// Player connections of a room owned by the host, `None` for other rooms.
+    pub fn attendance(&self, room_id: RoomId, host: &str) -> Option<Vec<AttendanceRow>> {
+        let room = self.rooms.get(&room_id).filter(|room| room.host == host)?;
+        let mut rows: Vec<AttendanceRow> = room.players.iter()
+            .map(|(conn_id, connection)| AttendanceRow{
+                client_id: *conn_id,
+                name: connection.player.name.clone(),
+                joined_at: connection.joined_at,
+                left_at: connection.left_at,
+            })
+            .collect();
+        rows.sort_by_key(|row| (row.joined_at, row.client_id));
+        Some(rows)
+    }
+
+    /// Cards of every socket the player opened in the room, `None` when the player never joined it.
+    pub fn player_cards(&self, room_id: RoomId, player_token: &str) -> Option<PlayerCards> {
+        let room = self.rooms.get(&room_id)?;
+        let mut conn_ids: Vec<SessionId> = room.players.iter()
+            .filter(|(_, connection)| connection.player.token == player_token)
+            .map(|(conn_id, _)| *conn_id)
+            .collect();
+        if conn_ids.is_empty(){
+            return None;
+        }
+        conn_ids.sort();
+
+        let called = room.draws.called();
+        let cards = conn_ids.iter()
+            .flat_map(|conn_id| room.cards.get(conn_id).into_iter().flatten().map(move |card| (*conn_id, card)))
+            .map(|(client_id, card)| PlayerCard::new(client_id, card, called))
+            .collect();
+        let claim = room.round.as_ref().map(|round| ClaimStatus{
+            won: round.winners.iter().any(|winner| conn_ids.contains(winner)),
+            pending: round.claim_window.iter().flat_map(|window| &window.claims).any(|(claimant, _)| conn_ids.contains(claimant)),
+            prizes: round.prizes.iter().filter(|prize| prize.winner.is_some_and(|winner| conn_ids.contains(&winner))).map(|prize| prize.pattern).collect(),
+        });
+        Some(PlayerCards{
+            room: room_id,
+            client_id: conn_ids.iter().rev().find(|conn_id| room.sessions.contains_key(conn_id)).copied(),
+            phase: room.phase,
+            round: room.round.as_ref().map(|round| round.id),
+            calls: called.to_vec(),
+            cards,
+            claim: claim.unwrap_or_default(),
+        })
+    }
+
+    /// Current and earlier player connections of the host's rooms whose name contains the lowercase query.
+    pub async fn find_players(&self, host: &str, name: &str) -> Vec<PlayerMatch> {
+        let mut matches: Vec<PlayerMatch> = self.rooms.values()
+            .filter(|room| room.host == host)
+            .flat_map(|room| room.players.iter()
+                .filter(|(_, connection)| connection.player.name.as_ref().is_some_and(|player| player.to_lowercase().contains(name)))
+                .map(|(conn_id, connection)| PlayerMatch{
+                    room: room.id,
+                    client_id: *conn_id,
+                    name: connection.player.name.clone(),
+                    connected: room.sessions.contains_key(conn_id),
+                    joined_at: connection.joined_at,
+                    left_at: connection.left_at,
+                    cards: room.cards.get(conn_id).map_or(0, Vec::len),
+                    seat: room.seats.get(conn_id).cloned(),
+                }))
+            .collect();
+        // Connected players first, then the most recent connections
+        matches.sort_by_key(|player| (!player.connected, std::cmp::Reverse(player.joined_at)));
+        matches.truncate(MAX_SEARCH_RESULTS);
+        matches
+    }
+
+    pub async fn list_rooms(&self, host: &str) -> Vec<RoomOverview> {
+        let mut rooms: Vec<RoomOverview> = self.rooms.values()
+            .filter(|room| room.host == host)
+            .map(|room| RoomOverview{
+                id: room.id,
+                players: room.sessions.len(),
+                round: room.round.as_ref().map(|round| round.id),
+                calls: room.draws.called().len(),
+            })
+            .collect();
+        rooms.sort_by_key(|room| room.id);
+        rooms
+    }
+
+    pub async fn run(mut self) -> io::Result<()> {
+        // Join/leave notifications are batched and flushed to the hosts periodically
+        let cmd_tx = self.cmd_tx.clone();
+        let presence_flush_interval = self.config.presence_flush_interval;
+        tokio::spawn(async move {
+            let mut interval = interval(presence_flush_interval);
+            loop {
+                interval.tick().await;
+                if cmd_tx.send(Command::FlushPresence).is_err(){
+                    break;
+                }
+            }
+        });
+
+        // Sockets whose task ended without a disconnect would otherwise get broadcasts forever
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(GHOST_SWEEP_INTERVAL);
+            loop {
+                interval.tick().await;
+                if cmd_tx.send(Command::SweepGhosts).is_err(){
+                    break;
+                }
+            }
+        });
+
+        // Scheduled announcements are sent by the server, whether or not a host is connected
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            let mut interval = interval(ANNOUNCEMENT_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                if cmd_tx.send(Command::FireAnnouncements).is_err(){
+                    break;
+                }
+            }
+        });
+
+        // Rooms of the previous day are archived shortly after midnight UTC
+        let cmd_tx = self.cmd_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let next_midnight = (room_date() + chrono::Days::new(1)).and_hms_opt(0, 0, 0).unwrap().and_utc();
+                let until_midnight = (next_midnight - Utc::now()).to_std().unwrap_or_default();
+                sleep(until_midnight + Duration::from_secs(1)).await;
+                if cmd_tx.send(Command::RotateRooms).is_err(){
+                    break;
+                }
+            }
+        });
+
+        while let Some(cmd) = self.cmd_rx.recv().await {
+            self.mailbox.dequeued();
+            match cmd {
+                Command::Create { host, variant, res_tx } => {
+                    let creds = self.create_room(host, variant).await;
+                    let _ = res_tx.send(creds);
+                }
+
+                Command::RoomExists { room_id, res_tx } => {
+                    let exists = self.room_exists(room_id).await;
+                    let _ = res_tx.send(exists);
+                }
+
+                Command::RoomVariant { room_id, res_tx } => {
+                    let _ = res_tx.send(self.rooms.get(&room_id).map(|room| room.variant));
+                }
+
+                Command::RoomHostAuth { room_id, host_token, res_tx } => {
+                    let has_privileges = self.has_room_host_privileges(room_id, host_token).await;
+                    let _ = res_tx.send(has_privileges);
+                }
+
+                Command::RoomBoardAuth { room_id, board_token, res_tx } => {
+                    let has_access = self.has_board_access(room_id, board_token).await;
+                    let _ = res_tx.send(has_access);
+                }
+
+                Command::Connect { room, conn_tx, res_tx, user_type, player } => {
+                    let conn_id = self.add_client(room, conn_tx, user_type, player).await;
+                    let _ = res_tx.send(conn_id);
+                }
+
+                Command::Disconnect { room, conn, user_type, reason } => {
+                    if !(reason == DisconnectReason::Timeout && user_type == UserType::Client && self.suspend_client(room, conn)){
+                        self.remove_client(room, conn, user_type, reason).await;
+                    }
+                }
+
+                Command::ExpireSuspended { room, conn, since } => {
+                    let expired = self.rooms.get(&room)
+                        .and_then(|room| room.suspended.get(&conn))
+                        .is_some_and(|suspended| suspended.since == since);
+                    if expired{
+                        log::info!("Client {} of room {} did not resume in time", conn, room);
+                        self.remove_client(room, conn, UserType::Client, DisconnectReason::Timeout).await;
+                    }
+                }
+
+                Command::SetResumeGrace { room, grace } => {
+                    let default_grace = self.config.resume_grace;
+                    if let Some(room) = self.rooms.get_mut(&room){
+                        room.resume_grace = grace;
+                        let grace = grace.unwrap_or(default_grace);
+                        log::info!("Room {} lets players resume for {:?}", room.id, grace);
+                        room.send_host(&serde_json::to_string(&ResumeGraceMessage::new(grace)).unwrap()).await;
+                    }
+                }
+
+                Command::Update { room, sender, msg, user_type } => {
+                    self.broadcast(room, sender, &msg, user_type).await;
+                }
+
+                Command::Relay { room, msg, user_type } => {
+                    self.relay(room, &msg, user_type).await;
+                }
+
+                Command::SetLanguage { room, locale, version } => {
+                    self.set_language(room, locale, version).await;
+                }
+
+                Command::SetSmsRecipients { room, numbers, version } => {
+                    self.set_sms_recipients(room, numbers, version).await;
+                }
+
+                Command::SmsOptInsLoaded { room, numbers, opted_in, version } => {
+                    self.apply_sms_recipients(room, numbers, opted_in, version).await;
+                }
+
+                Command::ConfirmSmsRecipient { room, number, code } => {
+                    self.confirm_sms_recipient(room, number, code).await;
+                }
+
+                Command::SmsOptInSaved { room, number, code, saved } => {
+                    self.finish_sms_opt_in(room, number, code, saved).await;
+                }
+
+                Command::SetMilestones { room, enabled, version } => {
+                    self.set_milestones(room, enabled, version).await;
+                }
+
+                Command::GetSettings { room } => {
+                    self.get_settings(room).await;
+                }
+
+                Command::Send { room, conn, msg, res_tx } => {
+                    let delivered = self.send(room, conn, &msg).await;
+                    if !delivered{
+                        log::info!("Message to client {} in room {} was not delivered", conn, room);
+                    }
+                    let _ = res_tx.send(delivered);
+                }
+
+                Command::NotifyHost { room, msg } => {
+                    self.notify_host(room, &msg).await;
+                }
+
+                Command::StartRound { room, settings } => {
+                    self.start_round(room, settings).await;
+                }
+
+                Command::EndRound { room, reason } => {
+                    self.end_round(room, reason).await;
+                }
+
+                Command::CloseClaimWindow { room, round, window } => {
+                    self.close_claim_window(room, round, window).await;
+                }
+
+                Command::RoundTimeout { room, round } => {
+                    self.round_timeout(room, round).await;
+                }
+
+                Command::SpeedCall { room, round } => {
+                    self.speed_call(room, round).await;
+                }
+
+                Command::StartAutoCall { room, interval } => {
+                    self.start_auto_call(room, interval).await;
+                }
+
+                Command::PauseAutoCall { room, paused } => {
+                    self.pause_auto_call(room, paused).await;
+                }
+
+                Command::AutoCall { room, generation } => {
+                    self.auto_call(room, generation).await;
+                }
+
+                Command::RecordWinner { room, conn } => {
+                    self.record_winner(room, conn, None).await;
+                }
+
+                Command::Draw { room, request_id } => {
+                    self.draw(room, request_id).await;
+                }
+
+                Command::ManualCall { room, number } => {
+                    self.manual_call(room, number).await;
+                }
+
+                Command::UndoLastCall { room } => {
+                    self.undo_last_call(room).await;
+                }
+
+                Command::SetCardSettings { room, settings, version } => {
+                    self.set_card_settings(room, settings, version).await;
+                }
+
+                Command::RequestCard { room, conn } => {
+                    self.request_card(room, self.card_holder(room, conn)).await;
+                }
+
+                Command::NewCard { room, conn, card_id } => {
+                    self.new_card(room, self.card_holder(room, conn), card_id).await;
+                }
+
+                Command::AssignCard { room, conn, card } => {
+                    self.assign_card(room, conn, card).await;
+                }
+
+                Command::ClaimBingo { room, conn } => {
+                    self.claim_bingo(room, self.card_holder(room, conn)).await;
+                }
+
+                Command::Report { room } => {
+                    self.report(room).await;
+                }
+
+                Command::IssueWsTicket { room, user_type, identity, res_tx } => {
+                    let ticket = self.issue_ws_ticket(room, user_type, identity).await;
+                    let _ = res_tx.send(ticket);
+                }
+
+                Command::RedeemWsTicket { room, ticket, user_type, res_tx } => {
+                    let identity = self.redeem_ws_ticket(room, &ticket, user_type).await;
+                    let _ = res_tx.send(identity);
+                }
+
+                Command::SubscribeHostEvents { host, tx } => {
+                    self.host_events.subscribe(host, tx);
+                }
+
+                Command::SubscribeAllEvents { tx } => {
+                    self.host_events.subscribe_all(tx);
+                }
+
+                Command::CloseRoom { room, res_tx } => {
+                    let _ = res_tx.send(self.close_room(room).await);
+                }
+
+                Command::ApplyConfig { overrides } => {
+                    self.apply_config(overrides).await;
+                }
+
+                Command::FlushPresence => {
+                    self.flush_presence().await;
+                }
+
+                Command::SweepGhosts => {
+                    self.sweep_ghosts().await;
+                }
+
+                Command::RotateRooms => {
+                    self.rotate_rooms().await;
+                }
+
+                Command::Roster { room } => {
+                    self.roster(room).await;
+                }
+
+                Command::Subscribe { room, conn, channels } => {
+                    self.subscribe(room, conn, channels).await;
+                }
+
+                Command::HostConnections { res_tx } => {
+                    let _ = res_tx.send(self.host_connections().await);
+                }
+
+                Command::LiveRooms { res_tx } => {
+                    let _ = res_tx.send(self.live_rooms());
+                }
+
+                Command::FindPlayers { host, name, res_tx } => {
+                    let _ = res_tx.send(self.find_players(&host, &name).await);
+                }
+
+                Command::PlayerCards { room, player_token, res_tx } => {
+                    let _ = res_tx.send(self.player_cards(room, &player_token));
+                }
+
+                Command::PlayerCard { room, player_token, res_tx } => {
+                    let _ = res_tx.send(self.player_card(room, &player_token).await);
+                }
+
+                Command::Attendance { room, host, res_tx } => {
+                    let _ = res_tx.send(self.attendance(room, &host));
+                }
+
+                Command::ListRooms { host, res_tx } => {
+                    let _ = res_tx.send(self.list_rooms(&host).await);
+                }
+
+                Command::ExportState { res_tx } => {
+                    let _ = res_tx.send(self.export_state().await);
+                }
+
+                Command::ImportState { rooms, res_tx } => {
+                    let _ = res_tx.send(self.import_state(rooms).await);
+                }
+
+                Command::SetDraining { draining, res_tx } => {
+                    self.set_draining(draining).await;
+                    let _ = res_tx.send(());
+                }
+
+                Command::DrainStatus { res_tx } => {
+                    let _ = res_tx.send(self.drain_status().await);
+                }
+
+                Command::ClientInfo { room, conn, info, policy } => {
+                    self.client_info(room, conn, info, policy).await;
+                }
+
+                Command::ClientVersions { room } => {
+                    self.client_versions(room).await;
+                }
+
+                Command::SetPhase { room, phase } => {
+                    self.set_phase(room, phase).await;
+                }
+
+                Command::TagConnection { room, conn, note } => {
+                    self.tag_connection(room, conn, note).await;
+                }
+
+                Command::SetChaos { room, request, by_admin, res_tx } => {
+                    let _ = res_tx.send(self.set_chaos(room, request, by_admin).await);
+                }
+
+                Command::SetAllowedOrigins { room, origins } => {
+                    self.set_allowed_origins(room, origins).await;
+                }
+
+                Command::OriginAllowed { room, origin, res_tx } => {
+                    let allowed = self.rooms.get(&room).is_none_or(|room| room.allowed_origins.allows(origin.as_deref()));
+                    let _ = res_tx.send(allowed);
+                }
+
+                Command::SetSeatMap { room, seat_map } => {
+                    self.set_seat_map(room, seat_map).await;
+                }
+
+                Command::TakeSeat { room, conn, seat, by_host } => {
+                    self.take_seat(room, conn, seat, by_host).await;
+                }
+
+                Command::ForceRefresh { room, request, res_tx } => {
+                    let _ = res_tx.send(self.force_refresh(room, request).await);
+                }
+
+                Command::AllClientVersions { res_tx } => {
+                    let _ = res_tx.send(self.all_client_versions().await);
+                }
+
+                Command::RecordPlayer { room, token, display_name, ip } => {
+                    let retention = self.rooms.get(&room).map(|room| room.retention).unwrap_or_default();
+                    self.persistence.submit_for(&retention, record_player(token, display_name, ip));
+                }
+
+                Command::SetRedactChat { room, enabled } => {
+                    let default_enabled = self.config.redact_chat;
+                    if let Some(room) = self.rooms.get_mut(&room){
+                        room.redact_chat = enabled;
+                        let enabled = enabled.unwrap_or(default_enabled);
+                        log::info!("Room {} redacts chat: {}", room.id, enabled);
+                        room.send_host(&serde_json::to_string(&RedactChatMessage::new(enabled)).unwrap()).await;
+                    }
+                }
+
+                Command::SetRetention { room, policy } => {
+                    if let Some(room) = self.rooms.get_mut(&room){
+                        log::info!("Room {} retains {:?}", room.id, policy);
+                        room.retention = policy;
+                        self.persistence.submit(save_retention(room.id, policy));
+                        room.send_host(&serde_json::to_string(&RetentionPolicyMessage::new(policy)).unwrap()).await;
+                    }
+                }
+
+                Command::RemoveHostRooms { host, res_tx } => {
+                    self.remove_host_rooms(&host).await;
+                    let _ = res_tx.send(());
+                }
+
+                Command::StartSoundCheck { room, request } => {
+                    self.start_sound_check(room, request).await;
+                }
+
+                Command::SoundCheckAck { room, conn, id } => {
+                    self.sound_check_ack(room, conn, id).await;
+                }
+
+                Command::FinishSoundCheck { room, id } => {
+                    self.finish_sound_check(room, id).await;
+                }
+
+                Command::ScheduleAnnouncement { room, request } => {
+                    self.schedule_announcement(room, request).await;
+                }
+
+                Command::CancelAnnouncement { room, id } => {
+                    self.cancel_announcement(room, id).await;
+                }
+
+                Command::ListAnnouncements { room } => {
+                    self.list_announcements(room).await;
+                }
+
+                Command::FireAnnouncements => {
+                    self.fire_announcements().await;
+                }
+
+                Command::OfferTransfer { room, host_token, from, to, res_tx } => {
+                    let _ = res_tx.send(self.offer_transfer(room, host_token, from, to).await);
+                }
+
+                Command::AcceptTransfer { room, user, res_tx } => {
+                    self.accept_transfer(room, user, res_tx);
+                }
+
+                Command::FinishTransfer { room, offer, creds, saved, res_tx } => {
+                    let _ = res_tx.send(self.finish_transfer(room, offer, creds, saved).await);
+                }
+
+                Command::CancelTransfer { room, user, res_tx } => {
+                    let _ = res_tx.send(self.cancel_transfer(room, &user).await);
+                }
+
+                Command::VerifyClaimCode { room, code } => {
+                    self.verify_claim_code(room, code).await;
+                }
+
+                Command::SetPlayerLimit { room, limit } => {
+                    if let Some(room) = self.rooms.get_mut(&room){
+                        room.set_player_limit(limit).await;
+                    }
+                }
+
+                Command::SetEmailSettings { room, settings } => {
+                    self.set_email_settings(room, settings).await;
+                }
+
+                Command::SetPlayerEmail { room, conn, email } => {
+                    self.set_player_email(room, self.card_holder(room, conn), email).await;
+                }
+
+                Command::SetDuplicateSessionPolicy { room, policy } => {
+                    if let Some(room) = self.rooms.get_mut(&room){
+                        log::info!("Room {} {} duplicate player sockets", room.id, if policy == DuplicateSessionPolicy::Mirror { "mirrors" } else { "replaces" });
+                        room.duplicate_session_policy = policy;
+                        room.send_host(&serde_json::to_string(&DuplicateSessionPolicyMessage::new(policy)).unwrap()).await;
+                    }
+                }
+
+                Command::SetCountdown { room, starts_at, time_zone } => {
+                    self.set_countdown(room, starts_at, time_zone).await;
+                }
+
+                Command::SetHostOfflinePolicy { room, policy } => {
+                    if let Some(room) = self.rooms.get_mut(&room){
+                        log::info!("Room {} {} speed rounds while the host is away", room.id, if policy == HostOfflinePolicy::Freeze { "freezes" } else { "continues" });
+                        room.host_offline_policy = policy;
+                        room.send_host(&serde_json::to_string(&HostOfflinePolicyMessage::new(policy)).unwrap()).await;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct BingoServerHandle {
+    cmd_tx: CountingSender<Command>,
+    mailbox: Arc<MailboxStatus>,
+    ghosts: Arc<GhostStatus>,
+    errors: Arc<RecentErrors>,
+    message_log: Arc<MessageLog>,
+}
+
+impl BingoServerHandle {
+    pub fn mailbox(&self) -> Arc<MailboxStatus> {
+        self.mailbox.clone()
+    }
+
+    pub fn ghosts(&self) -> Arc<GhostStatus> {
+        self.ghosts.clone()
+    }
+
+    pub fn errors(&self) -> Arc<RecentErrors> {
+        self.errors.clone()
+    }
+
+    pub fn message_log(&self) -> Arc<MessageLog> {
+        self.message_log.clone()
+    }
+
+    /// Drops a statistics query of a host while the command queue is overloaded, the hosts are told to retry.
+    async fn shed_query(&self, room: RoomId) -> bool {
+        if !self.mailbox.is_overloaded(){
+            return false;
+        }
+        self.mailbox.shed();
+        self.notify_host(room, ErrorMessage::new(SERVER_BUSY.to_owned()).to_string()).await;
+        true
+    }
+
+    /// Returns the host's room of the day, `None` when a new room is needed while draining.
+    pub async fn create_room(&self, host: String, variant: GameVariant) -> Option<RoomCreds> {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::Create { host, variant, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn room_exists(&self, room_id: RoomId) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::RoomExists { room_id, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    /// Variant the room is played with, `None` when the room does not exist.
+    pub async fn room_variant(&self, room_id: RoomId) -> Option<GameVariant> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::RoomVariant{ room_id, res_tx }).unwrap();
+        res_rx.await.unwrap()
+    }
+
+    pub async fn has_room_host_privileges(&self, room_id: RoomId, host_token: String) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::RoomHostAuth { room_id, host_token, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn has_board_access(&self, room_id: RoomId, board_token: String) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::RoomBoardAuth { room_id, board_token, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn connect(&self, room: RoomId, conn_tx: mpsc::UnboundedSender<Msg>, user_type: UserType, player: Option<PlayerIdentity>) -> Result<SessionId, ConnectError> {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::Connect { room, conn_tx, res_tx, user_type, player })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    /// Not async so it can run while a socket task unwinds, see `ConnectionGuard`.
+    pub fn disconnect(&self, room: RoomId, conn: SessionId, user_type: UserType, reason: DisconnectReason) {
+        // Failing here would abort a panicking task, and without the actor there is no session left anyway
+        let _ = self.cmd_tx.send(Command::Disconnect { room, conn, user_type, reason });
+    }
+
+    /// Turns removing email addresses and phone numbers from the room's chat on or off.
+    pub async fn set_redact_chat(&self, room: RoomId, enabled: Option<bool>){
+        self.cmd_tx.send(Command::SetRedactChat{room, enabled}).unwrap();
+    }
+
+    /// Decides what the server stores of the room from now on.
+    pub async fn set_retention(&self, room: RoomId, policy: RetentionPolicy){
+        self.cmd_tx.send(Command::SetRetention{room, policy}).unwrap();
+    }
+
+    /// Overrides how long players of the room can resume a timed out socket, `None` for the server default.
+    pub async fn set_resume_grace(&self, room: RoomId, grace: Option<Duration>){
+        self.cmd_tx.send(Command::SetResumeGrace{room, grace}).unwrap();
+    }
+
+    pub async fn update(&self, room: RoomId, sender: Option<SessionId>, msg: String, user_type: UserType){
+        // Player chat and reactions are the first to go when the actor falls behind, draws stay responsive
+        if user_type == UserType::Client && self.mailbox.is_overloaded() && matches!(Channel::of_message(&msg), Channel::Chat | Channel::Reactions){
+            self.mailbox.shed();
+            return;
+        }
+        self.cmd_tx.send(Command::Update{room, sender, msg, user_type}).unwrap();
+    }
+
+    /// Sets the language chat is translated to, `None` relays chat as sent.
+    pub async fn set_language(&self, room: RoomId, locale: Option<String>, version: Option<u64>){
+        self.cmd_tx.send(Command::SetLanguage{room, locale, version}).unwrap();
+    }
+
+    /// Replaces the phone numbers that get calls and winners, an empty list stops the announcements. Numbers
+    /// that haven't opted in to the host's announcements are sent a code first.
+    pub async fn set_sms_recipients(&self, room: RoomId, numbers: Vec<String>, version: Option<u64>){
+        self.cmd_tx.send(Command::SetSmsRecipients{room, numbers, version}).unwrap();
+    }
+
+    /// Adds a number whose owner gave the host the opt-in code.
+    pub async fn confirm_sms_recipient(&self, room: RoomId, number: String, code: String){
+        self.cmd_tx.send(Command::ConfirmSmsRecipient{room, number, code}).unwrap();
+    }
+
+    /// Turns the milestone events of a room on or off.
+    pub async fn set_milestones(&self, room: RoomId, enabled: bool, version: Option<u64>){
+        self.cmd_tx.send(Command::SetMilestones{room, enabled, version}).unwrap();
+    }
+
+    /// Sends the current settings and their version to the hosts.
+    pub async fn get_settings(&self, room: RoomId){
+        self.cmd_tx.send(Command::GetSettings{room}).unwrap();
+    }
+
+    /// Sends a message to a single client, returns whether the client was still connected.
+    pub async fn send(&self, room: RoomId, conn: SessionId, msg: String) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::Send { room, conn, msg, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn notify_host(&self, room: RoomId, msg: String){
+        self.cmd_tx.send(Command::NotifyHost{room, msg}).unwrap();
+    }
+
+    pub async fn start_round(&self, room: RoomId, settings: RoundSettings){
+        self.cmd_tx.send(Command::StartRound{room, settings}).unwrap();
+    }
+
+    pub async fn end_round(&self, room: RoomId){
+        self.cmd_tx.send(Command::EndRound{room, reason: RoundEndReason::Host}).unwrap();
+    }
+
+    pub async fn record_winner(&self, room: RoomId, conn: SessionId){
+        self.cmd_tx.send(Command::RecordWinner{room, conn}).unwrap();
+    }
+
+    /// Draws the next number, a repeated `request_id` returns the original draw instead of a new number.
+    pub async fn draw(&self, room: RoomId, request_id: Option<String>){
+        self.cmd_tx.send(Command::Draw{room, request_id}).unwrap();
+    }
+
+    /// Records a number called from a physical cage, broadcast like a server draw.
+    pub async fn manual_call(&self, room: RoomId, number: Number){
+        self.cmd_tx.send(Command::ManualCall{room, number}).unwrap();
+    }
+
+    /// Draws a number every `interval` until paused or the round ends.
+    pub async fn start_auto_call(&self, room: RoomId, interval: Duration){
+        self.cmd_tx.send(Command::StartAutoCall{room, interval}).unwrap();
+    }
+
+    pub async fn pause_auto_call(&self, room: RoomId, paused: bool){
+        self.cmd_tx.send(Command::PauseAutoCall{room, paused}).unwrap();
+    }
+
+    /// Takes back the most recent call and sends the corrected state to the room.
+    pub async fn undo_last_call(&self, room: RoomId){
+        self.cmd_tx.send(Command::UndoLastCall{room}).unwrap();
+    }
+
+    pub async fn set_card_settings(&self, room: RoomId, settings: CardSettings, version: Option<u64>){
+        self.cmd_tx.send(Command::SetCardSettings{room, settings, version}).unwrap();
+    }
+
+    /// Issues a new card to the client, up to the room's cards per player limit.
+    pub async fn request_card(&self, room: RoomId, conn: SessionId){
+        self.cmd_tx.send(Command::RequestCard{room, conn}).unwrap();
+    }
+
+    pub async fn new_card(&self, room: RoomId, conn: SessionId, card_id: Option<CardId>){
+        self.cmd_tx.send(Command::NewCard{room, conn, card_id}).unwrap();
+    }
+
+    pub async fn assign_card(&self, room: RoomId, conn: SessionId, card: Card){
+        self.cmd_tx.send(Command::AssignCard{room, conn, card}).unwrap();
+    }
+
+    pub async fn claim_bingo(&self, room: RoomId, conn: SessionId){
+        self.cmd_tx.send(Command::ClaimBingo{room, conn}).unwrap();
+    }
+
+    /// Requests the room report, delivered to the host pipe.
+    pub async fn report(&self, room: RoomId){
+        if self.shed_query(room).await{
+            return;
+        }
+        self.cmd_tx.send(Command::Report{room}).unwrap();
+    }
+
+    /// Issues a one-time websocket ticket, returns the ticket and its lifetime.
+    pub async fn issue_ws_ticket(&self, room: RoomId, user_type: UserType, identity: String) -> (String, Duration) {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::IssueWsTicket { room, user_type, identity, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    pub async fn redeem_ws_ticket(&self, room: RoomId, ticket: String, user_type: UserType) -> Option<String> {
+        let (res_tx, res_rx) = oneshot::channel();
+
+        self.cmd_tx
+            .send(Command::RedeemWsTicket { room, ticket, user_type, res_tx })
+            .unwrap();
+
+        res_rx.await.unwrap()
+    }
+
+    /// Streams the events of all rooms of a host to the sender.
+    pub async fn subscribe_host_events(&self, host: String, tx: mpsc::UnboundedSender<Msg>){
+        self.cmd_tx.send(Command::SubscribeHostEvents{host, tx}).unwrap();
+    }
+
+    /// Streams the events of every room to the sender.
+    pub async fn subscribe_all_events(&self, tx: mpsc::UnboundedSender<Msg>){
+        self.cmd_tx.send(Command::SubscribeAllEvents{tx}).unwrap();
+    }
+
+    /// Ends a room on behalf of an admin, see [`BingoServer::close_room`].
+    pub async fn close_room(&self, room: RoomId) -> Option<bool> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::CloseRoom{room, res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
+
+    pub fn apply_config(&self, overrides: ConfigOverrides){
+        self.cmd_tx.send(Command::ApplyConfig{overrides}).unwrap();
+    }
+
+    /// Requests the full list of connected clients, delivered to the host pipe.
+    pub async fn roster(&self, room: RoomId){
+        if self.shed_query(room).await{
+            return;
+        }
+        self.cmd_tx.send(Command::Roster{room}).unwrap();
+    }
+
+    /// Open host sockets of every room, most connections first.
+    pub async fn host_connections(&self) -> Vec<HostConnections> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::HostConnections{res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
+
+    /// Rooms with open connections, most players first.
+    pub async fn live_rooms(&self) -> Vec<LiveRoom> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::LiveRooms{res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
+
+    /// Searches the player connections of the host's rooms by name, `name` is matched case-insensitively.
+    /// None when the search was shed because the server is overloaded.
+    pub async fn find_players(&self, host: String, name: String) -> Option<Vec<PlayerMatch>> {
+        if self.mailbox.is_overloaded(){
+            self.mailbox.shed();
+            return None;
+        }
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::FindPlayers{host, name: name.to_lowercase(), res_tx}).unwrap();
+        Some(res_rx.await.unwrap())
+    }
+
+    /// Cards and claims of a player, `None` when the query was shed because the server is overloaded.
+    pub async fn player_cards(&self, room: RoomId, player_token: String) -> Option<Option<PlayerCards>> {
+        if self.mailbox.is_overloaded(){
+            self.mailbox.shed();
+            return None;
         }
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::PlayerCards{room, player_token, res_tx}).unwrap();
+        Some(res_rx.await.unwrap())
     }
-}
 
-/// A command received by the [`ChatServer`].
-#[derive(Debug)]
-enum Command {
-    Create{
-        host: String,
-        res_tx: tokio::sync::oneshot::Sender<RoomCreds>,
-    },
+    /// Deals the player a card unless it holds one, the inner `None` when it has no open socket in the room.
+    /// `None` when the request was shed because the server is overloaded.
+    pub async fn player_card(&self, room: RoomId, player_token: String) -> Option<Option<Result<PlayerCard, String>>> {
+        if self.mailbox.is_overloaded(){
+            self.mailbox.shed();
+            return None;
+        }
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::PlayerCard{room, player_token, res_tx}).unwrap();
+        Some(res_rx.await.unwrap())
+    }
 
-    RoomExists{
-        room_id: RoomId,
-        res_tx: tokio::sync::oneshot::Sender<bool>,
-    },
+    pub async fn attendance(&self, room: RoomId, host: String) -> Option<Vec<AttendanceRow>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::Attendance{room, host, res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-    RoomHostAuth{
-        room_id: RoomId,
-        host_token: String,
-        res_tx: tokio::sync::oneshot::Sender<bool>,
-    },
+    pub async fn list_rooms(&self, host: String) -> Vec<RoomOverview> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::ListRooms{host, res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-    Connect {
-        room: RoomId,
-        conn_tx: mpsc::UnboundedSender<Msg>,
-        res_tx: tokio::sync::oneshot::Sender<ConnId>,
-        user_type: ConnId,
-    },
+    pub async fn export_state(&self) -> Vec<RoomSnapshot> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::ExportState{res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-    Disconnect {
-        room: RoomId,
-        conn: ConnId,
-        user_type: ConnId,
-    },
+    /// Loads exported rooms, returns how many were imported.
+    pub async fn import_state(&self, rooms: Vec<RoomSnapshot>) -> usize {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::ImportState{rooms, res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-    Update{
-        room: RoomId,
-        msg: String,
-        user_type: ConnId,
-    },
+    /// Stops or resumes accepting new rooms and joins.
+    pub async fn set_draining(&self, draining: bool){
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::SetDraining{draining, res_tx}).unwrap();
+        let _ = res_rx.await;
+    }
 
-    Send{
-        room: RoomId,
-        conn: ConnId,
-        msg: String,
+    pub async fn drain_status(&self) -> DrainStatus {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::DrainStatus{res_tx}).unwrap();
+        res_rx.await.unwrap()
     }
-}
 
+    pub async fn is_draining(&self) -> bool {
+        self.drain_status().await.draining
+    }
 
-#[derive(Debug)]
-struct Room{
-    id: RoomId,
-    host: String,
-    host_token: String,
-    host_pipe: mpsc::UnboundedSender<Msg>,
-    /// Map of connection IDs to their message receivers.
-    sessions: HashMap<ConnId, mpsc::UnboundedSender<Msg>>,
-}
+    /// Records the app version and platform a client reported.
+    pub async fn client_info(&self, room: RoomId, conn: SessionId, info: ClientInfo, policy: VersionPolicy){
+        self.cmd_tx.send(Command::ClientInfo{room, conn, info, policy}).unwrap();
+    }
 
-impl Room{
-    pub fn new(host: String) -> Self {
-        let id = rng().random::<RoomId>();
-        let sessions = HashMap::new();
-        //Generated HOST ID has a 256 bit length UUID
-        let host_token = rng().random::<[u8; 32]>().to_vec().iter().map(|x| format!("{:02x}", x)).collect::<String>();
+    pub async fn set_phase(&self, room: RoomId, phase: RoomPhase){
+        self.cmd_tx.send(Command::SetPhase{room, phase}).unwrap();
+    }
 
-        Self{
-            id,
-            host,
-            host_token,
-            host_pipe: mpsc::unbounded_channel().0,
-            sessions,
-        }
+    pub async fn tag_connection(&self, room: RoomId, conn: SessionId, note: ConnectionNote){
+        self.cmd_tx.send(Command::TagConnection{room, conn, note}).unwrap();
     }
 
-    pub fn create_from_entry(host: String, id: RoomId, host_token: String) -> Self {
-        let sessions = HashMap::new();
-        Self{
-            id,
-            host,
-            host_token,
-            host_pipe: mpsc::unbounded_channel().0,
-            sessions,
-        }
+    /// Injects faults into a room for testing, see [`ChaosSettings`], `None` when the room doesn't exist.
+    pub async fn set_chaos(&self, room: RoomId, request: ChaosRequest, by_admin: bool) -> Option<Result<ChaosResult, String>> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::SetChaos{room, request, by_admin, res_tx}).unwrap();
+        res_rx.await.unwrap()
     }
 
-    pub async fn add_client(&mut self, tx: mpsc::UnboundedSender<Msg>, user_type: ConnId) -> ConnId {
+    pub async fn set_allowed_origins(&self, room: RoomId, origins: AllowedOrigins){
+        self.cmd_tx.send(Command::SetAllowedOrigins{room, origins}).unwrap();
+    }
 
-        if user_type == USER_HOST
-        {
-            self.host_pipe = tx;
-            return 0;
-        }
-        // register session with random connection ID
-        let id = rng().random::<ConnId>();
-        tracing::info!("Adding client {} to room {}", id, self.id);
-        self.sessions.insert(id, tx);
+    /// Checks the Origin header of a joining player against the room's allowed origins.
+    pub async fn origin_allowed(&self, room: RoomId, origin: Option<String>) -> bool {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::OriginAllowed{room, origin, res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-        id
+    pub async fn set_seat_map(&self, room: RoomId, seat_map: SeatMap){
+        self.cmd_tx.send(Command::SetSeatMap{room, seat_map}).unwrap();
     }
 
-    pub async fn remove_client(&mut self, conn_id: ConnId, user_type: ConnId){
-        if user_type == USER_HOST
-        {
-            self.host_pipe = mpsc::unbounded_channel().0;
-            return;
-        }
-        tracing::info!("Removing client {} from room {}", conn_id, self.id);
-        self.sessions.remove(&conn_id);
+    pub async fn take_seat(&self, room: RoomId, conn: SessionId, seat: Seat, by_host: bool){
+        self.cmd_tx.send(Command::TakeSeat{room, conn, seat, by_host}).unwrap();
     }
 
-    pub async fn broadcast(&self, msg: &str, user_type: ConnId){
-        if user_type == USER_CLIENT
-        {
-            let _ = self.host_pipe.send(msg.to_owned());
+    /// Sends the client versions of the room to the host.
+    pub async fn client_versions(&self, room: RoomId){
+        if self.shed_query(room).await{
             return;
         }
-        for tx in self.sessions.values(){
-            let _ = tx.send(msg.to_owned());
-        }
+        self.cmd_tx.send(Command::ClientVersions{room}).unwrap();
     }
 
-    pub async fn send(&self, conn_id: ConnId, msg: &str){
-        let tx = self.sessions.get(&conn_id);
-        if tx.is_none(){
-            return;
-        }
-        let _ = tx.unwrap().send(msg.to_owned());
+    /// Sends `force_refresh` to the room, or every room when unset.
+    pub async fn force_refresh(&self, room: Option<RoomId>, request: ForceRefreshRequest) -> ForceRefreshResult {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::ForceRefresh{room, request, res_tx}).unwrap();
+        res_rx.await.unwrap()
     }
-}
 
+    pub async fn all_client_versions(&self) -> Vec<RoomClientVersions> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::AllClientVersions{res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-#[derive(Debug)]
-pub struct BingoServer {
+    /// Stores the player's name and address through the background writer.
+    pub async fn record_player(&self, room: RoomId, token: String, display_name: Option<String>, ip: Option<String>){
+        self.cmd_tx.send(Command::RecordPlayer{room, token, display_name, ip}).unwrap();
+    }
 
-    /// Map of room name to participant IDs in that room.
-    rooms: HashMap<RoomId, Room>,
+    /// Drops the host's rooms from memory, resolves once they are gone.
+    pub async fn remove_host_rooms(&self, host: String){
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::RemoveHostRooms{host, res_tx}).unwrap();
+        let _ = res_rx.await;
+    }
 
-    /// Command receiver.
-    cmd_rx: mpsc::UnboundedReceiver<Command>,
+    pub async fn start_sound_check(&self, room: RoomId, request: SoundCheckRequest){
+        self.cmd_tx.send(Command::StartSoundCheck{room, request}).unwrap();
+    }
 
-    /// Postgres database pool
-    database: sqlx::PgPool,
-}
+    pub async fn sound_check_ack(&self, room: RoomId, conn: SessionId, id: u32){
+        self.cmd_tx.send(Command::SoundCheckAck{room, conn, id}).unwrap();
+    }
 
-impl BingoServer{
-    pub fn new(database: sqlx::PgPool) -> (Self, BingoServerHandle){
-        let rooms = HashMap::with_capacity(0);
-        let (cmd_tx, cmd_rx) = mpsc::unbounded_channel();
-        (
-            Self{
-                rooms,
-                cmd_rx,
-                database,
-            },
-            BingoServerHandle{
-                cmd_tx: cmd_tx.clone(),
-            }
-        )
+    pub async fn offer_transfer(&self, room: RoomId, host_token: String, from: String, to: String) -> Result<TransferOffer, TransferError> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::OfferTransfer{room, host_token, from, to, res_tx}).unwrap();
+        res_rx.await.unwrap()
     }
 
-    pub async fn populate_rooms(&mut self){
+    pub async fn accept_transfer(&self, room: RoomId, user: String) -> Result<RoomCreds, TransferError> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::AcceptTransfer{room, user, res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-        let result = sqlx::query_as::<_, RoomCreds>("SELECT * FROM rooms")
-        .fetch_all(&self.database)
-        .await;
+    pub async fn verify_claim_code(&self, room: RoomId, code: String){
+        self.cmd_tx.send(Command::VerifyClaimCode{room, code}).unwrap();
+    }
 
-        match result {
-            Ok(rows) =>
-            {
-                for row in rows
-                {
-                    let room = Room::create_from_entry(row.host, row.id, row.token);
-                    self.rooms.insert(row.id, room);
-                }
-            }
-            Err(e) => {
-                log::error!("Failed to load rooms from database: {}", e)
-            },
-        }
+    pub async fn set_email_settings(&self, room: RoomId, settings: EmailSettings){
+        self.cmd_tx.send(Command::SetEmailSettings{room, settings}).unwrap();
+    }
 
+    pub async fn set_player_email(&self, room: RoomId, conn: SessionId, email: Option<String>){
+        self.cmd_tx.send(Command::SetPlayerEmail{room, conn, email}).unwrap();
     }
 
-    pub async fn create_room(&mut self, host: String) -> RoomCreds {
+    /// Decides whether a second socket of a player replaces the first or mirrors it.
+    pub async fn set_duplicate_session_policy(&self, room: RoomId, policy: DuplicateSessionPolicy){
+        self.cmd_tx.send(Command::SetDuplicateSessionPolicy{room, policy}).unwrap();
+    }
 
-        //CHeck if host is already created a room in the database look up using the host
-        let result = sqlx::query_as::<_, RoomCreds>("SELECT * FROM rooms WHERE host = $1")
-            .bind(host.clone())
-            .fetch_optional(&self.database)
-            .await;
+    pub async fn set_countdown(&self, room: RoomId, starts_at: Option<StartTime>, time_zone: String){
+        self.cmd_tx.send(Command::SetCountdown{room, starts_at, time_zone}).unwrap();
+    }
 
-        match result {
-            Ok(room) => {
-                if room.is_some(){
-                    return room.unwrap();
-                }
-            }
-            Err(e) => log::error!("Failed to look up room for host {}: {}", host, e),
-        }
+    /// Decides whether speed rounds and the auto-caller keep calling while the room has no host socket open.
+    pub async fn set_host_offline_policy(&self, room: RoomId, policy: HostOfflinePolicy){
+        self.cmd_tx.send(Command::SetHostOfflinePolicy{room, policy}).unwrap();
+    }
 
+    /// Limits the players of a room, `None` lets everyone in.
+    pub async fn set_player_limit(&self, room: RoomId, limit: Option<PlayerLimit>){
+        self.cmd_tx.send(Command::SetPlayerLimit{room, limit}).unwrap();
+    }
 
-        // Check if rooms contains a room with the same host
-        for room in self.rooms.values(){
-            if room.host == host{
-                return RoomCreds::new(room.id, host, room.host_token.clone());
-            }
-        }
+    pub async fn cancel_transfer(&self, room: RoomId, user: String) -> Result<(), TransferError> {
+        let (res_tx, res_rx) = oneshot::channel();
+        self.cmd_tx.send(Command::CancelTransfer{room, user, res_tx}).unwrap();
+        res_rx.await.unwrap()
+    }
 
-        let room= Room::new(host.clone());
-        let room_id = room.id;
-        let room_token = room.host_token.clone();
-        self.rooms.insert(room_id, room);
+    pub async fn schedule_announcement(&self, room: RoomId, request: ScheduleAnnouncementRequest){
+        self.cmd_tx.send(Command::ScheduleAnnouncement{room, request}).unwrap();
+    }
 
-        //Insert room creds into the rooms table
-        let result = sqlx::query("INSERT INTO rooms (id, host, token) VALUES ($1, $2, $3)")
-            .bind(room_id)
-            .bind(host.clone())
-            .bind(room_token.clone())
-            .execute(&self.database)
-            .await;
+    pub async fn cancel_announcement(&self, room: RoomId, id: Uuid){
+        self.cmd_tx.send(Command::CancelAnnouncement{room, id}).unwrap();
+    }
 
-        match result {
-            Ok(_) => log::info!("Added room {} to database", room_id),
-            Err(e) => log::error!("Failed to add room {} to database: {}", room_id, e),
-        }
+    pub async fn list_announcements(&self, room: RoomId){
+        self.cmd_tx.send(Command::ListAnnouncements{room}).unwrap();
+    }
 
-        RoomCreds::new(room_id, host, room_token)
+    /// Limits the broadcasts a client receives, confirmed with a `subscribed` message.
+    pub async fn subscribe(&self, room: RoomId, conn: SessionId, channels: Vec<Channel>){
+        self.cmd_tx.send(Command::Subscribe{room, conn, channels}).unwrap();
     }
+}
 
-    pub async fn room_exists(&self, room_id: RoomId) -> bool {
-        self.rooms.contains_key(&room_id)
+#[cfg(test)]
+impl BingoServerHandle{
+    /// Runs a room server on a database that can't be reached, for tests that go through the real socket
+    /// handlers. Rooms are played from memory, their writes fail in the background.
+    pub fn without_database() -> (Self, sqlx::PgPool) {
+        let config = crate::config::Config::load(&Default::default()).unwrap();
+        let database = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(100))
+            .connect_lazy("postgres://localhost:1/bingoserver")
+            .unwrap();
+        let persistence = Persistence::start(database.clone(), config.persistence);
+        let (server, handle) = BingoServer::new(database.clone(), config.rooms, persistence, Keyring::default(), None, None, None, None, None, None);
+        tokio::spawn(server.run());
+        (handle, database)
     }
+}
 
-    pub async fn has_room_host_privileges(&self, room_id: RoomId, host_token: String) -> bool {
-        let room = self.rooms.get(&room_id);
-        match room {
-            None => {
-                log::error!("Room {} not found", room_id);
-                return false;}
-            Some(room) => {
-                let result = room.host_token == host_token;
-                if !result{
-                    log::error!("Host token mismatch for room {}", room_id);
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::waitlist::ADMITTED_PREFIX;
+
+    fn test_room() -> Room {
+        Room::create_from_entry("host".to_owned(), 1, generate_token(), room_date(), generate_token(), GameVariant::default())
+    }
+
+    /// Times the real fan-out of `Room::broadcast`, the criterion bench in `benches` only covers the channels.
+    /// Run with `cargo test --release broadcast_fan_out -- --ignored --nocapture`.
+    #[tokio::test]
+    #[ignore]
+    async fn broadcast_fan_out(){
+        const DRAW: &str = r#"{"type":"draw","number":42,"call":17,"duplicate":false,"bonus":false}"#;
+        const ITERATIONS: u32 = 100;
+        for count in [100, 1_000, 10_000]{
+            let mut room = test_room();
+            let mut receivers = Vec::with_capacity(count);
+            for _ in 0..count{
+                let (tx, rx) = mpsc::unbounded_channel();
+                room.add_client(tx, UserType::Client, None).await.unwrap();
+                receivers.push(rx);
+            }
+            for rx in &mut receivers{
+                while rx.try_recv().is_ok() {}
+            }
+
+            let started = Instant::now();
+            for _ in 0..ITERATIONS{
+                room.broadcast(DRAW, UserType::Host).await;
+                for rx in &mut receivers{
+                    while rx.try_recv().is_ok() {}
                 }
-                return result;
             }
+            let elapsed = started.elapsed() / ITERATIONS;
+            println!("{:>6} sessions: {:?} per broadcast, {:?} per session", count, elapsed, elapsed / count as u32);
         }
     }
 
-    pub async fn add_client(&mut self, room_id: RoomId, tx: mpsc::UnboundedSender<Msg>, user_type: ConnId) -> ConnId {
-        self.rooms.get_mut(&room_id).unwrap().add_client(tx, user_type).await
+    #[tokio::test]
+    async fn connections_get_distinct_ids(){
+        let mut room = test_room();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let host = room.add_client(tx.clone(), UserType::Host, None).await.unwrap();
+        let board = room.add_client(tx.clone(), UserType::Board, None).await.unwrap();
+        let mut ids = vec![host, board];
+        for _ in 0..1_000{
+            ids.push(room.add_client(tx.clone(), UserType::Client, None).await.unwrap());
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 1_002);
+        assert_eq!(room.sessions.len(), 1_000);
     }
 
-    pub async fn remove_client(&mut self, room_id: RoomId, conn_id: ConnId, user_type: ConnId){
-        self.rooms.get_mut(&room_id).unwrap().remove_client(conn_id, user_type).await;
+    #[tokio::test]
+    async fn new_connection_does_not_inherit_cards_of_a_departed_one(){
+        let mut room = test_room();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let departed = room.add_client(tx.clone(), UserType::Client, None).await.unwrap();
+        room.cards.insert(departed, vec![Card::generate(1, &[], GameVariant::Ball75)]);
+        room.remove_client(departed, UserType::Client, DisconnectReason::ClientClose).await;
+
+        let mut restored = Room::from_snapshot(room.snapshot());
+        let id = restored.add_client(tx, UserType::Client, None).await.unwrap();
+        assert_ne!(id, departed);
+        assert!(!restored.cards.contains_key(&id));
     }
 
-    pub async fn broadcast(&self, room_id: RoomId, msg: &str, user_type: ConnId){
-        self.rooms.get(&room_id).unwrap().broadcast(msg, user_type).await;
+    #[tokio::test]
+    async fn snapshot_without_counter_continues_after_known_ids(){
+        let mut snapshot = test_room().snapshot();
+        snapshot.next_conn_id = SessionId::default();
+        snapshot.cards = vec![(SessionId(41), Vec::new())];
+        let mut room = Room::from_snapshot(snapshot);
+        let (tx, _rx) = mpsc::unbounded_channel();
+        assert_eq!(room.add_client(tx, UserType::Client, None).await.unwrap(), SessionId(42));
     }
 
-    pub async fn send(&self, room_id: RoomId, conn_id: ConnId, msg: &str){
-        self.rooms.get(&room_id).unwrap().send(conn_id, msg).await;
+    #[test]
+    fn wrapped_counter_skips_ids_in_use(){
+        let mut room = test_room();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        room.next_conn_id = SessionId(u32::MAX);
+        room.sessions.insert(SessionId(u32::MAX), tx.clone());
+        room.host_pipes.insert(SessionId(0), HostConnection{ tx, connected_at: Instant::now() });
+        room.seats.insert(SessionId(1), Seat{ table: "A".to_owned(), seat: 1 });
+        assert_eq!(room.allocate_conn_id(), SessionId(2));
     }
 
-    pub async fn run(mut self) -> io::Result<()> {
-        while let Some(cmd) = self.cmd_rx.recv().await {
-            match cmd {
-                Command::Create { host, res_tx } => {
-                    let creds = self.create_room(host).await;
-                    let _ = res_tx.send(creds);
-                }
+    #[tokio::test]
+    async fn waiting_player_is_admitted_when_a_slot_frees_up(){
+        let mut room = test_room();
+        room.set_player_limit(Some(PlayerLimit{ max_players: 1, waitlist: true })).await;
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let first = room.add_client(tx.clone(), UserType::Client, None).await.unwrap();
+        let (waiting_tx, mut waiting_rx) = mpsc::unbounded_channel();
+        let Err(ConnectError::Waitlisted(waiting)) = room.add_client(waiting_tx, UserType::Client, None).await else {
+            panic!("the second player should wait");
+        };
+        assert!(!room.sessions.contains_key(&waiting));
+        assert!(waiting_rx.recv().await.unwrap().starts_with(r#"{"type":"waitlisted""#));
 
-                Command::RoomExists { room_id, res_tx } => {
-                    let exists = self.room_exists(room_id).await;
-                    let _ = res_tx.send(exists);
-                }
+        room.remove_client(first, UserType::Client, DisconnectReason::ClientClose).await;
+        assert!(room.sessions.contains_key(&waiting));
+        assert!(room.waitlist.is_empty());
+        assert!(waiting_rx.recv().await.unwrap().starts_with(ADMITTED_PREFIX));
+    }
 
-                Command::RoomHostAuth { room_id, host_token, res_tx } => {
-                    let has_privileges = self.has_room_host_privileges(room_id, host_token).await;
-                    let _ = res_tx.send(has_privileges);
-                }
+    #[tokio::test]
+    async fn second_tab_mirrors_the_cards_of_the_first(){
+        let mut room = test_room();
+        room.duplicate_session_policy = DuplicateSessionPolicy::Mirror;
+        room.set_player_limit(Some(PlayerLimit{ max_players: 1, waitlist: false })).await;
+        let player = PlayerIdentity{ token: "a".repeat(32), name: None };
+        let (first_tx, mut first_rx) = mpsc::unbounded_channel();
+        let first = room.add_client(first_tx, UserType::Client, Some(player.clone())).await.unwrap();
+        room.cards.insert(first, vec![Card::generate(1, &[], GameVariant::Ball75)]);
 
-                Command::Connect { room, conn_tx, res_tx, user_type } => {
-                    let conn_id = self.add_client(room, conn_tx, user_type).await;
-                    let _ = res_tx.send(conn_id);
-                }
+        let (second_tx, mut second_rx) = mpsc::unbounded_channel();
+        let second = room.add_client(second_tx, UserType::Client, Some(player)).await.unwrap();
+        assert_eq!(room.mirrors.get(&second), Some(&first));
+        assert_eq!(room.devices(first), 2);
+        assert_eq!(room.players_joined, 1);
+        assert!(second_rx.recv().await.unwrap().starts_with(r#"{"type":"session_mirrored""#));
+        assert!(second_rx.recv().await.unwrap().starts_with(r#"{"type":"card""#));
 
-                Command::Disconnect { room, conn, user_type } => {
-                    self.remove_client(room, conn, user_type).await;
-                }
+        room.send(first, "{}").await;
+        assert_eq!(&*first_rx.recv().await.unwrap(), "{}");
+        assert_eq!(&*second_rx.recv().await.unwrap(), "{}");
 
-                Command::Update { room, msg, user_type } => {
-                    self.broadcast(room, &msg, user_type).await;
-                }
+        room.remove_client(second, UserType::Client, DisconnectReason::ClientClose).await;
+        assert!(room.mirrors.is_empty());
+        assert_eq!(room.devices(first), 1);
+    }
 
-                Command::Send { room, conn, msg } => {
-                    self.send(room, conn, &msg).await;
-                }
-            }
-        }
+    #[tokio::test]
+    async fn taken_over_session_keeps_its_cards(){
+        let mut room = test_room();
+        let player = PlayerIdentity{ token: "e".repeat(32), name: None };
+        let (first_tx, _first_rx) = mpsc::unbounded_channel();
+        let first = room.add_client(first_tx, UserType::Client, Some(player.clone())).await.unwrap();
+        room.cards.insert(first, vec![Card::generate(1, &[], GameVariant::Ball75)]);
 
-        Ok(())
+        let (second_tx, mut second_rx) = mpsc::unbounded_channel();
+        let second = room.add_client(second_tx, UserType::Client, Some(player)).await.unwrap();
+        assert!(!room.sessions.contains_key(&first));
+        assert!(!room.cards.contains_key(&first));
+        assert_eq!(room.cards.get(&second).map(Vec::len), Some(1));
+        let mut messages = Vec::new();
+        while let Ok(msg) = second_rx.try_recv(){
+            messages.push(msg);
+        }
+        assert!(messages.iter().any(|msg| msg.starts_with(r#"{"type":"card""#)));
     }
-}
-
 
-#[derive(Debug, Clone)]
-pub struct BingoServerHandle {
-    cmd_tx: mpsc::UnboundedSender<Command>,
-}
+    #[tokio::test]
+    async fn departed_players_are_bounded(){
+        let mut room = test_room();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let kicked = room.add_client(tx.clone(), UserType::Client, Some(PlayerIdentity{ token: "k".repeat(32), name: None })).await.unwrap();
+        room.remove_client(kicked, UserType::Client, DisconnectReason::Kicked).await;
+        assert!(!room.players.contains_key(&kicked));
 
-impl BingoServerHandle {
-    pub async fn create_room(&self, host: String) -> RoomCreds {
-        let (res_tx, res_rx) = oneshot::channel();
+        let holder = room.add_client(tx.clone(), UserType::Client, Some(PlayerIdentity{ token: "h".repeat(32), name: None })).await.unwrap();
+        room.cards.insert(holder, vec![Card::generate(1, &[], GameVariant::Ball75)]);
+        room.remove_client(holder, UserType::Client, DisconnectReason::ClientClose).await;
+        for index in 0..MAX_DEPARTED_PLAYERS + 10{
+            let id = room.add_client(tx.clone(), UserType::Client, Some(PlayerIdentity{ token: format!("{:032}", index), name: None })).await.unwrap();
+            room.remove_client(id, UserType::Client, DisconnectReason::ClientClose).await;
+        }
+        assert_eq!(room.players.len(), MAX_DEPARTED_PLAYERS);
+        assert!(room.players.contains_key(&holder));
+    }
 
-        self.cmd_tx
-            .send(Command::Create { host, res_tx })
-            .unwrap();
+    #[tokio::test]
+    async fn timed_out_player_resumes_under_the_same_id(){
+        let mut room = test_room();
+        let player = PlayerIdentity{ token: "b".repeat(32), name: None };
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let id = room.add_client(tx, UserType::Client, Some(player.clone())).await.unwrap();
+        room.cards.insert(id, vec![Card::generate(1, &[], GameVariant::Ball75)]);
+        assert!(room.suspend_client(id).is_some());
+        assert!(!room.sessions.contains_key(&id));
 
-        res_rx.await.unwrap()
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        assert_eq!(room.add_client(tx, UserType::Client, Some(player)).await.unwrap(), id);
+        assert!(room.suspended.is_empty());
+        assert_eq!(room.players_joined, 1);
+        assert!(rx.recv().await.unwrap().starts_with(r#"{"type":"resumed""#));
+        rx.recv().await.unwrap();
+        assert!(rx.recv().await.unwrap().starts_with(r#"{"type":"card""#));
     }
 
-    pub async fn room_exists(&self, room_id: RoomId) -> bool {
-        let (res_tx, res_rx) = oneshot::channel();
-
-        self.cmd_tx
-            .send(Command::RoomExists { room_id, res_tx })
-            .unwrap();
+    #[tokio::test]
+    async fn suspended_players_keep_their_slot(){
+        let mut room = test_room();
+        room.duplicate_session_policy = DuplicateSessionPolicy::Mirror;
+        room.set_player_limit(Some(PlayerLimit{ max_players: 1, waitlist: false })).await;
+        let player = PlayerIdentity{ token: "d".repeat(32), name: None };
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let first = room.add_client(tx.clone(), UserType::Client, Some(player.clone())).await.unwrap();
+        let second = room.add_client(tx.clone(), UserType::Client, Some(player)).await.unwrap();
+        assert!(room.suspend_client(first).is_some());
+        assert!(room.suspend_client(second).is_some());
+        assert!(room.mirrors.is_empty());
+        assert_eq!(room.player_count(), 1);
+        assert!(matches!(room.add_client(tx.clone(), UserType::Client, None).await, Err(ConnectError::RoomFull(1))));
 
-        res_rx.await.unwrap()
+        room.resume_client(second, tx).await;
+        assert_eq!(room.mirrors.get(&second), Some(&first));
+        assert_eq!(room.player_count(), 1);
     }
 
-    pub async fn has_room_host_privileges(&self, room_id: RoomId, host_token: String) -> bool {
-        let (res_tx, res_rx) = oneshot::channel();
+    #[tokio::test]
+    async fn imported_player_reconnects_to_its_cards(){
+        let mut room = test_room();
+        let player = PlayerIdentity{ token: "c".repeat(32), name: None };
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let id = room.add_client(tx.clone(), UserType::Client, Some(player.clone())).await.unwrap();
+        room.cards.insert(id, vec![Card::generate(1, &[], GameVariant::Ball75)]);
+        room.add_client(tx, UserType::Client, None).await.unwrap();
 
-        self.cmd_tx
-            .send(Command::RoomHostAuth { room_id, host_token, res_tx })
-            .unwrap();
+        let mut restored = Room::from_snapshot(room.snapshot());
+        assert_eq!(restored.suspended.len(), 1);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        assert_eq!(restored.add_client(tx, UserType::Client, Some(player)).await.unwrap(), id);
+        assert!(rx.recv().await.unwrap().starts_with(r#"{"type":"resumed""#));
+        rx.recv().await.unwrap();
+        assert!(rx.recv().await.unwrap().starts_with(r#"{"type":"card""#));
+    }
 
-        res_rx.await.unwrap()
+    #[test]
+    fn round_timers_survive_a_handoff(){
+        let mut room = test_room();
+        let settings = RoundSettings{ max_duration_secs: Some(600), ..RoundSettings::default() };
+        room.round = Some(Round::new(1, settings, GameVariant::Ball75));
+        room.auto_caller = Some(AutoCaller{ interval: Duration::from_secs(5), paused: true, generation: 3 });
+
+        let restored = Room::from_snapshot(room.snapshot());
+        assert_eq!(restored.round.unwrap().deadline, room.round.unwrap().deadline);
+        let caller = restored.auto_caller.unwrap();
+        assert_eq!(caller.interval, Duration::from_secs(5));
+        assert!(caller.paused);
     }
 
-    pub async fn connect(&self, room: RoomId, conn_tx: mpsc::UnboundedSender<Msg>, user_type: ConnId ) -> ConnId {
-        let (res_tx, res_rx) = oneshot::channel();
+    #[test]
+    fn inconsistent_snapshots_are_rejected(){
+        let mut room = test_room();
+        room.draws = DrawPool::restore(vec![1, 2], vec![3], Vec::new(), false);
+        room.cards.insert(SessionId(5), vec![Card::generate(1, &[1, 2, 3], GameVariant::Ball75)]);
+        assert!(room.snapshot().validate().is_ok());
 
-        self.cmd_tx
-            .send(Command::Connect { room, conn_tx, res_tx, user_type })
-            .unwrap();
+        let mut snapshot = room.snapshot();
+        snapshot.remaining.push(3);
+        assert!(snapshot.validate().is_err());
+        let mut snapshot = room.snapshot();
+        snapshot.called.push(76);
+        assert!(snapshot.validate().is_err());
+        let mut snapshot = room.snapshot();
+        snapshot.cards[0].1[0].columns[0][0] = 20;
+        assert!(snapshot.validate().is_err());
+    }
 
-        res_rx.await.unwrap()
+    #[tokio::test]
+    async fn observer_sees_host_and_player_messages(){
+        let mut room = test_room();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        room.add_client(tx, UserType::Observer, None).await.unwrap();
+        assert!(rx.recv().await.unwrap().starts_with(r#"{"type":"board""#));
+        assert_eq!(room.player_count(), 0);
+        assert!(!room.is_active());
+
+        room.send_host(r#"{"type":"chat"}"#).await;
+        room.broadcast_clients("not json");
+        assert_eq!(&*rx.recv().await.unwrap(), r#"{"type":"observed","to":"host","message":{"type":"chat"}}"#);
+        assert_eq!(&*rx.recv().await.unwrap(), r#"{"type":"observed","to":"client","message":"not json"}"#);
     }
 
-    pub async fn disconnect(&self, room: RoomId, conn: ConnId, user_type: ConnId) {
-        self.cmd_tx.send(Command::Disconnect { room, conn, user_type }).unwrap();
+    #[tokio::test]
+    async fn closed_senders_are_ghosts(){
+        let mut room = test_room();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        room.add_client(tx, UserType::Client, None).await.unwrap();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let ghost = room.add_client(tx, UserType::Client, None).await.unwrap();
+        let (tx, host_rx) = mpsc::unbounded_channel();
+        let host = room.add_client(tx, UserType::Host, None).await.unwrap();
+        assert!(room.ghosts().is_empty());
+
+        drop(rx);
+        drop(host_rx);
+        let mut ghosts = room.ghosts();
+        ghosts.sort_unstable_by_key(|(id, _)| *id);
+        assert_eq!(ghosts, vec![(ghost, UserType::Client), (host, UserType::Host)]);
     }
 
-    pub async fn update(&self, room: RoomId, msg: String, user_type: ConnId){
-        self.cmd_tx.send(Command::Update{room, msg, user_type}).unwrap();
+    #[test]
+    fn call_times_follow_the_calls(){
+        let mut room = test_room();
+        room.draws.call(7);
+        room.draws.call(12);
+        room.draws.undo_last();
+        assert_eq!(room.draws.call_times().len(), 1);
+
+        let mut snapshot = room.snapshot();
+        let called_at = snapshot.called_at.clone();
+        assert_eq!(Room::from_snapshot(room.snapshot()).draws.call_times(), called_at);
+        snapshot.called_at.clear();
+        assert_eq!(Room::from_snapshot(snapshot).draws.call_times().len(), 1);
     }
 
-    pub async fn send(&self, room: RoomId, conn: ConnId, msg: String){
-        self.cmd_tx.send(Command::Send{room, conn, msg}).unwrap();
+    #[test]
+    fn dealt_cards_stay_within_the_limit(){
+        let mut room = test_room();
+        room.card_settings.max_cards_per_player = 1;
+        assert!(room.deal_card(SessionId(5)).unwrap().starts_with(r#"{"type":"card""#));
+        assert_eq!(room.deal_card(SessionId(5)), Err("Card limit of 1 reached".to_owned()));
+        assert_eq!(room.cards[&SessionId(5)].len(), 1);
     }
+
 }
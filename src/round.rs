@@ -0,0 +1,424 @@
+use std::{collections::HashSet, time::Duration};
+
+use chrono::{DateTime, Utc};
+
+use crate::card::{Card, CardId, Pattern};
+use crate::draw::Number;
+use crate::fairness::FairSeed;
+use crate::room::SessionId;
+use crate::variant::GameVariant;
+
+pub type RoundId = u32;
+
+/// Settings supplied by the host when starting a round.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct RoundSettings{
+    /// Maximum duration of the round in seconds, the round never expires when unset.
+    pub max_duration_secs: Option<u64>,
+    /// Number of winners after which the round ends automatically, unlimited when unset.
+    pub max_winners: Option<usize>,
+    /// Commit to a seed at the start of the round and reveal it at the end so the calls can be verified.
+    #[serde(default)]
+    pub provably_fair: bool,
+    /// Secondary prizes played alongside the main game, e.g. first four corners and first blackout.
+    #[serde(default)]
+    pub prizes: Vec<Pattern>,
+    /// Verified wins within this many calls also win the progressive jackpot.
+    pub jackpot_calls: Option<usize>,
+    /// Bonus balls, a win completed on one of them earns the bonus prize.
+    #[serde(default)]
+    pub bonus_numbers: Vec<Number>,
+    /// Seconds claims are collected after the first verified one, the next call closes the window early.
+    pub claim_window_secs: Option<u64>,
+    /// Numbers drawn this round, every number when empty, e.g. only 1 to 50 for a speed game.
+    #[serde(default)]
+    pub pool: Vec<Number>,
+    /// Numbers left out of the pool.
+    #[serde(default)]
+    pub excluded: Vec<Number>,
+    /// Speed mode, the server calls a number every this many milliseconds, daubs and verifies the cards itself
+    /// and ends the round on the first win.
+    pub speed_call_ms: Option<u64>,
+}
+
+/// Smallest pool a round can be played with, enough to complete a line.
+const MIN_POOL_SIZE: usize = 5;
+
+/// Call intervals of speed rounds in milliseconds.
+const SPEED_CALL_MS: std::ops::Range<u64> = 500..5_000;
+
+impl RoundSettings{
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.max_duration_secs.filter(|secs| *secs > 0).map(Duration::from_secs)
+    }
+
+    pub fn claim_window(&self) -> Option<Duration> {
+        self.claim_window_secs.filter(|secs| *secs > 0).map(Duration::from_secs)
+    }
+
+    pub fn speed_interval(&self) -> Option<Duration> {
+        self.speed_call_ms.map(Duration::from_millis)
+    }
+
+    /// Numbers in play this round in ascending order, the pool without the excluded numbers.
+    pub fn numbers(&self, variant: GameVariant) -> Vec<Number> {
+        let mut numbers: Vec<Number> = if self.pool.is_empty() { variant.numbers().collect() } else { self.pool.clone() };
+        numbers.retain(|number| !self.excluded.contains(number));
+        numbers.sort_unstable();
+        numbers.dedup();
+        numbers
+    }
+
+    /// True when the round draws from fewer than all numbers.
+    pub fn has_custom_pool(&self, variant: GameVariant) -> bool {
+        self.numbers(variant).len() < variant.ball_count() as usize
+    }
+
+    /// Rejects pools with unknown numbers or too few numbers left to win with, prizes the variant doesn't play
+    /// and speed settings out of range.
+    pub fn validate(&self, variant: GameVariant) -> Result<(), String> {
+        if let Some(number) = self.pool.iter().chain(&self.excluded).find(|number| !variant.numbers().contains(*number)){
+            return Err(format!("Number {} is out of range", number));
+        }
+        if let Some(pattern) = self.prizes.iter().find(|pattern| !variant.supports(**pattern)){
+            return Err(format!("The {} prize isn't played in {} games", pattern.name(), variant.name()));
+        }
+        if self.numbers(variant).len() < MIN_POOL_SIZE{
+            return Err(format!("The number pool needs at least {} numbers", MIN_POOL_SIZE));
+        }
+        if let Some(speed_call_ms) = self.speed_call_ms{
+            if !SPEED_CALL_MS.contains(&speed_call_ms){
+                return Err(format!("Speed rounds call every {} to {} milliseconds", SPEED_CALL_MS.start, SPEED_CALL_MS.end - 1));
+            }
+            if self.claim_window().is_some(){
+                return Err("Speed rounds end on the first win and cannot have a claim window".to_owned());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundEndReason{
+    Host,
+    Timeout,
+    MaxWinners,
+    /// A speed round ended on its first win.
+    FirstWin,
+    /// A speed round called every number without a win.
+    NumbersExhausted,
+}
+
+/// Secondary prize of a round, awarded to the first claim completing its pattern.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Prize{
+    pub pattern: Pattern,
+    pub winner: Option<SessionId>,
+    pub card_id: Option<CardId>,
+}
+
+/// Verified claims collected while a claim window is open, they become winners together when it closes.
+#[derive(Debug)]
+pub struct ClaimWindow{
+    /// Guards the timer of an earlier window from closing a later one.
+    pub id: u32,
+    pub claims: Vec<(SessionId, CardId)>,
+}
+
+#[derive(Debug)]
+pub struct Round{
+    pub id: RoundId,
+    pub settings: RoundSettings,
+    pub winners: Vec<SessionId>,
+    /// Seed the calls are derived from in provably fair rounds.
+    pub fair_seed: Option<FairSeed>,
+    pub prizes: Vec<Prize>,
+    pub jackpot_winners: Vec<SessionId>,
+    pub bonus_winners: Vec<SessionId>,
+    pub claim_window: Option<ClaimWindow>,
+    pub claim_windows_opened: u32,
+    /// When the round ends by itself, unset without a maximum duration.
+    pub deadline: Option<DateTime<Utc>>,
+    /// Set once the calls passed half of the pool, the milestone is only sent once a round.
+    pub halfway_reached: bool,
+}
+
+/// What a verified claim did to the claim window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollectedClaim{
+    /// The claim opened the window with this id.
+    Opened(u32),
+    /// The claim joined the open window.
+    Joined,
+    /// The player already won or already has a claim in the open window.
+    Repeated,
+}
+
+impl Round{
+    pub fn new(id: RoundId, mut settings: RoundSettings, variant: GameVariant) -> Self {
+        let numbers = settings.numbers(variant);
+        settings.bonus_numbers.retain(|number| numbers.contains(number));
+        // Each pattern is one prize, however often the host listed it
+        let mut seen = HashSet::new();
+        let mut patterns = settings.prizes.clone();
+        patterns.retain(|pattern| seen.insert(*pattern));
+        Self{
+            id,
+            deadline: settings.max_duration().and_then(|duration| chrono::Duration::from_std(duration).ok()).map(|duration| Utc::now() + duration),
+            fair_seed: settings.provably_fair.then(FairSeed::generate),
+            prizes: patterns.into_iter().map(|pattern| Prize{ pattern, winner: None, card_id: None }).collect(),
+            settings,
+            winners: Vec::new(),
+            jackpot_winners: Vec::new(),
+            bonus_winners: Vec::new(),
+            claim_window: None,
+            claim_windows_opened: 0,
+            halfway_reached: false,
+        }
+    }
+
+    /// Adds a verified claim to the open window, or opens a window with it.
+    pub fn collect_claim(&mut self, conn_id: SessionId, card_id: CardId) -> CollectedClaim {
+        if self.winners.contains(&conn_id) || self.claim_window.as_ref().is_some_and(|window| window.claims.iter().any(|(claimant, _)| *claimant == conn_id)){
+            return CollectedClaim::Repeated;
+        }
+        if let Some(window) = &mut self.claim_window{
+            window.claims.push((conn_id, card_id));
+            return CollectedClaim::Joined;
+        }
+        self.claim_windows_opened += 1;
+        self.claim_window = Some(ClaimWindow{ id: self.claim_windows_opened, claims: vec![(conn_id, card_id)] });
+        CollectedClaim::Opened(self.claim_windows_opened)
+    }
+
+    /// Winners including the claims of a window that is still open.
+    pub fn all_winners(&self) -> Vec<SessionId> {
+        let mut winners = self.winners.clone();
+        for (conn_id, _) in self.claim_window.iter().flat_map(|window| &window.claims){
+            if !winners.contains(conn_id){
+                winners.push(*conn_id);
+            }
+        }
+        winners
+    }
+
+    pub fn is_bonus(&self, number: Number) -> bool {
+        self.settings.bonus_numbers.contains(&number)
+    }
+
+    /// Records a verified win, returns true when one of the cards was completed by a bonus ball. Only the
+    /// call that completed the card counts, not whatever was called before the claim arrived.
+    pub fn award_bonus(&mut self, conn_id: SessionId, cards: &[Card], called: &[Number]) -> bool {
+        if !cards.iter().filter_map(|card| card.winning_number(called)).any(|number| self.is_bonus(number)){
+            return false;
+        }
+        if !self.bonus_winners.contains(&conn_id){
+            self.bonus_winners.push(conn_id);
+        }
+        true
+    }
+
+    /// True when a verified win after this many calls is within the jackpot limit.
+    pub fn is_jackpot(&self, calls: usize) -> bool {
+        self.settings.jackpot_calls.is_some_and(|limit| calls <= limit)
+    }
+
+    /// Records a verified win, returns true when it also won the jackpot.
+    pub fn award_jackpot(&mut self, conn_id: SessionId, calls: usize) -> bool {
+        if !self.is_jackpot(calls){
+            return false;
+        }
+        if !self.jackpot_winners.contains(&conn_id){
+            self.jackpot_winners.push(conn_id);
+        }
+        true
+    }
+
+    /// Awards every open prize one of the cards completes, independently of the main game.
+    pub fn award_prizes(&mut self, conn_id: SessionId, cards: &[Card], called: &[Number]) -> Vec<Prize> {
+        let mut awarded = Vec::new();
+        for prize in self.prizes.iter_mut().filter(|prize| prize.winner.is_none()){
+            if let Some(card) = cards.iter().find(|card| card.has_pattern(prize.pattern, called)){
+                prize.winner = Some(conn_id);
+                prize.card_id = Some(card.id);
+                awarded.push(prize.clone());
+            }
+        }
+        awarded
+    }
+
+    /// Records a winner, returns true once the configured number of winners has been reached.
+    pub fn add_winner(&mut self, conn_id: SessionId) -> bool {
+        if !self.winners.contains(&conn_id){
+            self.winners.push(conn_id);
+        }
+        match self.settings.max_winners {
+            Some(max) => max > 0 && self.winners.len() >= max,
+            None => false,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RoundStartedMessage{
+    r#type: String,
+    round: RoundId,
+    max_duration_secs: Option<u64>,
+    max_winners: Option<usize>,
+    /// SHA-256 of the seed in provably fair rounds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed_commitment: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    prizes: Vec<Pattern>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    jackpot_calls: Option<usize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bonus_numbers: Vec<Number>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    claim_window_secs: Option<u64>,
+    /// Numbers in play when the round uses a custom pool.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pool: Vec<Number>,
+    /// Milliseconds between server calls in speed rounds, chat is off until the round ends.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed_call_ms: Option<u64>,
+}
+
+impl RoundStartedMessage{
+    pub fn new(round: &Round, variant: GameVariant) -> Self {
+        Self{
+            r#type: "round_started".to_string(),
+            round: round.id,
+            max_duration_secs: round.settings.max_duration_secs,
+            max_winners: round.settings.max_winners,
+            seed_commitment: round.fair_seed.as_ref().map(FairSeed::commitment),
+            prizes: round.prizes.iter().map(|prize| prize.pattern).collect(),
+            jackpot_calls: round.settings.jackpot_calls,
+            bonus_numbers: round.settings.bonus_numbers.clone(),
+            claim_window_secs: round.settings.claim_window().map(|window| window.as_secs()),
+            pool: if round.settings.has_custom_pool(variant) { round.settings.numbers(variant) } else { Vec::new() },
+            speed_call_ms: round.settings.speed_call_ms,
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct RoundEndedMessage{
+    r#type: String,
+    round: RoundId,
+    reason: RoundEndReason,
+    winners: Vec<SessionId>,
+    /// Revealed seed of provably fair rounds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    prizes: Vec<Prize>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    jackpot_winners: Vec<SessionId>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bonus_winners: Vec<SessionId>,
+}
+
+impl RoundEndedMessage{
+    pub fn new(round: &Round, reason: RoundEndReason) -> Self {
+        Self{
+            r#type: "round_ended".to_string(),
+            round: round.id,
+            reason,
+            winners: round.winners.clone(),
+            seed: round.fair_seed.as_ref().map(|seed| seed.seed().to_owned()),
+            prizes: round.prizes.clone(),
+            jackpot_winners: round.jackpot_winners.clone(),
+            bonus_winners: round.bonus_winners.clone(),
+        }
+    }
+}
+
+/// Announces that claims are being collected so simultaneous winners across the hall are treated fairly.
+#[derive(serde::Serialize)]
+pub struct ClaimWindowOpenedMessage{
+    r#type: String,
+    round: RoundId,
+    closes_in_secs: u64,
+}
+
+impl ClaimWindowOpenedMessage{
+    pub fn new(round: RoundId, closes_in: Duration) -> Self {
+        Self{
+            r#type: "claim_window_opened".to_string(),
+            round,
+            closes_in_secs: closes_in.as_secs(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct WindowWinner{
+    client_id: SessionId,
+    card_id: CardId,
+}
+
+/// Every verified claim of the window, all of them are winners of the round.
+#[derive(serde::Serialize)]
+pub struct ClaimWindowClosedMessage{
+    r#type: String,
+    round: RoundId,
+    winners: Vec<WindowWinner>,
+}
+
+impl ClaimWindowClosedMessage{
+    pub fn new(round: RoundId, window: &ClaimWindow) -> Self {
+        Self{
+            r#type: "claim_window_closed".to_string(),
+            round,
+            winners: window.claims.iter().map(|(client_id, card_id)| WindowWinner{ client_id: *client_id, card_id: *card_id }).collect(),
+        }
+    }
+}
+
+/// Announces a secondary prize to the whole room.
+#[derive(serde::Serialize)]
+pub struct PrizeWonMessage{
+    r#type: String,
+    round: RoundId,
+    pattern: Pattern,
+    client_id: Option<SessionId>,
+    card_id: Option<CardId>,
+}
+
+impl PrizeWonMessage{
+    pub fn new(round: RoundId, prize: &Prize) -> Self {
+        Self{
+            r#type: "prize_won".to_string(),
+            round,
+            pattern: prize.pattern,
+            client_id: prize.winner,
+            card_id: prize.card_id,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn repeated_prizes_are_played_once(){
+        let settings = RoundSettings{ prizes: vec![Pattern::Line, Pattern::FourCorners, Pattern::Line], ..RoundSettings::default() };
+        let round = Round::new(1, settings, GameVariant::Ball75);
+        let patterns: Vec<Pattern> = round.prizes.iter().map(|prize| prize.pattern).collect();
+        assert_eq!(patterns, vec![Pattern::Line, Pattern::FourCorners]);
+    }
+
+    #[test]
+    fn repeated_claims_join_the_window_once(){
+        let settings = RoundSettings{ claim_window_secs: Some(3), ..RoundSettings::default() };
+        let mut round = Round::new(1, settings, GameVariant::Ball75);
+        let (first, second): (SessionId, SessionId) = (SessionId::new(1), SessionId::new(2));
+        assert_eq!(round.collect_claim(first, 1), CollectedClaim::Opened(1));
+        assert_eq!(round.collect_claim(first, 1), CollectedClaim::Repeated);
+        assert_eq!(round.collect_claim(second, 2), CollectedClaim::Joined);
+        assert_eq!(round.claim_window.as_ref().unwrap().claims.len(), 2);
+    }
+}
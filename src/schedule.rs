@@ -0,0 +1,277 @@
+use actix_web::{delete, error, get, post, web, HttpResponse};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use sqlx::types::Uuid;
+
+use crate::api_keys::{verify_api_key, HostIdentity, Scope};
+use crate::timezone::{is_valid_zone_name, StartTime, ZonedTime};
+
+const MAX_TITLE_LENGTH: usize = 120;
+const MAX_TEXT_LENGTH: usize = 1000;
+
+/// Longest game a host can schedule.
+const MAX_DURATION_MINS: i32 = 24 * 60;
+
+/// Past games stay in the feed this long so calendars don't drop them right away.
+const FEED_HISTORY_DAYS: i32 = 30;
+
+/// Lines of an iCalendar file are folded at 75 octets.
+const ICS_LINE_LENGTH: usize = 75;
+
+#[derive(Deserialize)]
+struct ScheduleRoomRequest{
+    title: String,
+    #[serde(default)]
+    description: Option<String>,
+    /// Venue or link shown in calendars.
+    #[serde(default)]
+    location: Option<String>,
+    /// Without an offset the time is read as wall-clock time in `time_zone`.
+    starts_at: StartTime,
+    /// IANA name of the zone the game is played in.
+    #[serde(default = "default_zone")]
+    time_zone: String,
+    #[serde(default = "default_duration_mins")]
+    duration_mins: i32,
+}
+
+fn default_duration_mins() -> i32 {
+    120
+}
+
+fn default_zone() -> String {
+    "UTC".to_owned()
+}
+
+fn clean_text(text: &str, max_length: usize) -> String {
+    text.trim().chars().filter(|c| !c.is_control() || *c == '\n').take(max_length).collect()
+}
+
+/// An upcoming game of a host, published in its calendar feed.
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct ScheduledRoom{
+    id: Uuid,
+    title: String,
+    description: Option<String>,
+    location: Option<String>,
+    starts_at: DateTime<Utc>,
+    time_zone: String,
+    /// Offset of the zone at the start, daylight saving included, for showing the local time.
+    utc_offset_secs: i32,
+    duration_mins: i32,
+    created_at: DateTime<Utc>,
+    /// Cancelled games stay in the feed so subscribed calendars remove them.
+    cancelled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct FeedQuery{
+    /// API key with the schedule scope, for calendar apps that can't send headers.
+    key: Option<String>,
+}
+
+const SCHEDULE_COLUMNS: &str = "id, title, description, location, starts_at, time_zone, \
+    EXTRACT(EPOCH FROM (starts_at AT TIME ZONE time_zone) - (starts_at AT TIME ZONE 'UTC'))::INTEGER AS utc_offset_secs, \
+    duration_mins, created_at, cancelled_at";
+
+async fn load_schedule(database: &sqlx::PgPool, host: &str) -> actix_web::Result<Vec<ScheduledRoom>> {
+    sqlx::query_as(&format!("SELECT {} FROM scheduled_rooms WHERE host = $1 AND starts_at > now() - make_interval(days => $2) ORDER BY starts_at", SCHEDULE_COLUMNS))
+        .bind(host)
+        .bind(FEED_HISTORY_DAYS)
+        .fetch_all(database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load the schedule of {}: {}", host, e);
+            error::ErrorInternalServerError("Failed to load schedule")
+        })
+}
+
+fn ics_time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+/// Appends a content line, folded without splitting a character.
+fn push_line(ics: &mut String, line: &str){
+    let mut length = 0;
+    for c in line.chars(){
+        if length + c.len_utf8() > ICS_LINE_LENGTH{
+            ics.push_str("\r\n ");
+            length = 1;
+        }
+        ics.push(c);
+        length += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+fn to_ics(host: &str, rooms: &[ScheduledRoom]) -> String {
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//bingoserver//schedule//EN");
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", ics_escape(&format!("Bingo with {}", host))));
+    for room in rooms{
+        push_line(&mut ics, "BEGIN:VEVENT");
+        push_line(&mut ics, &format!("UID:{}@bingoserver", room.id));
+        push_line(&mut ics, &format!("DTSTAMP:{}", ics_time(room.cancelled_at.unwrap_or(room.created_at))));
+        push_line(&mut ics, &format!("DTSTART:{}", ics_time(room.starts_at)));
+        push_line(&mut ics, &format!("DTEND:{}", ics_time(room.starts_at + Duration::minutes(room.duration_mins.into()))));
+        push_line(&mut ics, &format!("SUMMARY:{}", ics_escape(&room.title)));
+        // Calendars show the start in the zone of the subscriber, the description keeps the venue's clock
+        let local = ZonedTime::new(room.starts_at, room.time_zone.clone(), room.utc_offset_secs);
+        let description = match &room.description {
+            Some(description) => format!("Starts {}\n\n{}", local.display, description),
+            None => format!("Starts {}", local.display),
+        };
+        push_line(&mut ics, &format!("DESCRIPTION:{}", ics_escape(&description)));
+        if let Some(location) = &room.location{
+            push_line(&mut ics, &format!("LOCATION:{}", ics_escape(location)));
+        }
+        push_line(&mut ics, if room.cancelled_at.is_some() { "STATUS:CANCELLED" } else { "STATUS:CONFIRMED" });
+        push_line(&mut ics, "END:VEVENT");
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+#[post("/host/schedule")]
+async fn schedule_room(
+    user: HostIdentity,
+    request: web::Json<ScheduleRoomRequest>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    let title = clean_text(&request.title, MAX_TITLE_LENGTH);
+    if title.is_empty(){
+        return Err(error::ErrorBadRequest("Title is required"));
+    }
+    if !is_valid_zone_name(&request.time_zone){
+        return Err(error::ErrorBadRequest(format!("Unknown time zone {}", request.time_zone)));
+    }
+    let start = ZonedTime::resolve(request.starts_at, &request.time_zone)
+        .ok_or_else(|| error::ErrorBadRequest(format!("Unknown time zone {}", request.time_zone)))?;
+    if start.starts_at <= Utc::now(){
+        return Err(error::ErrorBadRequest("Scheduled rooms must start in the future"));
+    }
+    if request.duration_mins <= 0 || request.duration_mins > MAX_DURATION_MINS{
+        return Err(error::ErrorBadRequest(format!("Duration must be 1 to {} minutes", MAX_DURATION_MINS)));
+    }
+    let description = request.description.as_deref().map(|text| clean_text(text, MAX_TEXT_LENGTH)).filter(|text| !text.is_empty());
+    let location = request.location.as_deref().map(|text| clean_text(text, MAX_TEXT_LENGTH)).filter(|text| !text.is_empty());
+
+    let room: ScheduledRoom = sqlx::query_as(&format!("INSERT INTO scheduled_rooms (id, host, title, description, location, starts_at, time_zone, duration_mins) \
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING {}", SCHEDULE_COLUMNS))
+        .bind(Uuid::new_v4())
+        .bind(&user.username)
+        .bind(&title)
+        .bind(description)
+        .bind(location)
+        .bind(start.starts_at)
+        .bind(&start.time_zone)
+        .bind(request.duration_mins)
+        .fetch_one(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to schedule a room for {}: {}", user.username, e);
+            error::ErrorInternalServerError("Failed to schedule room")
+        })?;
+
+    log::info!("{} scheduled {} at {}", user.username, room.id, start.display);
+    Ok(HttpResponse::Created().json(room))
+}
+
+#[get("/host/schedule")]
+async fn list_schedule(
+    user: HostIdentity,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+    Ok(HttpResponse::Ok().json(load_schedule(&database, &user.username).await?))
+}
+
+/// Cancels a scheduled room, it stays in the feed marked as cancelled.
+#[delete("/host/schedule/{id}")]
+async fn cancel_scheduled_room(
+    user: HostIdentity,
+    path: web::Path<(Uuid,)>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    let result = sqlx::query("UPDATE scheduled_rooms SET cancelled_at = now() WHERE id = $1 AND host = $2 AND cancelled_at IS NULL")
+        .bind(path.0)
+        .bind(&user.username)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to cancel scheduled room {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to cancel scheduled room")
+        })?;
+
+    if result.rows_affected() == 0{
+        return Err(error::ErrorNotFound("Scheduled room not found"));
+    }
+    log::info!("{} cancelled scheduled room {}", user.username, path.0);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// iCalendar feed of the host's scheduled rooms. Venues subscribe with a `schedule` scoped key in the
+/// `key` query parameter, which can't be used for anything else.
+#[get("/host/schedule.ics")]
+async fn schedule_feed(
+    user: Option<HostIdentity>,
+    query: web::Query<FeedQuery>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let host = match (user, &query.key) {
+        (Some(user), _) => {
+            user.require_scope(Scope::Schedule)?;
+            user.username
+        }
+        (None, Some(key)) => match verify_api_key(&database, key).await {
+            Some((username, scopes)) if scopes.contains(&Scope::Schedule) => username,
+            Some(_) => return Err(error::ErrorForbidden("API key lacks the schedule scope")),
+            None => return Err(error::ErrorUnauthorized("Invalid API key")),
+        },
+        (None, None) => return Err(error::ErrorUnauthorized("Login required using /host endpoint or an API key")),
+    };
+
+    let rooms = load_schedule(&database, &host).await?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(to_ics(&host, &rooms)))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn feed_escapes_and_folds_lines(){
+        let starts_at = DateTime::parse_from_rfc3339("2026-03-01T19:00:00Z").unwrap().with_timezone(&Utc);
+        let room = ScheduledRoom{
+            id: Uuid::nil(),
+            title: "Bingo; prizes, drinks".to_owned(),
+            description: Some("é".repeat(60)),
+            location: None,
+            starts_at,
+            time_zone: "Europe/Berlin".to_owned(),
+            utc_offset_secs: 3600,
+            duration_mins: 90,
+            created_at: starts_at,
+            cancelled_at: None,
+        };
+        let ics = to_ics("hall", &[room]);
+        assert!(ics.contains("SUMMARY:Bingo\\; prizes\\, drinks\r\n"));
+        assert!(ics.contains("DTEND:20260301T203000Z\r\n"));
+        assert!(ics.contains("DESCRIPTION:Starts Sun 1 Mar 2026\\, 20:00 Europe/Berlin (UTC+01:00)\\n\\n"));
+        assert!(ics.lines().all(|line| line.trim_end_matches('\r').len() <= ICS_LINE_LENGTH));
+        assert!(ics.contains("\r\n é"));
+    }
+}
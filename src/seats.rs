@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::room::SessionId;
+
+/// Most tables in a seat map.
+pub const MAX_TABLES: usize = 200;
+
+pub const MAX_SEATS_PER_TABLE: u32 = 50;
+
+const MAX_TABLE_NAME_LENGTH: usize = 40;
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Table{
+    pub name: String,
+    pub seats: u32,
+}
+
+/// Tables of the hall, defined by the host.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct SeatMap{
+    pub tables: Vec<Table>,
+}
+
+impl SeatMap{
+    /// Checks the limits, table names must be unique.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tables.len() > MAX_TABLES{
+            return Err(format!("At most {} tables are allowed", MAX_TABLES));
+        }
+        for (index, table) in self.tables.iter().enumerate(){
+            if table.name.trim().is_empty() || table.name.len() > MAX_TABLE_NAME_LENGTH{
+                return Err(format!("Table names must be 1 to {} bytes", MAX_TABLE_NAME_LENGTH));
+            }
+            if table.seats == 0 || table.seats > MAX_SEATS_PER_TABLE{
+                return Err(format!("Table {} must have 1 to {} seats", table.name, MAX_SEATS_PER_TABLE));
+            }
+            if self.tables[..index].iter().any(|other| other.name == table.name){
+                return Err(format!("Table {} is listed twice", table.name));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, seat: &Seat) -> bool {
+        self.tables.iter().any(|table| table.name == seat.table && (1..=table.seats).contains(&seat.seat))
+    }
+}
+
+/// Seat number at a table, starting at 1.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct Seat{
+    pub table: String,
+    pub seat: u32,
+}
+
+/// Seated connections per table, for the roster and the report.
+pub fn group_by_table<'a>(seats: impl Iterator<Item = (&'a SessionId, &'a Seat)>) -> BTreeMap<String, Vec<SessionId>> {
+    let mut tables: BTreeMap<String, Vec<SessionId>> = BTreeMap::new();
+    for (conn_id, seat) in seats {
+        tables.entry(seat.table.clone()).or_default().push(*conn_id);
+    }
+    for conn_ids in tables.values_mut() {
+        conn_ids.sort_unstable();
+    }
+    tables
+}
+
+/// Connection holding the seat, if any.
+pub fn seat_holder(seats: &HashMap<SessionId, Seat>, seat: &Seat) -> Option<SessionId> {
+    seats.iter().find(|(_, held)| *held == seat).map(|(conn_id, _)| *conn_id)
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct AssignSeatRequest{
+    pub client_id: SessionId,
+    #[serde(flatten)]
+    pub seat: Seat,
+}
+
+/// Sent to every connection when the host changes the seat map.
+#[derive(serde::Serialize)]
+pub struct SeatMapMessage<'a>{
+    r#type: String,
+    #[serde(flatten)]
+    seat_map: &'a SeatMap,
+}
+
+impl<'a> SeatMapMessage<'a>{
+    pub fn new(seat_map: &'a SeatMap) -> Self {
+        Self{
+            r#type: "seat_map".to_string(),
+            seat_map,
+        }
+    }
+}
+
+/// Seat taken by a client, sent to the client and the hosts.
+#[derive(serde::Serialize)]
+pub struct SeatMessage<'a>{
+    r#type: String,
+    client_id: SessionId,
+    #[serde(flatten)]
+    seat: &'a Seat,
+}
+
+impl<'a> SeatMessage<'a>{
+    pub fn new(client_id: SessionId, seat: &'a Seat) -> Self {
+        Self{
+            r#type: "seat".to_string(),
+            client_id,
+            seat,
+        }
+    }
+}
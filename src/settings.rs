@@ -0,0 +1,100 @@
+use crate::card::CardSettings;
+
+/// A settings change from a host, `version` is the settings version the host last saw.
+/// Changes without a version are applied unconditionally, like before versioning existed.
+#[derive(serde::Deserialize)]
+pub struct Versioned<T>{
+    #[serde(flatten)]
+    pub update: T,
+    pub version: Option<u64>,
+}
+
+/// Host editable settings of a room, sent to the hosts after every change and on `get_settings`.
+#[derive(serde::Serialize)]
+pub struct SettingsMessage<'a>{
+    r#type: String,
+    version: u64,
+    card_settings: &'a CardSettings,
+    locale: Option<&'a str>,
+    sms_recipients: &'a [String],
+    milestones: bool,
+}
+
+impl<'a> SettingsMessage<'a>{
+    pub fn new(version: u64, card_settings: &'a CardSettings, locale: Option<&'a str>, sms_recipients: &'a [String], milestones: bool) -> Self {
+        Self{
+            r#type: "settings".to_string(),
+            version,
+            card_settings,
+            locale,
+            sms_recipients,
+            milestones,
+        }
+    }
+}
+
+/// Rejects a change made against outdated settings, e.g. when another co-host changed them first.
+#[derive(serde::Serialize)]
+pub struct SettingsConflictMessage{
+    r#type: String,
+    expected_version: u64,
+    current_version: u64,
+}
+
+impl SettingsConflictMessage{
+    pub fn new(expected_version: u64, current_version: u64) -> Self {
+        Self{
+            r#type: "settings_conflict".to_string(),
+            expected_version,
+            current_version,
+        }
+    }
+}
+
+/// Player visible part of a settings change, hosts get the full settings instead.
+#[derive(Default, serde::Serialize)]
+pub struct SettingsDelta{
+    #[serde(skip_serializing_if = "Option::is_none")]
+    card_settings: Option<CardSettings>,
+    /// `Some(None)` when translation was turned off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    locale: Option<Option<String>>,
+}
+
+impl SettingsDelta{
+    pub fn card_settings(settings: CardSettings) -> Self {
+        Self{
+            card_settings: Some(settings),
+            ..Self::default()
+        }
+    }
+
+    pub fn locale(locale: Option<String>) -> Self {
+        Self{
+            locale: Some(locale),
+            ..Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.card_settings.is_none() && self.locale.is_none()
+    }
+}
+
+/// Tells every connection which settings changed mid-game, so clients don't have to reconnect.
+#[derive(serde::Serialize)]
+pub struct SettingsChangedMessage{
+    r#type: String,
+    version: u64,
+    changes: SettingsDelta,
+}
+
+impl SettingsChangedMessage{
+    pub fn new(version: u64, changes: SettingsDelta) -> Self {
+        Self{
+            r#type: "settings_changed".to_string(),
+            version,
+            changes,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::{collections::HashMap, fmt, sync::{Arc, Mutex}, time::Duration};
+
+use chrono::{NaiveDate, Utc};
+use rand::{rng, Rng as _};
+
+use crate::accessibility::describe;
+use crate::variant::GameVariant;
+
+const TWILIO_API: &str = "https://api.twilio.com/2010-04-01/Accounts";
+
+/// Twilio requests are given up after this long, so a slow API can't pile up sending tasks.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest SMS list a host can configure, every call is one message per recipient.
+pub const MAX_RECIPIENTS: usize = 50;
+
+/// Twilio credentials, the bridge is disabled unless `TWILIO_ACCOUNT_SID`, `TWILIO_AUTH_TOKEN` and `TWILIO_FROM_NUMBER` are set.
+#[derive(Clone)]
+pub struct SmsConfig{
+    pub account_sid: String,
+    pub auth_token: String,
+    /// Twilio number or messaging service the announcements are sent from.
+    pub from: String,
+    /// Texts a host may send per day, including opt-in codes. Announcements stop once it is used up.
+    pub daily_budget: u32,
+}
+
+impl fmt::Debug for SmsConfig{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SmsConfig").field("account_sid", &self.account_sid).field("from", &self.from).field("daily_budget", &self.daily_budget).finish()
+    }
+}
+
+/// Code a recipient gets when a host adds their number, the host confirms it to start the announcements.
+pub fn generate_opt_in_code() -> String {
+    format!("{:06}", rng().random_range(0..1_000_000))
+}
+
+/// Numbers that were sent an opt-in code and are only announced to once the host confirms it.
+#[derive(serde::Serialize)]
+pub struct SmsOptInPendingMessage<'a>{
+    r#type: String,
+    numbers: Vec<&'a str>,
+}
+
+impl<'a> SmsOptInPendingMessage<'a>{
+    pub fn new(numbers: Vec<&'a str>) -> Self {
+        Self{
+            r#type: "sms_opt_in_pending".to_string(),
+            numbers,
+        }
+    }
+}
+
+/// Phone numbers in E.164 format, e.g. `+15551234567`.
+pub fn is_valid_phone_number(number: &str) -> bool {
+    number.strip_prefix('+')
+        .is_some_and(|digits| (8..=15).contains(&digits.len()) && digits.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Sends calls and winner announcements to the SMS list of a room, for players without a smartphone.
+#[derive(Debug, Clone)]
+pub struct SmsBridge{
+    config: SmsConfig,
+    client: reqwest::Client,
+    /// Texts of each host on the day they were sent.
+    spent: Arc<Mutex<HashMap<String, (NaiveDate, u32)>>>,
+}
+
+impl SmsBridge{
+    pub fn new(config: SmsConfig) -> Self {
+        Self{
+            config,
+            // unwrap: only fails when the TLS backend can't be initialized
+            client: reqwest::Client::builder().timeout(SEND_TIMEOUT).build().unwrap(),
+            spent: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Takes `messages` from the host's budget of the day, false when they don't fit.
+    fn spend(&self, host: &str, messages: usize) -> bool {
+        let today = Utc::now().date_naive();
+        let mut spent = self.spent.lock().unwrap();
+        let (day, count) = spent.entry(host.to_owned()).or_insert((today, 0));
+        if *day != today{
+            *day = today;
+            *count = 0;
+        }
+        match u32::try_from(messages).ok().and_then(|messages| count.checked_add(messages)) {
+            Some(total) if total <= self.config.daily_budget => {
+                *count = total;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Sends the plain description of an event of a room played with `variant` to every recipient, sending
+    /// happens in the background. Nothing is sent once the host's budget of the day is used up.
+    pub fn announce(&self, host: &str, recipients: &[String], event: &str, variant: GameVariant){
+        if recipients.is_empty(){
+            return;
+        }
+        let body = match describe(event, variant) {
+            Some(body) => body,
+            None => return,
+        };
+        if !self.spend(host, recipients.len()){
+            log::warn!("SMS budget of host {} is used up, skipped an announcement", host);
+            return;
+        }
+        for to in recipients{
+            self.deliver(to.clone(), body.clone());
+        }
+    }
+
+    /// Sends the opt-in code to a number the host added, false when the host's budget is used up.
+    pub fn request_opt_in(&self, host: &str, to: &str, code: &str) -> bool {
+        if !self.spend(host, 1){
+            return false;
+        }
+        let digits = code.chars().map(String::from).collect::<Vec<_>>().join(" ");
+        let body = format!("A bingo host would like to send you the calls of their game. To agree, give them the code {}. Otherwise ignore this message.", digits);
+        self.deliver(to.to_owned(), body);
+        true
+    }
+
+    fn deliver(&self, to: String, body: String){
+        let bridge = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = bridge.send(&to, &body).await{
+                log::warn!("Failed to send SMS announcement: {}", e);
+            }
+        });
+    }
+
+    async fn send(&self, to: &str, body: &str) -> anyhow::Result<()> {
+        self.post("Messages.json", &[("To", to), ("From", self.config.from.as_str()), ("Body", body)]).await
+    }
+
+    async fn post(&self, resource: &str, form: &[(&str, &str)]) -> anyhow::Result<()> {
+        let url = format!("{}/{}/{}", TWILIO_API, self.config.account_sid, resource);
+        let response = self.client.post(url)
+            .basic_auth(&self.config.account_sid, Some(&self.config.auth_token))
+            .form(form)
+            .send()
+            .await?;
+        if !response.status().is_success(){
+            anyhow::bail!("Twilio returned {}", response.status());
+        }
+        Ok(())
+    }
+}
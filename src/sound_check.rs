@@ -0,0 +1,164 @@
+use std::{collections::BTreeMap, time::{Duration, Instant}};
+
+use crate::room::SessionId;
+
+const DEFAULT_TIMEOUT_MS: u64 = 5_000;
+
+const MIN_TIMEOUT_MS: u64 = 500;
+
+const MAX_TIMEOUT_MS: u64 = 30_000;
+
+const DEFAULT_PAYLOAD_BYTES: usize = 1024;
+
+/// Largest diagnostic payload, well below the frame limits of the client sockets.
+const MAX_PAYLOAD_BYTES: usize = 16 * 1024;
+
+/// Host request to broadcast a diagnostic payload and collect the acknowledgements of the clients.
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct SoundCheckRequest{
+    /// How long to wait for acknowledgements.
+    pub timeout_ms: Option<u64>,
+    /// Size of the filler sent to every client, to exercise the data path like a full card would.
+    pub payload_bytes: Option<usize>,
+}
+
+impl SoundCheckRequest{
+    pub fn validate(&self) -> Result<(), String> {
+        if !(MIN_TIMEOUT_MS..=MAX_TIMEOUT_MS).contains(&self.timeout()){
+            return Err(format!("Sound checks wait {} to {} milliseconds", MIN_TIMEOUT_MS, MAX_TIMEOUT_MS));
+        }
+        if self.payload_bytes() > MAX_PAYLOAD_BYTES{
+            return Err(format!("Sound check payloads are at most {} bytes", MAX_PAYLOAD_BYTES));
+        }
+        Ok(())
+    }
+
+    fn timeout(&self) -> u64 {
+        self.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)
+    }
+
+    pub fn timeout_duration(&self) -> Duration {
+        Duration::from_millis(self.timeout())
+    }
+
+    fn payload_bytes(&self) -> usize {
+        self.payload_bytes.unwrap_or(DEFAULT_PAYLOAD_BYTES)
+    }
+}
+
+/// A diagnostic broadcast waiting for the acknowledgements of the clients it was sent to.
+#[derive(Debug)]
+pub struct SoundCheck{
+    pub id: u32,
+    started: Instant,
+    /// Round trip of every client sent the payload, unset until it acknowledges.
+    rtts: BTreeMap<SessionId, Option<Duration>>,
+}
+
+impl SoundCheck{
+    pub fn new(id: u32, recipients: impl Iterator<Item = SessionId>) -> Self {
+        Self{
+            id,
+            started: Instant::now(),
+            rtts: recipients.map(|conn_id| (conn_id, None)).collect(),
+        }
+    }
+
+    /// Records the round trip of a client, false for unknown, repeated or stale acknowledgements.
+    pub fn acknowledge(&mut self, conn_id: SessionId, id: u32) -> bool {
+        if id != self.id{
+            return false;
+        }
+        match self.rtts.get_mut(&conn_id) {
+            Some(rtt) if rtt.is_none() => {
+                *rtt = Some(self.started.elapsed());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.rtts.values().all(Option::is_some)
+    }
+
+    pub fn report(&self) -> SoundCheckReportMessage {
+        let mut acknowledged: Vec<u64> = self.rtts.values().flatten().map(|rtt| rtt.as_millis() as u64).collect();
+        acknowledged.sort_unstable();
+        SoundCheckReportMessage{
+            r#type: "sound_check_report".to_string(),
+            id: self.id,
+            sent: self.rtts.len(),
+            acknowledged: acknowledged.len(),
+            median_rtt_ms: acknowledged.get(acknowledged.len() / 2).copied(),
+            max_rtt_ms: acknowledged.last().copied(),
+            connections: self.rtts.iter()
+                .map(|(client_id, rtt)| ConnectionRtt{ client_id: *client_id, rtt_ms: rtt.map(|rtt| rtt.as_millis() as u64) })
+                .collect(),
+        }
+    }
+}
+
+/// Diagnostic payload, clients answer with `sound_check_ack` carrying the id.
+#[derive(serde::Serialize)]
+pub struct SoundCheckMessage{
+    r#type: String,
+    id: u32,
+    payload: String,
+}
+
+impl SoundCheckMessage{
+    pub fn new(id: u32, request: &SoundCheckRequest) -> Self {
+        Self{
+            r#type: "sound_check".to_string(),
+            id,
+            payload: "0".repeat(request.payload_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SoundCheckAck{
+    pub id: u32,
+}
+
+#[derive(serde::Serialize)]
+struct ConnectionRtt{
+    client_id: SessionId,
+    /// Unset when the client didn't acknowledge in time.
+    rtt_ms: Option<u64>,
+}
+
+/// Connectivity of every client, sent to the hosts once all clients answered or the check timed out.
+#[derive(serde::Serialize)]
+pub struct SoundCheckReportMessage{
+    r#type: String,
+    id: u32,
+    sent: usize,
+    acknowledged: usize,
+    median_rtt_ms: Option<u64>,
+    max_rtt_ms: Option<u64>,
+    connections: Vec<ConnectionRtt>,
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn counts_each_client_once(){
+        let mut check = SoundCheck::new(3, [SessionId::new(1), SessionId::new(2)].into_iter());
+        assert!(!check.acknowledge(SessionId::new(1), 2), "stale sound check");
+        assert!(!check.acknowledge(SessionId::new(9), 3), "client that wasn't sent the payload");
+        assert!(check.acknowledge(SessionId::new(1), 3));
+        assert!(!check.acknowledge(SessionId::new(1), 3), "repeated acknowledgement");
+        assert!(!check.is_complete());
+
+        let report = check.report();
+        assert_eq!((report.sent, report.acknowledged), (2, 1));
+        assert!(report.connections[1].rtt_ms.is_none());
+
+        assert!(check.acknowledge(SessionId::new(2), 3));
+        assert!(check.is_complete());
+    }
+}
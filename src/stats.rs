@@ -0,0 +1,184 @@
+use actix_web::{error, get, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::card::CardId;
+use crate::draw::Number;
+use crate::persistence::PendingWrite;
+use crate::retention::DataClass;
+use crate::room::RoomId;
+use crate::round::RoundId;
+
+/// Pattern recorded for main game wins, secondary prizes record their own pattern name.
+pub const LINE_PATTERN: &str = "line";
+
+/// Numbers listed as hot and cold.
+const HOT_COLD_COUNT: usize = 5;
+
+pub fn record_round_start(room: RoomId, host: String, round: RoundId) -> PendingWrite {
+    PendingWrite::new(
+        format!("start of round {} in room {}", round, room),
+        Box::new(move |database| {
+            let host = host.clone();
+            Box::pin(async move {
+                sqlx::query("INSERT INTO round_starts (room_id, host, round) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING")
+                    .bind(room)
+                    .bind(host)
+                    .bind(round as i32)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).classified(DataClass::Calls)
+}
+
+pub fn record_call(room: RoomId, host: String, round: RoundId, number: Number, call: usize) -> PendingWrite {
+    PendingWrite::new(
+        format!("call {} in room {}", call, room),
+        Box::new(move |database| {
+            let host = host.clone();
+            Box::pin(async move {
+                sqlx::query("INSERT INTO calls (room_id, host, round, number, call_index) VALUES ($1, $2, $3, $4, $5)")
+                    .bind(room)
+                    .bind(host)
+                    .bind(round as i32)
+                    .bind(number as i16)
+                    .bind(call as i32)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).classified(DataClass::Calls)
+}
+
+/// Removes an undone call from the statistics.
+pub fn remove_call(room: RoomId, round: RoundId, call: usize) -> PendingWrite {
+    PendingWrite::new(
+        format!("undo of call {} in room {}", call, room),
+        Box::new(move |database| Box::pin(async move {
+            sqlx::query("DELETE FROM calls WHERE room_id = $1 AND round = $2 AND call_index = $3")
+                .bind(room)
+                .bind(round as i32)
+                .bind(call as i32)
+                .execute(&database)
+                .await
+                .map(|_| ())
+        })),
+    )
+}
+
+pub fn record_win(room: RoomId, host: String, round: RoundId, pattern: &'static str, calls_to_win: usize, jackpot: bool) -> PendingWrite {
+    PendingWrite::new(
+        format!("win in round {} of room {}", round, room),
+        Box::new(move |database| {
+            let host = host.clone();
+            Box::pin(async move {
+                sqlx::query("INSERT INTO wins (room_id, host, round, pattern, calls_to_win, jackpot) VALUES ($1, $2, $3, $4, $5, $6)")
+                    .bind(room)
+                    .bind(host)
+                    .bind(round as i32)
+                    .bind(pattern)
+                    .bind(calls_to_win as i32)
+                    .bind(jackpot)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).classified(DataClass::Calls)
+}
+
+/// `card` replaced `voided_card` of a player, `round` is the round the new card is played in.
+pub fn record_trade_in(room: RoomId, host: String, round: RoundId, voided_card: CardId, card: CardId) -> PendingWrite {
+    PendingWrite::new(
+        format!("trade-in of card {} in room {}", voided_card, room),
+        Box::new(move |database| {
+            let host = host.clone();
+            Box::pin(async move {
+                sqlx::query("INSERT INTO card_trade_ins (room_id, host, round, voided_card, card) VALUES ($1, $2, $3, $4, $5)")
+                    .bind(room)
+                    .bind(host)
+                    .bind(round as i32)
+                    .bind(voided_card as i32)
+                    .bind(card as i32)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).classified(DataClass::Calls)
+}
+
+#[derive(Deserialize)]
+struct StatsQuery {
+    /// Limits the statistics to one room, all rooms of the host otherwise.
+    room: Option<RoomId>,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, async_graphql::SimpleObject)]
+pub struct NumberFrequency {
+    number: i16,
+    count: i64,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, async_graphql::SimpleObject)]
+pub struct PatternStats {
+    pattern: String,
+    wins: i64,
+    jackpots: i64,
+    average_calls_to_win: f64,
+}
+
+#[derive(serde::Serialize, async_graphql::SimpleObject)]
+pub struct StatsResult {
+    room: Option<RoomId>,
+    total_calls: i64,
+    /// Call count of every number, most frequent first.
+    frequency: Vec<NumberFrequency>,
+    hot: Vec<i16>,
+    cold: Vec<i16>,
+    patterns: Vec<PatternStats>,
+}
+
+/// Call and win statistics of a host, limited to one room when given.
+pub async fn load_stats(database: &sqlx::PgPool, host: &str, room: Option<RoomId>) -> Result<StatsResult, sqlx::Error> {
+    let frequency: Vec<NumberFrequency> = sqlx::query_as("SELECT number, COUNT(*) AS count FROM calls WHERE host = $1 AND ($2::integer IS NULL OR room_id = $2) GROUP BY number ORDER BY count DESC, number")
+        .bind(host)
+        .bind(room)
+        .fetch_all(database)
+        .await?;
+
+    let patterns: Vec<PatternStats> = sqlx::query_as("SELECT pattern, COUNT(*) AS wins, COUNT(*) FILTER (WHERE jackpot) AS jackpots, AVG(calls_to_win)::float8 AS average_calls_to_win FROM wins WHERE host = $1 AND ($2::integer IS NULL OR room_id = $2) GROUP BY pattern ORDER BY pattern")
+        .bind(host)
+        .bind(room)
+        .fetch_all(database)
+        .await?;
+
+    let hot = frequency.iter().take(HOT_COLD_COUNT).map(|entry| entry.number).collect();
+    let cold = frequency.iter().rev().take(HOT_COLD_COUNT).map(|entry| entry.number).collect();
+    Ok(StatsResult{
+        room,
+        total_calls: frequency.iter().map(|entry| entry.count).sum(),
+        frequency,
+        hot,
+        cold,
+        patterns,
+    })
+}
+
+#[get("/host/stats")]
+async fn host_stats(
+    user: HostIdentity,
+    query: web::Query<StatsQuery>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    let stats = load_stats(&database, &user.username, query.room).await.map_err(|e| {
+        log::error!("Failed to load statistics for {}: {}", user.username, e);
+        error::ErrorInternalServerError("Failed to load statistics")
+    })?;
+    Ok(HttpResponse::Ok().json(stats))
+}
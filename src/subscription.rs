@@ -0,0 +1,53 @@
+use serde::{Deserialize, Serialize};
+
+use crate::wshandler::WSMessage;
+
+/// Message categories a client can subscribe to, clients receive every channel until they subscribe.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel{
+    Draws,
+    Rounds,
+    Chat,
+    Reactions,
+    /// Everything else relayed by the host.
+    Updates,
+}
+
+impl Channel{
+    pub fn of(msg_type: &str) -> Self {
+        match msg_type {
+            "draw" => Channel::Draws,
+            "round_started" | "round_ended" | "prize_won" | "claim_window_opened" | "claim_window_closed" => Channel::Rounds,
+            "chat" => Channel::Chat,
+            "reaction" => Channel::Reactions,
+            _ => Channel::Updates,
+        }
+    }
+
+    pub fn of_message(msg: &str) -> Self {
+        serde_json::from_str::<WSMessage>(msg)
+            .map(|message| Channel::of(&message.r#type))
+            .unwrap_or(Channel::Updates)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SubscribeRequest{
+    pub channels: Vec<Channel>,
+}
+
+#[derive(Serialize)]
+pub struct SubscribedMessage{
+    r#type: String,
+    channels: Vec<Channel>,
+}
+
+impl SubscribedMessage{
+    pub fn new(channels: Vec<Channel>) -> Self {
+        Self{
+            r#type: "subscribed".to_string(),
+            channels,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+use crate::wshandler::WSMessage;
+
+/// Message types that are dropped first when a room exceeds its throughput ceiling.
+const NON_CRITICAL_TYPES: [&str; 2] = ["chat", "reaction"];
+
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Tracks the aggregate message rate of a room over one second windows.
+#[derive(Debug)]
+pub struct ThroughputLimiter{
+    /// Maximum messages per second, 0 disables the limit.
+    limit: u32,
+    window_start: Instant,
+    count: u32,
+    /// Set once the host has been alerted for the current window.
+    alerted: bool,
+}
+
+pub struct ThrottleDecision{
+    pub deliver: bool,
+    /// The host should be alerted that the room is over its ceiling.
+    pub alert: bool,
+}
+
+impl ThroughputLimiter{
+    pub fn new(limit: u32) -> Self {
+        Self{
+            limit,
+            window_start: Instant::now(),
+            count: 0,
+            alerted: false,
+        }
+    }
+
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Counts a message and decides whether it should still be delivered.
+    pub fn check(&mut self, msg: &str) -> ThrottleDecision {
+        if self.limit == 0{
+            return ThrottleDecision{ deliver: true, alert: false };
+        }
+
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= WINDOW{
+            self.window_start = now;
+            self.count = 0;
+            self.alerted = false;
+        }
+        self.count += 1;
+
+        if self.count <= self.limit{
+            return ThrottleDecision{ deliver: true, alert: false };
+        }
+
+        let alert = !self.alerted;
+        self.alerted = true;
+        ThrottleDecision{ deliver: is_critical(msg), alert }
+    }
+}
+
+fn is_critical(msg: &str) -> bool {
+    match serde_json::from_str::<WSMessage>(msg) {
+        Ok(message) => !NON_CRITICAL_TYPES.contains(&message.r#type.as_str()),
+        Err(_) => true,
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct ThrottledMessage{
+    r#type: String,
+    limit: u32,
+}
+
+impl ThrottledMessage{
+    pub fn new(limit: u32) -> Self {
+        Self{
+            r#type: "throttled".to_string(),
+            limit,
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use actix_web::{error, post, web, HttpResponse};
+use serde::Deserialize;
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::room::{BingoServerHandle, RoomId};
+
+#[derive(Deserialize)]
+struct ImportQuery {
+    room_token: String,
+    /// Whether joining the room requires one of the tickets, defaults to true.
+    require: Option<bool>,
+}
+
+#[derive(serde::Serialize)]
+struct ImportResult {
+    imported: u64,
+    require_ticket: bool,
+}
+
+/// Extracts the ticket codes from an uploaded CSV, the code is the first column and a `code` header is skipped.
+fn parse_ticket_codes(csv: &str) -> Vec<String> {
+    csv.lines()
+        .filter_map(|line| line.split(',').next())
+        .map(|code| code.trim().trim_matches('"').to_owned())
+        .filter(|code| !code.is_empty() && !code.eq_ignore_ascii_case("code"))
+        .collect()
+}
+
+#[post("/room/{room}/tickets")]
+async fn import_tickets(
+    user: HostIdentity,
+    path: web::Path<(RoomId,)>,
+    query: web::Query<ImportQuery>,
+    body: String,
+    server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Tickets)?;
+    let user_id = user.username;
+
+    if !server.has_room_host_privileges(path.0, query.room_token.clone()).await {
+        log::info!("User {} does not have host privileges for room {} or the room does not exist", user_id, path.0);
+        return Err(error::ErrorNotFound("Room not found"));
+    }
+
+    let codes = parse_ticket_codes(&body);
+    let require_ticket = query.require.unwrap_or(true);
+
+    let imported = sqlx::query("INSERT INTO tickets (room_id, code) SELECT $1, code FROM UNNEST($2::text[]) AS code ON CONFLICT DO NOTHING")
+        .bind(path.0)
+        .bind(&codes)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to import tickets for room {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to import tickets")
+        })?
+        .rows_affected();
+
+    sqlx::query("UPDATE rooms SET require_ticket = $2 WHERE id = $1")
+        .bind(path.0)
+        .bind(require_ticket)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to update ticket requirement for room {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to import tickets")
+        })?;
+
+    log::info!("Imported {} of {} tickets for room {}", imported, codes.len(), path.0);
+    Ok(HttpResponse::Ok().json(ImportResult{ imported, require_ticket }))
+}
+
+pub async fn requires_ticket(database: &sqlx::PgPool, room: RoomId) -> Result<bool, sqlx::Error> {
+    let require_ticket: Option<bool> = sqlx::query_scalar("SELECT require_ticket FROM rooms WHERE id = $1")
+        .bind(room)
+        .fetch_optional(database)
+        .await?;
+
+    Ok(require_ticket.unwrap_or(false))
+}
+
+/// Marks a ticket as used by the player, returns false when the code is unknown or was used by another
+/// player. The player who redeemed it can present it again to reconnect.
+pub async fn redeem_ticket(database: &sqlx::PgPool, room: RoomId, code: &str, player_token: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("UPDATE tickets SET used_at = COALESCE(used_at, now()), player_token = $3 \
+        WHERE room_id = $1 AND code = $2 AND (used_at IS NULL OR player_token = $3)")
+        .bind(room)
+        .bind(code)
+        .bind(player_token)
+        .execute(database)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
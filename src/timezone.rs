@@ -0,0 +1,138 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Offset as _, TimeDelta, TimeZone as _, Utc};
+use chrono_tz::Tz;
+
+/// Longest IANA zone name accepted, the real ones stay well below.
+const MAX_ZONE_LENGTH: usize = 64;
+
+/// A start time sent with an offset, or as wall-clock time in the time zone that comes with it.
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(untagged)]
+pub enum StartTime{
+    Exact(DateTime<Utc>),
+    Local(NaiveDateTime),
+}
+
+/// Cheap check before the zone is looked up.
+pub fn is_valid_zone_name(zone: &str) -> bool {
+    !zone.is_empty()
+        && zone.len() <= MAX_ZONE_LENGTH
+        && zone.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'))
+}
+
+/// A moment with the clock of the time zone a game is played in, so players in other regions see the
+/// host's time next to their own.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ZonedTime{
+    pub starts_at: DateTime<Utc>,
+    /// IANA name, e.g. `Europe/Berlin`.
+    pub time_zone: String,
+    /// Wall-clock time with the offset of the zone at that moment, e.g. `2026-03-01T19:00:00+01:00`.
+    pub local_time: String,
+    /// Ready to show, e.g. `Sun 1 Mar 2026, 19:00 Europe/Berlin (UTC+01:00)`.
+    pub display: String,
+}
+
+impl ZonedTime{
+    pub fn new(starts_at: DateTime<Utc>, time_zone: String, utc_offset_secs: i32) -> Self {
+        let offset = FixedOffset::east_opt(utc_offset_secs).unwrap_or(FixedOffset::east_opt(0).unwrap());
+        let local = starts_at.with_timezone(&offset);
+        Self{
+            starts_at,
+            local_time: local.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+            display: format!("{} {} (UTC{})", local.format("%a %-d %b %Y, %H:%M"), time_zone, local.format("%:z")),
+            time_zone,
+        }
+    }
+
+    /// Looks the zone up in the bundled IANA database, `None` for zones it doesn't know. Local times are
+    /// converted with the rules of the zone on that date, daylight saving included. A time skipped by a
+    /// clock change is moved forward by an hour, one that repeats is the earlier of the two.
+    pub fn resolve(time: StartTime, time_zone: &str) -> Option<Self> {
+        let zone: Tz = time_zone.parse().ok()?;
+        let starts_at = match time {
+            StartTime::Exact(time) => time,
+            StartTime::Local(time) => zone.from_local_datetime(&time).earliest()
+                .or_else(|| zone.from_local_datetime(&(time + TimeDelta::hours(1))).earliest())?
+                .with_timezone(&Utc),
+        };
+        let utc_offset_secs = starts_at.with_timezone(&zone).offset().fix().local_minus_utc();
+        Some(Self::new(starts_at, time_zone.to_owned(), utc_offset_secs))
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SetCountdownRequest{
+    /// Unset to stop the countdown.
+    pub starts_at: Option<StartTime>,
+    #[serde(default = "default_zone")]
+    pub time_zone: String,
+}
+
+fn default_zone() -> String {
+    "UTC".to_owned()
+}
+
+impl SetCountdownRequest{
+    pub fn validate(&self) -> Result<(), String> {
+        if !is_valid_zone_name(&self.time_zone){
+            return Err(format!("Unknown time zone {}", self.time_zone));
+        }
+        Ok(())
+    }
+}
+
+/// Start of the next game, sent to everyone in the room when the host sets it and to players joining
+/// while it runs.
+#[derive(serde::Serialize)]
+pub struct CountdownMessage<'a>{
+    r#type: String,
+    #[serde(flatten)]
+    start: Option<&'a ZonedTime>,
+    seconds_left: Option<i64>,
+}
+
+impl<'a> CountdownMessage<'a>{
+    pub fn new(start: Option<&'a ZonedTime>) -> Self {
+        Self{
+            r#type: "countdown".to_string(),
+            seconds_left: start.map(|start| (start.starts_at - Utc::now()).num_seconds().max(0)),
+            start,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn local_time_uses_the_zone_offset(){
+        let starts_at = DateTime::parse_from_rfc3339("2026-03-01T18:00:00Z").unwrap().with_timezone(&Utc);
+        let time = ZonedTime::new(starts_at, "Europe/Berlin".to_owned(), 3600);
+        assert_eq!(time.local_time, "2026-03-01T19:00:00+01:00");
+        assert_eq!(time.display, "Sun 1 Mar 2026, 19:00 Europe/Berlin (UTC+01:00)");
+
+        let time = ZonedTime::new(starts_at, "America/St_Johns".to_owned(), -12600);
+        assert_eq!(time.local_time, "2026-03-01T14:30:00-03:30");
+    }
+
+    #[test]
+    fn zones_resolve_with_their_rules_on_the_date(){
+        let summer = NaiveDateTime::parse_from_str("2026-07-01T19:00:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        let time = ZonedTime::resolve(StartTime::Local(summer), "Europe/Berlin").unwrap();
+        assert_eq!(time.starts_at.to_rfc3339(), "2026-07-01T17:00:00+00:00");
+        assert_eq!(time.local_time, "2026-07-01T19:00:00+02:00");
+
+        let skipped = NaiveDateTime::parse_from_str("2026-03-29T02:30:00", "%Y-%m-%dT%H:%M:%S").unwrap();
+        assert_eq!(ZonedTime::resolve(StartTime::Local(skipped), "Europe/Berlin").unwrap().local_time, "2026-03-29T03:30:00+02:00");
+        assert!(ZonedTime::resolve(StartTime::Local(summer), "Mars/Olympus").is_none());
+    }
+
+    #[test]
+    fn start_times_without_offset_are_local(){
+        let request: SetCountdownRequest = serde_json::from_str(r#"{ "starts_at": "2026-03-01T19:00:00", "time_zone": "Europe/Berlin" }"#).unwrap();
+        assert!(matches!(request.starts_at, Some(StartTime::Local(_))));
+        let request: SetCountdownRequest = serde_json::from_str(r#"{ "starts_at": "2026-03-01T19:00:00+01:00" }"#).unwrap();
+        assert!(matches!(request.starts_at, Some(StartTime::Exact(time)) if time.to_rfc3339() == "2026-03-01T18:00:00+00:00"));
+    }
+}
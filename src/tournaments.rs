@@ -0,0 +1,272 @@
+use actix_web::{error, get, post, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::persistence::PendingWrite;
+use crate::retention::DataClass;
+use crate::room::RoomId;
+use crate::round::RoundId;
+use crate::stats::LINE_PATTERN;
+
+pub type TournamentId = i32;
+
+/// Longest tournament name kept.
+const MAX_NAME_LENGTH: usize = 80;
+
+/// Players listed on a leaderboard unless the query asks for fewer.
+const MAX_LEADERBOARD_SIZE: i64 = 100;
+
+#[derive(Deserialize)]
+struct CreateTournamentRequest{
+    name: String,
+    /// Points for a main game win.
+    #[serde(default = "default_win_points")]
+    win_points: i32,
+    /// Points for each secondary prize.
+    #[serde(default)]
+    prize_points: i32,
+    /// Extra points for a win within the jackpot call limit.
+    #[serde(default)]
+    jackpot_points: i32,
+}
+
+fn default_win_points() -> i32 {
+    10
+}
+
+/// Tournament of a host account, the organizer that owns its rooms.
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct Tournament{
+    id: TournamentId,
+    name: String,
+    win_points: i32,
+    prize_points: i32,
+    jackpot_points: i32,
+    rooms: Vec<RoomId>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct AddRoomRequest{
+    room: RoomId,
+}
+
+#[derive(Deserialize)]
+struct LeaderboardQuery{
+    limit: Option<i64>,
+}
+
+#[derive(sqlx::FromRow, serde::Serialize)]
+struct LeaderboardEntry{
+    rank: i64,
+    /// Initials of the player's display name, the leaderboard is public so full names are never listed.
+    #[sqlx(rename = "display_name")]
+    #[serde(serialize_with = "serialize_initials")]
+    initials: Option<String>,
+    points: i64,
+    wins: i64,
+    /// Rooms of the tournament the player scored in.
+    games: i64,
+}
+
+#[derive(serde::Serialize)]
+struct Leaderboard{
+    tournament: TournamentId,
+    name: String,
+    players: Vec<LeaderboardEntry>,
+}
+
+/// Initials of a display name, e.g. `S. J.` for `Sam Jones`.
+fn initials(name: &str) -> Option<String> {
+    let initials: Vec<String> = name.split_whitespace()
+        .filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+        .map(|c| format!("{}.", c.to_uppercase()))
+        .collect();
+    (!initials.is_empty()).then(|| initials.join(" "))
+}
+
+fn serialize_initials<S: serde::Serializer>(name: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&name.as_deref().and_then(initials), serializer)
+}
+
+/// Awards tournament points for a win to the player in every tournament the room is part of.
+pub fn record_points(room: RoomId, player_token: String, round: RoundId, pattern: &'static str, jackpot: bool) -> PendingWrite {
+    PendingWrite::new(
+        format!("tournament points in round {} of room {}", round, room),
+        Box::new(move |database| {
+            let player_token = player_token.clone();
+            Box::pin(async move {
+                sqlx::query("INSERT INTO tournament_points (tournament_id, player_token, room_id, round, pattern, points) \
+                    SELECT t.id, $2, $1, $3, $4, CASE WHEN $4 = $5 THEN t.win_points ELSE t.prize_points END + CASE WHEN $6 THEN t.jackpot_points ELSE 0 END \
+                    FROM tournaments t JOIN tournament_rooms r ON r.tournament_id = t.id WHERE r.room_id = $1")
+                    .bind(room)
+                    .bind(player_token)
+                    .bind(round as i32)
+                    .bind(pattern)
+                    .bind(LINE_PATTERN)
+                    .bind(jackpot)
+                    .execute(&database)
+                    .await
+                    .map(|_| ())
+            })
+        }),
+    ).classified(DataClass::Roster)
+}
+
+const TOURNAMENT_COLUMNS: &str = "SELECT t.id, t.name, t.win_points, t.prize_points, t.jackpot_points, t.created_at, \
+    COALESCE(ARRAY_AGG(r.room_id ORDER BY r.room_id) FILTER (WHERE r.room_id IS NOT NULL), '{}') AS rooms \
+    FROM tournaments t LEFT JOIN tournament_rooms r ON r.tournament_id = t.id";
+
+async fn load_tournament(database: &sqlx::PgPool, host: &str, id: TournamentId) -> Result<Option<Tournament>, sqlx::Error> {
+    sqlx::query_as(&format!("{} WHERE t.host = $1 AND t.id = $2 GROUP BY t.id", TOURNAMENT_COLUMNS))
+        .bind(host)
+        .bind(id)
+        .fetch_optional(database)
+        .await
+}
+
+#[post("/host/tournaments")]
+async fn create_tournament(
+    user: HostIdentity,
+    request: web::Json<CreateTournamentRequest>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    let name: String = request.name.trim().chars().filter(|c| !c.is_control()).take(MAX_NAME_LENGTH).collect();
+    if name.is_empty(){
+        return Err(error::ErrorBadRequest("Tournament name is required"));
+    }
+    if [request.win_points, request.prize_points, request.jackpot_points].iter().any(|points| *points < 0){
+        return Err(error::ErrorBadRequest("Points can't be negative"));
+    }
+
+    let id: TournamentId = sqlx::query_scalar("INSERT INTO tournaments (host, name, win_points, prize_points, jackpot_points) VALUES ($1, $2, $3, $4, $5) RETURNING id")
+        .bind(&user.username)
+        .bind(&name)
+        .bind(request.win_points)
+        .bind(request.prize_points)
+        .bind(request.jackpot_points)
+        .fetch_one(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to create tournament for {}: {}", user.username, e);
+            error::ErrorInternalServerError("Failed to create tournament")
+        })?;
+
+    log::info!("{} created tournament {} ({})", user.username, id, name);
+    let tournament = load_tournament(&database, &user.username, id).await.map_err(|e| {
+        log::error!("Failed to load tournament {}: {}", id, e);
+        error::ErrorInternalServerError("Failed to load tournament")
+    })?;
+    Ok(HttpResponse::Created().json(tournament))
+}
+
+#[get("/host/tournaments")]
+async fn list_tournaments(
+    user: HostIdentity,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    let tournaments: Vec<Tournament> = sqlx::query_as(&format!("{} WHERE t.host = $1 GROUP BY t.id ORDER BY t.created_at DESC", TOURNAMENT_COLUMNS))
+        .bind(&user.username)
+        .fetch_all(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to list tournaments of {}: {}", user.username, e);
+            error::ErrorInternalServerError("Failed to list tournaments")
+        })?;
+    Ok(HttpResponse::Ok().json(tournaments))
+}
+
+/// Adds one of the host's rooms to the tournament, wins in the room score from then on.
+#[post("/host/tournaments/{id}/rooms")]
+async fn add_tournament_room(
+    user: HostIdentity,
+    path: web::Path<(TournamentId,)>,
+    request: web::Json<AddRoomRequest>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+
+    // Both the tournament and the room have to belong to the host
+    let result = sqlx::query("INSERT INTO tournament_rooms (tournament_id, room_id) \
+        SELECT t.id, r.id FROM tournaments t, rooms r WHERE t.id = $1 AND t.host = $3 AND r.id = $2 AND r.host = $3 \
+        ON CONFLICT DO NOTHING")
+        .bind(path.0)
+        .bind(request.room)
+        .bind(&user.username)
+        .execute(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to add room {} to tournament {}: {}", request.room, path.0, e);
+            error::ErrorInternalServerError("Failed to add room")
+        })?;
+
+    let tournament = load_tournament(&database, &user.username, path.0).await.map_err(|e| {
+        log::error!("Failed to load tournament {}: {}", path.0, e);
+        error::ErrorInternalServerError("Failed to load tournament")
+    })?;
+    match tournament {
+        Some(tournament) if tournament.rooms.contains(&request.room) => {
+            if result.rows_affected() > 0{
+                log::info!("Room {} joined tournament {}", request.room, path.0);
+            }
+            Ok(HttpResponse::Ok().json(tournament))
+        }
+        Some(_) => Err(error::ErrorNotFound("Room not found")),
+        None => Err(error::ErrorNotFound("Tournament not found")),
+    }
+}
+
+/// Points per player across every room of the tournament, public so boards and players can show it.
+#[get("/tournaments/{id}/leaderboard")]
+async fn tournament_leaderboard(
+    path: web::Path<(TournamentId,)>,
+    query: web::Query<LeaderboardQuery>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let name: Option<String> = sqlx::query_scalar("SELECT name FROM tournaments WHERE id = $1")
+        .bind(path.0)
+        .fetch_optional(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load tournament {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to load leaderboard")
+        })?;
+    let Some(name) = name else {
+        return Err(error::ErrorNotFound("Tournament not found"));
+    };
+
+    let limit = query.limit.unwrap_or(MAX_LEADERBOARD_SIZE).clamp(1, MAX_LEADERBOARD_SIZE);
+    let players: Vec<LeaderboardEntry> = sqlx::query_as("SELECT RANK() OVER (ORDER BY SUM(s.points) DESC) AS rank, p.display_name, \
+        SUM(s.points)::int8 AS points, COUNT(*) FILTER (WHERE s.pattern = $2) AS wins, COUNT(DISTINCT s.room_id) AS games \
+        FROM tournament_points s LEFT JOIN players p ON p.token = s.player_token \
+        WHERE s.tournament_id = $1 GROUP BY s.player_token, p.display_name ORDER BY points DESC, wins DESC LIMIT $3")
+        .bind(path.0)
+        .bind(LINE_PATTERN)
+        .bind(limit)
+        .fetch_all(&**database)
+        .await
+        .map_err(|e| {
+            log::error!("Failed to load leaderboard of tournament {}: {}", path.0, e);
+            error::ErrorInternalServerError("Failed to load leaderboard")
+        })?;
+
+    Ok(HttpResponse::Ok().json(Leaderboard{ tournament: path.0, name, players }))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn leaderboard_only_shows_initials(){
+        assert_eq!(initials("Sam Jones").as_deref(), Some("S. J."));
+        assert_eq!(initials("  émile  ").as_deref(), Some("É."));
+        assert_eq!(initials("(Pat) o'Neil").as_deref(), Some("P. O."));
+        assert_eq!(initials("  "), None);
+    }
+}
@@ -0,0 +1,152 @@
+use actix_web::{delete, error, post, web, HttpResponse};
+use chrono::{DateTime, TimeDelta, Utc};
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::room::{BingoServerHandle, RoomId};
+
+/// How long the recipient has to accept a transfer.
+pub const TRANSFER_OFFER_TTL: TimeDelta = TimeDelta::minutes(10);
+
+/// Ownership of a room offered to another host account, which has to accept it.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct TransferOffer{
+    pub from: String,
+    pub to: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TransferOffer{
+    pub fn new(from: String, to: String) -> Self {
+        Self{
+            from,
+            to,
+            expires_at: Utc::now() + TRANSFER_OFFER_TTL,
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferError{
+    /// The room doesn't exist or the caller doesn't own it.
+    RoomNotFound,
+    SameHost,
+    NoOffer,
+    /// The offer was made to another account.
+    NotRecipient,
+    /// The recipient already hosts a room of the same day, hosts have one room per day.
+    AlreadyHosting,
+    /// The new owner couldn't be saved, the offer stays open.
+    NotSaved,
+}
+
+impl From<TransferError> for actix_web::Error {
+    fn from(error: TransferError) -> Self {
+        match error {
+            TransferError::RoomNotFound => error::ErrorNotFound("Room not found"),
+            TransferError::SameHost => error::ErrorBadRequest("The room can't be transferred to its own host"),
+            TransferError::NoOffer => error::ErrorNotFound("No transfer of this room is pending"),
+            TransferError::NotRecipient => error::ErrorForbidden("The transfer was offered to another host"),
+            TransferError::AlreadyHosting => error::ErrorConflict("You already host a room today"),
+            TransferError::NotSaved => error::ErrorServiceUnavailable("The transfer couldn't be saved, try again"),
+        }
+    }
+}
+
+/// Tells the hosts of the room about a pending transfer.
+#[derive(serde::Serialize)]
+pub struct TransferOfferedMessage<'a>{
+    r#type: String,
+    #[serde(flatten)]
+    offer: &'a TransferOffer,
+}
+
+impl<'a> TransferOfferedMessage<'a>{
+    pub fn new(offer: &'a TransferOffer) -> Self {
+        Self{
+            r#type: "transfer_offered".to_string(),
+            offer,
+        }
+    }
+}
+
+/// Tells the hosts of the room that the pending transfer was withdrawn or declined.
+#[derive(serde::Serialize)]
+pub struct TransferCancelledMessage<'a>{
+    r#type: String,
+    by: &'a str,
+}
+
+impl<'a> TransferCancelledMessage<'a>{
+    pub fn new(by: &'a str) -> Self {
+        Self{
+            r#type: "transfer_cancelled".to_string(),
+            by,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TransferQuery{
+    room_token: String,
+}
+
+#[derive(serde::Deserialize)]
+struct TransferRequest{
+    /// Username of the host account taking over.
+    to: String,
+}
+
+#[derive(serde::Serialize)]
+struct TransferredRoom{
+    room_id: RoomId,
+    room_token: String,
+    board_token: String,
+}
+
+/// Offers the room to another host account, replacing an earlier offer. Nothing changes until the
+/// recipient accepts.
+#[post("/room/{room}/transfer")]
+async fn offer_transfer(
+    user: HostIdentity,
+    path: web::Path<(RoomId,)>,
+    query: web::Query<TransferQuery>,
+    request: web::Json<TransferRequest>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+    let to = request.into_inner().to.trim().to_owned();
+    if to.is_empty(){
+        return Err(error::ErrorBadRequest("to is required"));
+    }
+    let offer = server.offer_transfer(path.0, query.into_inner().room_token, user.username, to).await?;
+    Ok(HttpResponse::Ok().json(offer))
+}
+
+/// Takes over the room. The previous host's sockets are closed and the room gets a new host token,
+/// the board token stays so display boards keep working.
+#[post("/room/{room}/transfer/accept")]
+async fn accept_transfer(
+    user: HostIdentity,
+    path: web::Path<(RoomId,)>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+    let room = server.accept_transfer(path.0, user.username).await?;
+    Ok(HttpResponse::Ok().json(TransferredRoom{ room_id: room.id, room_token: room.token, board_token: room.board_token }))
+}
+
+/// Withdraws or declines a pending transfer, allowed to both the owner and the recipient.
+#[delete("/room/{room}/transfer")]
+async fn cancel_transfer(
+    user: HostIdentity,
+    path: web::Path<(RoomId,)>,
+    server: web::Data<BingoServerHandle>,
+) -> actix_web::Result<HttpResponse> {
+    user.require_scope(Scope::Host)?;
+    server.cancel_transfer(path.0, user.username).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
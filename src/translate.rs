@@ -0,0 +1,173 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::config::TranslatorConfig;
+use crate::room::UserType;
+use crate::wshandler::WSMessage;
+
+/// Translations taking longer are given up and the chat is relayed as sent, so a slow service can't stall a room's chat.
+const TRANSLATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Translates chat text to a room's locale before it is relayed.
+pub trait Translator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn translate<'a>(&'a self, text: &'a str, target: &'a str) -> BoxFuture<'a, anyhow::Result<String>>;
+}
+
+impl fmt::Debug for dyn Translator{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Creates the translator configured with `TRANSLATOR_URL`, chat is relayed untranslated without one.
+pub fn create_translator(config: Option<&TranslatorConfig>) -> Option<Arc<dyn Translator>> {
+    config.map(|config| Arc::new(HttpTranslator{
+        url: config.url.clone(),
+        api_key: config.api_key.clone(),
+        // unwrap: only fails when the TLS backend can't be initialized
+        client: reqwest::Client::builder().timeout(TRANSLATE_TIMEOUT).build().unwrap(),
+    }) as Arc<dyn Translator>)
+}
+
+/// A LibreTranslate compatible HTTP service.
+pub struct HttpTranslator{
+    url: String,
+    api_key: Option<String>,
+    client: reqwest::Client,
+}
+
+#[derive(serde::Serialize)]
+struct TranslateRequest<'a>{
+    q: &'a str,
+    source: &'a str,
+    target: &'a str,
+    format: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+#[derive(serde::Deserialize)]
+struct TranslateResponse{
+    #[serde(rename = "translatedText")]
+    translated_text: String,
+}
+
+impl HttpTranslator{
+    async fn request(&self, text: &str, target: &str) -> anyhow::Result<String> {
+        let request = TranslateRequest{ q: text, source: "auto", target, format: "text", api_key: self.api_key.as_deref() };
+        let response = self.client.post(&self.url)
+            .json(&request)
+            .send()
+            .await?;
+        if !response.status().is_success(){
+            return Err(anyhow!("Translator returned {}", response.status()));
+        }
+        Ok(response.json::<TranslateResponse>().await?.translated_text)
+    }
+}
+
+impl Translator for HttpTranslator{
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    fn translate<'a>(&'a self, text: &'a str, target: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(self.request(text, target))
+    }
+}
+
+/// Chat of a room waiting to be translated. Messages are translated one after another, so they are relayed in the
+/// order they were sent however long each translation takes.
+#[derive(Debug)]
+pub struct TranslationQueue{
+    tx: mpsc::UnboundedSender<(String, String, UserType)>,
+}
+
+impl TranslationQueue{
+    /// Starts the worker of a room, `relay` gets each translated message. The worker stops when the queue is dropped.
+    pub fn spawn(translator: Arc<dyn Translator>, relay: impl Fn(String, UserType) + Send + 'static) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<(String, String, UserType)>();
+        tokio::spawn(async move {
+            while let Some((msg, locale, user_type)) = rx.recv().await{
+                let translated = translate_chat(translator.as_ref(), msg, &locale).await;
+                relay(translated, user_type);
+            }
+        });
+        Self{ tx }
+    }
+
+    pub fn push(&self, msg: String, locale: String, user_type: UserType){
+        let _ = self.tx.send((msg, locale, user_type));
+    }
+}
+
+pub fn is_chat(msg: &str) -> bool {
+    serde_json::from_str::<WSMessage>(msg).is_ok_and(|message| message.r#type == "chat")
+}
+
+/// Replaces the `text` of a chat message with its translation, the original is kept in `original_text`.
+/// Messages that can't be translated are returned unchanged so chat keeps flowing when the service is down.
+pub async fn translate_chat(translator: &dyn Translator, msg: String, locale: &str) -> String {
+    let mut message: Value = match serde_json::from_str(&msg) {
+        Ok(message) => message,
+        Err(_) => return msg,
+    };
+    let text = match message.get("text").and_then(Value::as_str) {
+        Some(text) => text.to_owned(),
+        None => return msg,
+    };
+
+    match translator.translate(&text, locale).await {
+        Ok(translated) => {
+            message["text"] = Value::String(translated);
+            message["original_text"] = Value::String(text);
+            message["translated_to"] = Value::String(locale.to_owned());
+            message.to_string()
+        }
+        Err(e) => {
+            log::warn!("Failed to translate chat message to {} using {}: {}", locale, translator.name(), e);
+            msg
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    /// Takes longer for the first message than for the ones after it.
+    struct SlowStart;
+
+    impl Translator for SlowStart{
+        fn name(&self) -> &'static str {
+            "slow_start"
+        }
+
+        fn translate<'a>(&'a self, text: &'a str, target: &'a str) -> BoxFuture<'a, anyhow::Result<String>> {
+            Box::pin(async move {
+                if text == "first"{
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                }
+                Ok(format!("{}:{}", target, text))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn translated_chat_keeps_its_order(){
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let queue = TranslationQueue::spawn(Arc::new(SlowStart), move |msg, _| {
+            let _ = tx.send(msg);
+        });
+        queue.push(r#"{"type":"chat","text":"first"}"#.to_owned(), "es".to_owned(), UserType::Client);
+        queue.push(r#"{"type":"chat","text":"second"}"#.to_owned(), "es".to_owned(), UserType::Client);
+        assert!(rx.recv().await.unwrap().contains(r#""text":"es:first""#));
+        assert!(rx.recv().await.unwrap().contains(r#""text":"es:second""#));
+    }
+}
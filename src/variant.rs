@@ -0,0 +1,88 @@
+use std::{fmt, ops::RangeInclusive};
+
+use crate::card::Pattern;
+use crate::draw::Number;
+
+/// Game a room is played with, picked by the host when the room of the day is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum GameVariant{
+    /// US bingo, 5x5 cards with B, I, N, G and O columns of 15 numbers each and a free middle.
+    #[default]
+    #[serde(rename = "75_ball")]
+    Ball75,
+    /// UK bingo, 9x3 tickets with five numbers per row, column by column in tens. The main game is a
+    /// full house, one and two lines are played as prizes.
+    #[serde(rename = "90_ball")]
+    Ball90,
+}
+
+impl GameVariant{
+    pub fn name(self) -> &'static str {
+        match self {
+            GameVariant::Ball75 => "75_ball",
+            GameVariant::Ball90 => "90_ball",
+        }
+    }
+
+    pub fn ball_count(self) -> Number {
+        match self {
+            GameVariant::Ball75 => 75,
+            GameVariant::Ball90 => 90,
+        }
+    }
+
+    pub fn numbers(self) -> RangeInclusive<Number> {
+        1..=self.ball_count()
+    }
+
+    /// Columns and rows of a card.
+    pub fn card_size(self) -> (usize, usize) {
+        match self {
+            GameVariant::Ball75 => (5, 5),
+            GameVariant::Ball90 => (9, 3),
+        }
+    }
+
+    /// Numbers a column of the card is filled from, 90-ball tickets put 1-9 in the first column and 80-90
+    /// in the last.
+    pub fn column_range(self, column: usize) -> RangeInclusive<Number> {
+        let column = column as Number;
+        match self {
+            GameVariant::Ball75 => column * 15 + 1..=(column + 1) * 15,
+            GameVariant::Ball90 => match column {
+                0 => 1..=9,
+                8 => 80..=90,
+                column => column * 10..=column * 10 + 9,
+            },
+        }
+    }
+
+    pub fn supports(self, pattern: Pattern) -> bool {
+        match self {
+            GameVariant::Ball75 => pattern != Pattern::TwoLines,
+            GameVariant::Ball90 => matches!(pattern, Pattern::Line | Pattern::TwoLines | Pattern::Blackout),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UnknownVariant(String);
+
+impl fmt::Display for UnknownVariant{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown game variant {}", self.0)
+    }
+}
+
+impl std::error::Error for UnknownVariant{}
+
+/// Variants are stored by name in the `rooms` table.
+impl TryFrom<String> for GameVariant{
+    type Error = UnknownVariant;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        [GameVariant::Ball75, GameVariant::Ball90].into_iter()
+            .find(|variant| variant.name() == name)
+            .ok_or(UnknownVariant(name))
+    }
+}
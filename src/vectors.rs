@@ -0,0 +1,103 @@
+//! Runs the protocol test vectors in `protocol/vectors`, the frontend checks its own types against the same files.
+
+use std::{fs, path::Path, time::Duration};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::auto_call::{AutoCallMessage, AutoCaller};
+use crate::card::DaubMessage;
+use crate::devices::{PlayerDevicesMessage, SessionMirroredMessage};
+use crate::game::WinnerEvent;
+use crate::observer::ObservedMessage;
+use crate::phase::{PhaseMessage, RoomPhase};
+use crate::players::SessionTakeoverMessage;
+use crate::presence::PresenceBatch;
+use crate::room::{ConnectError, DisconnectReason, SessionId, UserType};
+use crate::subscription::{Channel, SubscribedMessage};
+use crate::waitlist::{AdmittedMessage, WaitlistedMessage};
+use crate::wshandler::{ConnectionRejectedMessage, ErrorMessage, IDMessage, SessionClosedMessage, WSMessage, CLOSE_SESSION_REPLACED};
+
+#[derive(Deserialize)]
+struct Vectors{
+    cases: Vec<Case>,
+}
+
+#[derive(Deserialize)]
+struct Case{
+    name: String,
+    message: Value,
+    /// Only set for inbound messages.
+    #[serde(default)]
+    valid: bool,
+    /// Text of the `error` reply to a message that parses but is refused.
+    error: Option<String>,
+}
+
+fn load(file: &str) -> Vec<Case> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("protocol/vectors").join(file);
+    let vectors = fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e));
+    serde_json::from_str::<Vectors>(&vectors).unwrap_or_else(|e| panic!("Invalid test vectors in {}: {}", file, e)).cases
+}
+
+/// Parses a message the way the socket handlers do.
+pub fn parse<T: DeserializeOwned>(msg: &str) -> Result<T, String> {
+    serde_json::from_str(msg).map_err(|e| e.to_string())
+}
+
+/// Feeds every inbound case to `handle`, which gets the message type and the raw message and returns
+/// the refusal the server would answer with, or why the message was dropped.
+pub async fn check_inbound(file: &str, handle: impl AsyncFn(&str, &str) -> Result<(), String>){
+    for case in load(file){
+        let msg = case.message.to_string();
+        let message_type = parse::<WSMessage>(&msg).map(|message| message.r#type).unwrap_or_default();
+        match (handle(&message_type, &msg).await, case.valid) {
+            (Ok(()), true) => {}
+            (Ok(()), false) => panic!("{}: {} was accepted", case.name, msg),
+            (Err(e), true) => panic!("{}: {} was refused with {}", case.name, msg, e),
+            (Err(e), false) => {
+                if let Some(error) = &case.error{
+                    assert_eq!(&e, error, "{}: unexpected error", case.name);
+                }
+            }
+        }
+    }
+}
+
+fn json(message: impl Serialize) -> Value {
+    serde_json::to_value(message).unwrap()
+}
+
+#[test]
+fn server_messages_match_vectors(){
+    for case in load("server.json"){
+        let message = match case.name.as_str() {
+            "id" => json(IDMessage::new(SessionId::new(7), UserType::Client)),
+            "error" => json(ErrorMessage::new("No card to trade in".to_owned())),
+            "session_closed" => json(SessionClosedMessage::new(CLOSE_SESSION_REPLACED, "Session taken over by a newer connection")),
+            "session_takeover" => json(SessionTakeoverMessage::new(SessionId::new(7))),
+            "connection_rejected_host_limit" => json(ConnectionRejectedMessage::new(ConnectError::HostConnectionLimit(2))),
+            "connection_rejected_room_closed" => json(ConnectionRejectedMessage::new(ConnectError::RoomClosed)),
+            "connection_rejected_room_full" => json(ConnectionRejectedMessage::new(ConnectError::RoomFull(200))),
+            "waitlisted" => json(WaitlistedMessage::new(3)),
+            "admitted" => json(AdmittedMessage::new(SessionId::new(7))),
+            "session_mirrored" => json(SessionMirroredMessage::new(SessionId::new(7), 2)),
+            "player_devices" => json(PlayerDevicesMessage::new(SessionId::new(7), 2)),
+            "presence" => {
+                let mut batch = PresenceBatch::default();
+                batch.join(SessionId::new(8));
+                batch.leave(SessionId::new(7), DisconnectReason::Timeout);
+                json(batch.take_summary(3))
+            }
+            "winner" => json(WinnerEvent::new(2, SessionId::new(7), Some(3), 31, false)),
+            "observed" => json(ObservedMessage::new(UserType::Host, r#"{"type":"chat","text":"Hi"}"#)),
+            "daub" => json(DaubMessage::new(42, vec![1, 3])),
+            "phase" => json(PhaseMessage::new(RoomPhase::Live, RoomPhase::Lobby)),
+            "subscribed" => json(SubscribedMessage::new(vec![Channel::Draws, Channel::Chat])),
+            "auto_call" => json(AutoCallMessage::new(Some(&AutoCaller{ interval: Duration::from_secs(10), paused: false, generation: 1 }))),
+            "auto_call_stopped" => json(AutoCallMessage::new(None)),
+            name => panic!("No server message for test vector {}", name),
+        };
+        assert_eq!(message, case.message, "{}", case.name);
+    }
+}
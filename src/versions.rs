@@ -0,0 +1,175 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::room::RoomId;
+
+/// App version as `major.minor.patch`, missing parts count as 0 and suffixes like `-beta` are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClientVersion(u32, u32, u32);
+
+impl<'de> serde::Deserialize<'de> for ClientVersion{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl FromStr for ClientVersion{
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let core = value.trim().trim_start_matches('v').split(['-', '+']).next().unwrap_or_default();
+        let mut parts = [0; 3];
+        for (index, part) in core.split('.').enumerate() {
+            let slot = parts.get_mut(index).ok_or_else(|| anyhow!("Too many version components in {}", value))?;
+            *slot = part.parse().map_err(|_| anyhow!("Invalid version {}", value))?;
+        }
+        Ok(ClientVersion(parts[0], parts[1], parts[2]))
+    }
+}
+
+impl fmt::Display for ClientVersion{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// Minimum supported client, from `MIN_CLIENT_VERSION` and `REJECT_OUTDATED_CLIENTS`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VersionPolicy{
+    pub min_version: Option<ClientVersion>,
+    /// Close outdated connections instead of only warning them.
+    pub reject_outdated: bool,
+}
+
+impl VersionPolicy{
+    /// Clients that don't report a parseable version are outdated once a minimum is set.
+    pub fn is_outdated(&self, info: &ClientInfo) -> bool {
+        match self.min_version {
+            Some(min_version) => info.version().is_none_or(|version| version < min_version),
+            None => false,
+        }
+    }
+}
+
+/// App version and platform, reported in the join query and in `client_info` messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
+pub struct ClientInfo{
+    pub app_version: Option<String>,
+    /// e.g. `web`, `ios` or `android`.
+    pub platform: Option<String>,
+}
+
+/// Stands in for clients that connected without reporting a version.
+pub const UNKNOWN_CLIENT: ClientInfo = ClientInfo{ app_version: None, platform: None };
+
+impl ClientInfo{
+    pub fn version(&self) -> Option<ClientVersion> {
+        self.app_version.as_deref().and_then(|version| version.parse().ok())
+    }
+}
+
+/// Tells an outdated client to upgrade, sent before closing it when outdated clients are rejected.
+#[derive(serde::Serialize)]
+pub struct OutdatedClientMessage{
+    r#type: String,
+    min_version: String,
+    rejected: bool,
+}
+
+impl OutdatedClientMessage{
+    pub fn new(policy: &VersionPolicy) -> Self {
+        Self{
+            r#type: "outdated_client".to_string(),
+            min_version: policy.min_version.map(|version| version.to_string()).unwrap_or_default(),
+            rejected: policy.reject_outdated,
+        }
+    }
+}
+
+/// Refresh request sent by a host for one room or by an operator for every room.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ForceRefreshRequest{
+    /// Clients below this version count as outdated.
+    pub min_version: Option<ClientVersion>,
+    pub reason: Option<String>,
+    /// Close the sockets of outdated clients, needs a minimum version.
+    #[serde(default)]
+    pub close_outdated: bool,
+}
+
+impl ForceRefreshRequest{
+    pub fn policy(&self) -> VersionPolicy {
+        VersionPolicy{ min_version: self.min_version, reject_outdated: self.close_outdated }
+    }
+}
+
+/// Asks clients to reload, e.g. after a breaking frontend fix shipped mid-event.
+#[derive(serde::Serialize)]
+pub struct ForceRefreshMessage<'a>{
+    r#type: String,
+    min_version: Option<String>,
+    reason: Option<&'a str>,
+}
+
+impl<'a> ForceRefreshMessage<'a>{
+    pub fn new(request: &'a ForceRefreshRequest) -> Self {
+        Self{
+            r#type: "force_refresh".to_string(),
+            min_version: request.min_version.map(|version| version.to_string()),
+            reason: request.reason.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct ForceRefreshResult{
+    pub rooms: usize,
+    /// Outdated client sockets that were closed.
+    pub closed: usize,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct VersionCount{
+    #[serde(flatten)]
+    pub client: ClientInfo,
+    pub clients: usize,
+}
+
+/// Connected clients per version and platform, most common first.
+pub fn count_versions<'a>(clients: impl Iterator<Item = &'a ClientInfo>) -> Vec<VersionCount> {
+    let mut counts: BTreeMap<&ClientInfo, usize> = BTreeMap::new();
+    for client in clients {
+        *counts.entry(client).or_default() += 1;
+    }
+    let mut versions: Vec<VersionCount> = counts.into_iter()
+        .map(|(client, clients)| VersionCount{ client: client.clone(), clients })
+        .collect();
+    versions.sort_by_key(|version| std::cmp::Reverse(version.clients));
+    versions
+}
+
+/// Answer to the host `client_versions` command.
+#[derive(serde::Serialize)]
+pub struct ClientVersionsMessage{
+    r#type: String,
+    versions: Vec<VersionCount>,
+}
+
+impl ClientVersionsMessage{
+    pub fn new(versions: Vec<VersionCount>) -> Self {
+        Self{
+            r#type: "client_versions".to_string(),
+            versions,
+        }
+    }
+}
+
+/// Client versions of a room, listed by the admin API.
+#[derive(Debug, serde::Serialize)]
+pub struct RoomClientVersions{
+    pub room: RoomId,
+    pub host: String,
+    pub versions: Vec<VersionCount>,
+}
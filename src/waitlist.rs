@@ -0,0 +1,98 @@
+use tokio::sync::mpsc;
+
+use crate::players::PlayerIdentity;
+use crate::room::{Msg, SessionId};
+
+/// Highest player limit a host can set.
+pub const MAX_PLAYER_LIMIT: usize = 10_000;
+
+/// Serialized [`AdmittedMessage`]s start with this, the socket accepts player commands from then on.
+pub const ADMITTED_PREFIX: &str = r#"{"type":"admitted""#;
+
+/// Most players a room lets in, set by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub struct PlayerLimit{
+    pub max_players: usize,
+    /// Queue players joining a full room instead of refusing them.
+    #[serde(default)]
+    pub waitlist: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct PlayerLimitRequest{
+    /// Unset to let everyone in, waiting players are admitted right away.
+    pub limit: Option<PlayerLimit>,
+}
+
+impl PlayerLimitRequest{
+    pub fn validate(&self) -> Result<(), String> {
+        match self.limit {
+            Some(limit) if limit.max_players == 0 || limit.max_players > MAX_PLAYER_LIMIT => {
+                Err(format!("The player limit must be 1 to {}", MAX_PLAYER_LIMIT))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A player socket waiting for a free slot, it only gets waitlist updates until admitted.
+#[derive(Debug)]
+pub struct WaitingConnection{
+    pub id: SessionId,
+    pub tx: mpsc::UnboundedSender<Msg>,
+    pub player: Option<PlayerIdentity>,
+}
+
+/// Position of a waiting player, sent when it joins the waitlist and whenever it moves up.
+#[derive(serde::Serialize)]
+pub struct WaitlistedMessage{
+    r#type: String,
+    /// 1 for the next player to be admitted.
+    position: usize,
+}
+
+impl WaitlistedMessage{
+    pub fn new(position: usize) -> Self {
+        Self{
+            r#type: "waitlisted".to_string(),
+            position,
+        }
+    }
+}
+
+/// Tells a waiting player it got a slot.
+#[derive(serde::Serialize)]
+pub struct AdmittedMessage{
+    r#type: String,
+    client_id: SessionId,
+}
+
+impl AdmittedMessage{
+    pub fn new(client_id: SessionId) -> Self {
+        Self{
+            r#type: "admitted".to_string(),
+            client_id,
+        }
+    }
+}
+
+/// Player limit and queue of the room, sent to the hosts whenever the queue changes.
+#[derive(serde::Serialize)]
+pub struct WaitlistMessage{
+    r#type: String,
+    limit: Option<PlayerLimit>,
+    players: usize,
+    /// Waiting connections in the order they will be admitted.
+    waiting: Vec<SessionId>,
+}
+
+impl WaitlistMessage{
+    pub fn new(limit: Option<PlayerLimit>, players: usize, waiting: Vec<SessionId>) -> Self {
+        Self{
+            r#type: "waitlist".to_string(),
+            limit,
+            players,
+            waiting,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::Instant;
+
+use actix_web::{error, post, web, HttpResponse};
+use rand::{rng, Rng as _};
+use serde::Deserialize;
+
+use crate::api_keys::{HostIdentity, Scope};
+use crate::drain::maintenance_error;
+use crate::room::{BingoServerHandle, RoomId, UserType};
+use crate::players::{generate_player_token, is_valid_player_token};
+use crate::tickets::{redeem_ticket, requires_ticket};
+
+/// A short lived, single use credential for a websocket upgrade, passed as `?ws_ticket=` in the URL.
+#[derive(Debug)]
+pub struct WsTicket{
+    pub room: RoomId,
+    pub user_type: UserType,
+    /// Host username, or the player token of a client, empty for clients joining without one.
+    pub identity: String,
+    pub expires: Instant,
+}
+
+pub fn generate_ticket() -> String {
+    rng().random::<[u8; 16]>().iter().map(|x| format!("{:02x}", x)).collect::<String>()
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TicketRole {
+    Host,
+    Client,
+}
+
+#[derive(Deserialize)]
+struct TicketRequest {
+    room: RoomId,
+    role: TicketRole,
+    /// Required for hosts.
+    room_token: Option<String>,
+    /// Ticket code for rooms that only admit ticket holders.
+    ticket: Option<String>,
+    /// Token of the player, the ticket code is bound to it so the player can reconnect.
+    player_token: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct TicketResult {
+    ws_ticket: String,
+    expires_in_secs: u64,
+}
+
+#[post("/ws-ticket")]
+async fn issue_ws_ticket(
+    user: Option<HostIdentity>,
+    request: web::Json<TicketRequest>,
+    server: web::Data<BingoServerHandle>,
+    database: web::Data<sqlx::PgPool>,
+) -> actix_web::Result<HttpResponse> {
+    let (user_type, identity) = match request.role {
+        TicketRole::Host => {
+            let user = user.ok_or_else(|| error::ErrorUnauthorized("Login required using /host endpoint or an API key"))?;
+            user.require_scope(Scope::Host)?;
+            let room_token = request.room_token.clone().unwrap_or_default();
+            if !server.has_room_host_privileges(request.room, room_token).await {
+                log::info!("User {} does not have host privileges for room {} or the room does not exist", user.username, request.room);
+                return Err(error::ErrorNotFound("Room not found"));
+            }
+            (UserType::Host, user.username)
+        }
+        TicketRole::Client => {
+            if server.is_draining().await {
+                return Err(maintenance_error());
+            }
+            if !server.room_exists(request.room).await {
+                return Err(error::ErrorNotFound("Room not found"));
+            }
+            let require_ticket = requires_ticket(&database, request.room).await.map_err(|e| {
+                log::error!("Failed to look up ticket requirement for room {}: {}", request.room, e);
+                error::ErrorInternalServerError("Failed to validate ticket")
+            })?;
+            let player_token = request.player_token.clone().filter(|token| is_valid_player_token(token));
+            if require_ticket {
+                let player_token = player_token.unwrap_or_else(generate_player_token);
+                let redeemed = match &request.ticket {
+                    Some(code) => redeem_ticket(&database, request.room, code, &player_token).await.map_err(|e| {
+                        log::error!("Failed to redeem ticket for room {}: {}", request.room, e);
+                        error::ErrorInternalServerError("Failed to validate ticket")
+                    })?,
+                    None => false,
+                };
+                if !redeemed {
+                    return Err(error::ErrorForbidden("A valid, unused ticket code is required"));
+                }
+                (UserType::Client, player_token)
+            }
+            else{
+                (UserType::Client, player_token.unwrap_or_default())
+            }
+        }
+    };
+
+    let (ws_ticket, ttl) = server.issue_ws_ticket(request.room, user_type, identity).await;
+    Ok(HttpResponse::Ok().json(TicketResult{ ws_ticket, expires_in_secs: ttl.as_secs() }))
+}
@@ -1,36 +1,81 @@
-use std::{future::Future, pin::{pin, Pin}, time::{Duration, Instant}};
+use std::{any::Any, fmt, future::Future, panic::AssertUnwindSafe, pin::{pin, Pin}, sync::LazyLock, time::Instant};
 
 use actix_web::web;
-use actix_ws::AggregatedMessage;
+use actix_ws::{AggregatedMessage, CloseCode, CloseReason};
 use tokio::{sync::mpsc, time::interval};
-use futures_util::future::{select, Either};
+use futures_util::{future::{select, Either}, FutureExt as _};
 
-use crate::room::{BingoServerHandle, ConnId, RoomId};
+use crate::accessibility::SocketFormat;
+use crate::config::WebSocketConfig;
+use crate::message_log::Direction;
+use crate::waitlist::ADMITTED_PREFIX;
+use crate::players::PlayerIdentity;
+use crate::room::{BingoServerHandle, ConnectError, DisconnectReason, RoomId, SessionId, UserType};
 
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
 
+/// Reference point of the monotonic clock reported in `time_sync` responses.
+static SERVER_START: LazyLock<Instant> = LazyLock::new(Instant::now);
 
-//Create an interface for command handler that accepts a string message
-pub type CommandHandler = Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+/// Close code of a player socket replaced by a newer socket with the same player token.
+pub const CLOSE_SESSION_REPLACED: u16 = 4001;
+
+/// Close code of the host sockets of a room taken over by another host account.
+pub const CLOSE_ROOM_TRANSFERRED: u16 = 4002;
+
+/// Serialized [`SessionClosedMessage`]s start with this, so other room updates are not parsed.
+const SESSION_CLOSED_PREFIX: &str = r#"{"type":"session_closed""#;
+
+//Create an interface for command handler that accepts the sender connection and a string message
+pub type CommandHandler = Box<dyn Fn(SessionId, String) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
 
 
 #[derive(Debug, serde::Deserialize)]
 pub struct WSMessage{
-    r#type: String
+    pub r#type: String
 }
 
 #[derive(serde::Serialize)]
 pub struct IDMessage{
     r#type: String,
-    conn_id: ConnId
+    conn_id: SessionId,
+    /// `host`, `client` or `board`.
+    role: UserType,
 }
 
 impl IDMessage{
-    pub fn new(conn_id: ConnId) -> Self {
+    pub fn new(conn_id: SessionId, role: UserType) -> Self {
         Self{
             r#type: "id".to_string(),
-            conn_id
+            conn_id,
+            role,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TimeSyncRequest{
+    client_time_ms: Option<i64>,
+}
+
+/// Server clock readings, lets clients estimate their clock offset and the round trip time.
+#[derive(serde::Serialize)]
+pub struct TimeSyncMessage{
+    r#type: String,
+    /// Milliseconds since the unix epoch.
+    server_time_ms: i64,
+    /// Milliseconds on the server monotonic clock, never goes backwards.
+    monotonic_ms: u128,
+    /// Echo of the client timestamp from the request.
+    client_time_ms: Option<i64>,
+}
+
+impl TimeSyncMessage{
+    pub fn new(client_time_ms: Option<i64>) -> Self {
+        Self{
+            r#type: "time_sync".to_string(),
+            server_time_ms: chrono::Utc::now().timestamp_millis(),
+            monotonic_ms: SERVER_START.elapsed().as_millis(),
+            client_time_ms,
         }
     }
 }
@@ -48,32 +93,140 @@ impl ErrorMessage{
             message
         }
     }
+}
+
+impl fmt::Display for ErrorMessage{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(self).unwrap())
+    }
+}
+
+/// Last message of a socket the room drops, the socket is closed with the code once it is delivered.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct SessionClosedMessage{
+    r#type: String,
+    code: u16,
+    reason: String,
+}
 
-    pub fn to_string(&self) -> String {
-        serde_json::to_string(self).unwrap()
+impl SessionClosedMessage{
+    pub fn new(code: u16, reason: &str) -> Self {
+        Self{
+            r#type: "session_closed".to_string(),
+            code,
+            reason: reason.to_owned(),
+        }
     }
 }
 
+/// Sent before closing a socket the room refused.
+#[derive(serde::Serialize)]
+pub struct ConnectionRejectedMessage{
+    r#type: String,
+    reason: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    limit: Option<usize>,
+}
+
+impl ConnectionRejectedMessage{
+    pub fn new(error: ConnectError) -> Self {
+        let limit = match error {
+            ConnectError::HostConnectionLimit(limit) => Some(limit),
+            ConnectError::RoomFull(limit) => Some(limit),
+            ConnectError::RoomClosed | ConnectError::Waitlisted(_) => None,
+        };
+        Self{
+            r#type: "connection_rejected".to_string(),
+            reason: error.reason().to_string(),
+            limit,
+        }
+    }
+}
+
+/// Tells the room a socket is gone when its task ends, also when the task panics, so the room doesn't
+/// keep a session that nobody reads.
+struct ConnectionGuard{
+    server: web::Data<BingoServerHandle>,
+    room: RoomId,
+    conn_id: SessionId,
+    user_type: UserType,
+    /// Stays `Error` unless the socket ends the regular way, e.g. when the task panics.
+    reason: DisconnectReason,
+}
+
+impl Drop for ConnectionGuard{
+    fn drop(&mut self){
+        self.server.disconnect(self.room, self.conn_id, self.user_type, self.reason);
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic.downcast_ref::<&str>().copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+/// Runs a socket until it closes, `player` identifies the player behind client sockets. A panic while
+/// handling the socket is logged and only ends this socket.
+#[allow(clippy::too_many_arguments)]
 pub async fn ws_handler(
     server: web::Data<BingoServerHandle>,
+    config: WebSocketConfig,
+    format: SocketFormat,
     room: RoomId,
-    user_type: ConnId,
+    user_type: UserType,
+    player: Option<PlayerIdentity>,
+    command_handler: CommandHandler,
+    session: actix_ws::Session,
+    msg_stream: actix_ws::MessageStream)
+{
+    let errors = server.errors();
+    let handler = handle_socket(server, config, format, room, user_type, player, command_handler, session, msg_stream);
+    if let Err(panic) = AssertUnwindSafe(handler).catch_unwind().await{
+        let message = format!("Socket task of {:?} in room {} panicked: {}", user_type, room, panic_message(panic.as_ref()));
+        log::error!("{}", message);
+        errors.record("socket", message);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn handle_socket(
+    server: web::Data<BingoServerHandle>,
+    config: WebSocketConfig,
+    format: SocketFormat,
+    room: RoomId,
+    user_type: UserType,
+    player: Option<PlayerIdentity>,
     command_handler: CommandHandler,
     mut session: actix_ws::Session,
     msg_stream: actix_ws::MessageStream)
 {
     let mut last_heartbeat = Instant::now();
-    let mut interval = interval(HEARTBEAT_INTERVAL);
+    let mut interval = interval(config.heartbeat_interval);
 
     let (conn_tx, mut conn_rx) = mpsc::unbounded_channel();
 
     // unwrap: chat server is not dropped before the HTTP server
-    let conn_id = server.connect(room, conn_tx, user_type).await;
+    // Players on the waitlist only get waitlist updates until they are admitted
+    let (conn_id, mut waiting) = match server.connect(room, conn_tx, user_type, player).await {
+        Ok(conn_id) => (conn_id, false),
+        Err(ConnectError::Waitlisted(conn_id)) => (conn_id, true),
+        Err(error) => {
+            if let Some(rejected) = format.render(&serde_json::to_string(&ConnectionRejectedMessage::new(error)).unwrap()) {
+                let _ = session.text(rejected.into_owned()).await;
+            }
+            let _ = session.close(Some(CloseReason{ code: CloseCode::Policy, description: Some(error.reason().to_string()) })).await;
+            return;
+        }
+    };
+
+    let mut guard = ConnectionGuard{ server: server.clone(), room, conn_id, user_type, reason: DisconnectReason::Error };
+    let message_log = server.message_log();
 
     let msg_stream = msg_stream
-        .max_frame_size(128 * 1024)
+        .max_frame_size(config.max_frame_size)
         .aggregate_continuations()
-        .max_continuation_size(2 * 1024 * 1024);
+        .max_continuation_size(config.max_continuation_size);
 
     let mut msg_stream = pin!(msg_stream);
 
@@ -96,25 +249,44 @@ pub async fn ws_handler(
                     AggregatedMessage::Pong(_) => {
                         last_heartbeat = Instant::now();
                     }
-                    AggregatedMessage::Close(reason) => break reason,
+                    AggregatedMessage::Close(reason) => {
+                        guard.reason = DisconnectReason::ClientClose;
+                        break reason;
+                    }
                     AggregatedMessage::Binary(_bin) => {
                         log::warn!("unexpected binary message");
                     }
                     AggregatedMessage::Text(_text) => {
+                        message_log.record(room, conn_id, user_type, Direction::Inbound, &_text);
                         // Check if _text is a request_id message and respond with the appropriate response
-                        let message: Result<WSMessage, serde_json::Error> = serde_json::from_str(&_text);
-                        if message.is_err() {
-                            log::warn!("Invalid message format: {} error {}", _text, message.unwrap_err());
-                            continue;
-                        }
-                        let message = message.unwrap();
+                        let message: WSMessage = match serde_json::from_str(&_text) {
+                            Ok(message) => message,
+                            Err(e) => {
+                                log::warn!("Invalid message format: {} error {}", _text, e);
+                                continue;
+                            }
+                        };
                         if message.r#type == "request_id" {
-                            let id_message = IDMessage::new(conn_id);
+                            let id_message = IDMessage::new(conn_id, user_type);
                             let response = serde_json::to_string(&id_message).unwrap();
-                            session.text(response).await.unwrap();
+                            if let Some(response) = format.render(&response) {
+                                session.text(response.into_owned()).await.unwrap();
+                            }
+                        }
+                        else if message.r#type == "time_sync" {
+                            let client_time_ms = serde_json::from_str::<TimeSyncRequest>(&_text)
+                                .ok()
+                                .and_then(|request| request.client_time_ms);
+                            let response = serde_json::to_string(&TimeSyncMessage::new(client_time_ms)).unwrap();
+                            if let Some(response) = format.render(&response) {
+                                session.text(response.into_owned()).await.unwrap();
+                            }
+                        }
+                        else if waiting {
+                            log::debug!("Ignored {} message of waiting client {}", message.r#type, conn_id);
                         }
                         else {
-                            command_handler(_text.to_string()).await;
+                            command_handler(conn_id, _text.to_string()).await;
                         }
 
                     }
@@ -126,19 +298,40 @@ pub async fn ws_handler(
             }
 
             // client WebSocket stream ended
-            Either::Left((Either::Left((None, _)), _)) => break None,
+            Either::Left((Either::Left((None, _)), _)) => {
+                guard.reason = DisconnectReason::ClientClose;
+                break None;
+            }
 
             // room update
             Either::Left((Either::Right((Some(room_update), _)), _)) => {
-                session.text(room_update).await.unwrap();
+                let closed = room_update.starts_with(SESSION_CLOSED_PREFIX)
+                    .then(|| serde_json::from_str::<SessionClosedMessage>(&room_update).ok())
+                    .flatten();
+                if waiting && room_update.starts_with(ADMITTED_PREFIX){
+                    waiting = false;
+                }
+                message_log.record(room, conn_id, user_type, Direction::Outbound, &room_update);
+                if let Some(room_update) = format.render(&room_update) {
+                    session.text(room_update.into_owned()).await.unwrap();
+                }
+                if let Some(closed) = closed {
+                    guard.reason = if closed.code == CLOSE_SESSION_REPLACED { DisconnectReason::Superseded } else { DisconnectReason::Kicked };
+                    break Some(CloseReason{ code: CloseCode::Other(closed.code), description: Some(closed.reason) });
+                }
             }
 
-            Either::Left((Either::Right((None, _)), _)) => unreachable!(),
+            // the room dropped the connection, e.g. because the room was removed
+            Either::Left((Either::Right((None, _)), _)) => {
+                guard.reason = DisconnectReason::Kicked;
+                break None;
+            }
 
             // heartbeat
             Either::Right((_inst, _)) => {
                 // if no heartbeat ping/pong received recently, close the connection
-                if Instant::now().duration_since(last_heartbeat) > CLIENT_TIMEOUT {
+                if Instant::now().duration_since(last_heartbeat) > config.client_timeout {
+                    guard.reason = DisconnectReason::Timeout;
                     break None;
                 }
 
@@ -148,7 +341,7 @@ pub async fn ws_handler(
         }
     };
 
-    server.disconnect(room, conn_id, user_type).await;
+    drop(guard);
 
     // attempt to close connection gracefully
     let _ = session.close(close_reason).await;